@@ -0,0 +1,365 @@
+//! AFS archives, the plain file containers many Dreamcast and GameCube games keep all their files
+//! in, GVR textures and GVM archives among them.
+//!
+//! An AFS archive starts with an "AFS\0" magic string and the amount of entries, followed by a
+//! table with the offset and the size of each entry. Right after the table, or at the end of the
+//! space left for it before the first entry, an optional offset and size point to the name table,
+//! which has the name, the time of the last change and the size of each entry.
+
+use crate::contents::Contents;
+use crate::error::Failure;
+use crate::output;
+
+/// The magic string every AFS archive starts with.
+const MAGIC: &[u8; 4] = b"AFS\0";
+/// Size of the header, from the magic string up to the amount of entries. The entry table starts
+/// right after this.
+const HEADER_SIZE_AFS: usize = 0x08;
+/// Size of each entry of the table, which holds the offset and the size of an entry.
+const TABLE_ENTRY_SIZE: usize = 0x08;
+/// Size of each entry of the name table.
+const NAME_ENTRY_SIZE: usize = 0x30;
+/// Size of the name field of the name table, which is padded with zeros.
+const NAME_SIZE: usize = 0x20;
+
+/// Contains all the possible errors that can occur while parsing an AFS archive.
+#[derive(Debug)]
+pub enum AfsError {
+    /// The file is shorter than the header of an AFS archive.
+    TooShort(usize),
+    /// The file doesn't start with an "AFS" magic string.
+    UnknownMagic([u8; 4]),
+    /// The table of the given amount of entries ends past the end of the file.
+    Table(usize),
+    /// The entry with the given `index` of `size` bytes at `offset` ends past the end of the file.
+    EntryOutOfBounds {
+        index: usize,
+        offset: usize,
+        size: usize,
+    },
+}
+
+impl std::error::Error for AfsError {}
+
+impl std::fmt::Display for AfsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(
+                f,
+                "The file is too short to be an AFS archive ({len} bytes, the header alone needs {HEADER_SIZE_AFS})."
+            ),
+            Self::UnknownMagic(magic) => write!(
+                f,
+                "Unknown header magic \"{}\", expected \"AFS\".",
+                magic.escape_ascii()
+            ),
+            Self::Table(count) => write!(
+                f,
+                "The table of {count} entries ends past the end of the file."
+            ),
+            Self::EntryOutOfBounds {
+                index,
+                offset,
+                size,
+            } => write!(
+                f,
+                "Entry {index} of {size} bytes at offset 0x{offset:X} ends past the end of the file."
+            ),
+        }
+    }
+}
+
+impl AfsError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        Failure::Codec
+    }
+}
+
+/// A single file of an AFS archive.
+pub struct AfsEntry<'a> {
+    /// The name of the entry, if the archive has a name table.
+    pub name: Option<String>,
+    /// Where the entry starts in the archive.
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// Parses the AFS archive in `buf`, checking that the table and every entry are inside of it. A
+/// name table that isn't inside of it is left out, as if there was none.
+pub fn parse(buf: &[u8]) -> Result<Vec<AfsEntry<'_>>, AfsError> {
+    if buf.len() < HEADER_SIZE_AFS {
+        return Err(AfsError::TooShort(buf.len()));
+    }
+    let magic: [u8; 4] = buf[0x00..0x04].try_into().unwrap();
+    if &magic != MAGIC {
+        return Err(AfsError::UnknownMagic(magic));
+    }
+    let count = read_u32(buf, 0x04) as usize;
+    let table_end = count
+        .checked_mul(TABLE_ENTRY_SIZE)
+        .and_then(|size| size.checked_add(HEADER_SIZE_AFS))
+        .filter(|&end| end <= buf.len())
+        .ok_or(AfsError::Table(count))?;
+
+    let mut entries = Vec::with_capacity(count);
+    for index in 0..count {
+        let field = HEADER_SIZE_AFS + index * TABLE_ENTRY_SIZE;
+        let offset = read_u32(buf, field) as usize;
+        let size = read_u32(buf, field + 4) as usize;
+        let data = offset
+            .checked_add(size)
+            .and_then(|end| buf.get(offset..end))
+            .ok_or(AfsError::EntryOutOfBounds {
+                index,
+                offset,
+                size,
+            })?;
+        entries.push(AfsEntry {
+            name: None,
+            offset,
+            data,
+        });
+    }
+
+    if let Some(names) = name_table(buf, table_end, &entries) {
+        for (entry, name) in entries.iter_mut().zip(names.chunks_exact(NAME_ENTRY_SIZE)) {
+            let name = &name[..NAME_SIZE];
+            let len = name.iter().position(|&byte| byte == 0).unwrap_or(NAME_SIZE);
+            entry.name = Some(String::from_utf8_lossy(&name[..len]).into_owned())
+                .filter(|name| !name.is_empty());
+        }
+    }
+    Ok(entries)
+}
+
+/// Returns the name table of the archive in `buf`, whose entry table ends at `table_end`, if it
+/// has one that's inside of it.
+///
+/// The offset and the size of the name table are either right after the entry table, or right
+/// before the first entry, at the end of the space the archive leaves for the entry table.
+fn name_table<'a>(buf: &'a [u8], table_end: usize, entries: &[AfsEntry]) -> Option<&'a [u8]> {
+    let first_entry = entries
+        .iter()
+        .map(|entry| entry.offset)
+        .filter(|&offset| offset >= table_end + TABLE_ENTRY_SIZE)
+        .min();
+    let candidates = [
+        Some(table_end),
+        first_entry.map(|offset| offset - TABLE_ENTRY_SIZE),
+    ];
+    let needed = entries.len() * NAME_ENTRY_SIZE;
+    candidates.into_iter().flatten().find_map(|field| {
+        if field + TABLE_ENTRY_SIZE > buf.len() {
+            return None;
+        }
+        let offset = read_u32(buf, field) as usize;
+        let size = read_u32(buf, field + 4) as usize;
+        if offset == 0 || size < needed {
+            return None;
+        }
+        offset
+            .checked_add(needed)
+            .and_then(|end| buf.get(offset..end))
+    })
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+impl AfsEntry<'_> {
+    /// Returns the name of the entry if it's usable as a file name.
+    pub fn file_name(&self) -> Option<&str> {
+        self.name
+            .as_deref()
+            .filter(|name| output::is_file_name(name))
+    }
+}
+
+/// Returns the file name `afs extract` saves each of the given `entries` as, with the given
+/// `extension` for `--only-gvr`: the name of the entry if it's usable as a file name, or its index
+/// in the archive followed by the extension of what it holds otherwise (e.g. `00003.gvr`). Names
+/// are never used twice, see [`output::entry_file_names`].
+pub fn file_names(entries: &[AfsEntry], extension: Option<&str>) -> Vec<String> {
+    let names = entries.iter().enumerate().map(|(index, entry)| {
+        let numbered = extension.unwrap_or_else(|| Contents::of(entry.data).0.extension());
+        (entry.file_name(), format!("{index:05}.{numbered}"))
+    });
+    output::entry_file_names(names, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an AFS archive of the given `(name, data)` entries, with a name table if `names` is
+    /// set. The offset and size of the name table are right after the entry table.
+    fn archive(entries: &[(&str, &[u8])], names: bool) -> Vec<u8> {
+        let table_end = HEADER_SIZE_AFS + entries.len() * TABLE_ENTRY_SIZE;
+        let mut buf = MAGIC.to_vec();
+        buf.extend((entries.len() as u32).to_le_bytes());
+        let mut offset = table_end + TABLE_ENTRY_SIZE;
+        for (_, data) in entries {
+            buf.extend((offset as u32).to_le_bytes());
+            buf.extend((data.len() as u32).to_le_bytes());
+            offset += data.len();
+        }
+        let name_table_size = entries.len() * NAME_ENTRY_SIZE;
+        match names {
+            true => {
+                buf.extend((offset as u32).to_le_bytes());
+                buf.extend((name_table_size as u32).to_le_bytes());
+            }
+            false => buf.extend([0; TABLE_ENTRY_SIZE]),
+        }
+        for (_, data) in entries {
+            buf.extend(*data);
+        }
+        if names {
+            for (name, data) in entries {
+                let mut field = [0; NAME_ENTRY_SIZE];
+                field[..name.len()].copy_from_slice(name.as_bytes());
+                field[0x2C..0x30].copy_from_slice(&(data.len() as u32).to_le_bytes());
+                buf.extend(field);
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn parses_entries() {
+        let buf = archive(&[("a.gvr", b"first"), ("b.bin", b"second")], true);
+        let entries = parse(&buf).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name.as_deref(), Some("a.gvr"));
+        assert_eq!(entries[0].offset, 0x20);
+        assert_eq!(entries[0].data, b"first");
+        assert_eq!(entries[1].name.as_deref(), Some("b.bin"));
+        assert_eq!(entries[1].offset, 0x25);
+        assert_eq!(entries[1].data, b"second");
+    }
+
+    #[test]
+    fn name_table_is_optional() {
+        let buf = archive(&[("a.gvr", b"first")], false);
+        let entries = parse(&buf).unwrap();
+        assert_eq!(entries[0].name, None);
+        assert_eq!(entries[0].data, b"first");
+
+        // a name table past the end of the file is left out
+        let mut buf = archive(&[("a.gvr", b"first")], true);
+        buf.truncate(buf.len() - 1);
+        let entries = parse(&buf).unwrap();
+        assert_eq!(entries[0].name, None);
+        assert_eq!(entries[0].data, b"first");
+    }
+
+    #[test]
+    fn name_table_before_first_entry() {
+        // the space left for the entry table is larger than it, and the offset and size of the
+        // name table are at the end of that space
+        let mut buf = archive(&[("a.gvr", b"first")], false);
+        let data = buf.split_off(0x18);
+        buf.resize(0x40, 0);
+        buf.extend(data);
+        buf[0x08..0x0C].copy_from_slice(&0x40u32.to_le_bytes());
+        let name_table = buf.len() as u32;
+        buf[0x38..0x3C].copy_from_slice(&name_table.to_le_bytes());
+        buf[0x3C..0x40].copy_from_slice(&(NAME_ENTRY_SIZE as u32).to_le_bytes());
+        let mut field = [0; NAME_ENTRY_SIZE];
+        field[..5].copy_from_slice(b"a.gvr");
+        buf.extend(field);
+
+        let entries = parse(&buf).unwrap();
+        assert_eq!(entries[0].name.as_deref(), Some("a.gvr"));
+        assert_eq!(entries[0].data, b"first");
+    }
+
+    #[test]
+    fn invalid_headers_fail() {
+        assert!(matches!(parse(b"AFS\0"), Err(AfsError::TooShort(4))));
+        assert!(matches!(
+            parse(b"ABC\0\0\0\0\0"),
+            Err(AfsError::UnknownMagic(magic)) if &magic == b"ABC\0"
+        ));
+    }
+
+    #[test]
+    fn table_past_end_fails() {
+        let mut buf = archive(&[("a.gvr", b"first")], false);
+        buf[0x04..0x08].copy_from_slice(&100u32.to_le_bytes());
+        assert!(matches!(parse(&buf), Err(AfsError::Table(100))));
+        buf[0x04..0x08].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(parse(&buf), Err(AfsError::Table(_))));
+    }
+
+    #[test]
+    fn entry_past_end_fails() {
+        let mut buf = archive(&[("a.gvr", b"first"), ("b.gvr", b"second")], false);
+        buf.truncate(buf.len() - 1);
+        assert!(matches!(
+            parse(&buf),
+            Err(AfsError::EntryOutOfBounds {
+                index: 1,
+                offset: 0x25,
+                size: 6
+            })
+        ));
+
+        // an offset so large that the end of the entry overflows
+        let mut buf = archive(&[("a.gvr", b"first")], false);
+        buf[0x08..0x0C].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            parse(&buf),
+            Err(AfsError::EntryOutOfBounds { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_archives_dont_panic() {
+        let buf = archive(&[("a.gvr", b"first"), ("b.gvr", b"second")], true);
+        for len in 0..buf.len() {
+            let _ = parse(&buf[..len]);
+        }
+    }
+
+    #[test]
+    fn file_names_never_collide() {
+        let buf = archive(
+            &[
+                ("tex.prs", b"1"),
+                ("tex.bin", b"2"),
+                ("", b"3"),
+                ("00002.GVR", b"4"),
+                ("00005.gvr", b"5"),
+                ("../up.gvr", b"6"),
+            ],
+            true,
+        );
+        let entries = parse(&buf).unwrap();
+        assert_eq!(
+            file_names(&entries, None),
+            [
+                "tex.prs",
+                "tex.bin",
+                "00002.bin",
+                "00002.GVR",
+                "00005.gvr",
+                "00005.bin"
+            ]
+        );
+        // with `--only-gvr` the names are compared with the extension they're saved with
+        assert_eq!(
+            file_names(&entries, Some("gvr")),
+            [
+                "tex.gvr",
+                "00001.gvr",
+                "00002.gvr",
+                "00003.gvr",
+                "00005.gvr",
+                "00005_1.gvr"
+            ]
+        );
+    }
+}
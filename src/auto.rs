@@ -0,0 +1,353 @@
+//! Picking a data format for an image from its contents, for `--data-format auto`.
+//!
+//! The image is looked at once, right after it's loaded, and the format is picked by these rules,
+//! in order:
+//!
+//! 1. Grayscale images without transparency use `intensity8`.
+//! 2. With `--prefer-compressed`, images whose pixels are all either fully opaque or fully
+//!    transparent use `dxt1`.
+//! 3. Images with at most 256 colors use `index8`, as long as the pixel format of the palette can
+//!    store their transparency.
+//! 4. Images without transparency use `rgb565`.
+//! 5. All the others use `rgb5a3`.
+//!
+//! With mipmaps, the formats that don't support them (`intensity8` and `index8`) are skipped.
+
+use crate::formats::{DataFormat, PixelFormat};
+use crate::transform::Transform;
+use clap::ValueEnum;
+use clap::builder::PossibleValue;
+use image::{DynamicImage, GenericImageView, Rgba};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// The data format given on the command line, which can be left for each image to decide.
+#[derive(Copy, Clone)]
+pub enum FormatChoice {
+    /// Pick the data format from the contents of each image.
+    Auto,
+    Fixed(DataFormat),
+}
+
+impl ValueEnum for FormatChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::Auto,
+            Self::Fixed(DataFormat::Intensity4),
+            Self::Fixed(DataFormat::Intensity8),
+            Self::Fixed(DataFormat::IntensityA4),
+            Self::Fixed(DataFormat::IntensityA8),
+            Self::Fixed(DataFormat::Rgb565),
+            Self::Fixed(DataFormat::Rgb5a3),
+            Self::Fixed(DataFormat::Argb8888),
+            Self::Fixed(DataFormat::Index4),
+            Self::Fixed(DataFormat::Index8),
+            Self::Fixed(DataFormat::Dxt1),
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            Self::Auto => Some(PossibleValue::new("auto").help(
+                "Picks one of `intensity8`, `rgb565`, `rgb5a3`, `dxt1` and `index8` for each image, depending on its colors and its alpha channel",
+            )),
+            Self::Fixed(data_format) => data_format.to_possible_value(),
+        }
+    }
+}
+
+impl FormatChoice {
+    /// Returns the data format, unless it's picked for each image.
+    pub fn fixed(self) -> Option<DataFormat> {
+        match self {
+            Self::Auto => None,
+            Self::Fixed(data_format) => Some(data_format),
+        }
+    }
+}
+
+/// How the automatic choice of data format is made.
+#[derive(Copy, Clone)]
+pub struct AutoFormat {
+    /// Pick `dxt1` for the images it can store without losing their transparency.
+    pub prefer_compressed: bool,
+}
+
+/// What an image has in its alpha channel.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transparency {
+    /// Every pixel is fully opaque.
+    None,
+    /// Every pixel is either fully opaque or fully transparent.
+    Binary,
+    /// Some pixels are partly transparent.
+    Smooth,
+}
+
+impl std::fmt::Display for Transparency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Binary => write!(f, "binary"),
+            Self::Smooth => write!(f, "smooth"),
+        }
+    }
+}
+
+/// What the rules for picking a data format look at in an image.
+pub struct Analysis {
+    /// Whether the red, green and blue channels are the same in every pixel.
+    pub grayscale: bool,
+    pub transparency: Transparency,
+    /// The amount of different colors, counting the alpha channel. Counting stops past the limit
+    /// it was analyzed with.
+    pub colors: usize,
+    /// The amount of fully transparent pixels.
+    pub transparent: u64,
+    /// The amount of partly transparent pixels.
+    pub translucent: u64,
+}
+
+/// Looks at the pixels of `image`, taking into account the changes `transform` makes to its
+/// alpha channel later on.
+pub fn analyze(image: &DynamicImage, transform: &Transform) -> Analysis {
+    let opaque = transform.force_opaque || transform.background.is_some();
+    let pixels = image.pixels().map(|(_, _, mut pixel)| {
+        if opaque {
+            pixel[3] = 0xFF;
+        } else if let Some(threshold) = transform.alpha_threshold {
+            pixel[3] = if pixel[3] < threshold { 0 } else { 0xFF };
+        }
+        pixel
+    });
+    scan(pixels, 256)
+}
+
+/// Looks at every `step`th pixel of every `step`th row of `image`, counting all of its colors.
+pub fn analyze_sample(image: &DynamicImage, step: u32) -> Analysis {
+    let step = step as usize;
+    let pixels = (0..image.height()).step_by(step).flat_map(|y| {
+        (0..image.width())
+            .step_by(step)
+            .map(move |x| image.get_pixel(x, y))
+    });
+    scan(pixels, usize::MAX)
+}
+
+/// Analyzes the given `pixels`, counting at most `color_limit` colors and one more.
+fn scan(pixels: impl Iterator<Item = Rgba<u8>>, color_limit: usize) -> Analysis {
+    let mut analysis = Analysis {
+        grayscale: true,
+        transparency: Transparency::None,
+        colors: 0,
+        transparent: 0,
+        translucent: 0,
+    };
+    let mut colors = HashSet::new();
+    for pixel in pixels {
+        let [r, g, b, a] = pixel.0;
+        analysis.grayscale &= r == g && g == b;
+        match a {
+            0xFF => {}
+            0 => analysis.transparent += 1,
+            _ => analysis.translucent += 1,
+        }
+        if colors.len() <= color_limit {
+            colors.insert(pixel.0);
+        }
+    }
+
+    analysis.transparency = if analysis.translucent > 0 {
+        Transparency::Smooth
+    } else if analysis.transparent > 0 {
+        Transparency::Binary
+    } else {
+        Transparency::None
+    };
+    analysis.colors = colors.len();
+    analysis
+}
+
+/// The data format picked for an image, along with a description of why.
+#[derive(Copy, Clone)]
+pub struct Choice {
+    pub data_format: DataFormat,
+    pub reason: &'static str,
+}
+
+/// Picks the data format for an image with the given `analysis`, following the rules listed at
+/// the top of this module. `palette` is the pixel format `index8` would use.
+pub fn choose(
+    analysis: &Analysis,
+    auto: AutoFormat,
+    mipmaps: bool,
+    palette: PixelFormat,
+) -> Choice {
+    let choice = |data_format, reason| Choice {
+        data_format,
+        reason,
+    };
+    let transparency = analysis.transparency;
+    if analysis.grayscale && transparency == Transparency::None && !mipmaps {
+        return choice(DataFormat::Intensity8, "the image is grayscale");
+    }
+    if auto.prefer_compressed && transparency != Transparency::Smooth {
+        return choice(
+            DataFormat::Dxt1,
+            "compression was preferred, and the image has no partly transparent pixels",
+        );
+    }
+    if analysis.colors <= 256
+        && !mipmaps
+        && (transparency == Transparency::None || palette != PixelFormat::Rgb565)
+    {
+        return choice(DataFormat::Index8, "the image has at most 256 colors");
+    }
+    match transparency {
+        Transparency::None => choice(DataFormat::Rgb565, "the image is fully opaque"),
+        _ => choice(DataFormat::Rgb5a3, "the image has transparent pixels"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    const AUTO: AutoFormat = AutoFormat {
+        prefer_compressed: false,
+    };
+    const COMPRESSED: AutoFormat = AutoFormat {
+        prefer_compressed: true,
+    };
+
+    /// Analyzes a 32x32 image made by `pixel`, without any transform.
+    fn analysis(pixel: impl Fn(u32, u32) -> [u8; 4]) -> Analysis {
+        let image = RgbaImage::from_fn(32, 32, |x, y| Rgba(pixel(x, y)));
+        analyze(&DynamicImage::ImageRgba8(image), &Transform::default())
+    }
+
+    /// A grayscale gradient.
+    fn gray(x: u32, _: u32) -> [u8; 4] {
+        let value = (x * 8) as u8;
+        [value, value, value, 0xFF]
+    }
+
+    /// A different color for every pixel, with the given `alpha` in the left half.
+    fn colorful(alpha: u8) -> impl Fn(u32, u32) -> [u8; 4] {
+        move |x, y| {
+            let a = if x < 16 { alpha } else { 0xFF };
+            [(x * 8) as u8, (y * 8) as u8, 0x80, a]
+        }
+    }
+
+    /// Only four colors, with the given `alpha` in the left half.
+    fn few_colors(alpha: u8) -> impl Fn(u32, u32) -> [u8; 4] {
+        move |x, y| {
+            let a = if x < 16 { alpha } else { 0xFF };
+            [
+                if x % 2 == 0 { 0xFF } else { 0 },
+                if y % 2 == 0 { 0xFF } else { 0 },
+                0,
+                a,
+            ]
+        }
+    }
+
+    fn data_format(analysis: &Analysis, auto: AutoFormat, mipmaps: bool) -> DataFormat {
+        choose(analysis, auto, mipmaps, PixelFormat::Rgb5a3).data_format
+    }
+
+    #[test]
+    fn analysis_of_pixels() {
+        let gray = analysis(gray);
+        assert!(gray.grayscale);
+        assert_eq!(gray.colors, 32);
+        assert!(gray.transparency == Transparency::None);
+
+        let binary = analysis(few_colors(0));
+        assert!(!binary.grayscale);
+        assert_eq!(binary.colors, 8);
+        assert_eq!((binary.transparent, binary.translucent), (512, 0));
+        assert!(binary.transparency == Transparency::Binary);
+
+        let smooth = analysis(colorful(0x80));
+        assert_eq!(smooth.colors, 257);
+        assert_eq!((smooth.transparent, smooth.translucent), (0, 512));
+        assert!(smooth.transparency == Transparency::Smooth);
+    }
+
+    #[test]
+    fn transform_changes_the_alpha() {
+        let image = RgbaImage::from_fn(32, 32, |x, y| Rgba(colorful(0x80)(x, y)));
+        let image = DynamicImage::ImageRgba8(image);
+        let opaque = Transform {
+            force_opaque: true,
+            ..Transform::default()
+        };
+        assert!(analyze(&image, &opaque).transparency == Transparency::None);
+        let cut = Transform {
+            alpha_threshold: Some(0x81),
+            ..Transform::default()
+        };
+        assert!(analyze(&image, &cut).transparency == Transparency::Binary);
+    }
+
+    #[test]
+    fn choose_each_rule() {
+        // 1. grayscale
+        assert_eq!(
+            data_format(&analysis(gray), AUTO, false),
+            DataFormat::Intensity8
+        );
+        assert_eq!(
+            data_format(&analysis(gray), COMPRESSED, false),
+            DataFormat::Intensity8
+        );
+        // 2. compression, unless pixels are partly transparent
+        let binary = analysis(colorful(0));
+        assert_eq!(data_format(&binary, COMPRESSED, false), DataFormat::Dxt1);
+        assert_eq!(
+            data_format(&analysis(colorful(0xFF)), COMPRESSED, true),
+            DataFormat::Dxt1
+        );
+        assert_eq!(
+            data_format(&analysis(colorful(0x80)), COMPRESSED, false),
+            DataFormat::Rgb5a3
+        );
+        // 3. few colors, as long as the palette keeps the transparency
+        let few = analysis(few_colors(0));
+        assert_eq!(data_format(&few, AUTO, false), DataFormat::Index8);
+        assert_eq!(
+            choose(&few, AUTO, false, PixelFormat::Rgb565).data_format,
+            DataFormat::Rgb5a3
+        );
+        let opaque_few = analysis(few_colors(0xFF));
+        assert_eq!(
+            choose(&opaque_few, AUTO, false, PixelFormat::Rgb565).data_format,
+            DataFormat::Index8
+        );
+        // 4. opaque
+        assert_eq!(
+            data_format(&analysis(colorful(0xFF)), AUTO, false),
+            DataFormat::Rgb565
+        );
+        // 5. transparent
+        assert_eq!(data_format(&binary, AUTO, false), DataFormat::Rgb5a3);
+    }
+
+    #[test]
+    fn mipmaps_skip_formats() {
+        // grayscale and few colors fall through to the formats with mipmaps
+        assert_eq!(data_format(&analysis(gray), AUTO, true), DataFormat::Rgb565);
+        assert_eq!(
+            data_format(&analysis(few_colors(0xFF)), AUTO, true),
+            DataFormat::Rgb565
+        );
+        assert_eq!(
+            data_format(&analysis(few_colors(0)), AUTO, true),
+            DataFormat::Rgb5a3
+        );
+    }
+}
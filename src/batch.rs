@@ -0,0 +1,424 @@
+//! Helpers for processing multiple input files in one go.
+
+use crate::error::{Error, Failure};
+use crate::output::{self, DryRun, Overwrite, Sink};
+use crate::report::{self, BatchCounts, ErrorReport, SummaryReport};
+use crate::stdio;
+use crate::term::{ceprintln, cprintln};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Contains all the possible errors that can occur while expanding glob patterns.
+#[derive(Debug)]
+pub enum ExpandError {
+    /// The given pattern is not a valid glob pattern.
+    Pattern(String, glob::PatternError),
+    /// The given pattern didn't match any files.
+    NoMatches(String),
+    /// A matching path couldn't be read.
+    Io(glob::GlobError),
+}
+
+impl std::error::Error for ExpandError {}
+
+impl std::fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pattern(pattern, err) => write!(f, "Invalid pattern `{pattern}`: {err}"),
+            Self::NoMatches(pattern) => {
+                write!(f, "The pattern `{pattern}` didn't match any files.")
+            }
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// Expands all the glob patterns (e.g. `textures/**/*.gvr`) in the given `paths` into the paths
+/// they match, sorted alphabetically.
+///
+/// Paths that exist as-is are never treated as patterns, so that literal file names containing
+/// `[` or `*` keep working.
+pub fn expand_globs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, ExpandError> {
+    let mut expanded = Vec::new();
+
+    for path in paths {
+        let pattern = path
+            .to_str()
+            .filter(|pattern| pattern.contains(['*', '?', '[']) && !path.exists());
+        let Some(pattern) = pattern else {
+            expanded.push(path.clone());
+            continue;
+        };
+
+        let matches = glob::glob(pattern)
+            .map_err(|e| ExpandError::Pattern(pattern.to_string(), e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ExpandError::Io)?;
+        if matches.is_empty() {
+            return Err(ExpandError::NoMatches(pattern.to_string()));
+        }
+        expanded.extend(matches);
+    }
+
+    Ok(expanded)
+}
+
+/// Reads a list of input paths from the file at `list`, or from standard input if it's `-`.
+///
+/// The paths are separated by newlines, or by NUL bytes if `nul_separated` is set. Empty entries
+/// are ignored, and so are the carriage returns of Windows-style line endings.
+pub fn read_file_list(list: &Path, nul_separated: bool) -> std::io::Result<Vec<PathBuf>> {
+    let data = if stdio::is_stdio(list) {
+        let mut data = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        data
+    } else {
+        std::fs::read(list)?
+    };
+
+    let separator = if nul_separated { b'\0' } else { b'\n' };
+    let paths = data
+        .split(|&b| b == separator)
+        .map(|entry| match nul_separated {
+            true => entry,
+            false => entry.strip_suffix(b"\r").unwrap_or(entry),
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect();
+    Ok(paths)
+}
+
+/// Turns the raw bytes of a path from a file list into a path.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+/// Turns the raw bytes of a path from a file list into a path.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Checks if the given `path` has the file extension of an image format that can be encoded.
+pub fn is_image_file(path: &Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok_and(|format| format.can_read())
+}
+
+/// Checks if the given `path` has the file extension of a GVR texture file.
+pub fn is_gvr_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gvr"))
+}
+
+/// An input file to process in batch mode.
+pub struct Input {
+    /// Path to the input file itself.
+    pub path: PathBuf,
+    /// Path of the input file relative to the input directory it was found in. For files that
+    /// were given directly, this is just the file name.
+    ///
+    /// The output file is saved under the same relative path in the output directory.
+    pub relative: PathBuf,
+}
+
+/// Collects all the input files from the given `paths`.
+///
+/// Files are taken as-is, while directories are expanded into all the files directly inside of
+/// them for which `filter` returns true. If `recursive` is set, all the subdirectories are
+/// expanded as well. The files found in a directory are sorted by name, so that they're always
+/// processed in the same order.
+pub fn collect_inputs(
+    paths: &[PathBuf],
+    filter: fn(&Path) -> bool,
+    recursive: bool,
+) -> std::io::Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            collect_dir(path, Path::new(""), filter, recursive, &mut inputs)?;
+        } else {
+            let relative = path.file_name().map(PathBuf::from).unwrap_or(path.clone());
+            inputs.push(Input {
+                path: path.clone(),
+                relative,
+            });
+        }
+    }
+
+    Ok(inputs)
+}
+
+fn collect_dir(
+    dir: &Path,
+    relative: &Path,
+    filter: fn(&Path) -> bool,
+    recursive: bool,
+    inputs: &mut Vec<Input>,
+) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let entry_relative = relative.join(entry.file_name());
+
+        // symlinked directories aren't followed, so that a link loop can't recurse forever
+        if entry.file_type()?.is_dir() {
+            if recursive {
+                collect_dir(&path, &entry_relative, filter, recursive, inputs)?;
+            }
+        } else if path.is_file() && filter(&path) {
+            inputs.push(Input {
+                path,
+                relative: entry_relative,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+impl Input {
+    /// Returns the file stem of the input file.
+    pub fn stem(&self) -> &OsStr {
+        self.relative
+            .file_stem()
+            .unwrap_or(self.relative.as_os_str())
+    }
+
+    /// Returns the file extension of the input file, or an empty string if it has none.
+    pub fn extension(&self) -> &OsStr {
+        self.relative.extension().unwrap_or_default()
+    }
+}
+
+/// Returns the path in `out_dir` to save the output of the given `input` file to, named after the
+/// input file's stem with the given `extension`. The input's relative path is kept, so files from
+/// subdirectories end up in the same subdirectories of `out_dir`.
+pub fn output_path(input: &Input, out_dir: &Path, extension: &str) -> PathBuf {
+    let mut name = input.stem().to_os_string();
+    name.push(".");
+    name.push(extension);
+    named_output_path(input, out_dir, name)
+}
+
+/// Same as [`output_path`], but saves the output under the given file `name` instead.
+pub fn named_output_path(input: &Input, out_dir: &Path, name: OsString) -> PathBuf {
+    match input.relative.parent() {
+        Some(parent) => out_dir.join(parent).join(name),
+        None => out_dir.join(name),
+    }
+}
+
+/// Finds the first two `inputs` that would be saved to the same one of their `outputs`, returning
+/// both inputs along with the output.
+pub fn find_collision<'a>(
+    inputs: &'a [Input],
+    outputs: &'a [PathBuf],
+) -> Option<(&'a Path, &'a Path, &'a Path)> {
+    let mut seen = HashMap::new();
+    for (input, output) in inputs.iter().zip(outputs) {
+        if let Some(first) = seen.insert(output, input) {
+            return Some((&first.path, &input.path, output));
+        }
+    }
+    None
+}
+
+/// Checks if any of the input `paths` are located directly inside of `out_dir`, meaning the output
+/// files would be saved next to the input files.
+pub fn is_same_dir(paths: &[PathBuf], out_dir: &Path) -> bool {
+    let Ok(out_dir) = out_dir.canonicalize() else {
+        return false;
+    };
+
+    paths.iter().any(|path| {
+        let dir = if path.is_dir() {
+            Some(path.as_path())
+        } else {
+            path.parent()
+        };
+        dir.map(|dir| {
+            if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }
+        })
+        .and_then(|dir| dir.canonicalize().ok())
+        .is_some_and(|dir| dir == out_dir)
+    })
+}
+
+/// The outcome of successfully processing a single input file.
+pub enum Outcome {
+    /// The file was processed, with the size of the output if it's known.
+    Processed(Option<usize>),
+    /// The file was skipped because its output already exists.
+    Skipped,
+    /// The file was skipped because its output is newer than the file itself.
+    UpToDate,
+}
+
+/// The options that decide how each input file gets processed.
+#[derive(Copy, Clone)]
+pub struct ProcessOptions {
+    pub dry_run: Option<DryRun>,
+    pub overwrite: Overwrite,
+    /// Skip input files whose output was modified after the input file.
+    pub if_newer: bool,
+}
+
+/// Processes the given `input` file into `output` with `process`, taking the given `options` into
+/// account.
+///
+/// `process` gets the sink to write its output to, and returns the size of the written output.
+/// That size is only known if the file was actually processed, which isn't the case when the dry
+/// run only checks the paths.
+///
+/// An existing `output` is checked for before `process` runs, so no work is wasted on a file that
+/// gets skipped or refused anyway. Standard output (`-`) never counts as existing.
+pub fn process_file(
+    input: &Path,
+    output: &Path,
+    options: ProcessOptions,
+    process: impl FnOnce(Sink) -> Result<usize, Error>,
+) -> Result<Outcome, Error> {
+    let to_file = !stdio::is_stdio(output);
+    if to_file && options.if_newer && is_up_to_date(input, output) {
+        return Ok(Outcome::UpToDate);
+    }
+    if to_file && output.exists() {
+        match options.overwrite {
+            Overwrite::Error => return Err(Error::OutputExists(output.to_path_buf())),
+            Overwrite::Skip => return Ok(Outcome::Skipped),
+            Overwrite::Force => {}
+        }
+    }
+
+    let size = match options.dry_run {
+        Some(DryRun::Paths) => {
+            output::check_readable(input).map_err(Error::Open)?;
+            None
+        }
+        Some(DryRun::Full) => Some(process(Sink::DryRun)?),
+        None => Some(process(Sink::Disk(options.overwrite))?),
+    };
+    Ok(Outcome::Processed(size))
+}
+
+/// Checks if `output` exists and was modified after `input`. Equal modification times count as
+/// out of date, since the input may have been changed right after the output was written.
+fn is_up_to_date(input: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified());
+    match (modified(input), modified(output)) {
+        (Ok(input), Ok(output)) => output > input,
+        _ => false,
+    }
+}
+
+/// Runs `process` on all the given `inputs` in parallel, using `jobs` worker threads (or one per
+/// logical CPU if not given).
+///
+/// The results are returned in the same order as the inputs, no matter in which order the files
+/// finished processing.
+pub fn run<T: Sync, R: Send>(
+    inputs: &[T],
+    jobs: Option<NonZeroUsize>,
+    process: impl Fn(&T) -> R + Send + Sync,
+) -> Vec<R> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.map_or(0, NonZeroUsize::get))
+        .build()
+        .expect("Couldn't create the worker threads.");
+
+    pool.install(|| inputs.par_iter().map(process).collect())
+}
+
+/// Collects the outcome of every file processed in a batch, to summarize them at the end.
+#[derive(Default)]
+pub struct BatchReport {
+    pub succeeded: usize,
+    pub skipped: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+impl BatchReport {
+    /// Records the `result` of processing the file at `path`.
+    pub fn record(&mut self, path: &Path, result: Result<(), Error>) {
+        match result {
+            Ok(()) => self.succeeded += 1,
+            Err(e) => self.failed.push((path.to_path_buf(), e)),
+        }
+    }
+
+    /// Records that the file at `path` was skipped, without attempting to process it.
+    pub fn skip(&mut self, path: &Path) {
+        self.skipped.push(path.to_path_buf());
+    }
+
+    /// Prints how many of the files succeeded, were skipped, or failed, followed by every failed
+    /// file along with its error. `action` describes what was done to the files (e.g. "encode").
+    ///
+    /// If `quiet` is set, only the failed files are printed. With JSON errors, every failed file
+    /// and the counts are printed as JSON to stderr instead.
+    pub fn print_summary(&self, action: &str, quiet: bool) {
+        if !quiet {
+            cprintln!(
+                "<c!>summary:</> {} succeeded, {} skipped, {} failed",
+                self.succeeded,
+                self.skipped.len(),
+                self.failed.len()
+            );
+        }
+
+        if report::json_errors() {
+            for (path, e) in &self.failed {
+                report::eprint_json(&ErrorReport::from_error(path, e));
+            }
+            report::eprint_json(&SummaryReport {
+                summary: BatchCounts {
+                    action: action.to_string(),
+                    succeeded: self.succeeded,
+                    skipped: self.skipped.len(),
+                    failed: self.failed.len(),
+                },
+            });
+            return;
+        }
+
+        if self.failed.is_empty() {
+            return;
+        }
+
+        if !quiet {
+            eprintln!();
+        }
+        ceprintln!(
+            "<r!>error:</> {} file(s) failed to {}:",
+            self.failed.len(),
+            action
+        );
+        for (path, e) in &self.failed {
+            eprintln!("  {}: {e}", path.display());
+        }
+    }
+
+    /// Returns the exit code for the whole batch, which is a batch failure if any file failed.
+    pub fn exit_code(&self) -> ExitCode {
+        if self.failed.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            Failure::Batch.exit_code()
+        }
+    }
+}
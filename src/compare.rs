@@ -0,0 +1,152 @@
+//! Comparing the pixels of GVR texture files, either to each other for the `compare` command, or to
+//! their source images for `compare-image`.
+
+use crate::decode;
+use crate::encode;
+use crate::error::Error;
+use crate::formats::PixelFormat;
+use crate::header::GvrHeader;
+use crate::metrics;
+use crate::prs;
+use gvrtex::error::TextureDecodeError;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::path::Path;
+
+/// How two textures differ.
+pub enum Comparison {
+    /// Every pixel is the same in both textures.
+    Identical,
+    /// The textures have the same dimensions, but some of their pixels differ.
+    Different(Difference),
+    /// The textures don't have the same dimensions, so their pixels can't be compared.
+    Dimensions { a: (u32, u32), b: (u32, u32) },
+}
+
+/// The pixels that differ between two textures of the same dimensions.
+pub struct Difference {
+    /// The amount of pixels that differ in at least one channel.
+    pub pixels: u64,
+    /// The amount of pixels in each texture.
+    pub total: u64,
+    /// The largest difference of the red, green, blue and alpha channels.
+    pub max_delta: [u8; 4],
+    /// The peak signal-to-noise ratio over all four channels, in dB.
+    pub psnr: f64,
+}
+
+/// How many times larger the differences are drawn in an [`amplified_diff`].
+pub const AMPLIFY: u8 = 16;
+
+/// Reads the GVR texture file in `path`, returning its header and its decoded full-size image.
+pub fn load(path: &Path) -> Result<(GvrHeader, RgbaImage), Error> {
+    let buffer = prs::read(path).map_err(Error::Open)?;
+    let header = GvrHeader::parse(&buffer).map_err(Error::Header)?;
+    Ok((header, decode::decode_buffer(buffer)?))
+}
+
+/// Converts the `source` image of a texture with the given `header` to the colors the texture can
+/// hold, the way the encoder does. The intensity formats only keep the brightness of each pixel,
+/// and the alpha channel is made opaque for the formats that don't store it.
+pub fn convert_source(source: DynamicImage, header: &GvrHeader) -> RgbaImage {
+    let data_format = header.data_format;
+    let alpha = data_format.stores_alpha(header.pixel_format.unwrap_or(PixelFormat::Rgb5a3));
+    let mut source = source.into_rgba8();
+    for pixel in source.pixels_mut() {
+        if data_format.is_intensity() {
+            let intensity = encode::encoded_intensity([pixel[0], pixel[1], pixel[2]]);
+            pixel.0[..3].fill(intensity);
+        }
+        if !alpha {
+            pixel[3] = 0xFF;
+        }
+    }
+    source
+}
+
+/// Makes every fully transparent pixel of `image` black, so that the colors that can't be seen
+/// aren't compared. Encoders are free to change them, and `dxt1` always does.
+pub fn clear_transparent(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        if pixel[3] == 0 {
+            *pixel = Rgba([0; 4]);
+        }
+    }
+}
+
+/// Saves a `diff` image made by [`diff_image`] or [`amplified_diff`] to `output`.
+pub fn save_diff(diff: &RgbaImage, output: &Path) -> Result<(), Error> {
+    diff.save(output)
+        .map_err(|e| Error::Save(TextureDecodeError::Image(e)))
+}
+
+/// Compares the pixels of the images `a` and `b`.
+pub fn compare(a: &RgbaImage, b: &RgbaImage) -> Comparison {
+    if a.dimensions() != b.dimensions() {
+        return Comparison::Dimensions {
+            a: a.dimensions(),
+            b: b.dimensions(),
+        };
+    }
+
+    let mut pixels = 0;
+    let mut max_delta = [0; 4];
+    let mut squared_error = 0;
+    for (a, b) in a.pixels().zip(b.pixels()) {
+        if a == b {
+            continue;
+        }
+        pixels += 1;
+        for channel in 0..4 {
+            let delta = a[channel].abs_diff(b[channel]);
+            max_delta[channel] = max_delta[channel].max(delta);
+            squared_error += u64::from(delta) * u64::from(delta);
+        }
+    }
+
+    if pixels == 0 {
+        return Comparison::Identical;
+    }
+    let total = u64::from(a.width()) * u64::from(a.height());
+    Comparison::Different(Difference {
+        pixels,
+        total,
+        max_delta,
+        psnr: metrics::psnr(squared_error, total * 4),
+    })
+}
+
+/// Builds an image showing where two images of the same dimensions differ. The identical pixels
+/// are a dimmed, opaque copy of `a`, and the differing ones are red, brighter the more they
+/// differ.
+pub fn diff_image(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pixel_a, pixel_b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let delta = (0..4)
+            .map(|channel| pixel_a[channel].abs_diff(pixel_b[channel]))
+            .max()
+            .unwrap_or(0);
+        if delta == 0 {
+            // Transparent pixels are shown as black, like on a dark background.
+            let dim = |channel: usize| {
+                (u16::from(pixel_a[channel]) * u16::from(pixel_a[3]) / 255 / 4) as u8
+            };
+            Rgba([dim(0), dim(1), dim(2), 0xFF])
+        } else {
+            Rgba([128 + delta / 2, 0, 0, 0xFF])
+        }
+    })
+}
+
+/// Builds an image of the differences between two images of the same dimensions, with each
+/// channel of each pixel being the difference of that channel, made [`AMPLIFY`] times larger so
+/// that even small quantization errors like the banding of `rgb565` are visible. Differences of
+/// the alpha channel show up in all three color channels.
+pub fn amplified_diff(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pixel_a, pixel_b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let delta = |channel: usize| pixel_a[channel].abs_diff(pixel_b[channel]);
+        let alpha = delta(3);
+        let amplify = |channel: usize| delta(channel).max(alpha).saturating_mul(AMPLIFY);
+        Rgba([amplify(0), amplify(1), amplify(2), 0xFF])
+    })
+}
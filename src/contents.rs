@@ -0,0 +1,76 @@
+//! Telling what the files inside of archives hold, to pick out the GVR textures among them.
+
+use crate::formats::HeaderId;
+use crate::header;
+use crate::prs::{self, PrsError};
+
+/// What an entry of an archive holds, judging by the magic string it starts with.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Contents {
+    /// A GVR texture, with or without its GCIX/GBIX header.
+    Gvr,
+    /// A GVM archive.
+    Gvm,
+    /// Anything else.
+    Other,
+}
+
+impl Contents {
+    /// Returns what the given `data` holds, and whether it's PRS-compressed. Compressed data only
+    /// counts as a GVR texture or a GVM archive if it decompresses into one.
+    pub fn of(data: &[u8]) -> (Self, bool) {
+        let contents = Self::of_magic(data.get(..4));
+        if contents != Self::Other {
+            return (contents, false);
+        }
+        match prs::magic(data).map(|magic| Self::of_magic(Some(&magic))) {
+            Some(contents) if contents != Self::Other => (contents, true),
+            _ => (Self::Other, false),
+        }
+    }
+
+    fn of_magic(magic: Option<&[u8]>) -> Self {
+        match magic {
+            Some(b"GCIX" | b"GBIX" | b"GVRT") => Self::Gvr,
+            Some(b"GVMH") => Self::Gvm,
+            _ => Self::Other,
+        }
+    }
+
+    /// Returns the file extension for files holding these contents.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gvr => "gvr",
+            Self::Gvm => "gvm",
+            Self::Other => "bin",
+        }
+    }
+}
+
+impl std::fmt::Display for Contents {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gvr => write!(f, "GVR texture"),
+            Self::Gvm => write!(f, "GVM archive"),
+            Self::Other => write!(f, "-"),
+        }
+    }
+}
+
+/// Returns the GVR texture in `data`, decompressing it first if it's compressed, as a standalone
+/// GVR texture file. Bare "GVRT" chunks get a GCIX header put in front of them, with a global index
+/// of 0. Returns `None` if `data` doesn't hold a GVR texture, and fails if it's compressed, but
+/// can't be decompressed.
+pub fn gvr_file(data: &[u8]) -> Result<Option<Vec<u8>>, PrsError> {
+    let texture = match Contents::of(data) {
+        (Contents::Gvr, false) => data.to_vec(),
+        (Contents::Gvr, true) => prs::decompress(data)?,
+        _ => return Ok(None),
+    };
+    if texture.starts_with(b"GVRT") {
+        let mut gvr = header::global_header(HeaderId::Gcix, 0).to_vec();
+        gvr.extend(texture);
+        return Ok(Some(gvr));
+    }
+    Ok(Some(texture))
+}
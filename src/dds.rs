@@ -0,0 +1,310 @@
+//! DDS files, for passing the BC1 blocks of `encode --passthrough` into `dxt1` textures as they
+//! are, and for decoding textures into DDS files with all of their image levels.
+//!
+//! BC1 is the same compression as `dxt1`, so its blocks don't have to be decoded. DDS files store
+//! them row by row, with little-endian end points and the first pixel of each row in the lowest
+//! bits of its index byte. GVR textures group them in tiles of 2x2 blocks, with big-endian end
+//! points and the first pixel in the highest bits, so the blocks are only reordered and their
+//! bytes rearranged. The other data formats are saved as uncompressed RGBA8 DDS files.
+
+use crate::decode;
+use crate::error::Error;
+use crate::formats::DataFormat;
+use crate::header::{self, GvrHeader};
+use gvrtex::error::TextureDecodeError;
+
+/// The magic string every DDS file starts with.
+const MAGIC: &[u8; 4] = b"DDS ";
+/// Size of the DDS header after the magic string.
+const HEADER_SIZE: usize = 124;
+/// Size of the extra header of the files whose four-character code is `DX10`.
+const DX10_HEADER_SIZE: usize = 20;
+/// The DXGI formats of BC1 data in the `DX10` header: typeless, linear and sRGB.
+const DXGI_BC1: [u32; 3] = [70, 71, 72];
+/// Set in the pixel format flags when the four-character code is used.
+const PIXEL_FORMAT_FOURCC: u32 = 0x4;
+/// Set in the pixel format flags of uncompressed data with an alpha channel.
+const PIXEL_FORMAT_RGBA: u32 = 0x41;
+
+/// The header flags every DDS file sets: the caps, the height, the width and the pixel format.
+const FLAGS_REQUIRED: u32 = 0x1007;
+/// Set in the header flags when the pitch of uncompressed data is given.
+const FLAGS_PITCH: u32 = 0x8;
+/// Set in the header flags when the mipmap count is given.
+const FLAGS_MIPMAP_COUNT: u32 = 0x2_0000;
+/// Set in the header flags when the size of the first level of compressed data is given.
+const FLAGS_LINEAR_SIZE: u32 = 0x8_0000;
+/// The caps of every texture.
+const CAPS_TEXTURE: u32 = 0x1000;
+/// The caps of textures with mipmaps, which are complex and have mipmaps.
+const CAPS_MIPMAPS: u32 = 0x40_0008;
+
+/// Size of a single BC1 or `dxt1` block of 4x4 pixels.
+const BLOCK_SIZE: usize = 8;
+/// A `dxt1` block whose pixels are all transparent, for the blocks of a tile that are past the
+/// edge of the image. These are the bytes the encoder stores for them.
+const TRANSPARENT_BLOCK: [u8; BLOCK_SIZE] = [0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+/// The parts of a DDS file needed to pass its blocks through.
+pub struct Dds {
+    pub width: u32,
+    pub height: u32,
+    /// The amount of image levels stored, counting the full-size image.
+    pub levels: usize,
+    /// The four-character code of the pixel format, or the name of the DXGI format for `DX10`
+    /// files, for telling which data the file holds.
+    pub format: String,
+    /// Whether the data is BC1 compressed.
+    pub bc1: bool,
+    /// Everything after the headers.
+    data: Vec<u8>,
+}
+
+impl Dds {
+    /// Parses the DDS file in `bytes`, or returns `None` if it isn't one.
+    pub fn parse(mut bytes: Vec<u8>) -> Option<Self> {
+        if bytes.len() < MAGIC.len() + HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return None;
+        }
+        let field =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let height = field(0x0C);
+        let width = field(0x10);
+        let levels = (field(0x1C) as usize).max(1);
+        let four_cc: [u8; 4] = bytes[0x54..0x58].try_into().unwrap();
+        let mut data_offset = MAGIC.len() + HEADER_SIZE;
+
+        let (format, bc1) = if field(0x50) & PIXEL_FORMAT_FOURCC == 0 {
+            ("uncompressed".to_string(), false)
+        } else if &four_cc == b"DX10" {
+            if bytes.len() < data_offset + DX10_HEADER_SIZE {
+                return None;
+            }
+            let dxgi_format = field(data_offset);
+            data_offset += DX10_HEADER_SIZE;
+            (
+                format!("DXGI format {dxgi_format}"),
+                DXGI_BC1.contains(&dxgi_format),
+            )
+        } else {
+            (four_cc.escape_ascii().to_string(), &four_cc == b"DXT1")
+        };
+
+        bytes.drain(..data_offset);
+        Some(Self {
+            width,
+            height,
+            levels,
+            format,
+            bc1,
+            data: bytes,
+        })
+    }
+
+    /// Returns the dimensions of the given image `level`, which are halved for each level but
+    /// never smaller than 1x1.
+    pub fn level_dimensions(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Returns the BC1 blocks of the given image `level`, or `None` if the file ends before them.
+    fn bc1_level(&self, level: usize) -> Option<&[u8]> {
+        let size = |level: usize| {
+            let (width, height) = self.level_dimensions(level);
+            width.div_ceil(4) as usize * height.div_ceil(4) as usize * BLOCK_SIZE
+        };
+        let offset: usize = (0..level).map(size).sum();
+        self.data.get(offset..offset + size(level))
+    }
+
+    /// Rearranges the BC1 blocks of the given image `level` into the data of a `dxt1` level, or
+    /// returns `None` if the file ends before them. The tiles are padded with transparent blocks
+    /// past the edge of the image, like the levels the encoder makes.
+    pub fn gvr_level(&self, level: usize) -> Option<Vec<u8>> {
+        let blocks = self.bc1_level(level)?;
+        let (width, height) = self.level_dimensions(level);
+        let (columns, rows) = (width.div_ceil(4), height.div_ceil(4));
+        let mut data =
+            Vec::with_capacity(width.div_ceil(8) as usize * height.div_ceil(8) as usize * 32);
+        for tile_y in (0..rows).step_by(2) {
+            for tile_x in (0..columns).step_by(2) {
+                for (block_x, block_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let (x, y) = (tile_x + block_x, tile_y + block_y);
+                    if x >= columns || y >= rows {
+                        data.extend(TRANSPARENT_BLOCK);
+                        continue;
+                    }
+                    let offset = (y * columns + x) as usize * BLOCK_SIZE;
+                    data.extend(swap_block(&blocks[offset..offset + BLOCK_SIZE]));
+                }
+            }
+        }
+        Some(data)
+    }
+}
+
+/// Turns a BC1 block into a `dxt1` block, or the other way around, as both only differ in the
+/// byte order of the end points and the bit order of the index of each pixel.
+fn swap_block(block: &[u8]) -> [u8; BLOCK_SIZE] {
+    let mut swapped = [0; BLOCK_SIZE];
+    swapped[0..2].copy_from_slice(&[block[1], block[0]]);
+    swapped[2..4].copy_from_slice(&[block[3], block[2]]);
+    for (row, &indices) in block[4..].iter().enumerate() {
+        swapped[4 + row] = (indices & 0x03) << 6
+            | (indices & 0x0C) << 2
+            | (indices & 0x30) >> 2
+            | (indices & 0xC0) >> 6;
+    }
+    swapped
+}
+
+/// Saves the GVR texture file in `buffer` as a DDS file with all of its image levels. The blocks
+/// of `dxt1` are copied as they are, and the other data formats are decoded into RGBA8.
+pub fn write(buffer: &[u8]) -> Result<Vec<u8>, Error> {
+    let header = GvrHeader::parse(buffer).map_err(Error::Header)?;
+    let levels = header.level_count();
+    let (width, height) = header.level_dimensions(0);
+    let compressed = header.data_format == DataFormat::Dxt1;
+
+    let mut data = Vec::new();
+    for level in 0..levels {
+        if compressed {
+            let offset = header::HEADER_SIZE + header.level_offset(level) as usize;
+            let size = header.level_size(level) as usize;
+            let level_data = buffer
+                .get(offset..offset + size)
+                .ok_or(Error::Decode(TextureDecodeError::InvalidFile))?;
+            let (width, height) = header.level_dimensions(level);
+            data.extend(bc1_level(level_data, width, height));
+        } else {
+            data.extend(decode::decode_level(buffer, level)?.into_raw());
+        }
+    }
+
+    let mut flags = FLAGS_REQUIRED;
+    let mut caps = CAPS_TEXTURE;
+    if levels > 1 {
+        flags |= FLAGS_MIPMAP_COUNT;
+        caps |= CAPS_MIPMAPS;
+    }
+    // compressed data gives the size of its first level, and uncompressed data its row length
+    let pitch_or_size = if compressed {
+        flags |= FLAGS_LINEAR_SIZE;
+        width.div_ceil(4) * height.div_ceil(4) * BLOCK_SIZE as u32
+    } else {
+        flags |= FLAGS_PITCH;
+        width * 4
+    };
+
+    let mut dds = Vec::with_capacity(MAGIC.len() + HEADER_SIZE + data.len());
+    dds.extend(MAGIC);
+    let fields: [(usize, u32); 8] = [
+        (0x04, HEADER_SIZE as u32),
+        (0x08, flags),
+        (0x0C, height),
+        (0x10, width),
+        (0x14, pitch_or_size),
+        (0x1C, levels as u32),
+        // the size of the pixel format structure
+        (0x4C, 32),
+        (0x6C, caps),
+    ];
+    dds.resize(MAGIC.len() + HEADER_SIZE, 0);
+    for (offset, value) in fields {
+        dds[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    if compressed {
+        dds[0x50..0x54].copy_from_slice(&PIXEL_FORMAT_FOURCC.to_le_bytes());
+        dds[0x54..0x58].copy_from_slice(b"DXT1");
+    } else {
+        // 32 bits per pixel, with the red, green, blue and alpha bytes in that order
+        let pixel_format: [u32; 6] = [
+            PIXEL_FORMAT_RGBA,
+            0,
+            32,
+            0x0000_00FF,
+            0x0000_FF00,
+            0x00FF_0000,
+        ];
+        for (index, value) in pixel_format.into_iter().enumerate() {
+            dds[0x50 + index * 4..0x54 + index * 4].copy_from_slice(&value.to_le_bytes());
+        }
+        dds[0x68..0x6C].copy_from_slice(&0xFF00_0000u32.to_le_bytes());
+    }
+    dds.extend(data);
+    Ok(dds)
+}
+
+/// Rearranges the data of a `dxt1` level of the given dimensions into BC1 blocks row by row,
+/// leaving out the blocks of its tiles that are past the edge of the image.
+fn bc1_level(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (columns, rows) = (width.div_ceil(4), height.div_ceil(4));
+    let tile_columns = width.div_ceil(8);
+    let mut blocks = Vec::with_capacity((columns * rows) as usize * BLOCK_SIZE);
+    for y in 0..rows {
+        for x in 0..columns {
+            // each tile holds its 4 blocks row by row
+            let tile = (y / 2 * tile_columns + x / 2) as usize;
+            let offset = (tile * 4 + (y % 2 * 2 + x % 2) as usize) * BLOCK_SIZE;
+            blocks.extend(swap_block(&data[offset..offset + BLOCK_SIZE]));
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dxt;
+    use crate::testing;
+    use image::{Rgba, RgbaImage};
+
+    /// Decodes the BC1 `blocks` of an image of the given size, which are stored row by row, with
+    /// the colors the decoder makes of their end points.
+    fn decode_bc1(blocks: &[u8], width: u32, height: u32) -> RgbaImage {
+        let columns = width.div_ceil(4);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let offset = ((y / 4 * columns + x / 4) as usize) * BLOCK_SIZE;
+            let block = &blocks[offset..offset + BLOCK_SIZE];
+            let first = u16::from_le_bytes([block[0], block[1]]);
+            let second = u16::from_le_bytes([block[2], block[3]]);
+            // the first pixel of each row is in the lowest bits
+            let index = (block[4 + (y % 4) as usize] >> (x % 4 * 2) & 3) as usize;
+            if first <= second && index == 3 {
+                return Rgba([0; 4]);
+            }
+            let [r, g, b] = dxt::palette([first, second])[index];
+            Rgba([r as u8, g as u8, b as u8, 0xFF])
+        })
+    }
+
+    #[test]
+    fn passed_through_blocks_decode_like_the_dds() {
+        let (width, height) = (24, 20);
+        let texture = testing::texture(DataFormat::Dxt1, width, height);
+        let mut dds = write(&texture).unwrap();
+
+        // blocks of both modes, with every index
+        let mut state = 0x9e37_79b9_u32;
+        let blocks = width.div_ceil(4) as usize * height.div_ceil(4) as usize;
+        dds.truncate(MAGIC.len() + HEADER_SIZE);
+        dds.extend((0..blocks * BLOCK_SIZE).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }));
+        let wanted = decode_bc1(&dds[MAGIC.len() + HEADER_SIZE..], width, height);
+        assert!(wanted.pixels().any(|pixel| pixel[3] == 0));
+
+        let parsed = Dds::parse(dds).unwrap();
+        assert!(parsed.bc1);
+        let mut passed = texture[..header::HEADER_SIZE].to_vec();
+        passed.extend(parsed.gvr_level(0).unwrap());
+        assert_eq!(passed.len(), texture.len());
+        let decoded = decode::decode_buffer(passed).unwrap();
+        for (x, y, pixel) in decoded.enumerate_pixels() {
+            assert_eq!(pixel, wanted.get_pixel(x, y), "{x}, {y}");
+        }
+    }
+}
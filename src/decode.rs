@@ -0,0 +1,441 @@
+//! The decoding of a single GVR texture file into an image file.
+
+use crate::dds;
+use crate::error::Error;
+use crate::formats::ImageFormat;
+use crate::header::GvrHeader;
+use crate::indexed;
+use crate::ktx2;
+use crate::mips;
+use crate::output::Sink;
+use crate::palette::{self, Indexed};
+use crate::prs;
+use crate::transform::Flip;
+use clap::ValueEnum;
+use gvrtex::TextureDecoder;
+use gvrtex::error::TextureDecodeError;
+use image::error::EncodingError;
+use image::{ImageError, RgbaImage, imageops};
+use log::{Level, debug, info};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Decodes the GVR texture file in `input`, writing the image to `output` through the given
+/// `sink`. Returns the size of the image file.
+///
+/// The image is saved in the given `image_format`. If none is given, the format is derived from
+/// the file extension of `output` instead. Which of the image levels end up in the image is chosen
+/// by `levels`, and each of them is mirrored as given by `flip`.
+///
+/// With `keep_indexed`, a single level of a texture in one of the indexed data formats is saved as
+/// an indexed-color PNG file instead, with the palette of the texture. See [`decode_indexed`].
+///
+/// DDS and KTX2 files always hold every image level of the texture, see [`dds::write`] and
+/// [`ktx2::write`].
+pub fn decode_file(
+    input: &Path,
+    output: &Path,
+    image_format: Option<ImageFormat>,
+    levels: Levels,
+    flip: Flip,
+    keep_indexed: bool,
+    sink: Sink,
+) -> Result<usize, Error> {
+    let start = Instant::now();
+    let buffer = prs::read(input).map_err(Error::Open)?;
+    log_loaded(input, &buffer);
+
+    if let Some(format) = all_levels_format(output, image_format) {
+        let encoded = match format {
+            ImageFormat::Ktx2 => ktx2::write(&buffer)?,
+            _ => dds::write(&buffer)?,
+        };
+        debug!(
+            "{}: saved {} bytes as a {} file in {:.2?}",
+            input.display(),
+            encoded.len(),
+            format.extension().to_uppercase(),
+            start.elapsed()
+        );
+        return sink.write(output, &encoded);
+    }
+    if keep_indexed
+        && let Levels::Single(level) = levels
+        && let Some(encoded) = decode_indexed(input, &buffer, level, flip)?
+    {
+        debug!("{}: decoded in {:.2?}", input.display(), start.elapsed());
+        return sink.write(output, &encoded);
+    }
+    let image = match levels {
+        Levels::Single(level) => {
+            let mut image = if level == 0 {
+                decode_buffer(buffer)?
+            } else {
+                decode_level(&buffer, level)?
+            };
+            flip.apply(&mut image);
+            image
+        }
+        Levels::Atlas(layout) => decode_atlas(&buffer, layout, flip)?,
+    };
+    debug!("{}: decoded in {:.2?}", input.display(), start.elapsed());
+
+    save_image(input, &image, output, image_format, sink)
+}
+
+/// Which of the image levels of a texture get decoded into the image.
+#[derive(Copy, Clone)]
+pub enum Levels {
+    /// Only the given level, where level 0 is the full-size image.
+    Single(usize),
+    /// All the levels, laid out next to each other in a single image.
+    Atlas(AtlasLayout),
+}
+
+/// How the levels are laid out in a mipmap atlas.
+#[derive(Copy, Clone, ValueEnum)]
+pub enum AtlasLayout {
+    /// The full-size image on the left, with each smaller level to the right of the previous one.
+    Horizontal,
+    /// The full-size image at the top, with each smaller level below the previous one.
+    Vertical,
+}
+
+/// A single mipmap level saved by [`decode_all_levels`].
+pub struct SavedLevel {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the image file, in bytes.
+    pub size: usize,
+}
+
+/// Decodes every image level of the GVR texture file in `input`, writing each of them to its own
+/// file through the given `sink`. The files are named after `output`, with the number of the level
+/// added to the file name, as in `output_mip0.png`. See [`decode_file`] for the `image_format` and
+/// `flip`, and for `keep_indexed`.
+///
+/// Textures without mipmaps only have level 0, which is the full-size image.
+pub fn decode_all_levels(
+    input: &Path,
+    output: &Path,
+    image_format: Option<ImageFormat>,
+    flip: Flip,
+    keep_indexed: bool,
+    sink: Sink,
+) -> Result<Vec<SavedLevel>, Error> {
+    let start = Instant::now();
+    let buffer = prs::read(input).map_err(Error::Open)?;
+    log_loaded(input, &buffer);
+    let header = GvrHeader::parse(&buffer).map_err(Error::Header)?;
+    let mut saved = Vec::with_capacity(header.level_count());
+    for level in 0..header.level_count() {
+        let path = level_path(output, level);
+        if keep_indexed && let Some(encoded) = decode_indexed(input, &buffer, level, flip)? {
+            let (width, height) = header.level_dimensions(level);
+            let size = sink.write(&path, &encoded)?;
+            saved.push(SavedLevel {
+                path,
+                width,
+                height,
+                size,
+            });
+            continue;
+        }
+        let mut image = if level == 0 {
+            decode_buffer(buffer.clone())?
+        } else {
+            decode_level(&buffer, level)?
+        };
+        flip.apply(&mut image);
+        let size = save_image(input, &image, &path, image_format, sink)?;
+        saved.push(SavedLevel {
+            path,
+            width: image.width(),
+            height: image.height(),
+            size,
+        });
+    }
+    debug!(
+        "{}: decoded {} levels in {:.2?}",
+        input.display(),
+        saved.len(),
+        start.elapsed()
+    );
+    Ok(saved)
+}
+
+/// Returns the path the given mipmap `level` is saved to by [`decode_all_levels`], which is
+/// `output` with `_mip<level>` added to the end of the file stem.
+pub fn level_path(output: &Path, level: usize) -> PathBuf {
+    let mut name = output.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("_mip{level}"));
+    if let Some(extension) = output.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    output.with_file_name(name)
+}
+
+/// Returns the format of the file [`decode_file`] saves if it's one that holds every image level,
+/// which is the given `image_format` or the one derived from the file extension of `output`.
+pub fn all_levels_format(output: &Path, image_format: Option<ImageFormat>) -> Option<ImageFormat> {
+    match image_format {
+        Some(format) => Some(format).filter(|format| format.image_format().is_none()),
+        None => ImageFormat::all_levels_from_path(output),
+    }
+}
+
+/// Logs the size and the data format of the texture in `buffer`, read from `input`, if info
+/// messages are enabled.
+fn log_loaded(input: &Path, buffer: &[u8]) {
+    if log::log_enabled!(Level::Info)
+        && let Ok(header) = GvrHeader::parse(buffer)
+    {
+        info!(
+            "{}: loaded {}x{} {} texture{}",
+            input.display(),
+            header.width,
+            header.height,
+            header.data_format,
+            if header.mipmaps { " with mipmaps" } else { "" }
+        );
+    }
+}
+
+/// Decodes the given image `level` of the GVR texture file in `buffer` into an indexed-color PNG
+/// file, mirrored as given by `flip`. The palette of the PNG file has the colors of the palette of
+/// the texture in the same order, and each pixel keeps its index, so encoding the image again
+/// with the same palette gives back the same texture data.
+///
+/// Returns `None` for textures that aren't in one of the indexed data formats, or whose palette is
+/// stored in a separate file, which are decoded as usual.
+fn decode_indexed(
+    input: &Path,
+    buffer: &[u8],
+    level: usize,
+    flip: Flip,
+) -> Result<Option<Vec<u8>>, Error> {
+    let header = GvrHeader::parse(buffer).map_err(Error::Header)?;
+    if !header.data_format.is_indexed() || header.external_palette {
+        info!(
+            "{}: the texture has no palette of its own, decoding it to RGBA instead",
+            input.display()
+        );
+        return Ok(None);
+    }
+    let (header, colors) = palette::read_texture(buffer)?;
+    let mut indices = palette::read_indices(buffer, &header, level)?;
+    flip.apply(&mut indices);
+    let encoded = indexed::write_png(&Indexed { colors, indices }).map_err(|e| {
+        Error::Save(TextureDecodeError::Image(ImageError::Encoding(
+            EncodingError::new(image::ImageFormat::Png.into(), e),
+        )))
+    })?;
+    debug!(
+        "{}: saved {} bytes as an indexed PNG",
+        input.display(),
+        encoded.len()
+    );
+    Ok(Some(encoded))
+}
+
+/// Saves the decoded `image` of `input` to `output` through the given `sink`, in the given
+/// `image_format` or in the one derived from the file extension of `output`. Returns the size of
+/// the image file.
+fn save_image(
+    input: &Path,
+    image: &RgbaImage,
+    output: &Path,
+    image_format: Option<ImageFormat>,
+    sink: Sink,
+) -> Result<usize, Error> {
+    let format = match image_format {
+        Some(format) => format
+            .image_format()
+            .expect("Formats with every image level are saved by `decode_file`."),
+        None => image::ImageFormat::from_path(output)
+            .map_err(|e| Error::Save(TextureDecodeError::Image(e)))?,
+    };
+
+    let mut encoded = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut encoded), format)
+        .map_err(|e| Error::Save(TextureDecodeError::Image(e)))?;
+    debug!(
+        "{}: saved {} bytes as {format:?}",
+        input.display(),
+        encoded.len()
+    );
+
+    sink.write(output, &encoded)
+}
+
+/// Decodes the GVR texture file in `buffer` into an image, without touching the disk.
+pub fn decode_buffer(buffer: Vec<u8>) -> Result<RgbaImage, Error> {
+    let mut decoder = TextureDecoder::new_from_buffer(buffer);
+    decoder.decode().map_err(Error::Decode)?;
+    decoder.into_decoded().map_err(Error::Decode)
+}
+
+/// Decodes only the given mipmap `level` of the GVR texture file in `buffer` into an image.
+pub fn decode_level(buffer: &[u8], level: usize) -> Result<RgbaImage, Error> {
+    let header = GvrHeader::parse(buffer).map_err(Error::Header)?;
+    let image = decode_buffer(mips::level_texture(&header, buffer, level)?)?;
+    let (width, height) = header.level_dimensions(level);
+    Ok(imageops::crop_imm(&image, 0, 0, width, height).to_image())
+}
+
+/// Decodes all the image levels of the GVR texture file in `buffer` into a single image, laid out
+/// as given by `layout`, with each level mirrored in its own place as given by `flip`. The space
+/// around the smaller levels is left transparent.
+pub fn decode_atlas(buffer: &[u8], layout: AtlasLayout, flip: Flip) -> Result<RgbaImage, Error> {
+    let header = GvrHeader::parse(buffer).map_err(Error::Header)?;
+    let dimensions: Vec<_> = (0..header.level_count())
+        .map(|level| header.level_dimensions(level))
+        .collect();
+    let (width, height, positions) = atlas_layout(&dimensions, layout);
+
+    let mut atlas = RgbaImage::new(width, height);
+    for (level, (x, y)) in positions.into_iter().enumerate() {
+        let mut image = decode_level(buffer, level)?;
+        flip.apply(&mut image);
+        imageops::replace(&mut atlas, &image, x.into(), y.into());
+    }
+    Ok(atlas)
+}
+
+/// Computes where each of the levels with the given `dimensions` goes in a mipmap atlas. Returns
+/// the width and height of the whole atlas, followed by the position of each level.
+pub fn atlas_layout(dimensions: &[(u32, u32)], layout: AtlasLayout) -> (u32, u32, Vec<(u32, u32)>) {
+    let mut positions = Vec::with_capacity(dimensions.len());
+    let (mut width, mut height) = (0, 0);
+    for &(level_width, level_height) in dimensions {
+        match layout {
+            AtlasLayout::Horizontal => {
+                positions.push((width, 0));
+                width += level_width;
+                height = height.max(level_height);
+            }
+            AtlasLayout::Vertical => {
+                positions.push((0, height));
+                width = width.max(level_width);
+                height += level_height;
+            }
+        }
+    }
+    (width, height, positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::EncodeSettings;
+    use crate::formats::DataFormat;
+    use crate::testing;
+    use image::{DynamicImage, Rgba};
+
+    /// The solid color of each image level of [`leveled_texture`], which `dxt1` stores exactly.
+    const LEVEL_COLORS: [[u8; 4]; 5] = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255, 255, 255, 255],
+        [0, 0, 0, 255],
+    ];
+
+    /// Returns a 16x16 `dxt1` texture where each image level is filled with its own color of
+    /// [`LEVEL_COLORS`].
+    fn leveled_texture() -> Vec<u8> {
+        let mut levels = LEVEL_COLORS.iter().enumerate().map(|(level, color)| {
+            let size = 16 >> level;
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(size, size, Rgba(*color)))
+        });
+        let image = levels.next().unwrap();
+        let custom: Vec<_> = levels.collect();
+        let settings = EncodeSettings {
+            mipmaps: true,
+            ..testing::settings(DataFormat::Dxt1)
+        };
+        mips::encode_custom_levels(&settings, image, &custom).unwrap()
+    }
+
+    #[test]
+    fn decode_each_level() {
+        let texture = leveled_texture();
+        for (level, color) in LEVEL_COLORS.iter().enumerate() {
+            let image = decode_level(&texture, level).unwrap();
+            let size = 16 >> level;
+            assert_eq!(image.dimensions(), (size, size), "level {level}");
+            assert!(
+                image.pixels().all(|pixel| pixel.0 == *color),
+                "level {level}"
+            );
+        }
+    }
+
+    #[test]
+    fn decode_missing_level() {
+        let texture = leveled_texture();
+        let error = decode_level(&texture, 5).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::MipLevel {
+                level: 5,
+                levels: 5
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Can't decode mipmap level 5, the texture only has 5 levels (0 to 4)."
+        );
+
+        let texture = testing::texture(DataFormat::Dxt1, 16, 16);
+        let error = decode_level(&texture, 1).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::MipLevel {
+                level: 1,
+                levels: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn atlas_placement() {
+        let dimensions: Vec<_> = (0..7).map(|level| (64 >> level, 64 >> level)).collect();
+        let offsets = [0, 64, 96, 112, 120, 124, 126];
+
+        let (width, height, positions) = atlas_layout(&dimensions, AtlasLayout::Horizontal);
+        assert_eq!((width, height), (127, 64));
+        assert_eq!(positions, offsets.map(|x| (x, 0)));
+
+        let (width, height, positions) = atlas_layout(&dimensions, AtlasLayout::Vertical);
+        assert_eq!((width, height), (64, 127));
+        assert_eq!(positions, offsets.map(|y| (0, y)));
+    }
+
+    #[test]
+    fn atlas_gaps_are_transparent() {
+        let atlas =
+            decode_atlas(&leveled_texture(), AtlasLayout::Horizontal, Flip::default()).unwrap();
+        assert_eq!(atlas.dimensions(), (31, 16));
+        for (level, (x, color)) in [0, 16, 24, 28, 30]
+            .into_iter()
+            .zip(LEVEL_COLORS)
+            .enumerate()
+        {
+            let size = 16 >> level;
+            assert_eq!(atlas.get_pixel(x, 0).0, color, "level {level}");
+            assert_eq!(
+                atlas.get_pixel(x + size - 1, size - 1).0,
+                color,
+                "level {level}"
+            );
+            if size < 16 {
+                assert_eq!(atlas.get_pixel(x, size).0, [0; 4], "level {level}");
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! The rules the data formats put on the dimensions of the images they encode.
+//!
+//! These are checked before handing the image to the encoder, so that the error can suggest the
+//! nearest sizes that would work.
+
+use crate::formats::DataFormat;
+use crate::transform::Crop;
+
+/// The largest width and height of a texture the GameCube can use.
+pub const MAX_SIZE: u32 = 1024;
+
+/// An image that can't be encoded at its size.
+#[derive(Debug)]
+pub enum DimensionError {
+    /// Mipmaps need square dimensions that are powers of two.
+    Mipmaps {
+        width: u32,
+        height: u32,
+        smaller: u32,
+        larger: u32,
+    },
+    /// The data format stores its pixels in blocks, so the dimensions have to be a multiple of
+    /// the given size.
+    Blocks {
+        data_format: DataFormat,
+        width: u32,
+        height: u32,
+        multiple: u32,
+    },
+    /// The crop rectangle doesn't fit inside the image.
+    Crop { crop: Crop, width: u32, height: u32 },
+    /// The alpha mask isn't the same size as the image.
+    AlphaMask { mask: (u32, u32), image: (u32, u32) },
+    /// The image is larger than [`MAX_SIZE`].
+    TooLarge { width: u32, height: u32 },
+}
+
+impl std::error::Error for DimensionError {}
+
+impl std::fmt::Display for DimensionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Mipmaps {
+                width,
+                height,
+                smaller,
+                larger,
+            } => {
+                write!(
+                    f,
+                    "The image is {width}x{height}, but mipmaps need square dimensions that are powers of two."
+                )?;
+                write_suggestions(f, (smaller, smaller), (larger, larger))
+            }
+            Self::Crop {
+                crop,
+                width,
+                height,
+            } => write!(
+                f,
+                "The crop rectangle {crop} (x,y,width,height) doesn't fit inside the {width}x{height} image."
+            ),
+            Self::AlphaMask { mask, image } => write!(
+                f,
+                "The alpha mask is {}x{}, but the image is {}x{}. Pass `--alpha-mask-resize` to resize the mask to fit.",
+                mask.0, mask.1, image.0, image.1
+            ),
+            Self::TooLarge { width, height } => write!(
+                f,
+                "The image is {width}x{height}, but GameCube textures can be at most {MAX_SIZE}x{MAX_SIZE}. Scale it down with `--resize`, `--scale` or `--max-dimension`, or pass `--allow-oversize` to encode it anyway."
+            ),
+            Self::Blocks {
+                data_format,
+                width,
+                height,
+                multiple,
+            } => {
+                write!(
+                    f,
+                    "The image is {width}x{height}, but {data_format} textures need dimensions that are multiples of {multiple}."
+                )?;
+                let round_down = |size: u32| (size / multiple * multiple).max(multiple);
+                write_suggestions(
+                    f,
+                    (round_down(width), round_down(height)),
+                    (
+                        width.next_multiple_of(multiple),
+                        height.next_multiple_of(multiple),
+                    ),
+                )
+            }
+        }
+    }
+}
+
+/// Writes the sizes closest to the invalid one, which are the same if the image is too small for
+/// any smaller size to work.
+fn write_suggestions(
+    f: &mut std::fmt::Formatter<'_>,
+    smaller: (u32, u32),
+    larger: (u32, u32),
+) -> std::fmt::Result {
+    if smaller == larger {
+        write!(f, " The nearest valid size is {}x{}.", larger.0, larger.1)
+    } else {
+        write!(
+            f,
+            " The nearest valid sizes are {}x{} and {}x{}.",
+            smaller.0, smaller.1, larger.0, larger.1
+        )
+    }
+}
+
+/// Returns what the dimensions of images encoded in `data_format` have to be a multiple of, if
+/// anything. `dxt1` images of any size are padded to whole blocks by the encoder.
+pub fn block_multiple(data_format: DataFormat) -> Option<u32> {
+    match data_format {
+        DataFormat::Dxt1 => None,
+        _ => {
+            let (width, height) = data_format.block_size();
+            Some(width.max(height))
+        }
+    }
+}
+
+/// Checks that an image of the given `width` and `height` can be encoded in `data_format`, with
+/// or without `mipmaps`.
+pub fn check(
+    data_format: DataFormat,
+    mipmaps: bool,
+    width: u32,
+    height: u32,
+) -> Result<(), DimensionError> {
+    if mipmaps && (width != height || !width.is_power_of_two()) {
+        let size = width.max(height);
+        let larger = size.next_power_of_two();
+        let smaller = if size.is_power_of_two() {
+            size
+        } else {
+            larger / 2
+        };
+        return Err(DimensionError::Mipmaps {
+            width,
+            height,
+            smaller,
+            larger,
+        });
+    }
+
+    if let Some(multiple) = block_multiple(data_format)
+        && (!width.is_multiple_of(multiple) || !height.is_multiple_of(multiple))
+    {
+        return Err(DimensionError::Blocks {
+            data_format,
+            width,
+            height,
+            multiple,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that an image of the given `width` and `height` isn't too large for the GameCube.
+pub fn check_limits(width: u32, height: u32) -> Result<(), DimensionError> {
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(DimensionError::TooLarge { width, height });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_multiple_by_format() {
+        let multiples = [
+            (DataFormat::Intensity4, Some(8)),
+            (DataFormat::Intensity8, Some(8)),
+            (DataFormat::IntensityA4, Some(8)),
+            (DataFormat::IntensityA8, Some(4)),
+            (DataFormat::Rgb565, Some(4)),
+            (DataFormat::Rgb5a3, Some(4)),
+            (DataFormat::Argb8888, Some(4)),
+            (DataFormat::Index4, Some(8)),
+            (DataFormat::Index8, Some(8)),
+            (DataFormat::Dxt1, None),
+        ];
+        for (data_format, multiple) in multiples {
+            assert_eq!(block_multiple(data_format), multiple, "{data_format}");
+            assert!(check(data_format, false, 16, 8).is_ok(), "{data_format}");
+
+            let result = check(data_format, false, 12, 20);
+            match multiple {
+                Some(8) => assert!(matches!(
+                    result,
+                    Err(DimensionError::Blocks { multiple: 8, .. })
+                )),
+                _ => assert!(result.is_ok(), "{data_format}"),
+            }
+        }
+        // `dxt1` pads any size to whole blocks
+        assert!(check(DataFormat::Dxt1, false, 13, 7).is_ok());
+    }
+
+    #[test]
+    fn mipmaps_need_square_powers_of_two() {
+        assert!(check(DataFormat::Rgb565, true, 64, 64).is_ok());
+        assert!(check(DataFormat::Dxt1, true, 1, 1).is_ok());
+        assert!(matches!(
+            check(DataFormat::Dxt1, true, 300, 200),
+            Err(DimensionError::Mipmaps {
+                smaller: 256,
+                larger: 512,
+                ..
+            })
+        ));
+        // mipmaps are checked before the blocks
+        assert!(matches!(
+            check(DataFormat::Index4, true, 12, 12),
+            Err(DimensionError::Mipmaps {
+                smaller: 8,
+                larger: 16,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn suggestions() {
+        let message = |data_format, mipmaps, width, height| {
+            check(data_format, mipmaps, width, height)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            message(DataFormat::Dxt1, true, 300, 200),
+            "The image is 300x200, but mipmaps need square dimensions that are powers of two. The nearest valid sizes are 256x256 and 512x512."
+        );
+        // a power of two that isn't square only has a single size of the same width
+        assert_eq!(
+            message(DataFormat::Rgb565, true, 64, 32),
+            "The image is 64x32, but mipmaps need square dimensions that are powers of two. The nearest valid size is 64x64."
+        );
+        assert_eq!(
+            message(DataFormat::Index4, false, 12, 20),
+            "The image is 12x20, but 4-bit Indexed textures need dimensions that are multiples of 8. The nearest valid sizes are 8x16 and 16x24."
+        );
+        // nothing smaller than a single block works
+        assert_eq!(
+            message(DataFormat::Rgb5a3, false, 3, 2),
+            "The image is 3x2, but RGB5A3 textures need dimensions that are multiples of 4. The nearest valid size is 4x4."
+        );
+    }
+
+    #[test]
+    fn size_limits() {
+        assert!(check_limits(MAX_SIZE, MAX_SIZE).is_ok());
+        assert!(matches!(
+            check_limits(MAX_SIZE + 1, 8),
+            Err(DimensionError::TooLarge { .. })
+        ));
+        assert!(check_limits(8, MAX_SIZE + 1).is_err());
+    }
+}
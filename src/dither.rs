@@ -0,0 +1,607 @@
+//! Dithering the pixels of an image before encoding it, for `--dither`.
+//!
+//! The encoder truncates every channel to the amount of bits the data format stores, which turns
+//! smooth gradients into visible bands. Dithering quantizes the pixels itself instead, and hands
+//! the encoder colors it stores exactly. Error diffusion spreads the error of each pixel over its
+//! neighbors, while ordered dithering nudges each pixel by a threshold that only depends on its
+//! position, which tiles seamlessly and gives the same result every time.
+//!
+//! For the indexed formats, the palette is built first, with the quantizer of `--quantizer`, and
+//! each pixel is then dithered to one of its colors, which are written to the texture as they are.
+
+use crate::encode;
+use crate::formats::{DataFormat, PixelFormat};
+use crate::palette::{self, Indexed, TransparentIndex};
+use crate::quantize::PaletteQuantizer;
+use clap::ValueEnum;
+use image::{GrayImage, Luma, Rgba, Rgba32FImage, RgbaImage};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// How the pixels are dithered.
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum Dither {
+    /// Leave the quantization to the encoder, which truncates each channel.
+    #[default]
+    None,
+    /// Floyd-Steinberg error diffusion, which passes the error of each pixel on to the pixels to
+    /// the right of it and below it.
+    FloydSteinberg,
+    /// Ordered dithering with a 4x4 Bayer matrix.
+    Ordered4,
+    /// Ordered dithering with an 8x8 Bayer matrix, which has more levels than `ordered4` but a
+    /// larger pattern.
+    Ordered8,
+}
+
+impl Dither {
+    /// Checks if dithering changes textures of the given data format.
+    pub fn supports(data_format: DataFormat) -> bool {
+        Quantizer::new(data_format).is_some() || data_format.is_indexed()
+    }
+
+    /// Quantizes the `wanted` colors, whose channels go from 0 to 255, to the colors the given
+    /// data format stores, returning the pixels to hand to the encoder. For the intensity formats,
+    /// the intensity is expected in all three color channels.
+    ///
+    /// With [`Dither::None`], each pixel is only rounded to the nearest stored color. Images of the
+    /// formats that aren't [supported](Self::supports) are only rounded to 8 bits.
+    pub fn apply(self, wanted: &Rgba32FImage, data_format: DataFormat) -> RgbaImage {
+        let Some(quantizer) = Quantizer::new(data_format) else {
+            return RgbaImage::from_fn(wanted.width(), wanted.height(), |x, y| {
+                Rgba(wanted.get_pixel(x, y).0.map(|value| value.round() as u8))
+            });
+        };
+        match self {
+            Self::None => ordered(wanted, quantizer, 0),
+            Self::FloydSteinberg => floyd_steinberg(wanted, quantizer),
+            Self::Ordered4 => ordered(wanted, quantizer, 2),
+            Self::Ordered8 => ordered(wanted, quantizer, 3),
+        }
+    }
+
+    /// Builds a palette of at most `colors` colors for the `wanted` colors, whose channels go from
+    /// 0 to 255, and dithers every pixel to one of them. The palette is built by the `quantizer`,
+    /// refining it up to the given amount of iterations for `kmeans`. Its colors are those a
+    /// palette of the given `pixel_format` stores.
+    ///
+    /// With [`Dither::None`], each pixel only gets the nearest color of the palette. With a
+    /// `transparent` index, that entry is fully transparent and only used by the pixels under its
+    /// cutoff, and the other colors are built from the rest of the pixels. With `stable`, the
+    /// colors are put in the order of [`sort_palette`].
+    pub fn apply_palette(
+        self,
+        wanted: &Rgba32FImage,
+        colors: u32,
+        pixel_format: PixelFormat,
+        quantizer: (PaletteQuantizer, u32),
+        transparent: Option<TransparentIndex>,
+        stable: bool,
+    ) -> Result<Indexed, imagequant::Error> {
+        let palette = Palette::build(wanted, colors, pixel_format, quantizer, transparent, stable)?;
+        Ok(self.dither_to(palette, wanted))
+    }
+
+    /// Dithers the `wanted` colors, whose channels go from 0 to 255, to the given fixed `colors`,
+    /// as a palette of the given `pixel_format` stores them. The palette keeps the colors in the
+    /// order they're given.
+    ///
+    /// With [`Dither::None`], each pixel only gets the nearest color of the palette.
+    pub fn apply_fixed_palette(
+        self,
+        wanted: &Rgba32FImage,
+        colors: &[Rgba<u8>],
+        pixel_format: PixelFormat,
+    ) -> Indexed {
+        let decoded = colors
+            .iter()
+            .map(|&color| palette::stored_color(color, pixel_format).0.map(f32::from))
+            .collect();
+        let palette = Palette::new(colors.to_vec(), decoded, pixel_format);
+        self.dither_to(palette, wanted)
+    }
+
+    /// Dithers the `wanted` colors to the colors of the `palette`.
+    fn dither_to(self, palette: Palette, wanted: &Rgba32FImage) -> Indexed {
+        let indices = match self {
+            Self::None => palette.ordered(wanted, 0),
+            Self::FloydSteinberg => palette.floyd_steinberg(wanted),
+            Self::Ordered4 => palette.ordered(wanted, 2),
+            Self::Ordered8 => palette.ordered(wanted, 3),
+        };
+        Indexed {
+            colors: palette.pixels,
+            indices,
+        }
+    }
+}
+
+/// The colors of a palette, both as handed to the encoder and as the texture decodes them.
+struct Palette {
+    pixels: Vec<Rgba<u8>>,
+    decoded: Vec<[f32; 4]>,
+    /// Whether the pixel format of the palette stores the alpha channel.
+    alpha: bool,
+    /// How far apart the colors of the palette are on average, which is how far ordered
+    /// dithering nudges each pixel.
+    spread: f32,
+    /// The nearest palette color of each color looked up so far, as searching the whole palette
+    /// for every pixel of a large image is slow.
+    nearest: RefCell<HashMap<[u8; 4], usize>>,
+    /// The entry that's kept for the pixels under its cutoff, and never used for the others.
+    transparent: Option<TransparentIndex>,
+}
+
+impl Palette {
+    /// Builds a palette of at most `colors` colors for the `wanted` colors with the given
+    /// `quantizer`, and moves each color to the nearest one the `pixel_format` stores.
+    fn build(
+        wanted: &Rgba32FImage,
+        colors: u32,
+        pixel_format: PixelFormat,
+        (quantizer, iterations): (PaletteQuantizer, u32),
+        transparent: Option<TransparentIndex>,
+        stable: bool,
+    ) -> Result<Self, imagequant::Error> {
+        let mut pixels = palette_pixels(wanted, pixel_format);
+        let stored = |colors| {
+            let (pixels, decoded) = stored_palette(colors, pixel_format);
+            if stable {
+                sort_palette(pixels, decoded)
+            } else {
+                (pixels, decoded)
+            }
+        };
+        let Some(transparent) = transparent else {
+            let dimensions = (wanted.width() as usize, wanted.height() as usize);
+            let colors = quantizer.palette(&pixels, dimensions, colors, iterations)?;
+            let (pixels, decoded) = stored(colors);
+            return Ok(Self::new(pixels, decoded, pixel_format));
+        };
+
+        // the other colors are only built from the pixels that don't get the transparent one
+        pixels.retain(|pixel| !transparent.covers(f32::from(pixel[3])));
+        let colors = if pixels.is_empty() || colors == 1 {
+            Vec::new()
+        } else {
+            quantizer.palette(&pixels, (pixels.len(), 1), colors - 1, iterations)?
+        };
+        let (mut pixels, mut decoded) = stored(colors);
+        // the entries before the transparent one are padded with copies of the last color, which
+        // the pixels never get, as the first of the same colors is the nearest one
+        let index = usize::from(transparent.index);
+        if pixels.len() < index {
+            let pixel = pixels.last().copied().unwrap_or(Rgba([0; 4]));
+            let color = decoded.last().copied().unwrap_or([0.0; 4]);
+            pixels.resize(index, pixel);
+            decoded.resize(index, color);
+        }
+        pixels.insert(index, Rgba([0; 4]));
+        decoded.insert(index, [0.0; 4]);
+        Ok(Self {
+            transparent: Some(transparent),
+            ..Self::new(pixels, decoded, pixel_format)
+        })
+    }
+
+    /// Creates a palette of the given encoder `pixels` and the colors they're `decoded` to, for a
+    /// palette of the given `pixel_format`.
+    fn new(pixels: Vec<Rgba<u8>>, decoded: Vec<[f32; 4]>, pixel_format: PixelFormat) -> Self {
+        let nearest_distances: Vec<f32> = decoded
+            .iter()
+            .map(|color| {
+                decoded
+                    .iter()
+                    .filter(|other| *other != color)
+                    .map(|other| distance(*color, *other).sqrt())
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .filter(|distance| distance.is_finite())
+            .collect();
+        let spread = if nearest_distances.is_empty() {
+            0.0
+        } else {
+            nearest_distances.iter().sum::<f32>() / nearest_distances.len() as f32
+        };
+        Self {
+            pixels,
+            decoded,
+            alpha: pixel_format != PixelFormat::Rgb565,
+            spread,
+            nearest: RefCell::default(),
+            transparent: None,
+        }
+    }
+
+    /// Returns the index of the color of the palette closest to `color`, once it's rounded.
+    fn nearest(&self, color: [f32; 4]) -> usize {
+        let mut key = color.map(|value| value.round().clamp(0.0, 255.0) as u8);
+        if !self.alpha {
+            key[3] = 0xFF;
+        }
+        *self.nearest.borrow_mut().entry(key).or_insert_with(|| {
+            let color = key.map(f32::from);
+            let transparent = self
+                .transparent
+                .map(|transparent| usize::from(transparent.index));
+            (0..self.decoded.len())
+                .filter(|&index| Some(index) != transparent)
+                .min_by(|&a, &b| {
+                    distance(color, self.decoded[a]).total_cmp(&distance(color, self.decoded[b]))
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    /// Returns the transparent index, if the palette has one and the `wanted` color of a pixel is
+    /// under its cutoff.
+    fn transparent_index(&self, wanted: &Rgba<f32>) -> Option<u8> {
+        self.transparent
+            .filter(|transparent| transparent.covers(wanted[3]))
+            .map(|transparent| transparent.index)
+    }
+
+    /// Dithers the `wanted` colors to the palette with Floyd-Steinberg error diffusion, returning
+    /// the index of the color of each pixel.
+    fn floyd_steinberg(&self, wanted: &Rgba32FImage) -> GrayImage {
+        let (width, height) = (wanted.width() as usize, wanted.height() as usize);
+        let mut colors: Vec<[f32; 4]> = wanted.pixels().map(|pixel| pixel.0).collect();
+        let mut indices = GrayImage::new(wanted.width(), wanted.height());
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(index) = self.transparent_index(wanted.get_pixel(x as u32, y as u32)) {
+                    // the color of transparent pixels doesn't matter, so there's no error to pass on
+                    indices.put_pixel(x as u32, y as u32, Luma([index]));
+                    continue;
+                }
+                let color = colors[y * width + x].map(|value| value.clamp(0.0, 255.0));
+                let index = self.nearest(color);
+                indices.put_pixel(x as u32, y as u32, Luma([index as u8]));
+                let decoded = self.decoded[index];
+                let mut error: [f32; 4] =
+                    std::array::from_fn(|channel| color[channel] - decoded[channel]);
+                if !self.alpha {
+                    error[3] = 0.0;
+                }
+                diffuse(&mut colors, width, height, x, y, error);
+            }
+        }
+        indices
+    }
+
+    /// Dithers the `wanted` colors to the palette with a Bayer matrix of `2^order` by `2^order`
+    /// pixels, returning the index of the color of each pixel. Only the color channels are
+    /// nudged, so that opaque pixels stay opaque.
+    fn ordered(&self, wanted: &Rgba32FImage, order: u32) -> GrayImage {
+        let size = 1 << order;
+        let levels = (size * size) as f32;
+        GrayImage::from_fn(wanted.width(), wanted.height(), |x, y| {
+            if let Some(index) = self.transparent_index(wanted.get_pixel(x, y)) {
+                return Luma([index]);
+            }
+            let threshold = bayer(x % size, y % size, order) as f32;
+            let offset = ((threshold + 0.5) / levels - 0.5) * self.spread;
+            let [r, g, b, a] = wanted.get_pixel(x, y).0;
+            Luma([self.nearest([r + offset, g + offset, b + offset, a]) as u8])
+        })
+    }
+}
+
+/// Rounds the `wanted` colors, whose channels go from 0 to 255, to the pixels a palette of the
+/// given `pixel_format` is built from. Palettes without alpha are built from opaque pixels.
+pub fn palette_pixels(wanted: &Rgba32FImage, pixel_format: PixelFormat) -> Vec<Rgba<u8>> {
+    let alpha = pixel_format != PixelFormat::Rgb565;
+    wanted
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0.map(|value| value.round().clamp(0.0, 255.0) as u8);
+            Rgba([r, g, b, if alpha { a } else { 0xFF }])
+        })
+        .collect()
+}
+
+/// Moves each of the `colors` a quantizer built to the nearest one a palette of the given
+/// `pixel_format` stores, leaving out the ones that end up the same as an earlier one. Returns
+/// the pixels to hand to the encoder for them, and the colors they're decoded to.
+pub fn stored_palette(
+    colors: Vec<Rgba<u8>>,
+    pixel_format: PixelFormat,
+) -> (Vec<Rgba<u8>>, Vec<[f32; 4]>) {
+    let rounding = match pixel_format {
+        PixelFormat::Rgb565 => Quantizer::Rgb565,
+        PixelFormat::Rgb5a3 => Quantizer::Rgb5a3,
+        PixelFormat::IntensityA8 => Quantizer::Intensity {
+            bits: 8,
+            alpha_bits: Some(8),
+        },
+    };
+    let (mut pixels, mut decoded_colors) = (Vec::new(), Vec::new());
+    for Rgba([r, g, b, a]) in colors {
+        let channels = match rounding {
+            // the intensity is expected in all three color channels
+            Quantizer::Intensity { .. } => {
+                let intensity = encode::encoded_intensity([r, g, b]);
+                [intensity, intensity, intensity, a]
+            }
+            _ => [r, g, b, a],
+        };
+        let (pixel, decoded) = rounding.quantize(channels.map(f32::from), 0.0);
+        if !decoded_colors.contains(&decoded) {
+            pixels.push(pixel);
+            decoded_colors.push(decoded);
+        }
+    }
+    (pixels, decoded_colors)
+}
+
+/// Sorts the `pixels` of a palette along with the colors they're `decoded` to, for
+/// `--stable-palette`. The colors are sorted by their luminance, as the encoder works out the
+/// intensity, then by their red, green, blue and alpha channels, which puts them in the same order
+/// no matter what order the quantizer built them in.
+pub fn sort_palette(
+    pixels: Vec<Rgba<u8>>,
+    decoded: Vec<[f32; 4]>,
+) -> (Vec<Rgba<u8>>, Vec<[f32; 4]>) {
+    let mut colors: Vec<_> = pixels.into_iter().zip(decoded).collect();
+    colors.sort_by_key(|(Rgba([r, g, b, a]), _)| {
+        (encode::encoded_intensity([*r, *g, *b]), *r, *g, *b, *a)
+    });
+    colors.into_iter().unzip()
+}
+
+/// Returns the squared distance between two colors.
+fn distance(a: [f32; 4], b: [f32; 4]) -> f32 {
+    (0..4)
+        .map(|channel| (a[channel] - b[channel]).powi(2))
+        .sum()
+}
+
+/// The quantization of a data format, as done by the encoder and undone by the decoder.
+#[derive(Copy, Clone)]
+enum Quantizer {
+    Rgb565,
+    Rgb5a3,
+    /// The intensity formats, with the amount of bits of the intensity and of the alpha channel,
+    /// if they store it.
+    Intensity {
+        bits: u32,
+        alpha_bits: Option<u32>,
+    },
+}
+
+impl Quantizer {
+    fn new(data_format: DataFormat) -> Option<Self> {
+        let intensity = |bits, alpha_bits| Some(Self::Intensity { bits, alpha_bits });
+        match data_format {
+            DataFormat::Rgb565 => Some(Self::Rgb565),
+            DataFormat::Rgb5a3 => Some(Self::Rgb5a3),
+            DataFormat::Intensity4 => intensity(4, None),
+            DataFormat::Intensity8 => intensity(8, None),
+            DataFormat::IntensityA4 => intensity(4, Some(4)),
+            DataFormat::IntensityA8 => intensity(8, Some(8)),
+            DataFormat::Argb8888 | DataFormat::Index4 | DataFormat::Index8 | DataFormat::Dxt1 => {
+                None
+            }
+        }
+    }
+
+    /// Picks the stored color closest to the `wanted` one, after moving each channel by `offset`
+    /// steps of its quantization. Returns the pixel to hand to the encoder for it, along with the
+    /// color the texture decodes to.
+    fn quantize(self, wanted: [f32; 4], offset: f32) -> (Rgba<u8>, [f32; 4]) {
+        let [r, g, b, a] = wanted;
+        match self {
+            Self::Rgb565 => {
+                let (r, g, b) = (
+                    shifted(r, 5, offset),
+                    shifted(g, 6, offset),
+                    shifted(b, 5, offset),
+                );
+                // the alpha channel isn't stored, so it has no error to pass on
+                (Rgba([r.0, g.0, b.0, 0xFF]), [r.1, g.1, b.1, a])
+            }
+            Self::Rgb5a3 => {
+                // pixels are either opaque with 5-bit colors, or have a 3-bit alpha channel with
+                // 4-bit colors, where the largest alpha the encoder keeps is 6
+                let alpha = shifted(a, 3, offset);
+                let bits = if alpha.0 >> 5 == 7 { 5 } else { 4 };
+                let (r, g, b) = (
+                    shifted(r, bits, offset),
+                    shifted(g, bits, offset),
+                    shifted(b, bits, offset),
+                );
+                let alpha = if bits == 5 { (0xFF, 255.0) } else { alpha };
+                (Rgba([r.0, g.0, b.0, alpha.0]), [r.1, g.1, b.1, alpha.1])
+            }
+            Self::Intensity { bits, alpha_bits } => {
+                let (intensity, decoded) = scaled(r, bits, offset);
+                let alpha = match alpha_bits {
+                    Some(alpha_bits) => scaled(a, alpha_bits, offset),
+                    None => (a.round() as u8, a),
+                };
+                let pixel = encode::intensity_pixel(intensity, alpha.0);
+                (pixel, [decoded, decoded, decoded, alpha.1])
+            }
+        }
+    }
+}
+
+/// Rounds `value`, moved by `offset` steps, to the nearest value a channel of the given amount of
+/// `bits` stores, for the channels the encoder truncates by shifting them. Returns the 8-bit value
+/// the encoder turns into it, along with the value the decoder expands it to.
+fn shifted(value: f32, bits: u32, offset: f32) -> (u8, f32) {
+    let max = ((1 << bits) - 1) as f32;
+    let code = (value * max / 255.0 + offset).round().clamp(0.0, max);
+    let decoded = (code * 255.0 / max) as u8;
+    ((code as u8) << (8 - bits), f32::from(decoded))
+}
+
+/// The same as [`shifted`], but for the channels the encoder scales down instead. The 8-bit value
+/// is the middle of the range the encoder truncates to the stored value, so that it doesn't end up
+/// one lower after the encoder works out the intensity in floats.
+fn scaled(value: f32, bits: u32, offset: f32) -> (u8, f32) {
+    let max = ((1 << bits) - 1) as f32;
+    let code = (value * max / 255.0 + offset).round().clamp(0.0, max);
+    let decoded = (code * 255.0 / max) as u8;
+    let middle = ((code + 0.5) * 255.0 / max).min(255.0) as u8;
+    (middle, f32::from(decoded))
+}
+
+/// Dithers the `wanted` colors with Floyd-Steinberg error diffusion, going through the pixels row
+/// by row.
+fn floyd_steinberg(wanted: &Rgba32FImage, quantizer: Quantizer) -> RgbaImage {
+    let (width, height) = (wanted.width() as usize, wanted.height() as usize);
+    let mut colors: Vec<[f32; 4]> = wanted.pixels().map(|pixel| pixel.0).collect();
+    let mut image = RgbaImage::new(wanted.width(), wanted.height());
+    for y in 0..height {
+        for x in 0..width {
+            let color = colors[y * width + x].map(|value| value.clamp(0.0, 255.0));
+            let (pixel, decoded) = quantizer.quantize(color, 0.0);
+            image.put_pixel(x as u32, y as u32, pixel);
+            let error = std::array::from_fn(|channel| color[channel] - decoded[channel]);
+            diffuse(&mut colors, width, height, x, y, error);
+        }
+    }
+    image
+}
+
+/// Passes the `error` of the pixel at `x` and `y` on to the pixels to the right of it and below
+/// it, with the weights of Floyd-Steinberg.
+fn diffuse(
+    colors: &mut [[f32; 4]],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    error: [f32; 4],
+) {
+    let mut spread = |dx: isize, dy: usize, weight: f32| {
+        let nx = x as isize + dx;
+        if nx < 0 || nx as usize >= width || y + dy >= height {
+            return;
+        }
+        let target = &mut colors[(y + dy) * width + nx as usize];
+        for channel in 0..4 {
+            target[channel] += error[channel] * weight;
+        }
+    };
+    spread(1, 0, 7.0 / 16.0);
+    spread(-1, 1, 3.0 / 16.0);
+    spread(0, 1, 5.0 / 16.0);
+    spread(1, 1, 1.0 / 16.0);
+}
+
+/// Dithers the `wanted` colors with a Bayer matrix of `2^order` by `2^order` pixels, which is
+/// repeated over the whole image. An `order` of 0 leaves only the rounding.
+fn ordered(wanted: &Rgba32FImage, quantizer: Quantizer, order: u32) -> RgbaImage {
+    let size = 1 << order;
+    let levels = (size * size) as f32;
+    RgbaImage::from_fn(wanted.width(), wanted.height(), |x, y| {
+        let threshold = bayer(x % size, y % size, order) as f32;
+        let offset = (threshold + 0.5) / levels - 0.5;
+        quantizer.quantize(wanted.get_pixel(x, y).0, offset).0
+    })
+}
+
+/// Returns the value at `x` and `y` of the Bayer matrix of `2^order` by `2^order` pixels, which
+/// holds every value from 0 to `4^order - 1` once. Each bit of the position picks one of the
+/// values of the 2x2 matrix `[0 2; 3 1]`, with the lowest bits picking the most significant one.
+fn bayer(x: u32, y: u32, order: u32) -> u32 {
+    (0..order).fold(0, |value, bit| {
+        let (x, y) = ((x >> bit) & 1, (y >> bit) & 1);
+        value * 4 + ((x ^ y) << 1 | y)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::encode::EncodeSettings;
+    use crate::testing;
+    use image::DynamicImage;
+
+    /// Returns a 256x16 image of the given colors, where each channel goes from `from` on the left
+    /// to `to` on the right.
+    fn gradient(from: [f32; 4], to: [f32; 4]) -> Rgba32FImage {
+        Rgba32FImage::from_fn(256, 16, |x, _| {
+            let t = x as f32 / 255.0;
+            Rgba([0, 1, 2, 3].map(|i| from[i] + (to[i] - from[i]) * t))
+        })
+    }
+
+    /// Returns how far the colors of the `dithered` image are from the `wanted` ones when looked at
+    /// from a distance, as the summed squared difference of the average of each block of 8x8
+    /// pixels. Banding leaves the same error in whole blocks, while dithering averages it out.
+    fn banding_energy(wanted: &Rgba32FImage, dithered: &RgbaImage) -> f64 {
+        let mut energy = 0.0;
+        for top in (0..wanted.height()).step_by(8) {
+            for left in (0..wanted.width()).step_by(8) {
+                let (mut wanted_sum, mut dithered_sum) = ([0.0f64; 4], [0.0f64; 4]);
+                for y in top..top + 8 {
+                    for x in left..left + 8 {
+                        for i in 0..4 {
+                            wanted_sum[i] += f64::from(wanted.get_pixel(x, y)[i]);
+                            dithered_sum[i] += f64::from(dithered.get_pixel(x, y)[i]);
+                        }
+                    }
+                }
+                for i in 0..4 {
+                    let difference = (wanted_sum[i] - dithered_sum[i]) / 64.0;
+                    energy += difference * difference;
+                }
+            }
+        }
+        energy
+    }
+
+    /// Encodes the `wanted` colors in `data_format` with the given `dither`, and returns the image
+    /// the texture decodes to.
+    fn decoded(wanted: &Rgba32FImage, data_format: DataFormat, dither: Dither) -> RgbaImage {
+        let mut image = wanted.clone();
+        image
+            .pixels_mut()
+            .for_each(|pixel| pixel.0 = pixel.0.map(|value| value / 255.0));
+        let settings = EncodeSettings {
+            dither,
+            ..testing::settings(data_format)
+        };
+        let texture = settings
+            .encode_image(DynamicImage::ImageRgba32F(image))
+            .unwrap();
+        decode::decode_buffer(texture).unwrap()
+    }
+
+    fn check_banding(wanted: &Rgba32FImage, data_format: DataFormat) {
+        let banded = banding_energy(wanted, &decoded(wanted, data_format, Dither::None));
+        let dithered = banding_energy(
+            wanted,
+            &decoded(wanted, data_format, Dither::FloydSteinberg),
+        );
+        assert!(
+            dithered * 10.0 < banded,
+            "{data_format}: {dithered} dithered, {banded} banded"
+        );
+    }
+
+    #[test]
+    fn dithering_reduces_banding() {
+        // a dark gradient, where the steps of 5 and 6 bits are far apart
+        let wanted = gradient([0.0, 0.0, 0.0, 255.0], [40.0, 60.0, 80.0, 255.0]);
+        check_banding(&wanted, DataFormat::Rgb565);
+        check_banding(&wanted, DataFormat::Rgb5a3);
+        // the 3-bit alpha of the translucent colors of rgb5a3
+        let wanted = gradient([200.0, 100.0, 50.0, 0.0], [200.0, 100.0, 50.0, 200.0]);
+        check_banding(&wanted, DataFormat::Rgb5a3);
+    }
+
+    #[test]
+    fn no_dithering_is_stable() {
+        // without dithering, every pixel of a column gets the same color
+        let wanted = gradient([0.0, 0.0, 0.0, 255.0], [40.0, 60.0, 80.0, 255.0]);
+        let banded = Dither::None.apply(&wanted, DataFormat::Rgb565);
+        for x in 0..256 {
+            assert!((1..16).all(|y| banded.get_pixel(x, y) == banded.get_pixel(x, 0)));
+        }
+    }
+}
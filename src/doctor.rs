@@ -0,0 +1,302 @@
+//! Diagnosis of GVR texture files that load fine, but look suspicious.
+//!
+//! Every check looks at a single aspect of the file and reports at most one finding, so that they
+//! can be run on crafted headers on their own.
+
+use crate::formats::HeaderId;
+use crate::header::{GvrHeader, HEADER_SIZE, HeaderError};
+
+/// How serious a finding is.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Unusual, but most likely intended.
+    Info,
+    /// Probably a mistake, although the texture might still load.
+    Warning,
+    /// The texture is broken.
+    Error,
+}
+
+/// Something noteworthy about a GVR texture file.
+pub struct Finding {
+    pub severity: Severity,
+    /// What was found, specific to the file.
+    pub message: String,
+    /// Why the finding matters.
+    pub explanation: &'static str,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: String, explanation: &'static str) -> Self {
+        Self {
+            severity,
+            message,
+            explanation,
+        }
+    }
+}
+
+/// Runs all the checks on the GVR texture file in `buffer`, returning the findings sorted from the
+/// most to the least serious. Fails only if the header itself can't be parsed.
+pub fn diagnose(buffer: &[u8]) -> Result<Vec<Finding>, HeaderError> {
+    let header = GvrHeader::parse(buffer)?;
+    let mut findings: Vec<_> = [
+        check_file_size(&header, buffer.len()),
+        check_data_size(&header),
+        check_mipmaps(&header),
+        check_dimensions(&header),
+        check_global_index(&header),
+        check_magic(&header),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    Ok(findings)
+}
+
+/// Checks if any of the `findings` is an error, which makes `doctor` fail. Warnings and info
+/// alone don't.
+pub fn has_errors(findings: &[Finding]) -> bool {
+    findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error)
+}
+
+/// Checks that the file is exactly as long as the header says.
+fn check_file_size(header: &GvrHeader, file_size: usize) -> Option<Finding> {
+    let expected = HEADER_SIZE + header.data_size as usize;
+    if file_size < expected {
+        Some(Finding::new(
+            Severity::Error,
+            format!(
+                "The file is truncated, it should be {expected} bytes long but is only {file_size} bytes."
+            ),
+            "The end of the texture data is missing, so the texture can't be fully decoded.",
+        ))
+    } else if file_size > expected {
+        Some(Finding::new(
+            Severity::Warning,
+            format!(
+                "There are {} trailing bytes after the texture data.",
+                file_size - expected
+            ),
+            "gvrtex refuses to decode files with trailing bytes, although games usually ignore them. They may be padding, or part of another file that was extracted along with this one.",
+        ))
+    } else {
+        None
+    }
+}
+
+/// Checks that the texture data is as large as the dimensions and the data format need, without
+/// counting the mipmaps.
+fn check_data_size(header: &GvrHeader) -> Option<Finding> {
+    let needed = header.base_data_size();
+    let size = u64::from(header.data_size);
+    if size < needed {
+        Some(Finding::new(
+            Severity::Error,
+            format!(
+                "The texture data is {size} bytes long, but a {}x{} {} texture needs {needed} bytes.",
+                header.width, header.height, header.data_format
+            ),
+            "Either the dimensions or the data format in the header are wrong, or the data is incomplete.",
+        ))
+    } else if size > header.expected_data_size() {
+        Some(Finding::new(
+            Severity::Info,
+            format!(
+                "The texture data is {} bytes larger than needed.",
+                size - header.expected_data_size()
+            ),
+            "The extra data is ignored. Some tools pad the texture data, or generate more mipmap levels than gvrtex does.",
+        ))
+    } else {
+        None
+    }
+}
+
+/// Checks that there's enough data for all the mipmaps, if the mipmap flag is set.
+fn check_mipmaps(header: &GvrHeader) -> Option<Finding> {
+    let needed = header.expected_data_size();
+    let size = u64::from(header.data_size);
+    if !header.mipmaps || size < header.base_data_size() || size >= needed {
+        return None;
+    }
+    if header.ends_on_level() {
+        return Some(Finding::new(
+            Severity::Info,
+            format!(
+                "The mipmap chain stops after {} of the {} levels.",
+                header.level_count(),
+                header.full_level_count()
+            ),
+            "The smallest mipmap levels were left out, which some games do on purpose to save space. Only the stored levels can be decoded.",
+        ));
+    }
+    Some(Finding::new(
+        Severity::Error,
+        format!(
+            "The mipmap flag is set, but the texture data is {} bytes too short for the mipmap levels.",
+            needed - size
+        ),
+        "The smaller mipmap levels are missing, so the texture may look corrupted when it's drawn at a distance.",
+    ))
+}
+
+/// Checks that the dimensions are powers of two.
+fn check_dimensions(header: &GvrHeader) -> Option<Finding> {
+    if header.width.is_power_of_two() && header.height.is_power_of_two() {
+        return None;
+    }
+    Some(Finding::new(
+        Severity::Warning,
+        format!(
+            "The dimensions {}x{} aren't powers of two.",
+            header.width, header.height
+        ),
+        "The GameCube can only repeat and mipmap textures whose dimensions are powers of two.",
+    ))
+}
+
+/// Checks that the texture has a global index.
+fn check_global_index(header: &GvrHeader) -> Option<Finding> {
+    if header.global_index != 0 {
+        return None;
+    }
+    Some(Finding::new(
+        Severity::Info,
+        "The global index is 0.".to_string(),
+        "Games usually give each texture a unique global index to look it up by. Use `--global-index` when encoding to set one.",
+    ))
+}
+
+/// Checks that the header magic is the one GameCube textures usually use.
+fn check_magic(header: &GvrHeader) -> Option<Finding> {
+    if header.header_id != HeaderId::Gbix {
+        return None;
+    }
+    Some(Finding::new(
+        Severity::Info,
+        "The header magic is \"GBIX\".".to_string(),
+        "\"GBIX\" comes from the Dreamcast and is used by only some GameCube games, while most of them expect \"GCIX\".",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::DataFormat;
+    use crate::testing;
+
+    /// Returns the header of a 16x16 `rgb565` texture with a global index, whose texture data is
+    /// exactly as large as it needs to be.
+    fn header() -> GvrHeader {
+        let mut header = GvrHeader {
+            global_index: 1,
+            width: 16,
+            height: 16,
+            ..testing::header(DataFormat::Rgb565)
+        };
+        header.data_size = header.expected_data_size() as u32;
+        header
+    }
+
+    /// Returns the severity of the `finding`, if there is one.
+    fn severity(finding: Option<Finding>) -> Option<Severity> {
+        finding.map(|finding| finding.severity)
+    }
+
+    /// Returns a GVR texture file with the given `header`, followed by `data_size` bytes.
+    fn file(header: &GvrHeader, data_size: usize) -> Vec<u8> {
+        let mut buffer = header.to_bytes().to_vec();
+        buffer.resize(HEADER_SIZE + data_size, 0);
+        buffer
+    }
+
+    #[test]
+    fn file_size() {
+        let header = header();
+        let size = HEADER_SIZE + 512;
+        assert!(severity(check_file_size(&header, size)).is_none());
+        assert!(severity(check_file_size(&header, size - 1)) == Some(Severity::Error));
+        assert!(severity(check_file_size(&header, size + 1)) == Some(Severity::Warning));
+    }
+
+    #[test]
+    fn data_size() {
+        let mut header = header();
+        assert!(severity(check_data_size(&header)).is_none());
+        header.data_size = 511;
+        assert!(severity(check_data_size(&header)) == Some(Severity::Error));
+        header.data_size = 513;
+        assert!(severity(check_data_size(&header)) == Some(Severity::Info));
+    }
+
+    #[test]
+    fn mipmaps() {
+        let mut header = GvrHeader {
+            mipmaps: true,
+            ..header()
+        };
+        header.data_size = header.expected_data_size() as u32;
+        assert!(severity(check_mipmaps(&header)).is_none());
+        // the chain stops right after the 8x8 level
+        header.data_size = header.level_offset(2) as u32;
+        assert!(severity(check_mipmaps(&header)) == Some(Severity::Info));
+        // the data ends in the middle of the 8x8 level
+        header.data_size = header.level_offset(2) as u32 - 1;
+        assert!(severity(check_mipmaps(&header)) == Some(Severity::Error));
+        // without the mipmap flag, only the full-size level is needed
+        header.mipmaps = false;
+        assert!(severity(check_mipmaps(&header)).is_none());
+    }
+
+    #[test]
+    fn dimensions() {
+        let mut header = header();
+        assert!(severity(check_dimensions(&header)).is_none());
+        header.width = 24;
+        assert!(severity(check_dimensions(&header)) == Some(Severity::Warning));
+    }
+
+    #[test]
+    fn global_index() {
+        let mut header = header();
+        assert!(severity(check_global_index(&header)).is_none());
+        header.global_index = 0;
+        assert!(severity(check_global_index(&header)) == Some(Severity::Info));
+    }
+
+    #[test]
+    fn magic() {
+        let mut header = header();
+        assert!(severity(check_magic(&header)).is_none());
+        header.header_id = HeaderId::Gbix;
+        assert!(severity(check_magic(&header)) == Some(Severity::Info));
+    }
+
+    #[test]
+    fn only_errors_fail() {
+        let header = header();
+        assert!(diagnose(&file(&header, 512)).unwrap().is_empty());
+
+        // trailing bytes, a GBIX header and no global index aren't errors
+        let header = GvrHeader {
+            header_id: HeaderId::Gbix,
+            global_index: 0,
+            ..header
+        };
+        let findings = diagnose(&file(&header, 520)).unwrap();
+        let severities: Vec<_> = findings.iter().map(|finding| finding.severity).collect();
+        assert!(severities == [Severity::Warning, Severity::Info, Severity::Info]);
+        assert!(!has_errors(&findings));
+
+        // a truncated file is, and comes first
+        let findings = diagnose(&file(&header, 500)).unwrap();
+        assert!(findings[0].severity == Severity::Error);
+        assert!(has_errors(&findings));
+
+        assert!(diagnose(&[0; 8]).is_err());
+    }
+}
@@ -0,0 +1,509 @@
+//! DXT1 block compression done here instead of by the encoder, for `--dxt-quality`.
+//!
+//! Each 4x4 block of a `dxt1` texture stores two RGB565 end points and a 2-bit index for each
+//! pixel, which picks one of the end points or one of the colors between them. The encoder takes
+//! the two colors of the block that are the farthest apart as the end points, which leaves the
+//! colors in between with a large error whenever those two are outliers. The other qualities fit
+//! the end points to all the colors of the block instead, and pick the index of each pixel by the
+//! colors the decoder actually makes of the end points.
+//!
+//! With the `dxt-alt` feature, `--dxt-backend squish` fits the end points with the methods of
+//! libsquish instead, see [`crate::squish`].
+
+use clap::ValueEnum;
+use image::RgbaImage;
+
+/// Pixels with an alpha below this are stored transparent, like the encoder does.
+const TRANSPARENT_BELOW: u8 = 16;
+
+/// How much work goes into compressing each block of `dxt1`.
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum DxtQuality {
+    /// The encoder's own compression, which takes the two colors of each block that are the
+    /// farthest apart as its end points.
+    #[default]
+    Fast,
+    /// Fit the end points to the main direction the colors of each block vary in, keeping the
+    /// ones of `fast` for the blocks where they do better.
+    Normal,
+    /// Like `normal`, then refine the end points with least squares, search the stored end
+    /// points around them and also try the mode with 3 colors on opaque blocks. Slower.
+    High,
+}
+
+impl std::fmt::Display for DxtQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+/// Which implementation fits the end points of each block of `dxt1`.
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum DxtBackend {
+    /// The fitting described for each `--dxt-quality`.
+    #[default]
+    Builtin,
+    /// The fitting of libsquish: range fit for `fast`, cluster fit for `normal` and iterative
+    /// cluster fit for `high`.
+    #[cfg(feature = "dxt-alt")]
+    Squish,
+}
+
+impl std::fmt::Display for DxtBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+impl DxtQuality {
+    /// Compresses `image` into the texture data of a single `dxt1` level, with the blocks in the
+    /// order the texture stores them. Returns `None` for [`DxtQuality::Fast`] with the builtin
+    /// backend, which is left to the encoder.
+    pub fn compress(self, image: &RgbaImage, backend: DxtBackend) -> Option<Vec<u8>> {
+        if self == Self::Fast && backend == DxtBackend::Builtin {
+            return None;
+        }
+        let (width, height) = image.dimensions();
+        let mut data =
+            Vec::with_capacity(width.div_ceil(8) as usize * height.div_ceil(8) as usize * 32);
+        // the 4x4 blocks are grouped in tiles of 2x2 blocks
+        for tile_y in (0..height).step_by(8) {
+            for tile_x in (0..width).step_by(8) {
+                for (block_x, block_y) in [(0, 0), (4, 0), (0, 4), (4, 4)] {
+                    let block = Block::read(image, tile_x + block_x, tile_y + block_y);
+                    data.extend(block.compress(self, backend));
+                }
+            }
+        }
+        Some(data)
+    }
+}
+
+/// What a pixel of a block needs from its index.
+#[derive(Copy, Clone, PartialEq)]
+enum Pixel {
+    /// A pixel past the edge of the image, which can get any index.
+    Outside,
+    Transparent,
+    Opaque([i32; 3]),
+}
+
+/// The 16 pixels of a 4x4 block, row by row.
+struct Block {
+    pixels: [Pixel; 16],
+}
+
+/// A way of storing a block: its two end points, whose order gives the mode, the index of each
+/// pixel and the error of the opaque pixels.
+#[derive(Copy, Clone)]
+struct Encoding {
+    endpoints: [u16; 2],
+    indices: [u8; 16],
+    error: u32,
+}
+
+impl Block {
+    /// Reads the block whose top left pixel is at `x`, `y` in `image`.
+    fn read(image: &RgbaImage, x: u32, y: u32) -> Self {
+        let pixels = std::array::from_fn(|index| {
+            let (x, y) = (x + index as u32 % 4, y + index as u32 / 4);
+            match image.get_pixel_checked(x, y) {
+                None => Pixel::Outside,
+                Some(pixel) if pixel[3] < TRANSPARENT_BELOW => Pixel::Transparent,
+                Some(pixel) => Pixel::Opaque([0, 1, 2].map(|channel| i32::from(pixel[channel]))),
+            }
+        });
+        Self { pixels }
+    }
+
+    /// Returns the colors of the opaque pixels.
+    fn opaque(&self) -> impl Iterator<Item = [i32; 3]> + '_ {
+        self.pixels.iter().filter_map(|pixel| match pixel {
+            Pixel::Opaque(color) => Some(*color),
+            _ => None,
+        })
+    }
+
+    /// Compresses the block with the given `quality` and `backend` into the 8 bytes stored for
+    /// it.
+    fn compress(&self, quality: DxtQuality, backend: DxtBackend) -> [u8; 8] {
+        let transparent = self.pixels.contains(&Pixel::Transparent);
+        let colors: Vec<_> = self.opaque().collect();
+        let best = match backend {
+            DxtBackend::Builtin => self.fit(&colors, transparent, quality),
+            #[cfg(feature = "dxt-alt")]
+            DxtBackend::Squish => {
+                // like libsquish, try both modes on opaque blocks
+                let modes: &[bool] = if transparent { &[true] } else { &[false, true] };
+                modes
+                    .iter()
+                    .map(|&three| self.evaluate(crate::squish::fit(&colors, three, quality), three))
+                    .min_by_key(|encoding| encoding.error)
+                    .unwrap()
+            }
+        };
+
+        let [first, second] = best.endpoints;
+        let mut bytes = [0; 8];
+        bytes[..2].copy_from_slice(&first.to_be_bytes());
+        bytes[2..4].copy_from_slice(&second.to_be_bytes());
+        for (row, indices) in best.indices.chunks_exact(4).enumerate() {
+            // the first pixel of each row is in the highest bits
+            bytes[4 + row] = indices.iter().fold(0, |byte, &index| byte << 2 | index);
+        }
+        bytes
+    }
+
+    /// Picks the encoding of the block with the builtin fitting for `quality`.
+    fn fit(&self, colors: &[[i32; 3]], transparent: bool, quality: DxtQuality) -> Encoding {
+        // the mode with 4 colors can't store transparent pixels
+        let modes: &[bool] = match (transparent, quality) {
+            (true, _) => &[true],
+            (false, DxtQuality::High) => &[false, true],
+            (false, _) => &[false],
+        };
+
+        let candidates = [principal_endpoints(colors), farthest_endpoints(colors)];
+        let mut best: Option<Encoding> = None;
+        for &three in modes {
+            for &endpoints in &candidates {
+                let encoding = self.evaluate(endpoints, three);
+                if best.is_none_or(|best| encoding.error < best.error) {
+                    best = Some(encoding);
+                }
+            }
+        }
+        let mut best = best.unwrap();
+
+        if quality == DxtQuality::High && !colors.is_empty() {
+            for &three in modes {
+                let mut encoding = self.evaluate(best.endpoints, three);
+                encoding = self.refine(encoding, three);
+                encoding = self.search(encoding, three);
+                if encoding.error < best.error {
+                    best = encoding;
+                }
+            }
+        }
+        best
+    }
+
+    /// Stores the block with the given end points, in the mode with 3 colors if `three` is set,
+    /// picking the nearest color the decoder makes for each pixel.
+    fn evaluate(&self, [a, b]: [u16; 2], three: bool) -> Encoding {
+        // the order of the end points is what tells the decoder which mode the block uses, and
+        // equal end points always mean the mode with 3 colors
+        let endpoints = if three || a == b {
+            [a.min(b), a.max(b)]
+        } else {
+            [a.max(b), a.min(b)]
+        };
+        let three = endpoints[0] <= endpoints[1];
+        let palette = palette(endpoints);
+        let usable = if three { 3 } else { 4 };
+
+        let mut indices = [0; 16];
+        let mut error = 0;
+        for (index, pixel) in indices.iter_mut().zip(&self.pixels) {
+            match pixel {
+                Pixel::Outside => {}
+                Pixel::Transparent => *index = 3,
+                Pixel::Opaque(color) => {
+                    let (nearest, distance) = (0..usable)
+                        .map(|entry| (entry, distance(*color, palette[entry])))
+                        .min_by_key(|&(_, distance)| distance)
+                        .unwrap();
+                    *index = nearest as u8;
+                    error += distance;
+                }
+            }
+        }
+        Encoding {
+            endpoints,
+            indices,
+            error,
+        }
+    }
+
+    /// Moves the end points of `encoding` to the ones that fit the colors of its indices best, by
+    /// least squares, for as long as that lowers the error.
+    fn refine(&self, mut encoding: Encoding, three: bool) -> Encoding {
+        for _ in 0..4 {
+            // how much of the first end point each index is made of
+            let weights: [f32; 4] = if encoding.endpoints[0] <= encoding.endpoints[1] {
+                [1.0, 0.0, 0.5, 0.0]
+            } else {
+                [1.0, 0.0, 2.0 / 3.0, 1.0 / 3.0]
+            };
+            let (mut aa, mut ab, mut bb) = (0.0, 0.0, 0.0);
+            let (mut ax, mut bx) = ([0.0; 3], [0.0; 3]);
+            for (pixel, &index) in self.pixels.iter().zip(&encoding.indices) {
+                let Pixel::Opaque(color) = pixel else {
+                    continue;
+                };
+                let alpha = weights[usize::from(index)];
+                let beta = 1.0 - alpha;
+                aa += alpha * alpha;
+                ab += alpha * beta;
+                bb += beta * beta;
+                for channel in 0..3 {
+                    ax[channel] += alpha * color[channel] as f32;
+                    bx[channel] += beta * color[channel] as f32;
+                }
+            }
+            let determinant = aa * bb - ab * ab;
+            if determinant.abs() < f32::EPSILON {
+                break;
+            }
+            let first =
+                std::array::from_fn(|channel| (ax[channel] * bb - bx[channel] * ab) / determinant);
+            let second =
+                std::array::from_fn(|channel| (bx[channel] * aa - ax[channel] * ab) / determinant);
+            let refined = self.evaluate([to_rgb565(first), to_rgb565(second)], three);
+            if refined.error >= encoding.error {
+                break;
+            }
+            encoding = refined;
+        }
+        encoding
+    }
+
+    /// Tries moving each channel of each end point of `encoding` one step up or down, keeping
+    /// every move that lowers the error, until none of them do.
+    fn search(&self, mut encoding: Encoding, three: bool) -> Encoding {
+        // the bit offset and the largest value of the red, green and blue channels
+        const CHANNELS: [(u32, u16); 3] = [(11, 31), (5, 63), (0, 31)];
+        for _ in 0..16 {
+            let mut improved = false;
+            for endpoint in 0..2 {
+                for (shift, max) in CHANNELS {
+                    for up in [false, true] {
+                        let mut endpoints = encoding.endpoints;
+                        let value = endpoints[endpoint] >> shift & max;
+                        let moved = match up {
+                            true if value < max => value + 1,
+                            false if value > 0 => value - 1,
+                            _ => continue,
+                        };
+                        endpoints[endpoint] =
+                            endpoints[endpoint] & !(max << shift) | moved << shift;
+                        let moved = self.evaluate(endpoints, three);
+                        if moved.error < encoding.error {
+                            encoding = moved;
+                            improved = true;
+                        }
+                    }
+                }
+            }
+            if !improved || encoding.error == 0 {
+                break;
+            }
+        }
+        encoding
+    }
+}
+
+/// Returns the end points at both ends of the colors along the direction they vary in the most.
+fn principal_endpoints(colors: &[[i32; 3]]) -> [u16; 2] {
+    if colors.is_empty() {
+        return [0, 0];
+    }
+    let (mean, axis) = principal_axis(colors);
+    let Some(axis) = axis else {
+        let endpoint = to_rgb565(mean);
+        return [endpoint, endpoint];
+    };
+
+    let projections = colors.iter().map(|color| {
+        (0..3)
+            .map(|channel| (color[channel] as f32 - mean[channel]) * axis[channel])
+            .sum::<f32>()
+    });
+    let (low, high) = projections.fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), t| {
+        (low.min(t), high.max(t))
+    });
+    let along = |t: f32| std::array::from_fn(|channel| mean[channel] + axis[channel] * t);
+    [to_rgb565(along(high)), to_rgb565(along(low))]
+}
+
+/// Returns the mean of the colors, which can't be empty, and the direction they vary in the
+/// most, or `None` for it if all of them are the same.
+pub fn principal_axis(colors: &[[i32; 3]]) -> ([f32; 3], Option<[f32; 3]>) {
+    let count = colors.len() as f32;
+    let mean: [f32; 3] = std::array::from_fn(|channel| {
+        colors
+            .iter()
+            .map(|color| color[channel] as f32)
+            .sum::<f32>()
+            / count
+    });
+    let mut covariance = [[0.0f32; 3]; 3];
+    for color in colors {
+        let offset: [f32; 3] = std::array::from_fn(|channel| color[channel] as f32 - mean[channel]);
+        for (row, values) in covariance.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value += offset[row] * offset[column];
+            }
+        }
+    }
+
+    // power iteration, starting from the channel that varies the most
+    let start = (0..3)
+        .max_by(|&a, &b| covariance[a][a].total_cmp(&covariance[b][b]))
+        .unwrap();
+    let mut axis = [0.0f32; 3];
+    axis[start] = 1.0;
+    for _ in 0..8 {
+        let next: [f32; 3] = std::array::from_fn(|row| {
+            (0..3)
+                .map(|column| covariance[row][column] * axis[column])
+                .sum()
+        });
+        let length = next.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if length < f32::EPSILON {
+            // all the colors are the same
+            return (mean, None);
+        }
+        axis = next.map(|value| value / length);
+    }
+    (mean, Some(axis))
+}
+
+/// Returns the two colors that are the farthest apart as the end points, like the encoder does.
+fn farthest_endpoints(colors: &[[i32; 3]]) -> [u16; 2] {
+    let first = colors.first().copied().unwrap_or_default();
+    let mut farthest = (0, [first, first]);
+    for (index, &a) in colors.iter().enumerate() {
+        for &b in &colors[index + 1..] {
+            let distance = distance(a, b);
+            if distance > farthest.0 {
+                farthest = (distance, [a, b]);
+            }
+        }
+    }
+    farthest
+        .1
+        .map(|color| to_rgb565(color.map(|value| value as f32)))
+}
+
+/// Returns the nearest RGB565 color to the given one, whose channels go from 0 to 255.
+pub fn to_rgb565(color: [f32; 3]) -> u16 {
+    let channel = |value: f32, max: f32| (value.clamp(0.0, 255.0) * max / 255.0).round() as u16;
+    channel(color[0], 31.0) << 11 | channel(color[1], 63.0) << 5 | channel(color[2], 31.0)
+}
+
+/// Returns the colors the decoder makes of the given end points, with the same rounding. The
+/// fourth color is transparent in the mode with 3 colors, so it's never picked for opaque pixels.
+pub fn palette([first, second]: [u16; 2]) -> [[i32; 3]; 4] {
+    let (a, b) = (decode_rgb565(first), decode_rgb565(second));
+    if first > second {
+        [
+            a,
+            b,
+            std::array::from_fn(|channel| (a[channel] * 2 + b[channel]) / 3),
+            std::array::from_fn(|channel| (b[channel] * 2 + a[channel]) / 3),
+        ]
+    } else {
+        [
+            a,
+            b,
+            std::array::from_fn(|channel| (a[channel] + b[channel]) / 2),
+            [0; 3],
+        ]
+    }
+}
+
+/// Returns the color the decoder makes of an RGB565 end point.
+pub fn decode_rgb565(color: u16) -> [i32; 3] {
+    let channel = |value: u16, max: f32| (value as f32 * 255.0 / max) as u8 as i32;
+    [
+        channel(color >> 11 & 0x1F, 31.0),
+        channel(color >> 5 & 0x3F, 63.0),
+        channel(color & 0x1F, 31.0),
+    ]
+}
+
+/// Returns the squared distance between two colors.
+fn distance(a: [i32; 3], b: [i32; 3]) -> u32 {
+    (0..3)
+        .map(|channel| (a[channel] - b[channel]).pow(2) as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::encode::EncodeSettings;
+    use crate::formats::DataFormat;
+    use crate::metrics;
+    use crate::testing;
+    use image::DynamicImage;
+
+    /// Encodes `image` as `dxt1` with the given `quality` and `backend`, and returns the PSNR of
+    /// the image the texture decodes to.
+    fn psnr(image: &RgbaImage, quality: DxtQuality, backend: DxtBackend) -> f64 {
+        let settings = EncodeSettings {
+            dxt_quality: quality,
+            dxt_backend: backend,
+            ..testing::settings(DataFormat::Dxt1)
+        };
+        let texture = settings
+            .encode_image(DynamicImage::ImageRgba8(image.clone()))
+            .unwrap();
+        let decoded = decode::decode_buffer(texture).unwrap();
+        metrics::compare(image, &decoded, false).psnr
+    }
+
+    #[test]
+    fn higher_quality_has_higher_psnr() {
+        let image = testing::photo();
+        let [fast, normal, high] = [DxtQuality::Fast, DxtQuality::Normal, DxtQuality::High]
+            .map(|quality| psnr(&image, quality, DxtBackend::Builtin));
+        assert!(
+            fast <= normal && normal <= high,
+            "{fast} fast, {normal} normal, {high} high"
+        );
+    }
+
+    #[test]
+    fn transparent_pixels_are_kept() {
+        // some pixels of every block are fully transparent
+        let mut image = testing::image(16, 16);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            if (x + 2 * y) % 5 == 0 {
+                pixel[3] = 0;
+            }
+        }
+        let settings = EncodeSettings {
+            dxt_quality: DxtQuality::High,
+            ..testing::settings(DataFormat::Dxt1)
+        };
+        let texture = settings
+            .encode_image(DynamicImage::ImageRgba8(image.clone()))
+            .unwrap();
+        let decoded = decode::decode_buffer(texture).unwrap();
+        for (original, decoded) in image.pixels().zip(decoded.pixels()) {
+            assert_eq!(
+                decoded[3] == 0,
+                original[3] == 0,
+                "{original:?} {decoded:?}"
+            );
+        }
+    }
+
+    #[cfg(feature = "dxt-alt")]
+    #[test]
+    fn squish_roundtrip() {
+        let image = testing::photo();
+        let builtin = psnr(&image, DxtQuality::Fast, DxtBackend::Builtin);
+        for quality in [DxtQuality::Fast, DxtQuality::Normal, DxtQuality::High] {
+            // the texture decodes to an image as close as the encoder's own
+            let squish = psnr(&image, quality, DxtBackend::Squish);
+            assert!(
+                squish >= builtin,
+                "{quality}: {squish} squish, {builtin} builtin"
+            );
+        }
+    }
+}
@@ -0,0 +1,1028 @@
+//! The encoding of a single image file into a GVR texture file.
+
+use crate::auto::{self, AutoFormat};
+use crate::dds::Dds;
+use crate::decode;
+use crate::dimensions;
+use crate::dither::{self, Dither};
+use crate::dxt::{DxtBackend, DxtQuality};
+use crate::error::Error;
+use crate::formats::{DataFormat, HeaderId, PixelFormat};
+use crate::header::{FLAG_MIPMAPS, FLAGS_OFFSET, GvrHeader, HEADER_SIZE};
+use crate::indexed;
+use crate::metrics::{self, Metrics};
+use crate::mips::{self, MipChain};
+use crate::output::Sink;
+use crate::palette::{self, FixedPalette, Indexed, TransparentIndex};
+use crate::prs::{self, Compression};
+use crate::quantize::{self, PaletteQuantizer};
+use crate::stdio;
+use crate::term::ceprintln;
+use crate::transform::Transform;
+use crate::verify;
+use clap::ValueEnum;
+use gvrtex::TextureEncoder;
+use gvrtex::error::TextureEncodeError;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::metadata::Orientation;
+use image::{
+    DynamicImage, GenericImageView, ImageDecoder, ImageReader, Rgba, Rgba32FImage, RgbaImage,
+};
+use log::{Level, debug, info};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// All the settings that apply to every texture being encoded.
+#[derive(Copy, Clone)]
+pub struct EncodeSettings {
+    /// The data format to encode in. With `auto_format`, this is replaced by the one picked for
+    /// each image.
+    pub data_format: DataFormat,
+    /// Pick the data format from the contents of each image, see [`auto`].
+    pub auto_format: Option<AutoFormat>,
+    pub pixel_format: PixelFormat,
+    pub mipmaps: bool,
+    /// How the mipmap levels are generated, if `mipmaps` is set.
+    pub mip_chain: MipChain,
+    pub header: HeaderId,
+    pub global_index: u32,
+    /// The changes made to each input image before encoding it.
+    pub transform: Transform,
+    /// Encode input images that are larger than the GameCube supports.
+    pub allow_oversize: bool,
+    /// Leave the input images as they're stored, instead of turning them as given by their EXIF
+    /// orientation.
+    pub ignore_exif: bool,
+    /// Where the intensity formats take the intensity of each pixel from.
+    pub intensity_channel: IntensityChannel,
+    /// How much each color channel counts towards the intensity, with [`IntensityChannel::Luma`].
+    pub luma_weights: LumaWeights,
+    /// How the colors are quantized to the ones the data format stores.
+    pub dither: Dither,
+    /// What builds the color palettes of the indexed formats.
+    pub quantizer: PaletteQuantizer,
+    /// How many colors of the palettes of the indexed formats are used at most, instead of all of
+    /// them. The rest of the palette is transparent black.
+    pub colors: Option<u32>,
+    /// The palette the indexed formats use for every image, instead of building one.
+    pub palette: Option<FixedPalette>,
+    /// The entry of the palettes of the indexed formats that's kept fully transparent.
+    pub transparent_index: Option<TransparentIndex>,
+    /// Sort the colors of the palettes that are built, see [`dither::sort_palette`].
+    pub stable_palette: bool,
+    /// How much work goes into compressing the blocks of `dxt1`.
+    pub dxt_quality: DxtQuality,
+    /// Which implementation fits the end points of the blocks of `dxt1`.
+    pub dxt_backend: DxtBackend,
+    /// Take the blocks of DDS files that are already BC1 compressed as they are for `dxt1`,
+    /// instead of compressing the decoded image again.
+    pub passthrough: bool,
+    /// How many times [`PaletteQuantizer::Kmeans`] refines its palettes at most.
+    pub quantizer_iterations: u32,
+    /// Fail instead of warning when encoding would lose information without being asked to, like
+    /// the alpha channel of formats that don't store it.
+    pub strict: bool,
+    /// Decode each texture after encoding it, to measure how close it is to the input image.
+    pub metrics: bool,
+    /// Read each texture back after writing it to disk, to check that it was written correctly.
+    pub verify: bool,
+    /// Compress each texture file before writing it, for games that load them compressed.
+    pub compression: Option<Compression>,
+}
+
+/// Where the intensity formats take the intensity of each pixel from.
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum IntensityChannel {
+    /// The brightness of the color.
+    #[default]
+    Luma,
+    /// The red channel, as it is.
+    R,
+    /// The green channel, as it is.
+    G,
+    /// The blue channel, as it is.
+    B,
+    /// The alpha channel, as it is.
+    A,
+}
+
+impl IntensityChannel {
+    /// Returns the intensity of a pixel with the given channels, which can be of any depth.
+    fn intensity(self, [r, g, b, a]: [f64; 4], weights: LumaWeights) -> f64 {
+        let LumaWeights([r_weight, g_weight, b_weight]) = weights;
+        match self {
+            Self::Luma => r_weight * r + g_weight * g + b_weight * b,
+            Self::R => r,
+            Self::G => g,
+            Self::B => b,
+            Self::A => a,
+        }
+    }
+}
+
+/// How much the red, green and blue channels each count towards the brightness of a color. These
+/// always add up to 1.
+#[derive(Copy, Clone, PartialEq)]
+pub struct LumaWeights(pub [f64; 3]);
+
+impl Default for LumaWeights {
+    /// The weights the encoder itself uses.
+    fn default() -> Self {
+        Self([0.30, 0.59, 0.11])
+    }
+}
+
+impl LumaWeights {
+    /// Parses either three weights separated by commas, which are scaled to add up to 1, or the
+    /// name of a standard set of weights.
+    pub fn parse(weights: &str) -> Result<Self, String> {
+        match weights {
+            "rec601" => return Ok(Self([0.299, 0.587, 0.114])),
+            "rec709" => return Ok(Self([0.2126, 0.7152, 0.0722])),
+            "average" => return Ok(Self([1.0 / 3.0; 3])),
+            _ => {}
+        }
+
+        let invalid = || {
+            format!(
+                "invalid luma weights `{weights}`, expected three numbers like `0.3,0.59,0.11`, or one of `rec601`, `rec709` and `average`"
+            )
+        };
+        let parsed = weights
+            .split(',')
+            .map(|weight| weight.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        let [r, g, b] = parsed[..] else {
+            return Err(invalid());
+        };
+        let sum = r + g + b;
+        if [r, g, b]
+            .iter()
+            .any(|weight| *weight < 0.0 || !weight.is_finite())
+            || sum <= 0.0
+        {
+            return Err(format!(
+                "invalid luma weights `{weights}`, the weights can't be negative and can't all be 0"
+            ));
+        }
+        Ok(Self([r / sum, g / sum, b / sum]))
+    }
+}
+
+impl std::fmt::Display for LumaWeights {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "{r:.4} red, {g:.4} green, {b:.4} blue")
+    }
+}
+
+/// A GVR texture file that was just encoded.
+pub struct Encoded {
+    /// Size of the GVR texture file, in bytes.
+    pub size: usize,
+    /// The amount of image levels in the texture, counting the full-size image.
+    pub levels: usize,
+    /// The dimensions of the input image, before any changes were made to it.
+    pub source_dimensions: (u32, u32),
+    /// The dimensions of the image that was actually encoded.
+    pub dimensions: (u32, u32),
+    /// The data format the image was encoded in.
+    pub data_format: DataFormat,
+    /// Why the data format was picked, if it was picked automatically.
+    pub auto_reason: Option<&'static str>,
+    /// How close the texture is to the input image, if it was measured.
+    pub metrics: Option<Metrics>,
+    /// How many colors of the palette the pixels use, for the indexed formats.
+    pub palette_colors: Option<usize>,
+    /// Size of the GVR texture file before it was compressed, if it was.
+    pub uncompressed_size: Option<usize>,
+}
+
+impl EncodeSettings {
+    /// Returns the settings the texture with the given `header` was encoded with, but with the
+    /// given `mipmaps` setting instead.
+    pub fn from_header(header: &GvrHeader, mipmaps: bool) -> Self {
+        Self {
+            data_format: header.data_format,
+            auto_format: None,
+            // the pixel format is only used by the indexed formats, which always have one
+            pixel_format: header.pixel_format.unwrap_or(PixelFormat::Rgb5a3),
+            mipmaps,
+            mip_chain: MipChain::default(),
+            header: header.header_id,
+            global_index: header.global_index,
+            transform: Transform::default(),
+            allow_oversize: false,
+            ignore_exif: false,
+            intensity_channel: IntensityChannel::default(),
+            luma_weights: LumaWeights::default(),
+            dither: Dither::None,
+            quantizer: PaletteQuantizer::default(),
+            colors: None,
+            palette: None,
+            transparent_index: None,
+            stable_palette: false,
+            dxt_quality: DxtQuality::Fast,
+            dxt_backend: DxtBackend::Builtin,
+            passthrough: false,
+            quantizer_iterations: quantize::DEFAULT_ITERATIONS,
+            strict: false,
+            metrics: false,
+            verify: false,
+            compression: None,
+        }
+    }
+
+    /// Creates a new texture encoder, configured with these settings.
+    pub fn encoder(&self) -> Result<TextureEncoder, TextureEncodeError> {
+        let mut encoder = match (self.data_format.is_indexed(), self.header) {
+            (true, HeaderId::Gcix) => TextureEncoder::new_gcix_palettized(
+                self.pixel_format.into(),
+                self.data_format.into(),
+            )?,
+            (true, HeaderId::Gbix) => TextureEncoder::new_gbix_palettized(
+                self.pixel_format.into(),
+                self.data_format.into(),
+            )?,
+            (false, HeaderId::Gcix) => TextureEncoder::new_gcix(self.data_format.into())?,
+            (false, HeaderId::Gbix) => TextureEncoder::new_gbix(self.data_format.into())?,
+        };
+
+        if self.mipmaps {
+            encoder = encoder.with_mipmaps()?;
+        }
+        if self.global_index > 0 {
+            encoder = encoder.with_global_index(self.global_index);
+        }
+
+        Ok(encoder)
+    }
+
+    /// Encodes the given `image` with these settings, returning the GVR texture file.
+    pub fn encode_image(&self, image: DynamicImage) -> Result<Vec<u8>, Error> {
+        dimensions::check(
+            self.data_format,
+            self.mipmaps,
+            image.width(),
+            image.height(),
+        )
+        .map_err(Error::Dimensions)?;
+        // dithered levels are each made from the undithered image, so the encoder can't be left to
+        // scale them down from the dithered one, and the same goes for compressing them here
+        if self.mipmaps
+            && (self.mip_chain.is_custom() || self.dither != Dither::None || self.compresses_dxt())
+        {
+            return mips::encode_custom_levels(self, image, &[]);
+        }
+        if self.builds_palette() {
+            let wanted = wanted_colors(image, self);
+            let indexed = match &self.palette {
+                Some(palette) => {
+                    let colors = palette.colors();
+                    if colors.len() > self.data_format.palette_colors() as usize {
+                        return Err(Error::PaletteColors {
+                            colors: colors.len(),
+                            data_format: self.data_format,
+                        });
+                    }
+                    self.dither
+                        .apply_fixed_palette(&wanted, colors, self.pixel_format)
+                }
+                None => self
+                    .dither
+                    .apply_palette(
+                        &wanted,
+                        self.palette_colors(),
+                        self.pixel_format,
+                        (self.quantizer, self.quantizer_iterations),
+                        self.transparent_index,
+                        self.stable_palette,
+                    )
+                    .map_err(|e| Error::Encode(TextureEncodeError::Palette(e)))?,
+            };
+            return self.encode_indexed(&indexed);
+        }
+        let mut encoder = self.encoder().map_err(Error::Init)?;
+        let pixels = encoder_pixels(image, self);
+        if self.compresses_dxt()
+            && let Some(data) = self.dxt_quality.compress(&pixels, self.dxt_backend)
+        {
+            // the encoder still makes the header, and its own blocks are then replaced
+            let mut texture = encoder
+                .encode_buffer(png_buffer(pixels)?)
+                .map_err(Error::Encode)?;
+            texture.truncate(HEADER_SIZE);
+            texture.extend(data);
+            return Ok(texture);
+        }
+        encoder
+            .encode_buffer(png_buffer(pixels)?)
+            .map_err(Error::Encode)
+    }
+
+    /// Takes the BC1 blocks of the DDS file in `input` as they are, for `--passthrough`, along
+    /// with as many of its mipmap levels as the texture has. Returns `None` if `input` isn't a DDS
+    /// file, or with a warning if its blocks can't be used, so that it's decoded and compressed
+    /// again instead.
+    fn passthrough(&self, input: &Path, extra: &ExtraInputs) -> Result<Option<Vec<u8>>, Error> {
+        if stdio::is_stdio(input) {
+            return Ok(None);
+        }
+        let Some(dds) = Dds::parse(std::fs::read(input).map_err(Error::Open)?) else {
+            return Ok(None);
+        };
+        let fall_back = |reason: String| {
+            ceprintln!(
+                "<y!>warning:</> {}: can't pass the blocks of the DDS file through, as {}, so it's compressed again",
+                input.display(),
+                reason
+            );
+            Ok(None)
+        };
+        if !dds.bc1 {
+            return fall_back(format!("its data is {}, not BC1", dds.format));
+        }
+        if self.auto_format.is_some() || self.data_format != DataFormat::Dxt1 {
+            return fall_back("the data format isn't `dxt1`".to_string());
+        }
+        if !self.transform.is_identity() {
+            return fall_back("the image is changed before encoding".to_string());
+        }
+        if extra.alpha_mask.is_some() {
+            return fall_back("its alpha channel is replaced by an alpha mask".to_string());
+        }
+        if !extra.mip_images.is_empty() {
+            return fall_back("the mipmap levels are given as images".to_string());
+        }
+
+        let (width, height) = (dds.width, dds.height);
+        dimensions::check(DataFormat::Dxt1, self.mipmaps, width, height)
+            .map_err(Error::Dimensions)?;
+        if !self.allow_oversize {
+            dimensions::check_limits(width, height).map_err(Error::Dimensions)?;
+        }
+        let levels = if self.mipmaps {
+            self.mip_chain
+                .mipmap_levels(DataFormat::Dxt1, width, height)?
+        } else {
+            0
+        };
+        if dds.levels < levels + 1 {
+            return fall_back(format!(
+                "it has {} image levels, but the texture needs {}",
+                dds.levels,
+                levels + 1
+            ));
+        }
+        let mut data = Vec::new();
+        for level in 0..=levels {
+            let Some(level_data) = dds.gvr_level(level) else {
+                return fall_back(format!("it ends before the blocks of level {level}"));
+            };
+            data.extend(level_data);
+        }
+
+        // the encoder still makes the header, of a blank image the size of the DDS file
+        let header_settings = EncodeSettings {
+            mipmaps: false,
+            dxt_quality: DxtQuality::Fast,
+            dxt_backend: DxtBackend::Builtin,
+            ..*self
+        };
+        let mut texture = header_settings.encode_image(DynamicImage::new_rgba8(width, height))?;
+        texture.truncate(HEADER_SIZE);
+        texture.extend(data);
+        let data_size = texture.len() - HEADER_SIZE;
+        mips::set_data_size(&mut texture, data_size);
+        if levels > 0 {
+            texture[FLAGS_OFFSET] |= FLAG_MIPMAPS;
+        }
+        info!(
+            "{}: passed the BC1 blocks of {} image levels through",
+            input.display(),
+            levels + 1
+        );
+        Ok(Some(texture))
+    }
+
+    /// Checks if the blocks of `dxt1` are compressed here instead of by the encoder, for a
+    /// [`DxtQuality`] or a [`DxtBackend`] other than the encoder's own.
+    fn compresses_dxt(&self) -> bool {
+        self.data_format == DataFormat::Dxt1
+            && (self.dxt_quality != DxtQuality::Fast || self.dxt_backend != DxtBackend::Builtin)
+    }
+
+    /// Encodes the image made of the `indexed` colors into a texture of one of the indexed
+    /// formats, with exactly this palette and these indices, and without mipmaps.
+    pub fn encode_indexed(&self, indexed: &Indexed) -> Result<Vec<u8>, Error> {
+        let mut encoder = self.encoder().map_err(Error::Init)?;
+        // the encoder builds a palette of its own, which is then replaced, so it's handed the
+        // colors of this palette to build it from quickly
+        let (width, height) = indexed.indices.dimensions();
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            indexed.colors[usize::from(indexed.indices.get_pixel(x, y)[0])]
+        });
+        let mut texture = encoder
+            .encode_buffer(png_buffer(image)?)
+            .map_err(Error::Encode)?;
+        palette::write(&mut texture, indexed, self.data_format, self.pixel_format);
+        Ok(texture)
+    }
+
+    /// Reads the palette index of each pixel from `input`, if it's an indexed-color PNG file with
+    /// the same colors as the fixed palette, in the same order, and `image` is still made of the
+    /// colors of those indices after the changes made to it. Keeping the indices instead of
+    /// matching each pixel to the palette matters for palettes that have the same color more than
+    /// once, like the ones of `decode --keep-indexed`.
+    fn input_indices(&self, input: &Path, image: &DynamicImage) -> Option<Indexed> {
+        let palette = self.palette.as_ref()?;
+        if !self.data_format.is_indexed() || self.mipmaps || stdio::is_stdio(input) {
+            return None;
+        }
+        let indexed = indexed::read_png(&std::fs::read(input).ok()?)?;
+        let image = image.to_rgba8();
+        let matches = indexed.colors == palette.colors()
+            && indexed.indices.dimensions() == image.dimensions()
+            && image
+                .pixels()
+                .zip(indexed.indices.pixels())
+                .all(|(pixel, index)| indexed.colors.get(usize::from(index[0])) == Some(pixel));
+        matches.then_some(indexed)
+    }
+
+    /// Checks if the palette of the indexed formats is built here instead of by the encoder, to
+    /// dither it, to build it with another [`PaletteQuantizer`], to give it fewer colors, to use a
+    /// fixed one, to keep one of its entries transparent or to sort it.
+    fn builds_palette(&self) -> bool {
+        self.data_format.is_indexed()
+            && (self.dither != Dither::None
+                || self.palette.is_some()
+                || self.transparent_index.is_some()
+                || self.stable_palette
+                || self.quantizer != PaletteQuantizer::Imagequant
+                || self.palette_colors() < self.data_format.palette_colors())
+    }
+
+    /// Returns the amount of colors the palette of the indexed formats is built with at most.
+    pub fn palette_colors(&self) -> u32 {
+        let entries = self.data_format.palette_colors();
+        self.colors.map_or(entries, |colors| colors.min(entries))
+    }
+
+    /// Loads the image in `input` and changes it like [`encode_file`] does, returning the pixels a
+    /// palette would be built from for it.
+    pub fn palette_pixels(&self, input: &Path) -> Result<Vec<Rgba<u8>>, Error> {
+        let image = load_image(input, !self.ignore_exif)?;
+        let image = self
+            .transform
+            .apply(input, image, self.data_format, self.mipmaps)
+            .map_err(Error::Dimensions)?;
+        Ok(dither::palette_pixels(
+            &wanted_colors(image, self),
+            self.pixel_format,
+        ))
+    }
+
+    /// Warns about the transparent pixels of the image in `input`, if the data format doesn't store
+    /// the alpha channel. In strict mode, this is an error instead.
+    ///
+    /// With [`IntensityChannel::A`], the alpha channel isn't lost, since it's used as the intensity.
+    fn check_discarded_alpha(&self, input: &Path, image: &DynamicImage) -> Result<(), Error> {
+        if !image.color().has_alpha()
+            || self.data_format.stores_alpha(self.pixel_format)
+            || (self.data_format.is_intensity() && self.intensity_channel == IntensityChannel::A)
+        {
+            return Ok(());
+        }
+
+        let pixels = image
+            .pixels()
+            .filter(|(_, _, pixel)| pixel[3] < 0xFF)
+            .count();
+        if pixels == 0 {
+            return Ok(());
+        }
+        let pixel_format = self.data_format.is_indexed().then_some(self.pixel_format);
+        if self.strict {
+            return Err(Error::AlphaDiscarded {
+                pixels,
+                data_format: self.data_format,
+                pixel_format,
+            });
+        }
+        let palette = pixel_format
+            .map(|pixel_format| format!(" with an {pixel_format} palette"))
+            .unwrap_or_default();
+        ceprintln!(
+            "<y!>warning:</> {} transparent pixels will be opaque, as {} textures{} don't store the alpha channel:",
+            pixels,
+            self.data_format,
+            palette
+        );
+        eprintln!("  {}", input.display());
+        Ok(())
+    }
+
+    /// Logs how the image in `input` is going to be encoded, given the `dimensions` it has after
+    /// the [`Self::transform`].
+    fn log_plan(&self, input: &Path, dimensions: (u32, u32)) {
+        let input = input.display();
+        match self.data_format {
+            DataFormat::Index4 | DataFormat::Index8 if let Some(palette) = &self.palette => {
+                info!(
+                    "{input}: encoding as {} with a fixed {} color {} palette",
+                    self.data_format,
+                    palette.colors().len(),
+                    self.pixel_format
+                );
+            }
+            DataFormat::Index4 | DataFormat::Index8 => {
+                info!(
+                    "{input}: encoding as {} with a {} color {} palette, built with {}",
+                    self.data_format,
+                    self.palette_colors(),
+                    self.pixel_format,
+                    self.quantizer
+                );
+            }
+            DataFormat::Dxt1 => info!(
+                "{input}: encoding as {}, with the `{}` compression quality of the `{}` backend",
+                self.data_format, self.dxt_quality, self.dxt_backend
+            ),
+            _ => info!("{input}: encoding as {}", self.data_format),
+        }
+
+        if self.data_format.is_intensity() {
+            match self.intensity_channel {
+                IntensityChannel::Luma => info!(
+                    "{input}: taking the intensity from the color, weighted {}",
+                    self.luma_weights
+                ),
+                channel => info!(
+                    "{input}: taking the intensity from the `{}` channel",
+                    channel.to_possible_value().unwrap().get_name()
+                ),
+            }
+        }
+
+        if self.dither != Dither::None && Dither::supports(self.data_format) {
+            info!(
+                "{input}: dithering with {}",
+                self.dither.to_possible_value().unwrap().get_name()
+            );
+        }
+
+        // a chain that can't be generated fails when encoding, with a better message than here
+        if self.mipmaps
+            && let Ok(levels) =
+                self.mip_chain
+                    .mipmap_levels(self.data_format, dimensions.0, dimensions.1)
+        {
+            info!("{input}: generating {levels} mipmap levels");
+        }
+    }
+}
+
+/// Reads and decodes the image in `input`, or from standard input if `input` is `-`.
+///
+/// The format of the image is derived from the file extension of `input`, falling back to
+/// guessing it from the contents of the file. With `orient`, the image is rotated and flipped
+/// as given by its EXIF orientation, if it has one.
+pub fn load_image(input: &Path, orient: bool) -> Result<DynamicImage, Error> {
+    let image_error = |e| Error::Encode(TextureEncodeError::Encode(e));
+    let mut decoder = open_image(input)?;
+    let orientation = if orient {
+        decoder.orientation().map_err(image_error)?
+    } else {
+        Orientation::NoTransforms
+    };
+    let mut image = DynamicImage::from_decoder(decoder).map_err(image_error)?;
+    info!(
+        "{}: loaded {}x{} image with {} bits per channel",
+        input.display(),
+        image.width(),
+        image.height(),
+        bits_per_channel(&image)
+    );
+
+    if let Some(change) = describe_orientation(orientation) {
+        info!(
+            "{}: applying its EXIF orientation ({change})",
+            input.display()
+        );
+        image.apply_orientation(orientation);
+    }
+    Ok(image)
+}
+
+/// Reads only the dimensions of the image in `input`, the same way as [`load_image`], without
+/// decoding the pixels. With `orient`, the dimensions are swapped if the EXIF orientation turns
+/// the image on its side.
+pub fn image_dimensions(input: &Path, orient: bool) -> Result<(u32, u32), Error> {
+    let image_error = |e| Error::Encode(TextureEncodeError::Encode(e));
+    let mut decoder = open_image(input)?;
+    let (width, height) = decoder.dimensions();
+    let orientation = if orient {
+        decoder.orientation().map_err(image_error)?
+    } else {
+        Orientation::NoTransforms
+    };
+    match orientation {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => Ok((height, width)),
+        _ => Ok((width, height)),
+    }
+}
+
+/// Opens a decoder for the image in `input`, or for the one on standard input if `input` is `-`.
+fn open_image(input: &Path) -> Result<impl ImageDecoder, Error> {
+    let (buffer, format) = if stdio::is_stdio(input) {
+        (stdio::read_stdin()?, None)
+    } else {
+        (
+            std::fs::read(input).map_err(Error::Open)?,
+            image::ImageFormat::from_path(input).ok(),
+        )
+    };
+
+    let mut reader = ImageReader::new(Cursor::new(buffer));
+    match format {
+        Some(format) => reader.set_format(format),
+        None => reader = reader.with_guessed_format().map_err(Error::Open)?,
+    }
+    reader
+        .into_decoder()
+        .map_err(|e| Error::Encode(TextureEncodeError::Encode(e)))
+}
+
+/// Describes what has to be done to an image with the given EXIF `orientation` to show it the
+/// right way up, or returns `None` if it already is.
+fn describe_orientation(orientation: Orientation) -> Option<&'static str> {
+    match orientation {
+        Orientation::NoTransforms => None,
+        Orientation::Rotate90 => Some("rotating 90 degrees clockwise"),
+        Orientation::Rotate180 => Some("rotating 180 degrees"),
+        Orientation::Rotate270 => Some("rotating 90 degrees counterclockwise"),
+        Orientation::FlipHorizontal => Some("flipping horizontally"),
+        Orientation::FlipVertical => Some("flipping vertically"),
+        Orientation::Rotate90FlipH => Some("rotating 90 degrees clockwise and flipping"),
+        Orientation::Rotate270FlipH => Some("rotating 90 degrees counterclockwise and flipping"),
+    }
+}
+
+/// Converts `image` to the pixels to hand to the encoder of `settings`, dithering them with its
+/// [`Dither`].
+fn encoder_pixels(image: DynamicImage, settings: &EncodeSettings) -> RgbaImage {
+    if settings.dither == Dither::None || !Dither::supports(settings.data_format) {
+        to_rgba8(image, settings)
+    } else {
+        settings
+            .dither
+            .apply(&wanted_colors(image, settings), settings.data_format)
+    }
+}
+
+/// Stores `image` as an in-memory PNG file, which is how images are handed to the encoder.
+/// Compression is kept to a minimum, since the file never hits the disk.
+fn png_buffer(image: RgbaImage) -> Result<Vec<u8>, Error> {
+    let mut buffer = Vec::new();
+    let encoder = PngEncoder::new_with_quality(
+        Cursor::new(&mut buffer),
+        CompressionType::Fast,
+        FilterType::NoFilter,
+    );
+    DynamicImage::ImageRgba8(image)
+        .write_with_encoder(encoder)
+        .map_err(|e| Error::Encode(TextureEncodeError::Encode(e)))?;
+    Ok(buffer)
+}
+
+/// Converts `image` to the 8-bit RGBA pixels the encoder of `settings` takes, rounding any
+/// channels with more than 8 bits to the nearest value.
+///
+/// For the intensity formats, the intensity is taken from the [`IntensityChannel`] of `settings`
+/// and rounded to the nearest value. Only 8-bit images with the default [`LumaWeights`] are left
+/// to the encoder, which truncates it instead. Images with more than 8 bits per channel have it
+/// worked out from all the bits, instead of from the already rounded 8-bit colors.
+fn to_rgba8(image: DynamicImage, settings: &EncodeSettings) -> RgbaImage {
+    let (channel, weights) = (settings.intensity_channel, settings.luma_weights);
+    if !settings.data_format.is_intensity() {
+        return image.into_rgba8();
+    }
+    if bits_per_channel(&image) <= 8 {
+        if channel == IntensityChannel::Luma && weights == LumaWeights::default() {
+            return image.into_rgba8();
+        }
+        let mut image = image.into_rgba8();
+        for pixel in image.pixels_mut() {
+            let intensity = channel.intensity(pixel.0.map(f64::from), weights);
+            *pixel = intensity_pixel(intensity.round().min(255.0) as u8, pixel[3]);
+        }
+        return image;
+    }
+
+    let image = image.into_rgba16();
+    let round = |value: f64| (value / 257.0).round().min(255.0) as u8;
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let channels = image.get_pixel(x, y).0.map(f64::from);
+        intensity_pixel(
+            round(channel.intensity(channels, weights)),
+            round(channels[3]),
+        )
+    })
+}
+
+/// Returns the intensity the encoder stores for the given color, with its default
+/// [`LumaWeights`].
+pub fn encoded_intensity([r, g, b]: [u8; 3]) -> u8 {
+    (0.30 * r as f32 + 0.59 * g as f32 + 0.11 * b as f32) as u8
+}
+
+/// Converts `image` to the colors the encoder of `settings` should store, for dithering them, with
+/// channels going from 0 to 255 and without rounding them. For the intensity formats and the
+/// `intensity-a8` palettes, the three color channels hold the intensity, taken from the
+/// [`IntensityChannel`] of `settings`.
+fn wanted_colors(image: DynamicImage, settings: &EncodeSettings) -> Rgba32FImage {
+    let data_format = settings.data_format;
+    let intensity = data_format.is_intensity()
+        || (data_format.is_indexed() && settings.pixel_format == PixelFormat::IntensityA8);
+    let mut image = image.into_rgba32f();
+    for pixel in image.pixels_mut() {
+        let channels = pixel.0.map(|value| value * 255.0);
+        *pixel = if intensity {
+            let intensity = settings
+                .intensity_channel
+                .intensity(channels.map(f64::from), settings.luma_weights)
+                as f32;
+            Rgba([intensity, intensity, intensity, channels[3]])
+        } else {
+            Rgba(channels)
+        };
+    }
+    image
+}
+
+/// Returns a pixel the encoder turns into exactly the given `intensity`.
+///
+/// The encoder works out the intensity from the color channels in 32-bit floats and truncates it,
+/// which comes out one too low for some grays. For those, one of the channels is raised by one.
+pub fn intensity_pixel(intensity: u8, alpha: u8) -> Rgba<u8> {
+    let raised = intensity.saturating_add(1);
+    let [r, g, b] = [
+        [intensity; 3],
+        [intensity, intensity, raised],
+        [intensity, raised, intensity],
+    ]
+    .into_iter()
+    .find(|&color| encoded_intensity(color) == intensity)
+    .unwrap_or([intensity; 3]);
+    Rgba([r, g, b, alpha])
+}
+
+/// Returns how many bits each channel of `image` is stored with.
+pub fn bits_per_channel(image: &DynamicImage) -> u16 {
+    let color = image.color();
+    color.bits_per_pixel() / u16::from(color.channel_count())
+}
+
+/// The other image files that go into a texture, next to the input image itself.
+#[derive(Default)]
+pub struct ExtraInputs<'a> {
+    /// The images to use as the first mipmap levels instead of generating them, see
+    /// [`mips::encode_custom_levels`].
+    pub mip_images: &'a [PathBuf],
+    /// A grayscale image to take the alpha channel of the input image from.
+    pub alpha_mask: Option<&'a Path>,
+}
+
+/// Encodes the image file in `input` using the given `settings`, writing the GVR texture file to
+/// `output` through the given `sink`. The `extra` inputs are loaded along with it.
+///
+/// If `input` is `-`, the image is read from standard input instead, and its format is guessed
+/// from its contents.
+pub fn encode_file(
+    settings: &EncodeSettings,
+    input: &Path,
+    extra: &ExtraInputs,
+    output: &Path,
+    sink: Sink,
+) -> Result<Encoded, Error> {
+    let start = Instant::now();
+    let passthrough = match settings.passthrough {
+        true => settings.passthrough(input, extra)?,
+        false => None,
+    };
+    let mut image = load_image(input, !settings.ignore_exif)?;
+    let source_dimensions = image.dimensions();
+    if let Some(path) = extra.alpha_mask {
+        let mask = load_image(path, !settings.ignore_exif)?;
+        settings
+            .transform
+            .apply_alpha_mask(&mut image, mask)
+            .map_err(Error::Dimensions)?;
+        info!(
+            "{}: took the alpha channel from {}",
+            input.display(),
+            path.display()
+        );
+    }
+    let resolved;
+    let mut auto_reason = None;
+    let settings = match settings.auto_format {
+        Some(auto_format) => {
+            let analysis = auto::analyze(&image, &settings.transform);
+            let choice = auto::choose(
+                &analysis,
+                auto_format,
+                settings.mipmaps,
+                settings.pixel_format,
+            );
+            info!(
+                "{}: picked {}, as {}",
+                input.display(),
+                choice.data_format,
+                choice.reason
+            );
+            auto_reason = Some(choice.reason);
+            resolved = EncodeSettings {
+                data_format: choice.data_format,
+                auto_format: None,
+                ..*settings
+            };
+            &resolved
+        }
+        None => settings,
+    };
+    let image = settings
+        .transform
+        .apply(input, image, settings.data_format, settings.mipmaps)
+        .map_err(Error::Dimensions)?;
+    let dimensions = image.dimensions();
+    if !settings.allow_oversize {
+        dimensions::check_limits(dimensions.0, dimensions.1).map_err(Error::Dimensions)?;
+    }
+    settings.check_discarded_alpha(input, &image)?;
+    let reference = settings.metrics.then(|| image.to_rgba8());
+    if log::log_enabled!(Level::Info) {
+        settings.log_plan(input, dimensions);
+    }
+    let encoded = if let Some(texture) = passthrough {
+        texture
+    } else if let Some(indexed) = settings.input_indices(input, &image) {
+        dimensions::check(settings.data_format, false, dimensions.0, dimensions.1)
+            .map_err(Error::Dimensions)?;
+        info!(
+            "{}: kept the palette index of each pixel of the image",
+            input.display()
+        );
+        settings.encode_indexed(&indexed)?
+    } else if extra.mip_images.is_empty() {
+        settings.encode_image(image)?
+    } else {
+        let custom = extra
+            .mip_images
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let image = load_image(path, !settings.ignore_exif)?;
+                Ok(settings.transform.apply_level(
+                    image,
+                    settings.data_format,
+                    source_dimensions,
+                    index + 1,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        mips::encode_custom_levels(settings, image, &custom)?
+    };
+    debug!(
+        "{}: encoded {} bytes in {:.2?}",
+        input.display(),
+        encoded.len(),
+        start.elapsed()
+    );
+
+    let metrics = match reference {
+        Some(reference) => {
+            let decoded = decode::decode_level(&encoded, 0)?;
+            let alpha = settings.data_format.stores_alpha(settings.pixel_format);
+            let metrics = metrics::compare(&reference, &decoded, alpha);
+            info!(
+                "{}: PSNR {:.2} dB, SSIM {:.4}",
+                input.display(),
+                metrics.psnr,
+                metrics.ssim
+            );
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    let levels = GvrHeader::parse(&encoded).map_or(1, |header| header.level_count());
+    let compressed = settings.compression.map(|compression| match compression {
+        Compression::Prs => prs::compress(&encoded),
+    });
+    if let Some(compressed) = &compressed {
+        info!(
+            "{}: compressed {} bytes into {}",
+            input.display(),
+            encoded.len(),
+            compressed.len()
+        );
+    }
+    let file = compressed.as_deref().unwrap_or(&encoded);
+    let size = sink.write(output, file)?;
+    if settings.verify && sink != Sink::DryRun && !stdio::is_stdio(output) {
+        verify::verify_output(output, file, compressed.is_some(), dimensions, levels)
+            .map_err(Error::Verify)?;
+        info!("{}: verified {}", input.display(), output.display());
+    }
+    Ok(Encoded {
+        size,
+        levels,
+        source_dimensions,
+        dimensions,
+        data_format: settings.data_format,
+        auto_reason,
+        metrics,
+        palette_colors: settings
+            .data_format
+            .is_indexed()
+            .then(|| palette::colors_used(&encoded, settings.data_format)),
+        uncompressed_size: compressed.is_some().then_some(encoded.len()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// The colors the intensity is checked for.
+    const COLORS: [[u8; 3]; 5] = [
+        [255, 0, 0],
+        [0, 255, 0],
+        [0, 0, 255],
+        [100, 150, 200],
+        [1, 2, 2],
+    ];
+
+    /// Encodes [`COLORS`] as `intensity8` with the given `weights`, and returns the intensity each
+    /// of them decodes to.
+    fn intensities(weights: LumaWeights) -> Vec<u8> {
+        let image = RgbaImage::from_fn(8, 8, |x, _| {
+            let [r, g, b] = COLORS.get(x as usize).copied().unwrap_or_default();
+            Rgba([r, g, b, 255])
+        });
+        let settings = EncodeSettings {
+            luma_weights: weights,
+            ..testing::settings(DataFormat::Intensity8)
+        };
+        let texture = settings
+            .encode_image(DynamicImage::ImageRgba8(image))
+            .unwrap();
+        let decoded = decode::decode_buffer(texture).unwrap();
+        (0..COLORS.len() as u32)
+            .map(|x| {
+                let [r, g, b, _] = decoded.get_pixel(x, 0).0;
+                assert!(r == g && g == b);
+                r
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_luma_weights() {
+        assert_eq!(
+            LumaWeights::parse("rec709").unwrap().0,
+            [0.2126, 0.7152, 0.0722]
+        );
+        assert_eq!(LumaWeights::parse("1, 1,2").unwrap().0, [0.25, 0.25, 0.5]);
+        assert_eq!(
+            LumaWeights::parse("1,1,2").unwrap().to_string(),
+            "0.2500 red, 0.2500 green, 0.5000 blue"
+        );
+        for invalid in ["", "1,2", "1,2,3,4", "a,b,c", "-1,1,1", "0,0,0", "inf,1,1"] {
+            assert!(LumaWeights::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn intensity_reference_values() {
+        // the encoder truncates 76.5, 150.45, 28.05, 140.5 and 1.7
+        assert_eq!(intensities(LumaWeights::default()), [76, 150, 28, 140, 1]);
+        // while other weights are rounded: 54.21, 182.38, 18.41, 142.98 and 1.79
+        let rec709 = LumaWeights::parse("rec709").unwrap();
+        assert_eq!(intensities(rec709), [54, 182, 18, 143, 2]);
+        // 76.24, 149.69, 29.07, 140.75 and 1.70
+        let rec601 = LumaWeights::parse("rec601").unwrap();
+        assert_eq!(intensities(rec601), [76, 150, 29, 141, 2]);
+        // 85, 85, 85, 150 and 1.67
+        let average = LumaWeights::parse("average").unwrap();
+        assert_eq!(intensities(average), [85, 85, 85, 150, 2]);
+    }
+}
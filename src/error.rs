@@ -0,0 +1,353 @@
+//! Contains the errors that can occur while processing a single file.
+
+use crate::afs::AfsError;
+use crate::batch::ExpandError;
+use crate::dimensions::DimensionError;
+use crate::formats::{DataFormat, PixelFormat};
+use crate::gvm::GvmError;
+use crate::header::HeaderError;
+use crate::one::OneError;
+use crate::palette::PaletteError;
+use crate::prs::PrsError;
+use crate::tpl::TplError;
+use crate::verify::VerifyError;
+use gvrtex::error::{TextureDecodeError, TextureEncodeError};
+use image::ImageError;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// The categories a failure can fall into, each of which has its own exit code.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The arguments are invalid, or can't be used together.
+    Usage,
+    /// An input file couldn't be found or read.
+    Input,
+    /// Something went wrong while encoding or decoding the data of a file.
+    Codec,
+    /// An output file couldn't be written.
+    Output,
+    /// Some of the files of a batch failed.
+    Batch,
+}
+
+impl Failure {
+    /// Returns the name of this category, used as the `kind` of JSON error reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Usage => "usage",
+            Self::Input => "input",
+            Self::Codec => "codec",
+            Self::Output => "output",
+            Self::Batch => "batch",
+        }
+    }
+
+    /// Returns the exit code of this category. These are also documented in the `--help` text.
+    pub fn exit_code(self) -> ExitCode {
+        let code = match self {
+            Self::Usage => 2,
+            Self::Input => 3,
+            Self::Codec => 4,
+            Self::Output => 5,
+            Self::Batch => 6,
+        };
+        ExitCode::from(code)
+    }
+}
+
+/// An error that occurred while processing a single input file.
+#[derive(Debug)]
+pub enum Error {
+    /// Something went wrong while initializing the texture encoder.
+    Init(TextureEncodeError),
+    /// Something went wrong while encoding the texture.
+    Encode(TextureEncodeError),
+    /// Something went wrong while writing the output file.
+    Write(std::io::Error),
+    /// Something went wrong while creating the given output directory.
+    CreateDir(PathBuf, std::io::Error),
+    /// The output file already exists, and the overwrite policy doesn't allow replacing it.
+    OutputExists(PathBuf),
+    /// Something went wrong while reading the input from standard input.
+    Stdin(std::io::Error),
+    /// Something went wrong while opening the input GVR file.
+    Open(std::io::Error),
+    /// Something went wrong while decoding the texture.
+    Decode(TextureDecodeError),
+    /// Something went wrong while saving the decoded image.
+    Save(TextureDecodeError),
+    /// The header of the input GVR file is invalid.
+    Header(HeaderError),
+    /// The requested mipmap level doesn't exist in the texture, which has `levels` levels.
+    MipLevel { level: usize, levels: usize },
+    /// A custom mipmap image doesn't have the dimensions of the mipmap level it's used for.
+    MipDimensions {
+        level: usize,
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// More custom mipmap images were given than the texture has mipmap levels.
+    TooManyMips { given: usize, levels: usize },
+    /// More image levels were asked for than the texture can have, which is `max` levels.
+    MipCount { count: usize, max: usize },
+    /// The image can't be encoded at its size.
+    Dimensions(DimensionError),
+    /// The image has `pixels` transparent pixels, but `data_format` doesn't store the alpha
+    /// channel, or the `pixel_format` of its palette doesn't. This is only an error in strict mode.
+    AlphaDiscarded {
+        pixels: usize,
+        data_format: DataFormat,
+        pixel_format: Option<PixelFormat>,
+    },
+    /// The written output file failed the checks of `--verify`.
+    Verify(VerifyError),
+    /// The palette file of `--palette` couldn't be loaded.
+    Palette(PaletteError),
+    /// The palette of `--shared-palette-out` couldn't be saved.
+    SavePalette(PaletteError),
+    /// The texture has no color palette, as its data format isn't indexed.
+    NoPalette(DataFormat),
+    /// The color palette of the texture is stored in a separate file.
+    ExternalPalette,
+    /// The texture file of `size` bytes ends before the end of its `palette` bytes long palette.
+    TruncatedPalette { size: usize, palette: usize },
+    /// The fixed palette has `colors` colors, more than the palette of `data_format` has.
+    PaletteColors {
+        colors: usize,
+        data_format: DataFormat,
+    },
+    /// The new palette has `colors` colors, not the `entries` of the palette it would replace.
+    PaletteEntries { colors: usize, entries: usize },
+    /// The input TPL file is invalid.
+    Tpl(TplError),
+    /// The input GVM archive is invalid.
+    Gvm(GvmError),
+    /// The input PRS data is invalid.
+    Prs(PrsError),
+    /// The input AFS archive is invalid.
+    Afs(AfsError),
+    /// The input `.one` archive is invalid.
+    One(OneError),
+}
+
+impl Error {
+    /// Describes the step the error occurred in, used as the heading of the error message.
+    pub fn context(&self) -> &'static str {
+        match self {
+            Self::Init(_) => "while initializing",
+            Self::Encode(_) => "while encoding texture",
+            Self::Write(_) | Self::OutputExists(_) => "while writing output file",
+            Self::CreateDir(..) => "while creating output directory",
+            Self::Stdin(_) => "while reading standard input",
+            Self::Open(_) => "while opening input file",
+            Self::Decode(_) => "while decoding input file",
+            Self::Save(_) => "while saving output image",
+            Self::Header(_) => "while reading header",
+            Self::MipLevel { .. } => "while selecting mipmap level",
+            Self::MipDimensions { .. } | Self::TooManyMips { .. } | Self::MipCount { .. } => {
+                "while assembling mipmaps"
+            }
+            Self::Dimensions(_) => "while checking image dimensions",
+            Self::AlphaDiscarded { .. } => "while checking the alpha channel",
+            Self::Verify(_) => "while verifying output file",
+            Self::Palette(_) => "while reading palette file",
+            Self::SavePalette(_) => "while saving palette file",
+            Self::NoPalette(_) | Self::ExternalPalette | Self::TruncatedPalette { .. } => {
+                "while reading the palette"
+            }
+            Self::PaletteColors { .. } | Self::PaletteEntries { .. } => {
+                "while checking the palette"
+            }
+            Self::Tpl(_) => "while reading TPL file",
+            Self::Gvm(_) => "while reading GVM archive",
+            Self::Prs(_) => "while decompressing PRS data",
+            Self::Afs(_) => "while reading AFS archive",
+            Self::One(_) => "while reading .one archive",
+        }
+    }
+
+    /// Returns a short name of the step the error occurred in, used as the `stage` of JSON error
+    /// reports.
+    pub fn stage(&self) -> &'static str {
+        match self {
+            Self::Init(_) => "init",
+            Self::Encode(_)
+            | Self::MipDimensions { .. }
+            | Self::TooManyMips { .. }
+            | Self::MipCount { .. }
+            | Self::Dimensions(_)
+            | Self::AlphaDiscarded { .. }
+            | Self::PaletteColors { .. }
+            | Self::PaletteEntries { .. } => "encode",
+            Self::Write(_) | Self::CreateDir(..) | Self::OutputExists(_) | Self::SavePalette(_) => {
+                "write"
+            }
+            Self::Stdin(_)
+            | Self::Open(_)
+            | Self::Header(_)
+            | Self::Palette(_)
+            | Self::NoPalette(_)
+            | Self::ExternalPalette
+            | Self::TruncatedPalette { .. }
+            | Self::Tpl(_)
+            | Self::Gvm(_)
+            | Self::Prs(_)
+            | Self::Afs(_)
+            | Self::One(_) => "read",
+            Self::Decode(_) | Self::MipLevel { .. } => "decode",
+            Self::Save(_) => "save",
+            Self::Verify(_) => "verify",
+        }
+    }
+}
+
+impl From<&HeaderError> for Failure {
+    fn from(value: &HeaderError) -> Self {
+        match value {
+            HeaderError::Io(_) => Self::Input,
+            _ => Self::Codec,
+        }
+    }
+}
+
+impl From<&ExpandError> for Failure {
+    fn from(value: &ExpandError) -> Self {
+        match value {
+            ExpandError::Pattern(..) => Self::Usage,
+            ExpandError::NoMatches(_) | ExpandError::Io(_) => Self::Input,
+        }
+    }
+}
+
+impl Error {
+    /// Returns the category this error falls into.
+    pub fn failure(&self) -> Failure {
+        match self {
+            Self::Init(_) => Failure::Usage,
+            Self::Encode(TextureEncodeError::Encode(ImageError::IoError(_))) => Failure::Input,
+            Self::Encode(TextureEncodeError::Mipmap | TextureEncodeError::Format) => Failure::Usage,
+            Self::Encode(_) => Failure::Codec,
+            Self::Write(_) | Self::CreateDir(..) | Self::OutputExists(_) => Failure::Output,
+            Self::SavePalette(PaletteError::Extension) => Failure::Usage,
+            Self::SavePalette(_) => Failure::Output,
+            Self::Stdin(_) | Self::Open(_) => Failure::Input,
+            Self::Decode(TextureDecodeError::Io(_)) => Failure::Input,
+            Self::Decode(_) => Failure::Codec,
+            Self::Save(TextureDecodeError::Image(ImageError::Unsupported(_))) => Failure::Usage,
+            Self::Save(_) => Failure::Codec,
+            Self::Header(err) => err.into(),
+            Self::MipLevel { .. }
+            | Self::MipDimensions { .. }
+            | Self::TooManyMips { .. }
+            | Self::MipCount { .. }
+            | Self::Dimensions(_)
+            | Self::AlphaDiscarded { .. }
+            | Self::PaletteColors { .. }
+            | Self::PaletteEntries { .. } => Failure::Usage,
+            Self::Palette(_) | Self::ExternalPalette => Failure::Input,
+            Self::NoPalette(_) => Failure::Usage,
+            Self::TruncatedPalette { .. } => Failure::Codec,
+            Self::Verify(VerifyError::Read(_) | VerifyError::Contents { .. }) => Failure::Output,
+            Self::Verify(_) => Failure::Codec,
+            Self::Tpl(err) => err.failure(),
+            Self::Gvm(err) => err.failure(),
+            Self::Prs(err) => err.failure(),
+            Self::Afs(err) => err.failure(),
+            Self::One(err) => err.failure(),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Init(err) | Self::Encode(err) => write!(f, "{err}"),
+            Self::Write(err) | Self::Stdin(err) | Self::Open(err) => write!(f, "{err}"),
+            Self::CreateDir(path, err) => write!(f, "{}: {err}", path.display()),
+            Self::OutputExists(path) => write!(
+                f,
+                "The output file \"{}\" already exists. Use `--overwrite` to replace or skip it.",
+                path.display()
+            ),
+            Self::Decode(err) | Self::Save(err) => write!(f, "{err}"),
+            Self::Header(err) => write!(f, "{err}"),
+            Self::MipLevel { level, levels: 1 } => write!(
+                f,
+                "Can't decode mipmap level {level}, the texture has no mipmaps. Only level 0 is available."
+            ),
+            Self::MipLevel { level, levels } => write!(
+                f,
+                "Can't decode mipmap level {level}, the texture only has {levels} levels (0 to {}).",
+                levels - 1
+            ),
+            Self::MipDimensions {
+                level,
+                expected: (expected_width, expected_height),
+                actual: (width, height),
+            } => write!(
+                f,
+                "The image for mipmap level {level} is {width}x{height}, but it has to be {expected_width}x{expected_height}."
+            ),
+            Self::TooManyMips { given, levels } => write!(
+                f,
+                "Got {given} mipmap images, but the texture only has {levels} mipmap levels."
+            ),
+            Self::Dimensions(err) => write!(f, "{err}"),
+            Self::Verify(err) => write!(f, "{err}"),
+            Self::Tpl(err) => write!(f, "{err}"),
+            Self::Gvm(err) => write!(f, "{err}"),
+            Self::Prs(err) => write!(f, "{err}"),
+            Self::Afs(err) => write!(f, "{err}"),
+            Self::One(err) => write!(f, "{err}"),
+            Self::Palette(err) | Self::SavePalette(err) => write!(f, "{err}"),
+            Self::NoPalette(data_format) => write!(
+                f,
+                "{data_format} textures have no color palette, only the indexed data formats `index4` and `index8` do."
+            ),
+            Self::ExternalPalette => write!(
+                f,
+                "The color palette of the texture is stored in a separate file, not in the texture."
+            ),
+            Self::TruncatedPalette { size, palette } => write!(
+                f,
+                "The file is {size} bytes, too short to hold the header and the {palette} byte color palette."
+            ),
+            Self::PaletteColors {
+                colors,
+                data_format,
+            } => write!(
+                f,
+                "The palette has {colors} colors, but {data_format} textures only have {}.",
+                data_format.palette_colors()
+            ),
+            Self::PaletteEntries { colors, entries } => write!(
+                f,
+                "The palette has {colors} colors, but the palette of the texture has {entries}. Both need the same amount."
+            ),
+            Self::AlphaDiscarded {
+                pixels,
+                data_format,
+                pixel_format,
+            } => {
+                write!(
+                    f,
+                    "{pixels} pixels of the image are transparent, but {data_format} textures"
+                )?;
+                if let Some(pixel_format) = pixel_format {
+                    write!(f, " with an {pixel_format} palette")?;
+                }
+                write!(
+                    f,
+                    " don't store the alpha channel. Blend the image over a color with `--background`, or pass `--force-opaque` to drop the alpha channel on purpose."
+                )
+            }
+            Self::MipCount { count, max } => write!(
+                f,
+                "Can't store {count} image levels, the texture can only have up to {max} levels at its size."
+            ),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Working out the size of the GVR texture files an image would make, for the `estimate` command.
+//!
+//! This only needs the dimensions of the image, so nothing is encoded.
+
+use crate::dimensions;
+use crate::formats::{DataFormat, HeaderId, PixelFormat};
+use crate::header::{GvrHeader, HEADER_SIZE};
+use clap::ValueEnum;
+
+/// The size of the texture file a single data format would make.
+pub struct Estimate {
+    pub data_format: DataFormat,
+    /// The size of the whole GVR texture file, including the header and the color palette.
+    pub size: u64,
+    /// The amount of image levels in the texture, counting the full-size image. This is 1 for the
+    /// formats that don't support mipmaps.
+    pub levels: usize,
+    /// Whether the image can be encoded at its size, without padding it.
+    pub fits: bool,
+}
+
+/// Parses dimensions given as `WxH`, or returns `None` if `size` isn't in that form.
+pub fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once(['x', 'X'])?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+/// Returns the size of the texture every data format would make for an image of the given
+/// dimensions, smallest first. With `mipmaps`, the formats that support them include the full
+/// mipmap chain.
+///
+/// The dimensions have to fit in the 16 bits the GVR header stores them in.
+pub fn estimate(width: u16, height: u16, mipmaps: bool) -> Vec<Estimate> {
+    let mut estimates: Vec<_> = DataFormat::value_variants()
+        .iter()
+        .map(|&data_format| {
+            let header = GvrHeader {
+                header_id: HeaderId::Gcix,
+                global_index: 0,
+                data_format,
+                pixel_format: data_format.is_indexed().then_some(PixelFormat::Rgb5a3),
+                width,
+                height,
+                mipmaps: mipmaps && data_format.supports_mipmaps(),
+                internal_palette: data_format.is_indexed(),
+                external_palette: false,
+                data_size: 0,
+            };
+            Estimate {
+                data_format,
+                size: HEADER_SIZE as u64 + header.expected_data_size(),
+                levels: header.full_level_count(),
+                fits: dimensions::check(data_format, header.mipmaps, width.into(), height.into())
+                    .is_ok(),
+            }
+        })
+        .collect();
+    estimates.sort_by_key(|estimate| estimate.size);
+    estimates
+}
@@ -0,0 +1,111 @@
+//! Finding and extracting GVR textures embedded at arbitrary offsets inside other files.
+
+use crate::formats::HeaderId;
+use crate::header::{self, CHUNK_HEADER_SIZE, GLOBAL_HEADER_SIZE, GvrHeader, HEADER_SIZE};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How much of the file is read at once while searching it.
+const READ_SIZE: usize = 1 << 20;
+
+/// The largest width and height a GameCube texture can have.
+const MAX_DIMENSION: u16 = 1024;
+
+/// A texture embedded in a larger file.
+pub struct Embedded {
+    /// Where the texture starts in the file.
+    pub offset: u64,
+    /// The size of the texture in the file, including its header.
+    pub size: u64,
+    /// Whether the texture is a bare "GVRT" chunk without a GCIX/GBIX header.
+    pub bare: bool,
+    pub header: GvrHeader,
+}
+
+/// Searches the file at `path` for embedded GVR textures, returning them in the order they appear.
+///
+/// Every GCIX, GBIX and GVRT magic string is a candidate, which only counts as a texture if its
+/// header parses, its dimensions are plausible, and its texture data fits both the dimensions and
+/// the file. The search continues after the end of every texture found, so the chunk of a texture
+/// or any bytes in its data that look like a header are never found on their own.
+///
+/// The file is read in pieces, so it can be much larger than the available memory.
+pub fn find_textures(path: &Path) -> std::io::Result<Vec<Embedded>> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+
+    let mut found = Vec::new();
+    let mut buf = Vec::with_capacity(READ_SIZE + HEADER_SIZE);
+    // the offset of the start of `buf` in the file
+    let mut buf_offset = 0;
+    // the offset before which nothing is searched, as it's part of a found texture
+    let mut resume_at = 0;
+    loop {
+        let read = (&mut file).take(READ_SIZE as u64).read_to_end(&mut buf)?;
+        let at_end = read == 0;
+
+        // keep enough bytes in the buffer to parse a header that starts near its end
+        let searchable = if at_end {
+            buf.len()
+        } else {
+            buf.len().saturating_sub(HEADER_SIZE - 1)
+        };
+        for i in 0..searchable {
+            let offset = buf_offset + i as u64;
+            if offset < resume_at {
+                continue;
+            }
+            if let Some(texture) = parse_candidate(&buf[i..], offset, file_size) {
+                resume_at = offset + texture.size;
+                found.push(texture);
+            }
+        }
+
+        if at_end {
+            return Ok(found);
+        }
+        buf.drain(..searchable);
+        buf_offset += searchable as u64;
+    }
+}
+
+/// Checks if a texture starts at the beginning of `buf`, which is at `offset` in a file of
+/// `file_size` bytes.
+fn parse_candidate(buf: &[u8], offset: u64, file_size: u64) -> Option<Embedded> {
+    let (header, header_size, bare) = match buf.get(..4)? {
+        b"GCIX" | b"GBIX" => (GvrHeader::parse(buf).ok()?, HEADER_SIZE, false),
+        b"GVRT" => (GvrHeader::parse_chunk(buf).ok()?, CHUNK_HEADER_SIZE, true),
+        _ => return None,
+    };
+
+    let plausible = (1..=MAX_DIMENSION).contains(&header.width)
+        && (1..=MAX_DIMENSION).contains(&header.height)
+        && u64::from(header.data_size) >= header.base_data_size();
+    let size = header_size as u64 + u64::from(header.data_size);
+    if !plausible || offset + size > file_size {
+        return None;
+    }
+
+    Some(Embedded {
+        offset,
+        size,
+        bare,
+        header,
+    })
+}
+
+/// Reads the given embedded `texture` from the file at `path`, as a standalone GVR texture file.
+/// Bare GVRT chunks get a GCIX header put in front of them, with a global index of 0, so that the
+/// extracted files can be decoded.
+pub fn read_texture(path: &Path, texture: &Embedded) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(texture.offset))?;
+
+    let mut data = Vec::with_capacity(texture.size as usize + GLOBAL_HEADER_SIZE);
+    if texture.bare {
+        data.extend(header::global_header(HeaderId::Gcix, 0));
+    }
+    file.take(texture.size).read_to_end(&mut data)?;
+    Ok(data)
+}
@@ -1,6 +1,8 @@
 use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::path::Path;
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum PixelFormat {
     /// Stores 8-bit intensity values (each pixel is composed of just one value) along with an
     /// alpha channel. This makes the image look grayscale.
@@ -11,7 +13,7 @@ pub enum PixelFormat {
     Rgb5a3,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum DataFormat {
     /// Stores 4-bit intensity values (each pixel is composed of just one value). This makes the
     /// image look grayscale. This format stores no alpha channel.
@@ -30,28 +32,72 @@ pub enum DataFormat {
     /// Stores 16-bit color values, but saves the alpha channel as well.
     Rgb5a3,
     /// Stores 24-bit depth true color (1 byte per color). It also stores an 8-bit alpha channel.
-    ///
-    /// This format is by far the one with the largest filesize, although the most accurate in terms of
-    /// color.
     Argb8888,
     /// Stores 4-bit indices into a quantized color palette.
-    ///
-    /// The color palette can only encode a maximum of 16 colors, which means images with a larger
-    /// variety of colors will not look that great.
     Index4,
     /// Stores 8-bit indices into a quantized color palette.
-    ///
-    /// The color palette can encode a maximum of 256 colors, which means images preserve a decent
-    /// amount of their color quality, as opposed to the Index4 format.
     Index8,
     /// Encodes the image using a DXT1 compression algorithm, also known as BC1 (Block Compression 1).
-    ///
-    /// Works well in environments where the texture cannot be easily viewed (like a 3D model in
-    /// motion), but not that well in other cases (like on a 2D menu), as the compression artifacts
-    /// can be quite visible at times.
     Dxt1,
 }
 
+/// How well a data format can store the alpha channel of an image.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Alpha {
+    None,
+    /// Each pixel is either fully opaque or fully transparent.
+    OneBit,
+    Full,
+    /// The alpha channel depends on the pixel format of the color palette.
+    Palette,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum HeaderId {
+    /// Sets the GVR texture's header string to "GCIX".
+    Gcix,
+    /// Sets the GVR texture's header string to "GBIX".
+    Gbix,
+}
+
+/// The image formats decoded textures can be saved in. Only formats that support transparency are
+/// listed.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ImageFormat {
+    Png,
+    Bmp,
+    Tga,
+    Tiff,
+    Webp,
+    /// A DDS file with every image level of the texture. `dxt1` keeps its compressed blocks, and
+    /// the other data formats are saved as RGBA8.
+    Dds,
+    /// A KTX2 file with every image level of the texture, saved as RGBA8.
+    Ktx2,
+}
+
+impl PixelFormat {
+    /// Returns the pixel format stored under the given `code` in the flags of a GVR header.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::IntensityA8),
+            1 => Some(Self::Rgb565),
+            2 => Some(Self::Rgb5a3),
+            _ => None,
+        }
+    }
+
+    /// Returns the code this pixel format is stored under in the flags of a GVR header.
+    pub fn code(self) -> u8 {
+        match self {
+            Self::IntensityA8 => 0,
+            Self::Rgb565 => 1,
+            Self::Rgb5a3 => 2,
+        }
+    }
+}
+
 impl std::fmt::Display for PixelFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -72,6 +118,181 @@ impl From<PixelFormat> for gvrtex::formats::PixelFormat {
     }
 }
 
+impl DataFormat {
+    /// Returns the data format stored under the given `code` in a GVR header.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x00 => Some(Self::Intensity4),
+            0x01 => Some(Self::Intensity8),
+            0x02 => Some(Self::IntensityA4),
+            0x03 => Some(Self::IntensityA8),
+            0x04 => Some(Self::Rgb565),
+            0x05 => Some(Self::Rgb5a3),
+            0x06 => Some(Self::Argb8888),
+            0x08 => Some(Self::Index4),
+            0x09 => Some(Self::Index8),
+            0x0E => Some(Self::Dxt1),
+            _ => None,
+        }
+    }
+
+    /// Returns the code this data format is stored under in a GVR header.
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Intensity4 => 0x00,
+            Self::Intensity8 => 0x01,
+            Self::IntensityA4 => 0x02,
+            Self::IntensityA8 => 0x03,
+            Self::Rgb565 => 0x04,
+            Self::Rgb5a3 => 0x05,
+            Self::Argb8888 => 0x06,
+            Self::Index4 => 0x08,
+            Self::Index8 => 0x09,
+            Self::Dxt1 => 0x0E,
+        }
+    }
+
+    /// Checks if this format stores indices into a color palette.
+    pub fn is_indexed(self) -> bool {
+        matches!(self, Self::Index4 | Self::Index8)
+    }
+
+    /// Checks if this format only stores the intensity (and possibly the alpha) of each pixel.
+    pub fn is_intensity(self) -> bool {
+        matches!(
+            self,
+            Self::Intensity4 | Self::Intensity8 | Self::IntensityA4 | Self::IntensityA8
+        )
+    }
+
+    /// Returns how many bits each pixel takes up in the image data. The color palette of the
+    /// indexed formats isn't included.
+    pub fn bits_per_pixel(self) -> u32 {
+        match self {
+            Self::Intensity4 | Self::Index4 | Self::Dxt1 => 4,
+            Self::Intensity8 | Self::IntensityA4 | Self::Index8 => 8,
+            Self::IntensityA8 | Self::Rgb565 | Self::Rgb5a3 => 16,
+            Self::Argb8888 => 32,
+        }
+    }
+
+    /// Returns the width and height of the blocks the pixels of this format are stored in. Every
+    /// block takes up 32 bytes, except for `argb8888` which needs two of them.
+    pub fn block_size(self) -> (u32, u32) {
+        match self.bits_per_pixel() {
+            4 => (8, 8),
+            8 => (8, 4),
+            _ => (4, 4),
+        }
+    }
+
+    /// Returns the size in bytes of a single image level of the given dimensions, including the
+    /// padding up to whole blocks. The color palette isn't included.
+    pub fn level_size(self, width: u32, height: u32) -> u64 {
+        let (block_width, block_height) = self.block_size();
+        let width = u64::from(width.div_ceil(block_width) * block_width);
+        let height = u64::from(height.div_ceil(block_height) * block_height);
+        width * height * u64::from(self.bits_per_pixel()) / 8
+    }
+
+    /// Returns the amount of colors in the color palette, which is 0 for the formats that aren't
+    /// indexed.
+    pub fn palette_colors(self) -> u32 {
+        match self {
+            Self::Index4 => 16,
+            Self::Index8 => 256,
+            _ => 0,
+        }
+    }
+
+    /// Returns the size in bytes of the color palette stored before the image data, which is 0 for
+    /// the formats that aren't indexed. Every color of the palette takes up 2 bytes.
+    pub fn palette_size(self) -> u32 {
+        self.palette_colors() * 2
+    }
+
+    /// Returns the amount of mipmap levels the encoder generates for a square texture of the
+    /// given `size`. The mipmaps stop once they would get smaller than a block of the data format.
+    pub fn mipmap_levels(self, size: u32) -> usize {
+        let block_size = match self {
+            Self::Dxt1 => 1,
+            _ => 4,
+        };
+        (1..=size.checked_ilog2().unwrap_or(0))
+            .take_while(|level| size >> level >= block_size)
+            .count()
+    }
+
+    /// Returns how well this format can store the alpha channel of an image.
+    pub fn alpha(self) -> Alpha {
+        match self {
+            Self::Intensity4 | Self::Intensity8 | Self::Rgb565 => Alpha::None,
+            Self::Dxt1 => Alpha::OneBit,
+            Self::IntensityA4 | Self::IntensityA8 | Self::Rgb5a3 | Self::Argb8888 => Alpha::Full,
+            Self::Index4 | Self::Index8 => Alpha::Palette,
+        }
+    }
+
+    /// Checks if textures of this format keep pixels that are partly transparent, given the
+    /// `pixel_format` of the color palette for the indexed formats. `dxt1` doesn't, since its
+    /// pixels are either fully opaque or fully transparent.
+    pub fn keeps_partial_alpha(self, pixel_format: PixelFormat) -> bool {
+        match self.alpha() {
+            Alpha::None | Alpha::OneBit => false,
+            Alpha::Full => true,
+            Alpha::Palette => pixel_format != PixelFormat::Rgb565,
+        }
+    }
+
+    /// Checks if textures of this format store the alpha channel at all, given the `pixel_format`
+    /// of the color palette for the indexed formats.
+    pub fn stores_alpha(self, pixel_format: PixelFormat) -> bool {
+        match self.alpha() {
+            Alpha::None => false,
+            Alpha::OneBit | Alpha::Full => true,
+            Alpha::Palette => pixel_format != PixelFormat::Rgb565,
+        }
+    }
+
+    /// Checks if textures of this format can be encoded with mipmaps.
+    pub fn supports_mipmaps(self) -> bool {
+        matches!(self, Self::Dxt1 | Self::Rgb565 | Self::Rgb5a3)
+    }
+
+    /// Returns the one-line description of this format, which is also shown in `--help`.
+    pub fn description(self) -> String {
+        self.to_possible_value()
+            .and_then(|value| value.get_help().map(ToString::to_string))
+            .unwrap_or_default()
+    }
+
+    /// Returns a longer explanation of the trade-offs of this format, for the formats that need
+    /// one. This is too long for `--help`, so it only goes into the man page.
+    pub fn details(self) -> Option<&'static str> {
+        match self {
+            Self::Argb8888 => Some(
+                "This format is by far the one with the largest filesize, although the most \
+                 accurate in terms of color.",
+            ),
+            Self::Index4 => Some(
+                "The color palette can only encode a maximum of 16 colors, which means images \
+                 with a larger variety of colors will not look that great.",
+            ),
+            Self::Index8 => Some(
+                "The color palette can encode a maximum of 256 colors, which means images \
+                 preserve a decent amount of their color quality, as opposed to the Index4 \
+                 format.",
+            ),
+            Self::Dxt1 => Some(
+                "Works well in environments where the texture cannot be easily viewed (like a 3D \
+                 model in motion), but not that well in other cases (like on a 2D menu), as the \
+                 compression artifacts can be quite visible at times.",
+            ),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for DataFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -105,3 +326,101 @@ impl From<DataFormat> for gvrtex::formats::DataFormat {
         }
     }
 }
+
+impl HeaderId {
+    /// Returns the header id matching the given magic string, if it's a known one.
+    pub fn from_magic(magic: &[u8]) -> Option<Self> {
+        match magic {
+            b"GCIX" => Some(Self::Gcix),
+            b"GBIX" => Some(Self::Gbix),
+            _ => None,
+        }
+    }
+
+    /// Returns the magic string of this header id, as stored at the start of a GVR file.
+    pub fn magic(self) -> &'static [u8; 4] {
+        match self {
+            Self::Gcix => b"GCIX",
+            Self::Gbix => b"GBIX",
+        }
+    }
+}
+
+impl ImageFormat {
+    /// Returns the file extension used for this image format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Bmp => "bmp",
+            Self::Tga => "tga",
+            Self::Tiff => "tiff",
+            Self::Webp => "webp",
+            Self::Dds => "dds",
+            Self::Ktx2 => "ktx2",
+        }
+    }
+
+    /// Returns the format of the image crate that saves this image format, or `None` for the
+    /// formats that hold every image level of a texture, which are written by this crate itself.
+    pub fn image_format(self) -> Option<image::ImageFormat> {
+        match self {
+            Self::Png => Some(image::ImageFormat::Png),
+            Self::Bmp => Some(image::ImageFormat::Bmp),
+            Self::Tga => Some(image::ImageFormat::Tga),
+            Self::Tiff => Some(image::ImageFormat::Tiff),
+            Self::Webp => Some(image::ImageFormat::WebP),
+            Self::Dds | Self::Ktx2 => None,
+        }
+    }
+
+    /// Returns the format that holds every image level of a texture whose extension `path` has,
+    /// if there is one.
+    pub fn all_levels_from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        [Self::Dds, Self::Ktx2]
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+}
+
+impl std::fmt::Display for Alpha {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "no"),
+            Self::OneBit => write!(f, "1-bit"),
+            Self::Full => write!(f, "yes"),
+            Self::Palette => write!(f, "palette"),
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gcix => write!(f, "GCIX"),
+            Self::Gbix => write!(f, "GBIX"),
+        }
+    }
+}
+
+/// Serializes the format enums as their command-line names (e.g. `rgb5a3`), so that the names are
+/// the same ones that are accepted as arguments.
+macro_rules! impl_serialize_as_value_name {
+    ($($ty:ty),*) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_str(self.to_possible_value().unwrap().get_name())
+                }
+            }
+        )*
+    };
+}
+
+impl_serialize_as_value_name!(PixelFormat, DataFormat);
+
+impl Serialize for HeaderId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
@@ -0,0 +1,518 @@
+//! GVM archives, which hold the GVR textures of most games that use them.
+//!
+//! A GVM archive starts with a "GVMH" header, followed by a table with an entry for each texture.
+//! The flags of the header tell which optional fields each entry of the table has: its name, the
+//! formats and the dimensions of its texture, and its global index. The textures follow the
+//! table one after another, usually as bare "GVRT" chunks whose global index is only stored in the
+//! table.
+
+use crate::error::{Error, Failure};
+use crate::formats::HeaderId;
+use crate::header::{
+    self, CHUNK_HEADER_SIZE, FLAGS_OFFSET, GLOBAL_HEADER_SIZE, GvrHeader, HEADER_SIZE, HeaderError,
+};
+use crate::output;
+use gvrtex::error::TextureDecodeError;
+use std::collections::HashSet;
+
+/// The magic string every GVM archive starts with.
+const MAGIC: &[u8; 4] = b"GVMH";
+/// Size of the header, from the magic string up to the amount of entries. The entry table starts
+/// right after this.
+const HEADER_SIZE_GVM: usize = 0x0C;
+/// Size of the name field of the entries, which is padded with zeros.
+pub const NAME_SIZE: usize = 28;
+
+/// Set in the flags when the entries have a global index.
+pub const FLAG_GLOBAL_INDEX: u16 = 0x1;
+/// Set in the flags when the entries have the dimensions of their texture.
+pub const FLAG_DIMENSIONS: u16 = 0x2;
+/// Set in the flags when the entries have the formats of their texture.
+pub const FLAG_FORMATS: u16 = 0x4;
+/// Set in the flags when the entries have a name.
+pub const FLAG_NAMES: u16 = 0x8;
+/// The flags of the archives built from scratch, whose entries have all the fields.
+pub const FLAGS_ALL: u16 = FLAG_GLOBAL_INDEX | FLAG_DIMENSIONS | FLAG_FORMATS | FLAG_NAMES;
+/// The entry table is padded with zeros up to a multiple of this, where the textures start.
+const TABLE_ALIGNMENT: usize = 16;
+
+/// Contains all the possible errors that can occur while parsing a GVM archive.
+#[derive(Debug)]
+pub enum GvmError {
+    /// The file is shorter than the header of a GVM archive.
+    TooShort(usize),
+    /// The file doesn't start with a "GVMH" magic string.
+    UnknownMagic([u8; 4]),
+    /// The table of `count` entries doesn't fit between the header and the first texture, which
+    /// starts at `data_offset`.
+    Table { count: usize, data_offset: usize },
+    /// The texture of the entry with the given index ends past the end of the archive.
+    EntryOutOfBounds(usize),
+    /// The texture of the entry with the given index isn't a valid GVR texture.
+    EntryHeader(usize, HeaderError),
+}
+
+impl std::error::Error for GvmError {}
+
+impl std::fmt::Display for GvmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(
+                f,
+                "The file is too short to be a GVM archive ({len} bytes, the header alone needs {HEADER_SIZE_GVM})."
+            ),
+            Self::UnknownMagic(magic) => write!(
+                f,
+                "Unknown header magic \"{}\", expected \"GVMH\".",
+                magic.escape_ascii()
+            ),
+            Self::Table { count, data_offset } => write!(
+                f,
+                "The table of {count} entries doesn't fit before the first texture at offset 0x{data_offset:X}."
+            ),
+            Self::EntryOutOfBounds(index) => write!(
+                f,
+                "The texture of entry {index} ends past the end of the archive."
+            ),
+            Self::EntryHeader(index, err) => write!(f, "Entry {index}: {err}"),
+        }
+    }
+}
+
+impl GvmError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        match self {
+            Self::EntryHeader(_, err) => err.into(),
+            _ => Failure::Codec,
+        }
+    }
+}
+
+/// A single texture of a GVM archive, along with its entry in the table.
+pub struct GvmEntry<'a> {
+    /// The name, padded with zeros, if the entries have names.
+    pub name: Option<[u8; NAME_SIZE]>,
+    /// The global index of the texture, if the entries have one.
+    pub global_index: Option<u32>,
+    /// Whether the texture is stored as a bare "GVRT" chunk without a GCIX/GBIX header.
+    pub bare: bool,
+    /// The header of the texture. For bare "GVRT" chunks, its header id and global index are the
+    /// ones of [`GvrHeader::parse_chunk`].
+    pub header: GvrHeader,
+    /// Where the texture starts in the archive.
+    pub offset: usize,
+    /// The texture as it's stored in the archive.
+    pub texture: &'a [u8],
+}
+
+/// A parsed GVM archive.
+pub struct Gvm<'a> {
+    /// The flags of the header, which tell which fields the entries have.
+    pub flags: u16,
+    pub entries: Vec<GvmEntry<'a>>,
+}
+
+/// A GVR texture to store in a GVM archive, along with the name of its entry.
+pub struct PackEntry<'a> {
+    pub name: [u8; NAME_SIZE],
+    pub header: GvrHeader,
+    /// The GVR texture file, without anything past the end of its texture chunk.
+    texture: &'a [u8],
+}
+
+/// Returns the size of each entry of the table for the given `flags`.
+pub fn entry_size(flags: u16) -> usize {
+    let mut size = 2;
+    if flags & FLAG_NAMES != 0 {
+        size += NAME_SIZE;
+    }
+    if flags & FLAG_FORMATS != 0 {
+        size += 2;
+    }
+    if flags & FLAG_DIMENSIONS != 0 {
+        size += 2;
+    }
+    if flags & FLAG_GLOBAL_INDEX != 0 {
+        size += 4;
+    }
+    size
+}
+
+/// Parses the GVM archive in `buf`, checking that the table and every texture are inside of it.
+pub fn parse(buf: &[u8]) -> Result<Gvm<'_>, GvmError> {
+    if buf.len() < HEADER_SIZE_GVM {
+        return Err(GvmError::TooShort(buf.len()));
+    }
+    let magic: [u8; 4] = buf[0x00..0x04].try_into().unwrap();
+    if &magic != MAGIC {
+        return Err(GvmError::UnknownMagic(magic));
+    }
+    // the length of the header counts from after the length itself
+    let data_offset = u32::from_le_bytes(buf[0x04..0x08].try_into().unwrap()) as usize + 8;
+    let flags = u16::from_be_bytes(buf[0x08..0x0A].try_into().unwrap());
+    let count = usize::from(u16::from_be_bytes(buf[0x0A..0x0C].try_into().unwrap()));
+
+    let table_end = HEADER_SIZE_GVM + count * entry_size(flags);
+    if table_end > data_offset || data_offset > buf.len() {
+        return Err(GvmError::Table { count, data_offset });
+    }
+
+    let mut entries = Vec::with_capacity(count);
+    let mut field_offset = HEADER_SIZE_GVM;
+    let mut offset = data_offset;
+    for index in 0..count {
+        let mut field = |size: usize| {
+            let field = &buf[field_offset..field_offset + size];
+            field_offset += size;
+            field
+        };
+        // the number of the entry, which is usually its index in the table
+        field(2);
+        let name = (flags & FLAG_NAMES != 0).then(|| field(NAME_SIZE).try_into().unwrap());
+        // the formats and the dimensions of the texture, which its own header has as well
+        if flags & FLAG_FORMATS != 0 {
+            field(2);
+        }
+        if flags & FLAG_DIMENSIONS != 0 {
+            field(2);
+        }
+        let global_index = (flags & FLAG_GLOBAL_INDEX != 0)
+            .then(|| u32::from_be_bytes(field(4).try_into().unwrap()));
+
+        let (header, size, bare) = parse_texture(&buf[offset..]).map_err(|e| match e {
+            HeaderError::TooShort(_) => GvmError::EntryOutOfBounds(index),
+            e => GvmError::EntryHeader(index, e),
+        })?;
+        let texture = offset
+            .checked_add(size)
+            .and_then(|end| buf.get(offset..end))
+            .ok_or(GvmError::EntryOutOfBounds(index))?;
+
+        entries.push(GvmEntry {
+            name,
+            global_index,
+            bare,
+            header,
+            offset,
+            texture,
+        });
+        offset += size;
+    }
+    Ok(Gvm { flags, entries })
+}
+
+/// Parses the header of the texture at the start of `buf`, returning it along with the size of the
+/// texture including its header, and whether it's a bare "GVRT" chunk.
+fn parse_texture(buf: &[u8]) -> Result<(GvrHeader, usize, bool), HeaderError> {
+    match buf.get(..4) {
+        Some(b"GVRT") => {
+            let header = GvrHeader::parse_chunk(buf)?;
+            let size = CHUNK_HEADER_SIZE + header.data_size as usize;
+            Ok((header, size, true))
+        }
+        _ => {
+            let header = GvrHeader::parse(buf)?;
+            let size = HEADER_SIZE + header.data_size as usize;
+            Ok((header, size, false))
+        }
+    }
+}
+
+impl GvmEntry<'_> {
+    /// Returns the name of the entry, without the zeros it's padded with, if it has a non-empty
+    /// one.
+    pub fn name(&self) -> Option<String> {
+        let name = self.name?;
+        let len = name.iter().position(|&byte| byte == 0).unwrap_or(NAME_SIZE);
+        Some(String::from_utf8_lossy(&name[..len]).into_owned()).filter(|name| !name.is_empty())
+    }
+
+    /// Returns the name of the entry if it's usable as a file name, which it isn't if it contains
+    /// anything but printable ASCII characters, or any path separators.
+    pub fn file_name(&self) -> Option<String> {
+        self.name().filter(|name| output::is_file_name(name))
+    }
+
+    /// Returns the global index of the texture, which is the one in the entry table if there is
+    /// one, or the one in the header of the texture otherwise. Bare "GVRT" chunks have none
+    /// without the one in the table.
+    pub fn texture_global_index(&self) -> Option<u32> {
+        self.global_index
+            .or((!self.bare).then_some(self.header.global_index))
+    }
+
+    /// Returns the texture of this entry as a standalone GVR texture file. Bare "GVRT" chunks get a
+    /// GCIX header put in front of them, with the global index of the entry.
+    pub fn to_gvr(&self) -> Vec<u8> {
+        if !self.bare {
+            return self.texture.to_vec();
+        }
+        let mut gvr = Vec::with_capacity(HEADER_SIZE + self.texture.len());
+        gvr.extend(header::global_header(
+            HeaderId::Gcix,
+            self.global_index.unwrap_or(0),
+        ));
+        gvr.extend(self.texture);
+        gvr
+    }
+}
+
+/// Returns the file stem `gvm unpack` saves each of the given `entries` as: the name of the entry
+/// if it's usable as a file name, or its index in the archive otherwise (e.g. `003`). Names that
+/// an earlier entry already has, ignoring case, are replaced by the index too.
+pub fn file_stems(entries: &[GvmEntry]) -> Vec<String> {
+    let mut names = HashSet::new();
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            entry
+                .file_name()
+                .filter(|name| names.insert(name.to_ascii_lowercase()))
+                .unwrap_or_else(|| format!("{index:03}"))
+        })
+        .collect()
+}
+
+/// Parses a glob pattern given on the command line to filter the entries with.
+pub fn parse_filter(filter: &str) -> Result<glob::Pattern, String> {
+    glob::Pattern::new(filter).map_err(|e| format!("invalid pattern `{filter}`: {e}"))
+}
+
+/// Checks if the file stem an entry is saved as matches the `filter`, ignoring case.
+pub fn matches_filter(filter: &glob::Pattern, stem: &str) -> bool {
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    filter.matches_with(stem, options)
+}
+
+/// Returns the name field of an entry named `name`, padded with zeros, or `None` if the name
+/// isn't made of ASCII characters. Names longer than the field are cut off.
+pub fn entry_name(name: &str) -> Option<[u8; NAME_SIZE]> {
+    if !name.is_ascii() {
+        return None;
+    }
+    let mut field = [0; NAME_SIZE];
+    let len = name.len().min(NAME_SIZE);
+    field[..len].copy_from_slice(&name.as_bytes()[..len]);
+    Some(field)
+}
+
+/// Returns the dimensions field of an entry for a texture of the given `width` and `height`,
+/// which holds the base-2 logarithm of the width minus 2 in its second nibble, and the one of the
+/// height in its lowest nibble.
+pub fn dimensions_field(width: u16, height: u16) -> u16 {
+    let log = |size: u16| size.checked_ilog2().unwrap_or(0).saturating_sub(2) as u16 & 0xF;
+    log(width) << 4 | log(height)
+}
+
+impl<'a> PackEntry<'a> {
+    /// Prepares the GVR texture file in `texture` to be stored in an entry with the given `name`.
+    pub fn new(name: [u8; NAME_SIZE], texture: &'a [u8]) -> Result<Self, Error> {
+        let header = GvrHeader::parse(texture).map_err(Error::Header)?;
+        let texture = texture
+            .get(..HEADER_SIZE + header.data_size as usize)
+            .ok_or(Error::Decode(TextureDecodeError::InvalidFile))?;
+        Ok(Self {
+            name,
+            header,
+            texture,
+        })
+    }
+}
+
+/// Builds a GVM archive holding the given `entries`, in that order, whose entry table has the
+/// fields the given `flags` ask for. With `bare`, the textures are stored as bare "GVRT" chunks,
+/// leaving their global index to the entry table.
+///
+/// The entry table is padded up to a multiple of 16 bytes, after which the textures follow one
+/// another.
+pub fn write(entries: &[PackEntry], flags: u16, bare: bool) -> Vec<u8> {
+    let table_end = HEADER_SIZE_GVM + entries.len() * entry_size(flags);
+    let data_offset = table_end.next_multiple_of(TABLE_ALIGNMENT);
+    let header_size = if bare { GLOBAL_HEADER_SIZE } else { 0 };
+    let data_size: usize = entries
+        .iter()
+        .map(|entry| entry.texture.len() - header_size)
+        .sum();
+
+    let mut gvm = Vec::with_capacity(data_offset + data_size);
+    gvm.extend(MAGIC);
+    gvm.extend(((data_offset - 8) as u32).to_le_bytes());
+    gvm.extend(flags.to_be_bytes());
+    gvm.extend((entries.len() as u16).to_be_bytes());
+    for (number, entry) in entries.iter().enumerate() {
+        gvm.extend((number as u16).to_be_bytes());
+        if flags & FLAG_NAMES != 0 {
+            gvm.extend(entry.name);
+        }
+        if flags & FLAG_FORMATS != 0 {
+            gvm.extend(&entry.texture[FLAGS_OFFSET..FLAGS_OFFSET + 2]);
+        }
+        if flags & FLAG_DIMENSIONS != 0 {
+            let header = &entry.header;
+            gvm.extend(dimensions_field(header.width, header.height).to_be_bytes());
+        }
+        if flags & FLAG_GLOBAL_INDEX != 0 {
+            gvm.extend(entry.header.global_index.to_be_bytes());
+        }
+    }
+    gvm.resize(data_offset, 0);
+    for entry in entries {
+        gvm.extend(&entry.texture[header_size..]);
+    }
+    gvm
+}
+
+/// Returns the GVM archive in `buf`, parsed as `archive`, with the texture of the entry with the
+/// given `index` replaced by the one of `new`, along with the size the new texture takes up in it.
+///
+/// The new texture is stored the same way as the old one, with or without its GCIX/GBIX header,
+/// and keeps the header id and the global index of the old one. The formats and the dimensions in
+/// the entry table are updated to the ones of the new texture. Every other byte of the archive
+/// stays as it is, so the other textures only move.
+pub fn replace(buf: &[u8], archive: &Gvm, index: usize, new: &PackEntry) -> (Vec<u8>, usize) {
+    let entry = &archive.entries[index];
+    let global_index = entry
+        .texture_global_index()
+        .unwrap_or(new.header.global_index);
+    let header_id = match entry.bare {
+        true => new.header.header_id,
+        false => entry.header.header_id,
+    };
+    let mut texture = new.texture.to_vec();
+    texture[..GLOBAL_HEADER_SIZE].copy_from_slice(&header::global_header(header_id, global_index));
+    if entry.bare {
+        texture.drain(..GLOBAL_HEADER_SIZE);
+    }
+
+    let end = entry.offset + entry.texture.len();
+    let mut gvm = Vec::with_capacity(buf.len() - entry.texture.len() + texture.len());
+    gvm.extend(&buf[..entry.offset]);
+    // the formats and the dimensions follow the number and the name of the entry
+    let mut field = HEADER_SIZE_GVM + index * entry_size(archive.flags) + 2;
+    if archive.flags & FLAG_NAMES != 0 {
+        field += NAME_SIZE;
+    }
+    if archive.flags & FLAG_FORMATS != 0 {
+        gvm[field..field + 2].copy_from_slice(&new.texture[FLAGS_OFFSET..FLAGS_OFFSET + 2]);
+        field += 2;
+    }
+    if archive.flags & FLAG_DIMENSIONS != 0 {
+        let dimensions = dimensions_field(new.header.width, new.header.height);
+        gvm[field..field + 2].copy_from_slice(&dimensions.to_be_bytes());
+    }
+    let size = texture.len();
+    gvm.extend(texture);
+    gvm.extend(&buf[end..]);
+    (gvm, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::DataFormat;
+    use crate::testing;
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    fn name(name: &str) -> [u8; NAME_SIZE] {
+        let mut field = [0; NAME_SIZE];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        field
+    }
+
+    fn hash(data: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Builds an archive of three textures, replaces the middle one and checks that the other two
+    /// are stored exactly as they were.
+    fn check_replace(bare: bool) {
+        let textures = [
+            testing::texture(DataFormat::Rgb565, 8, 8),
+            testing::texture(DataFormat::Dxt1, 16, 16),
+            testing::texture(DataFormat::Index4, 8, 16),
+        ];
+        let entries: Vec<_> = textures
+            .iter()
+            .zip(["first", "second", "third"])
+            .map(|(texture, stem)| PackEntry::new(name(stem), texture).unwrap())
+            .collect();
+        let buf = write(&entries, FLAGS_ALL, bare);
+        let archive = parse(&buf).unwrap();
+
+        let new_texture = testing::texture(DataFormat::Rgb5a3, 32, 8);
+        let new = PackEntry::new(name("new"), &new_texture).unwrap();
+        let (replaced, size) = replace(&buf, &archive, 1, &new);
+        let replaced = parse(&replaced).unwrap();
+
+        assert_eq!(replaced.entries.len(), 3);
+        for index in [0, 2] {
+            assert_eq!(
+                hash(archive.entries[index].texture),
+                hash(replaced.entries[index].texture),
+                "entry {index} changed"
+            );
+            assert_eq!(archive.entries[index].name, replaced.entries[index].name);
+        }
+        let entry = &replaced.entries[1];
+        assert_eq!(entry.name, Some(name("second")));
+        assert_eq!(entry.bare, bare);
+        assert_eq!(entry.texture.len(), size);
+        assert_eq!(entry.header.data_format, DataFormat::Rgb5a3);
+        assert_eq!((entry.header.width, entry.header.height), (32, 8));
+        let header_size = if bare { GLOBAL_HEADER_SIZE } else { 0 };
+        assert_eq!(entry.texture, &new_texture[header_size..]);
+    }
+
+    #[test]
+    fn replace_keeps_other_entries_bare() {
+        check_replace(true);
+    }
+
+    #[test]
+    fn replace_keeps_other_entries_with_headers() {
+        check_replace(false);
+    }
+
+    #[test]
+    fn replace_updates_table() {
+        let textures = [
+            testing::texture(DataFormat::Rgb565, 8, 8),
+            testing::texture(DataFormat::Rgb565, 8, 8),
+        ];
+        let entries: Vec<_> = textures
+            .iter()
+            .map(|texture| PackEntry::new(name("tex"), texture).unwrap())
+            .collect();
+        let buf = write(&entries, FLAGS_ALL, true);
+        let archive = parse(&buf).unwrap();
+
+        let new_texture = testing::texture(DataFormat::Intensity8, 64, 32);
+        let new = PackEntry::new(name("new"), &new_texture).unwrap();
+        let (replaced, _) = replace(&buf, &archive, 0, &new);
+
+        // the second field of the entry, after its number and its name
+        let field = HEADER_SIZE_GVM + 2 + NAME_SIZE;
+        assert_eq!(
+            replaced[field..field + 2],
+            new_texture[FLAGS_OFFSET..FLAGS_OFFSET + 2]
+        );
+        assert_eq!(
+            replaced[field + 2..field + 4],
+            dimensions_field(64, 32).to_be_bytes()
+        );
+        // everything before the table of the second entry is left as it is
+        assert_eq!(buf[..HEADER_SIZE_GVM + 2], replaced[..HEADER_SIZE_GVM + 2]);
+        let second = HEADER_SIZE_GVM + entry_size(FLAGS_ALL);
+        assert_eq!(
+            buf[second..second + entry_size(FLAGS_ALL)],
+            replaced[second..second + entry_size(FLAGS_ALL)]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use crate::formats::{DataFormat, HeaderId, PixelFormat};
+use std::io::Read;
+use std::path::Path;
+
+/// Size of the GCIX/GBIX header and the GVRT chunk header combined. The texture data starts right
+/// after this.
+pub const HEADER_SIZE: usize = GLOBAL_HEADER_SIZE + CHUNK_HEADER_SIZE;
+
+/// Where the flags are stored in a GVR file. The data flags are in the low nibble, and the pixel
+/// format of the palette is in the high nibble.
+pub const FLAGS_OFFSET: usize = 0x1A;
+/// Where the width and the height of the texture are stored in a GVR file, as big-endian u16s.
+pub const DIMENSIONS_OFFSET: usize = 0x1C;
+/// Where the length of the texture chunk is stored in a GVR file, as a little-endian u32.
+pub const CHUNK_LENGTH_OFFSET: usize = 0x14;
+
+/// Size of the GCIX/GBIX header on its own.
+pub const GLOBAL_HEADER_SIZE: usize = 0x10;
+/// Size of the header of the GVRT chunk on its own.
+pub const CHUNK_HEADER_SIZE: usize = 0x10;
+
+pub const FLAG_MIPMAPS: u8 = 0x1;
+const FLAG_EXTERNAL_PALETTE: u8 = 0x2;
+const FLAG_INTERNAL_PALETTE: u8 = 0x8;
+
+/// All the information stored in the header of a GVR texture file.
+///
+/// This is parsed without touching any of the texture data, so it works even on files where the
+/// image data itself is corrupt.
+pub struct GvrHeader {
+    pub header_id: HeaderId,
+    pub global_index: u32,
+    pub data_format: DataFormat,
+    /// The format of the color palette. This is only set for the indexed data formats.
+    pub pixel_format: Option<PixelFormat>,
+    pub width: u16,
+    pub height: u16,
+    pub mipmaps: bool,
+    /// Whether the color palette is stored before the texture data.
+    pub internal_palette: bool,
+    /// Whether the color palette is stored in a separate file.
+    pub external_palette: bool,
+    /// Size of the texture data following the header, in bytes.
+    pub data_size: u32,
+}
+
+/// Contains all the possible errors that can occur while parsing a GVR header.
+#[derive(Debug)]
+pub enum HeaderError {
+    /// The file is shorter than a full GVR header.
+    TooShort(usize),
+    /// The file doesn't start with a "GCIX" or "GBIX" magic string.
+    UnknownMagic([u8; 4]),
+    /// The "GVRT" magic string of the texture chunk is missing.
+    MissingTextureChunk,
+    /// The length of the texture chunk is too small to even hold its own header.
+    InvalidChunkLength(u32),
+    /// The data flags contain bits that aren't known.
+    InvalidFlags(u8),
+    UnknownDataFormat(u8),
+    UnknownPixelFormat(u8),
+    /// A standard IO error has occurred.
+    Io(std::io::Error),
+}
+
+impl std::error::Error for HeaderError {}
+
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(
+                f,
+                "The file is too short to be a GVR texture ({len} bytes, the header alone needs {HEADER_SIZE})."
+            ),
+            Self::UnknownMagic(magic) => write!(
+                f,
+                "Unknown header magic \"{}\", expected \"GCIX\" or \"GBIX\".",
+                magic.escape_ascii()
+            ),
+            Self::MissingTextureChunk => write!(f, "The \"GVRT\" texture chunk is missing."),
+            Self::InvalidChunkLength(len) => {
+                write!(f, "The texture chunk has an invalid length of {len} bytes.")
+            }
+            Self::InvalidFlags(flags) => write!(f, "Unknown data flags 0x{flags:X}."),
+            Self::UnknownDataFormat(code) => write!(f, "Unknown data format 0x{code:02X}."),
+            Self::UnknownPixelFormat(code) => write!(f, "Unknown pixel format 0x{code:X}."),
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for HeaderError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl GvrHeader {
+    /// Parses the header at the start of the given `buf`. Anything past the header is ignored.
+    pub fn parse(buf: &[u8]) -> Result<Self, HeaderError> {
+        if buf.len() < HEADER_SIZE {
+            return Err(HeaderError::TooShort(buf.len()));
+        }
+
+        let magic: [u8; 4] = buf[0x00..0x04].try_into().unwrap();
+        let Some(header_id) = HeaderId::from_magic(&magic) else {
+            return Err(HeaderError::UnknownMagic(magic));
+        };
+        let global_index = u32::from_be_bytes(buf[0x08..0x0C].try_into().unwrap());
+
+        Self::parse_texture_chunk(&buf[GLOBAL_HEADER_SIZE..], header_id, global_index)
+    }
+
+    /// Parses a bare "GVRT" texture chunk at the start of the given `buf`, for textures that are
+    /// stored without the GCIX/GBIX header in front of them. As that's where the header id and the
+    /// global index come from, these are set to GCIX and 0.
+    pub fn parse_chunk(buf: &[u8]) -> Result<Self, HeaderError> {
+        if buf.len() < CHUNK_HEADER_SIZE {
+            return Err(HeaderError::TooShort(buf.len()));
+        }
+        Self::parse_texture_chunk(buf, HeaderId::Gcix, 0)
+    }
+
+    /// Parses the header of the texture chunk at the start of `buf`, which must be long enough to
+    /// hold it.
+    fn parse_texture_chunk(
+        buf: &[u8],
+        header_id: HeaderId,
+        global_index: u32,
+    ) -> Result<Self, HeaderError> {
+        if &buf[0x00..0x04] != b"GVRT" {
+            return Err(HeaderError::MissingTextureChunk);
+        }
+        let chunk_len = u32::from_le_bytes(buf[0x04..0x08].try_into().unwrap());
+        let Some(data_size) = chunk_len.checked_sub(8) else {
+            return Err(HeaderError::InvalidChunkLength(chunk_len));
+        };
+
+        let flags = buf[0x0A];
+        let data_flags = flags & 0xF;
+        if data_flags & !(FLAG_MIPMAPS | FLAG_EXTERNAL_PALETTE | FLAG_INTERNAL_PALETTE) != 0 {
+            return Err(HeaderError::InvalidFlags(data_flags));
+        }
+
+        let data_format =
+            DataFormat::from_code(buf[0x0B]).ok_or(HeaderError::UnknownDataFormat(buf[0x0B]))?;
+
+        let pixel_format = if data_format.is_indexed() {
+            let code = flags >> 4;
+            Some(PixelFormat::from_code(code).ok_or(HeaderError::UnknownPixelFormat(code))?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            header_id,
+            global_index,
+            data_format,
+            pixel_format,
+            width: u16::from_be_bytes(buf[0x0C..0x0E].try_into().unwrap()),
+            height: u16::from_be_bytes(buf[0x0E..0x10].try_into().unwrap()),
+            mipmaps: data_flags & FLAG_MIPMAPS != 0,
+            internal_palette: data_flags & FLAG_INTERNAL_PALETTE != 0,
+            external_palette: data_flags & FLAG_EXTERNAL_PALETTE != 0,
+            data_size,
+        })
+    }
+
+    /// Returns the size in bytes the texture data needs for the dimensions and the data format
+    /// stored in this header, including the color palette and all the mipmap levels.
+    pub fn expected_data_size(&self) -> u64 {
+        self.base_data_size() + self.mipmap_data_size()
+    }
+
+    /// Returns the size in bytes of the color palette and the full-size image level, which is the
+    /// texture data without any of the mipmaps.
+    pub fn base_data_size(&self) -> u64 {
+        let format = self.data_format;
+        let mut size = format.level_size(self.width.into(), self.height.into());
+        if self.internal_palette {
+            size += u64::from(format.palette_size());
+        }
+        size
+    }
+
+    /// Returns the size in bytes of all the mipmap levels, or 0 if the texture has no mipmaps.
+    pub fn mipmap_data_size(&self) -> u64 {
+        (1..self.full_level_count())
+            .map(|level| self.level_size(level))
+            .sum()
+    }
+
+    /// Returns the amount of image levels a full mipmap chain has for the dimensions in this
+    /// header, counting the full-size image as level 0. This is 1 for textures without mipmaps.
+    pub fn full_level_count(&self) -> usize {
+        if self.mipmaps {
+            1 + self.data_format.mipmap_levels(self.width.into())
+        } else {
+            1
+        }
+    }
+
+    /// Returns the amount of image levels that are actually stored in the texture data. This is
+    /// less than [`Self::full_level_count`] for textures whose mipmap chain stops early.
+    pub fn level_count(&self) -> usize {
+        let size = u64::from(self.data_size);
+        1 + (1..self.full_level_count())
+            .take_while(|&level| self.level_offset(level + 1) <= size)
+            .count()
+    }
+
+    /// Checks if the texture data ends right after one of the image levels, which is the case for
+    /// full mipmap chains as well as for chains that stop early.
+    pub fn ends_on_level(&self) -> bool {
+        u64::from(self.data_size) >= self.expected_data_size()
+            || u64::from(self.data_size) == self.level_offset(self.level_count())
+    }
+
+    /// Returns the width and height of the given image `level`. Each level is half the size of the
+    /// previous one, rounded down, but never smaller than 1x1.
+    pub fn level_dimensions(&self, level: usize) -> (u32, u32) {
+        let shrink = |size: u16| (u32::from(size) >> level).max(1);
+        (shrink(self.width), shrink(self.height))
+    }
+
+    /// Returns the size in bytes of the given image `level`, including the padding up to whole
+    /// blocks.
+    pub fn level_size(&self, level: usize) -> u64 {
+        let (width, height) = self.level_dimensions(level);
+        self.data_format.level_size(width, height)
+    }
+
+    /// Returns where the given image `level` starts in the texture data, which comes after the
+    /// color palette and all the larger levels.
+    pub fn level_offset(&self, level: usize) -> u64 {
+        let palette = if self.internal_palette {
+            u64::from(self.data_format.palette_size())
+        } else {
+            0
+        };
+        palette + (0..level).map(|level| self.level_size(level)).sum::<u64>()
+    }
+
+    /// Returns the GCIX/GBIX header and the header of the texture chunk as they're stored at the
+    /// start of a GVR file, which is the counterpart of [`Self::parse`].
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut flags = 0;
+        if self.mipmaps {
+            flags |= FLAG_MIPMAPS;
+        }
+        if self.external_palette {
+            flags |= FLAG_EXTERNAL_PALETTE;
+        }
+        if self.internal_palette {
+            flags |= FLAG_INTERNAL_PALETTE;
+        }
+        if let Some(pixel_format) = self.pixel_format {
+            flags |= pixel_format.code() << 4;
+        }
+
+        let mut buf = [0; HEADER_SIZE];
+        buf[..GLOBAL_HEADER_SIZE]
+            .copy_from_slice(&global_header(self.header_id, self.global_index));
+        buf[0x10..0x14].copy_from_slice(b"GVRT");
+        buf[CHUNK_LENGTH_OFFSET..CHUNK_LENGTH_OFFSET + 4]
+            .copy_from_slice(&(self.data_size + 8).to_le_bytes());
+        buf[FLAGS_OFFSET] = flags;
+        buf[FLAGS_OFFSET + 1] = self.data_format.code();
+        buf[DIMENSIONS_OFFSET..DIMENSIONS_OFFSET + 2].copy_from_slice(&self.width.to_be_bytes());
+        buf[DIMENSIONS_OFFSET + 2..DIMENSIONS_OFFSET + 4]
+            .copy_from_slice(&self.height.to_be_bytes());
+        buf
+    }
+
+    /// Reads and parses only the header of the GVR file at the given `path`.
+    pub fn read(path: &Path) -> Result<Self, HeaderError> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        std::fs::File::open(path)?
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut buf)?;
+        Self::parse(&buf)
+    }
+}
+
+/// Returns the GCIX/GBIX header with the given `header_id` and `global_index`, which goes in front
+/// of the "GVRT" texture chunk.
+pub fn global_header(header_id: HeaderId, global_index: u32) -> [u8; GLOBAL_HEADER_SIZE] {
+    let mut buf = [0; GLOBAL_HEADER_SIZE];
+    buf[0x00..0x04].copy_from_slice(header_id.magic());
+    // the length of the rest of the GCIX/GBIX header
+    buf[0x04..0x08].copy_from_slice(&8u32.to_le_bytes());
+    buf[0x08..0x0C].copy_from_slice(&global_index.to_be_bytes());
+    buf
+}
@@ -0,0 +1,96 @@
+//! Indexed-color PNG files, for `decode --keep-indexed` and for encoding them back with the same
+//! palette indices.
+//!
+//! The image crate turns indexed-color PNG files into RGBA images, which loses the order of the
+//! palette and which of its colors each pixel uses when some of them are the same, so these are
+//! read and written with the png crate instead.
+
+use crate::palette::Indexed;
+use image::{GrayImage, Rgba};
+use std::io::Cursor;
+
+/// Encodes `indexed` as an indexed-color PNG file, with the colors of its palette in the same
+/// order. Palettes of up to 16 colors are stored with 4 bits per pixel, and the others with 8.
+pub fn write_png(indexed: &Indexed) -> Result<Vec<u8>, png::EncodingError> {
+    let indices = &indexed.indices;
+    let (width, height) = indices.dimensions();
+    let palette: Vec<u8> = indexed
+        .colors
+        .iter()
+        .flat_map(|color| [color[0], color[1], color[2]])
+        .collect();
+    let mut alpha: Vec<u8> = indexed.colors.iter().map(|color| color[3]).collect();
+    // the colors after the last transparent one are opaque without being listed
+    while alpha.last() == Some(&0xFF) {
+        alpha.pop();
+    }
+
+    let nibbles = indexed.colors.len() <= 16;
+    let data: Vec<u8> = if nibbles {
+        indices
+            .rows()
+            .flat_map(|row| {
+                let row: Vec<u8> = row.map(|index| index[0]).collect();
+                row.chunks(2)
+                    .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    } else {
+        indices.as_raw().clone()
+    };
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(Cursor::new(&mut buffer), width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(if nibbles {
+        png::BitDepth::Four
+    } else {
+        png::BitDepth::Eight
+    });
+    encoder.set_palette(palette);
+    if !alpha.is_empty() {
+        encoder.set_trns(alpha);
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(buffer)
+}
+
+/// Reads the PNG file in `bytes` as the colors of its palette and the index of each pixel, or
+/// returns `None` if it isn't a valid indexed-color PNG file. The colors the `tRNS` chunk leaves
+/// out are opaque.
+pub fn read_png(bytes: &[u8]) -> Option<Indexed> {
+    let mut decoder = png::Decoder::new(Cursor::new(bytes));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder.read_info().ok()?;
+    let info = reader.info();
+    if info.color_type != png::ColorType::Indexed {
+        return None;
+    }
+    let alpha = info.trns.as_deref().unwrap_or_default();
+    let colors: Vec<_> = info
+        .palette
+        .as_deref()?
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, color)| {
+            let alpha = alpha.get(index).copied().unwrap_or(0xFF);
+            Rgba([color[0], color[1], color[2], alpha])
+        })
+        .collect();
+    let bits = info.bit_depth as usize;
+
+    let mut data = vec![0; reader.output_buffer_size()?];
+    let frame = reader.next_frame(&mut data).ok()?;
+    let per_byte = 8 / bits;
+    let mask = ((1u16 << bits) - 1) as u8;
+    let indices = GrayImage::from_fn(frame.width, frame.height, |x, y| {
+        let byte = data[y as usize * frame.line_size + x as usize / per_byte];
+        // the first pixel of each byte is in its highest bits
+        let shift = 8 - bits * (x as usize % per_byte + 1);
+        image::Luma([byte >> shift & mask])
+    });
+    Some(Indexed { colors, indices })
+}
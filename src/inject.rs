@@ -0,0 +1,128 @@
+//! Splicing GVR textures back into the files they were extracted from.
+
+use crate::error::Failure;
+use crate::header::{CHUNK_HEADER_SIZE, GvrHeader, HEADER_SIZE, HeaderError};
+use crate::patch;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Contains all the possible errors that can occur before a texture gets injected.
+#[derive(Debug)]
+pub enum InjectError {
+    /// There's no valid GVR texture at the offset in the container.
+    Existing(u64, HeaderError),
+    /// The texture at the offset in the container extends past the end of the container.
+    ExistingTruncated(u64),
+    /// The texture to inject isn't a valid GVR texture.
+    Texture(HeaderError),
+    /// The texture to inject is smaller than the existing one, and padding wasn't allowed.
+    Smaller { existing: u64, new: u64 },
+    /// The texture to inject is larger than the existing one.
+    Larger { existing: u64, new: u64 },
+}
+
+impl std::error::Error for InjectError {}
+
+impl std::fmt::Display for InjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Existing(offset, err) => {
+                write!(f, "No valid GVR texture at offset 0x{offset:X}: {err}")
+            }
+            Self::ExistingTruncated(offset) => write!(
+                f,
+                "The texture at offset 0x{offset:X} extends past the end of the container."
+            ),
+            Self::Texture(err) => write!(f, "{err}"),
+            Self::Smaller { existing, new } => write!(
+                f,
+                "The new texture is {new} bytes, but the existing one is {existing} bytes. Use `--allow-smaller` to pad it with zeros."
+            ),
+            Self::Larger { existing, new } => write!(
+                f,
+                "The new texture is {new} bytes, which doesn't fit in the {existing} bytes of the existing one."
+            ),
+        }
+    }
+}
+
+impl InjectError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        match self {
+            Self::Existing(_, err) | Self::Texture(err) => err.into(),
+            Self::ExistingTruncated(_) => Failure::Codec,
+            Self::Smaller { .. } | Self::Larger { .. } => Failure::Usage,
+        }
+    }
+}
+
+/// The bytes to write into a container, replacing the texture at some offset.
+pub struct Patch {
+    /// The new texture, padded with zeros to the size of the existing one.
+    pub data: Vec<u8>,
+    /// The amount of zeros the new texture was padded with.
+    pub padding: usize,
+}
+
+/// Parses an offset given on the command line, either in decimal or in hexadecimal with a `0x`
+/// prefix.
+pub fn parse_offset(offset: &str) -> Result<u64, String> {
+    patch::parse_number(offset).map_err(|e| format!("invalid offset `{offset}`: {e}"))
+}
+
+/// Prepares the `texture` to be written into the `container` file at `offset`, checking that it
+/// replaces a texture of the same size there.
+///
+/// If the texture in the container is a bare "GVRT" chunk, the GCIX/GBIX header of the new
+/// `texture` is left out, so that it's stored the same way. With `allow_smaller`, a new texture
+/// that's smaller than the existing one is padded with zeros.
+pub fn prepare(
+    container: &Path,
+    offset: u64,
+    texture: &[u8],
+    allow_smaller: bool,
+) -> Result<Patch, InjectError> {
+    let existing_error = |e| InjectError::Existing(offset, e);
+    let mut file = std::fs::File::open(container).map_err(|e| existing_error(e.into()))?;
+    let container_size = file.metadata().map_err(|e| existing_error(e.into()))?.len();
+
+    let mut buf = Vec::with_capacity(HEADER_SIZE);
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.take(HEADER_SIZE as u64).read_to_end(&mut buf))
+        .map_err(|e| existing_error(e.into()))?;
+    let (existing, bare) = match buf.get(..4) {
+        Some(b"GVRT") => (GvrHeader::parse_chunk(&buf), true),
+        _ => (GvrHeader::parse(&buf), false),
+    };
+    let existing = existing.map_err(existing_error)?;
+    let header_size = if bare { CHUNK_HEADER_SIZE } else { HEADER_SIZE };
+    let existing_size = header_size as u64 + u64::from(existing.data_size);
+    if offset + existing_size > container_size {
+        return Err(InjectError::ExistingTruncated(offset));
+    }
+
+    GvrHeader::parse(texture).map_err(InjectError::Texture)?;
+    let mut data = match bare {
+        true => texture[HEADER_SIZE - CHUNK_HEADER_SIZE..].to_vec(),
+        false => texture.to_vec(),
+    };
+
+    let new_size = data.len() as u64;
+    if new_size > existing_size {
+        return Err(InjectError::Larger {
+            existing: existing_size,
+            new: new_size,
+        });
+    }
+    if new_size < existing_size && !allow_smaller {
+        return Err(InjectError::Smaller {
+            existing: existing_size,
+            new: new_size,
+        });
+    }
+
+    let padding = (existing_size - new_size) as usize;
+    data.resize(existing_size as usize, 0);
+    Ok(Patch { data, padding })
+}
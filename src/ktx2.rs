@@ -0,0 +1,200 @@
+//! KTX2 files, for decoding textures with all of their image levels into a format that current
+//! engines read.
+//!
+//! The levels are decoded into uncompressed sRGB RGBA8, so the file needs no supercompression
+//! and only the basic data format descriptor. The level index lists the full-size image first,
+//! but the data of the levels is stored from the smallest one up, as the format wants.
+
+use crate::decode;
+use crate::error::Error;
+use crate::header::GvrHeader;
+
+/// The identifier every KTX2 file starts with.
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// `VK_FORMAT_R8G8B8A8_SRGB`.
+const VK_FORMAT_RGBA8_SRGB: u32 = 43;
+/// Size of the header, from the identifier up to the end of the index of the other sections.
+const HEADER_SIZE: usize = 80;
+/// Size of the entry of each level in the level index.
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+/// Size of the basic data format descriptor block with its 4 samples.
+const DESCRIPTOR_BLOCK_SIZE: u16 = 24 + 4 * 16;
+
+/// Decodes every image level of the GVR texture file in `buffer`, and saves them as a KTX2 file.
+pub fn write(buffer: &[u8]) -> Result<Vec<u8>, Error> {
+    let header = GvrHeader::parse(buffer).map_err(Error::Header)?;
+    let levels = (0..header.level_count())
+        .map(|level| decode::decode_level(buffer, level).map(|image| image.into_raw()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (width, height) = header.level_dimensions(0);
+
+    let descriptor = data_format_descriptor();
+    let key_values = key_value_data();
+    let descriptor_offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+    let key_values_offset = descriptor_offset + descriptor.len();
+    // the data of each level starts on a multiple of the 4 bytes of a pixel
+    let data_offset = (key_values_offset + key_values.len()).next_multiple_of(4);
+
+    let mut ktx2 = Vec::with_capacity(data_offset + levels.iter().map(Vec::len).sum::<usize>());
+    ktx2.extend(IDENTIFIER);
+    let fields: [u32; 13] = [
+        VK_FORMAT_RGBA8_SRGB,
+        // the type size, which is 1 byte for formats made of bytes
+        1,
+        width,
+        height,
+        // the depth, the amount of layers and the amount of faces of a plain 2D texture
+        0,
+        0,
+        1,
+        levels.len() as u32,
+        // no supercompression
+        0,
+        descriptor_offset as u32,
+        descriptor.len() as u32,
+        key_values_offset as u32,
+        key_values.len() as u32,
+    ];
+    for field in fields {
+        ktx2.extend(field.to_le_bytes());
+    }
+    // there's no supercompression global data
+    ktx2.extend([0; 16]);
+
+    // the smallest level is stored first, right after the other sections
+    let mut offsets = vec![0; levels.len()];
+    let mut offset = data_offset;
+    for (level, data) in levels.iter().enumerate().rev() {
+        offsets[level] = offset;
+        offset += data.len();
+    }
+    for (data, offset) in levels.iter().zip(&offsets) {
+        let length = data.len() as u64;
+        ktx2.extend((*offset as u64).to_le_bytes());
+        ktx2.extend(length.to_le_bytes());
+        ktx2.extend(length.to_le_bytes());
+    }
+
+    ktx2.extend(descriptor);
+    ktx2.extend(key_values);
+    ktx2.resize(data_offset, 0);
+    for data in levels.iter().rev() {
+        ktx2.extend(data);
+    }
+    Ok(ktx2)
+}
+
+/// Returns the data format descriptor of sRGB RGBA8 with straight alpha, led by its total size.
+fn data_format_descriptor() -> Vec<u8> {
+    let mut descriptor = Vec::with_capacity(4 + usize::from(DESCRIPTOR_BLOCK_SIZE));
+    descriptor.extend((4 + u32::from(DESCRIPTOR_BLOCK_SIZE)).to_le_bytes());
+    // the vendor and the type of the basic descriptor block, then its version and size
+    descriptor.extend(0u32.to_le_bytes());
+    descriptor.extend(2u16.to_le_bytes());
+    descriptor.extend(DESCRIPTOR_BLOCK_SIZE.to_le_bytes());
+    // the RGBSDA color model, BT.709 primaries, the sRGB transfer function and no flags
+    descriptor.extend([1, 1, 2, 0]);
+    // a texel block of 1x1 pixels, stored as 4 bytes in a single plane
+    descriptor.extend([0; 4]);
+    descriptor.extend([4, 0, 0, 0, 0, 0, 0, 0]);
+    // the red, green, blue and alpha channels, each one byte, and the alpha is stored linear
+    for (index, channel) in [0u8, 1, 2, 0x1F].into_iter().enumerate() {
+        descriptor.extend((index as u16 * 8).to_le_bytes());
+        descriptor.extend([7, channel]);
+        descriptor.extend([0; 4]);
+        descriptor.extend(0u32.to_le_bytes());
+        descriptor.extend(255u32.to_le_bytes());
+    }
+    descriptor
+}
+
+/// Returns the key/value data, which names the program that wrote the file.
+fn key_value_data() -> Vec<u8> {
+    let pair = format!("KTXwriter\0gvrtex-cli {}\0", env!("CARGO_PKG_VERSION"));
+    let mut data = Vec::with_capacity(4 + pair.len().next_multiple_of(4));
+    data.extend((pair.len() as u32).to_le_bytes());
+    data.extend(pair.as_bytes());
+    data.resize(data.capacity(), 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::EncodeSettings;
+    use crate::formats::DataFormat;
+    use crate::testing;
+    use image::DynamicImage;
+
+    fn u32_at(buf: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    fn u64_at(buf: &[u8], offset: usize) -> usize {
+        u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn header_fields() {
+        let settings = EncodeSettings {
+            mipmaps: true,
+            ..testing::settings(DataFormat::Rgb5a3)
+        };
+        let texture = settings
+            .encode_image(DynamicImage::ImageRgba8(testing::image(32, 32)))
+            .unwrap();
+        let levels = GvrHeader::parse(&texture).unwrap().level_count();
+        assert!(levels > 1);
+        let ktx2 = write(&texture).unwrap();
+
+        assert_eq!(ktx2[..12], IDENTIFIER);
+        assert_eq!(u32_at(&ktx2, 12), 43);
+        // the type size, the dimensions, the depth, the layers, the faces and the levels
+        let fields: Vec<_> = (0..8).map(|field| u32_at(&ktx2, 16 + field * 4)).collect();
+        assert_eq!(fields, [1, 32, 32, 0, 0, 1, levels, 0]);
+
+        // the data format descriptor, which starts with its own size, and the key/value data
+        // right after the level index
+        let (descriptor_offset, descriptor_length) = (u32_at(&ktx2, 48), u32_at(&ktx2, 52));
+        assert_eq!(
+            descriptor_offset,
+            HEADER_SIZE + levels * LEVEL_INDEX_ENTRY_SIZE
+        );
+        assert_eq!(descriptor_length, 92);
+        assert_eq!(u32_at(&ktx2, descriptor_offset), descriptor_length);
+        let (key_values_offset, key_values_length) = (u32_at(&ktx2, 56), u32_at(&ktx2, 60));
+        assert_eq!(key_values_offset, descriptor_offset + descriptor_length);
+        let key_values = &ktx2[key_values_offset..key_values_offset + key_values_length];
+        // a single pair, padded to a multiple of 4 bytes
+        assert_eq!(
+            (4 + u32_at(key_values, 0)).next_multiple_of(4),
+            key_values_length
+        );
+        assert!(key_values[4..].starts_with(b"KTXwriter\0gvrtex-cli "));
+        // no supercompression global data
+        assert_eq!(ktx2[64..80], [0; 16]);
+
+        // the level index lists the full-size level first, but its data is stored last
+        let mut end = ktx2.len();
+        for level in 0..levels {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            let (offset, length) = (u64_at(&ktx2, entry), u64_at(&ktx2, entry + 8));
+            assert_eq!(u64_at(&ktx2, entry + 16), length, "level {level}");
+            assert_eq!(offset + length, end, "level {level}");
+            let data = decode::decode_level(&texture, level).unwrap().into_raw();
+            assert!(ktx2[offset..end] == data, "level {level}");
+            assert_eq!(offset % 4, 0, "level {level}");
+            end = offset;
+        }
+        assert_eq!(
+            end,
+            (key_values_offset + key_values_length).next_multiple_of(4)
+        );
+
+        for offset in [descriptor_offset, key_values_offset] {
+            assert_eq!(offset % 4, 0);
+        }
+    }
+}
@@ -1,42 +1,451 @@
-use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
-use color_print::{ceprintln, cprintln};
-use formats::{DataFormat, PixelFormat};
-use gvrtex::{TextureDecoder, TextureEncoder};
-use std::{ops::Not, path::PathBuf, process::ExitCode};
+use auto::{AutoFormat, FormatChoice, Transparency};
+use batch::{BatchReport, Outcome, ProcessOptions};
+use clap::{
+    ArgAction, ArgGroup, ColorChoice, CommandFactory, Parser, Subcommand, ValueEnum,
+    error::ErrorKind,
+};
+use clap_complete::Shell;
+use color_print::cformat;
+use compare::Comparison;
+use decode::{AtlasLayout, Levels};
+use dither::Dither;
+use doctor::Severity;
+use dxt::{DxtBackend, DxtQuality};
+use encode::{EncodeSettings, Encoded, ExtraInputs, IntensityChannel, LumaWeights};
+use env_logger::WriteStyle;
+use error::{Error, Failure};
+use formats::{DataFormat, HeaderId, ImageFormat, PixelFormat};
+use header::{GvrHeader, HeaderError};
+use image::{DynamicImage, Rgba};
+use log::LevelFilter;
+use mips::{MipChain, MipFilter};
+use output::{DryRun, Overwrite};
+use palette::{PaletteError, TransparentIndex};
+use patch::PatchError;
+use progress::Progress;
+use prs::Compression;
+use quantize::PaletteQuantizer;
+use report::{
+    DecodeReport, EncodeReport, ErrorFormat, ErrorReport, EstimateFormatReport, EstimateReport,
+    FormatReport, GvmEntryReport, InfoReport, LevelReport, MetricsReport, ScanReport,
+    SuggestReport, SuggestionReport, TextureReport,
+};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    io::Write,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use template::{NameTemplate, TemplateValues};
+use term::{ceprintln, cprintln};
+use tpl::{TplFormat, TplImage};
+use transform::{Crop, Flip, Resize, Transform};
 
+mod afs;
+mod auto;
+mod batch;
+mod compare;
+mod contents;
+mod dds;
+mod decode;
+mod dimensions;
+mod dither;
+mod doctor;
+mod dxt;
+mod encode;
+mod error;
+mod estimate;
+mod extract;
 mod formats;
+mod gvm;
+mod header;
+mod indexed;
+mod inject;
+mod ktx2;
+mod man;
+mod metrics;
+mod mips;
+mod one;
+mod output;
+mod palette;
+mod patch;
+mod progress;
+mod prs;
+mod quantize;
+mod report;
+mod scan;
+#[cfg(feature = "dxt-alt")]
+mod squish;
+mod stdio;
+mod suggest;
+mod template;
+mod term;
+#[cfg(test)]
+mod testing;
+mod tpl;
+mod transform;
+mod validate;
+mod verify;
+mod watch;
+
+/// Describes the exit codes, which are defined in [`Failure::exit_code()`].
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success
+  2  Invalid arguments, or settings that can't be used together
+  3  An input file couldn't be found or read
+  4  A texture or image couldn't be encoded or decoded
+  5  An output file couldn't be written
+  6  Some of the files of a batch failed
+
+The `validate` command exits with the number of invalid files instead, up to 125. The `compare`
+and `compare-image` commands exit with 1 if the textures differ, and with 2 on any error.";
 
 #[derive(Parser)]
 #[command(name = "gvrtex")]
 #[command(version, about = "Encodes and decodes images in the GVR texture format", long_about = None)]
+#[command(after_long_help = EXIT_CODES_HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Only print errors and warnings, without any progress bars, success messages or info
+    /// blocks. The output of `--dry-run` and of the `info` command is still printed.
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Print what's being done to each file to stderr. Use `-vv` to also print timings and sizes.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// When to use colors in the output. With `auto`, colors are only used on terminals, unless
+    /// the `NO_COLOR` or `CLICOLOR_FORCE` environment variables say otherwise.
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// How to print errors. With `json`, each error is printed to stderr as a single line of JSON
+    /// with the `stage`, `kind`, `path` and `message` of the error. Batches also print a final
+    /// object with the `summary` of the batch.
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
 }
 
+// the commands are only parsed once, so the size of the larger ones doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Encodes the given image file into an appropriate GVR texture file.
+    #[command(group(ArgGroup::new("resizing").multiple(true).args(["resize", "scale", "max_dimension"])))]
     Encode {
-        /// Input image file to operate on. Can be any standardized image format (.png, .jpg, etc).
-        input: PathBuf,
+        /// Input image file to operate on, followed by the path to where to save the encoded GVR
+        /// file to. The input can be any standardized image format (.png, .jpg, etc). Use `-` as the
+        /// input to read the image from standard input.
+        ///
+        /// When `--out-dir` is given, all the paths are input files instead. Directories are
+        /// expanded into all the image files inside of them.
+        #[arg(
+            required_unless_present = "files_from",
+            value_name = "INPUT>... <OUTPUT"
+        )]
+        paths: Vec<PathBuf>,
 
-        /// Path to where to save the encoded GVR file to.
-        output: PathBuf,
+        /// Encode all the input files into this directory, naming each GVR file after its input
+        /// file.
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
 
-        /// The format the image data should be encoded in.
-        #[arg(short, long, value_enum, default_value_t = DataFormat::Dxt1)]
-        data_format: DataFormat,
+        /// Treat the input paths literally, instead of expanding glob patterns like `*.png`.
+        #[arg(long)]
+        no_glob: bool,
+
+        /// Read more input paths from the given file (or from standard input, if it's `-`), one
+        /// path per line. The paths in the file are always taken literally.
+        #[arg(long, value_name = "PATH", requires = "out_dir")]
+        files_from: Option<PathBuf>,
+
+        /// Separate the paths in the `--files-from` list by NUL bytes instead of newlines.
+        #[arg(short = '0', long, requires = "files_from")]
+        null: bool,
+
+        /// Also encode the image files in all the subdirectories of the input directories. The
+        /// directory structure is mirrored in `--out-dir`.
+        #[arg(short, long, requires = "out_dir")]
+        recursive: bool,
+
+        /// The number of files to process in parallel when using `--out-dir`. Defaults to the
+        /// number of logical CPUs.
+        #[arg(short, long)]
+        jobs: Option<NonZeroUsize>,
+
+        /// Check all the inputs and print where each output would be saved to, without writing
+        /// anything.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Same as `--dry-run`, but also do all the work except for writing to disk, which shows
+        /// the size of each output file.
+        #[arg(long)]
+        dry_run_full: bool,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+
+        /// Create the parent directories of the output path if they don't exist yet. Batch mode
+        /// always creates the directories inside of the output directory.
+        #[arg(long)]
+        create_dirs: bool,
+
+        /// Skip input files in batch mode whose output file is newer than the input file.
+        #[arg(long, requires = "out_dir")]
+        if_newer: bool,
+
+        /// Names the output files in batch mode after the given template, e.g. `{stem}_{format}.gvr`.
+        ///
+        /// The placeholders are `{stem}` and `{ext}` for the input file's stem and extension,
+        /// `{format}` for the data format, `{global_index}` for the global index and `{index}` for
+        /// the position of the input file in the batch (starting at 0). The numeric placeholders can
+        /// be padded with zeros to a width, as in `{index:04}`. Use `{{` and `}}` for literal braces.
+        #[arg(long, value_parser = NameTemplate::parse, requires = "out_dir")]
+        name_template: Option<NameTemplate>,
+
+        /// After encoding all the input files, keep watching them and encode each file again
+        /// whenever it's created or changed, with the same options, until Ctrl-C is pressed.
+        /// Changes inside of `--out-dir` are ignored.
+        #[arg(long, requires = "out_dir", conflicts_with_all = ["dry_run", "dry_run_full"])]
+        watch: bool,
+
+        /// The format the image data should be encoded in. With `auto`, the format is picked for
+        /// each image, and the choice is printed along with the reason for it.
+        #[arg(short, long, value_enum, default_value_t = FormatChoice::Fixed(DataFormat::Dxt1))]
+        data_format: FormatChoice,
+
+        /// With `--data-format auto`, pick `dxt1` for the images it can store without losing
+        /// their transparency, which are the ones without partly transparent pixels.
+        #[arg(long)]
+        prefer_compressed: bool,
 
         /// The format to use for the color data of the color palette, when using either `index4`
-        /// or `index8` data format. This option is ignored in other cases.
-        #[arg(short, long, value_enum, default_value_t = PixelFormat::Rgb5a3)]
-        pixel_format: PixelFormat,
+        /// or `index8` data format. Defaults to `rgb5a3`. Other data formats have no palette, so
+        /// this is ignored with a warning, or refused with `--strict`.
+        #[arg(short, long, value_enum)]
+        pixel_format: Option<PixelFormat>,
+
+        /// How the color palette of `index4` and `index8` is built. Defaults to `imagequant`, like
+        /// the encoder. Other data formats have no palette, so this is ignored with a warning, or
+        /// refused with `--strict`.
+        #[arg(long, value_enum, value_name = "ALGORITHM")]
+        quantizer: Option<PaletteQuantizer>,
+
+        /// Only use up to this many colors of the color palette, e.g. to leave room for colors
+        /// swapped in at runtime. At most 16 for `index4` and 256 for `index8`, which use all of
+        /// them by default. The unused colors are transparent black.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=256))]
+        colors: Option<u32>,
+
+        /// Match the pixels to the colors of this palette file instead of building a palette for
+        /// each image, e.g. for textures that share a palette defined by the game. The palette is
+        /// stored in the order it's given. Either an image like a `.png` strip, with each pixel
+        /// being a color, or an Adobe `.act`, JASC `.pal` or GIMP `.gpl` file. Only images have
+        /// partly transparent colors, and `.act` files can have one fully transparent color. Can be
+        /// dithered with `--dither`.
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["quantizer", "colors"])]
+        palette: Option<PathBuf>,
+
+        /// In batch mode, build one palette for all the input files instead of one for each, and
+        /// encode every file with it, e.g. for the sprites of a sprite sheet, which have to match.
+        /// Only the colors of each image are kept while counting them, so the input files are
+        /// read twice. The palette is built with `--quantizer` and `--colors`. Only works with
+        /// `index4` and `index8`.
+        #[arg(long, requires = "out_dir", conflicts_with = "palette")]
+        shared_palette: bool,
+
+        /// Save the palette of `--shared-palette` to this file, for reusing it with `--palette`.
+        /// Either an image like a `.png` file, or an Adobe `.act`, JASC `.pal` or GIMP `.gpl` file,
+        /// which leave out the alpha channel.
+        #[arg(long, value_name = "FILE", requires = "shared_palette")]
+        shared_palette_out: Option<PathBuf>,
+
+        /// Keep this entry of the color palette fully transparent, for games that treat one of the
+        /// indices as the transparent color. The pixels whose alpha is below
+        /// `--transparent-cutoff` all get this entry, and the rest of the palette is built from
+        /// the other pixels. Needs a pixel format with an alpha channel.
+        #[arg(long, value_name = "N", conflicts_with_all = ["palette", "shared_palette"])]
+        transparent_index: Option<u8>,
+
+        /// The alpha (from 0 to 255) below which pixels get the `--transparent-index` entry.
+        #[arg(
+            long,
+            value_name = "ALPHA",
+            default_value_t = 128,
+            requires = "transparent_index"
+        )]
+        transparent_cutoff: u8,
+
+        /// Sort the colors of the palettes that are built by their luminance, then by their red,
+        /// green, blue and alpha channels, so that encoding the same image with the same options
+        /// always stores the palette in the same order. Fixed palettes of `--palette` keep the
+        /// order they're given in.
+        #[arg(long, conflicts_with = "palette")]
+        stable_palette: bool,
+
+        /// How many times `--quantizer kmeans` refines its palette at most. It stops earlier once
+        /// the palette stops changing. Defaults to 10.
+        #[arg(long, value_name = "COUNT")]
+        quantizer_iterations: Option<u32>,
 
         /// Encode this texture with mipmaps. Only supported on `dxt1`, `rgb565` and `rgb5a3`.
         #[arg(short, long)]
         mipmaps: bool,
 
+        /// Use this image as the next mipmap level, instead of generating it from the input image.
+        /// Give this once per level, starting with level 1, which has to be half the size of the
+        /// input image. The levels after the last given image are generated from it. Implies
+        /// `--mipmaps`.
+        #[arg(long = "mip", value_name = "IMAGE", conflicts_with = "out_dir")]
+        mip_images: Vec<PathBuf>,
+
+        /// Store at most this many image levels, counting the full-size image, instead of the
+        /// full mipmap chain down to the smallest size. Requires `--mipmaps`.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+        mip_count: Option<u16>,
+
+        /// The filter to scale the mipmap levels down with. Requires `--mipmaps`.
+        #[arg(long, value_enum, value_name = "FILTER", default_value_t = MipFilter::Triangle)]
+        mip_filter: MipFilter,
+
+        /// Stop the mipmap chain before the width or the height of a level would drop below this
+        /// size. Defaults to 1, which keeps going down to the smallest size the data format
+        /// allows. Requires `--mipmaps`.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        min_mip_size: Option<u32>,
+
+        /// Crop the image to the given rectangle before encoding it, given as `x,y,width,height`
+        /// in pixels from the top left. This happens before any of the other changes, like
+        /// `--resize`.
+        #[arg(long, value_name = "RECT", value_parser = Crop::parse)]
+        crop: Option<Crop>,
+
+        /// Mirror the image horizontally before encoding it.
+        #[arg(long)]
+        flip_x: bool,
+
+        /// Mirror the image vertically before encoding it.
+        #[arg(long)]
+        flip_y: bool,
+
+        /// Resize the image before encoding it, given as `WxH`. Either side can be left out, as in
+        /// `512x` or `x512`, to keep the aspect ratio of the image.
+        #[arg(long, value_name = "SIZE", value_parser = Resize::parse)]
+        resize: Option<Resize>,
+
+        /// Scale the image by this factor before encoding it, e.g. `0.5` for half the size. The
+        /// dimensions are rounded to the nearest pixel.
+        #[arg(long, value_name = "FACTOR", value_parser = Resize::parse_scale, conflicts_with = "resize")]
+        scale: Option<Resize>,
+
+        /// Scale down any image whose width or height is larger than this, keeping its aspect
+        /// ratio. Images whose dimensions are powers of two are scaled down to powers of two.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        max_dimension: Option<u32>,
+
+        /// The filter to resize the image with when using `--resize`, `--scale` or
+        /// `--max-dimension`.
+        #[arg(long, value_enum, value_name = "FILTER", default_value_t = MipFilter::Lanczos3, requires = "resizing")]
+        resize_filter: MipFilter,
+
+        /// Pad the image up to power-of-two dimensions before encoding it, keeping it at the top
+        /// left. With `--mipmaps`, the image is padded up to a square.
+        #[arg(long)]
+        pad_pot: bool,
+
+        /// The color to fill the padding of `--pad-pot` with, as `RRGGBB` or `RRGGBBAA` in
+        /// hexadecimal. Defaults to transparent.
+        #[arg(long, value_name = "COLOR", value_parser = transform::parse_color, requires = "pad_pot")]
+        pad_color: Option<Rgba<u8>>,
+
+        /// Make the pixels whose alpha is below this value (from 0 to 255) fully transparent, and
+        /// all the others fully opaque, before encoding the image. This is mostly for `dxt1`, which
+        /// can't store partial transparency. Without it, the encoder makes `dxt1` pixels
+        /// transparent when their alpha is below 16.
+        #[arg(long, value_name = "ALPHA")]
+        alpha_threshold: Option<u8>,
+
+        /// Blend the image over this color before encoding it, for data formats that don't store
+        /// the alpha channel. Given as `RRGGBB` in hexadecimal or as a name like `white`. For
+        /// `dxt1`, the pixels that would be fully transparent are left transparent.
+        #[arg(long, value_name = "COLOR", value_parser = transform::parse_color)]
+        background: Option<Rgba<u8>>,
+
+        /// Take the alpha channel of the image from this grayscale image, which has to be the
+        /// same size. White is fully opaque, and black fully transparent.
+        #[arg(long, value_name = "IMAGE", conflicts_with = "out_dir")]
+        alpha_mask: Option<PathBuf>,
+
+        /// Resize the `--alpha-mask` to the size of the image if it doesn't match, instead of
+        /// refusing it.
+        #[arg(long, requires = "alpha_mask")]
+        alpha_mask_resize: bool,
+
+        /// Make every pixel of the image fully opaque before encoding it, keeping its color. This
+        /// comes after `--background`, so it only changes the pixels that weren't blended over it.
+        #[arg(long, conflicts_with = "alpha_mask")]
+        force_opaque: bool,
+
+        /// Where the intensity data formats take the intensity of each pixel from. Any channel
+        /// other than `luma` is used as it is, e.g. for height maps stored in the red channel.
+        #[arg(long, value_enum, value_name = "CHANNEL")]
+        intensity_channel: Option<IntensityChannel>,
+
+        /// How much the red, green and blue channels count towards the intensity of the
+        /// intensity data formats. Either three numbers separated by commas, which are scaled to
+        /// add up to 1, or one of `rec601`, `rec709` and `average`. Defaults to `0.3,0.59,0.11`.
+        #[arg(long, value_name = "R,G,B", value_parser = LumaWeights::parse)]
+        luma_weights: Option<LumaWeights>,
+
+        /// Dither the colors to the ones the data format stores, instead of letting the encoder
+        /// truncate them, which avoids banding in smooth gradients. For `index4` and `index8`,
+        /// the pixels are dithered to the colors of the palette. `argb8888` and `dxt1` ignore
+        /// this with a warning, or refuse it with `--strict`.
+        #[arg(long, value_enum, default_value_t = Dither::None)]
+        dither: Dither,
+
+        /// How much work goes into compressing the blocks of `dxt1`. Defaults to `fast`, which is
+        /// the encoder's own compression. Other data formats aren't compressed, so this is ignored
+        /// with a warning, or refused with `--strict`.
+        #[arg(long, value_enum, value_name = "QUALITY")]
+        dxt_quality: Option<DxtQuality>,
+
+        /// Which implementation fits the end points of the blocks of `dxt1`. `squish` uses the
+        /// fitting of libsquish, to compare against. Ignored like `--dxt-quality` on other data
+        /// formats.
+        #[cfg(feature = "dxt-alt")]
+        #[arg(long, value_enum, value_name = "BACKEND")]
+        dxt_backend: Option<DxtBackend>,
+
+        /// For DDS input files that are already BC1 compressed, take their blocks as they are
+        /// when encoding to `dxt1`, with their mipmap levels for `--mipmaps`, instead of
+        /// compressing the decoded image again. Falls back to compressing it again with a
+        /// warning when the blocks can't be used as they are, like when the image is resized.
+        #[arg(long)]
+        passthrough: bool,
+
+        /// Fail instead of warning when the image would lose information it wasn't asked to, like
+        /// transparent pixels in a data format that doesn't store the alpha channel.
+        #[arg(long)]
+        strict: bool,
+
+        /// Encode images larger than 1024x1024, which the GameCube can't use, e.g. for emulators.
+        #[arg(long)]
+        allow_oversize: bool,
+
+        /// Encode the images as they're stored, instead of rotating or flipping them as given by
+        /// their EXIF orientation, like photos taken on phones have.
+        #[arg(long)]
+        ignore_exif: bool,
+
         /// The magic string to use in the header of the encoded GVR file.
         #[arg(short = 'i', long, value_enum, default_value_t = HeaderId::Gcix)]
         header: HeaderId,
@@ -44,170 +453,4145 @@ enum Commands {
         /// The global index to use in the header of the encoded GVR file.
         #[arg(short, long, default_value_t = 0)]
         global_index: u32,
+
+        /// Decode the texture after encoding it, and print how close it is to the input image:
+        /// the PSNR overall and of each channel, and the SSIM. The alpha channel is only compared
+        /// for the data formats that store it.
+        #[arg(long)]
+        metrics: bool,
+
+        /// Read each texture back from disk after writing it, and check that it's what was
+        /// encoded: that the file wasn't corrupted, that it has the right dimensions and amount of
+        /// mipmap levels, and that every level can be decoded. Textures written to standard
+        /// output aren't checked.
+        #[arg(long)]
+        verify: bool,
+
+        /// Compress each texture file before writing it, for games that load their textures
+        /// compressed.
+        #[arg(long, value_name = "COMPRESSION")]
+        compress: Option<Compression>,
+
+        /// Print the information of the encoded texture as a single JSON object, together with
+        /// the input and output paths, instead of the success message. This is printed to stderr
+        /// when the texture is written to standard output.
+        #[arg(long, conflicts_with = "out_dir")]
+        json: bool,
     },
 
     /// Decodes the given GVR texture file into an image file.
     Decode {
+        /// Input GVR texture file to operate on, followed by the path to where to save the output
+        /// image file to.
+        /// Make sure to include the file extension for the output file, so that it can save the
+        /// image in the given format. Only image formats that support transparency will work.
+        /// Use `-` as the output to write the image to standard output, together with
+        /// `--image-format`.
+        ///
+        /// When `--out-dir` is given, all the paths are input files instead. Directories are
+        /// expanded into all the .gvr files inside of them.
+        #[arg(
+            required_unless_present = "files_from",
+            value_name = "INPUT>... <OUTPUT"
+        )]
+        paths: Vec<PathBuf>,
+
+        /// Decode all the input files into this directory, naming each image file after its
+        /// input file.
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+
+        /// The format to save the decoded images in. Defaults to `png` when using `--out-dir`,
+        /// otherwise the format is derived from the output file extension.
+        #[arg(short = 'f', long, value_enum)]
+        image_format: Option<ImageFormat>,
+
+        /// Allow `--out-dir` to be the same directory the input files are in.
+        #[arg(long)]
+        allow_same_dir: bool,
+
+        /// Treat the input paths literally, instead of expanding glob patterns like `*.gvr`.
+        #[arg(long)]
+        no_glob: bool,
+
+        /// Read more input paths from the given file (or from standard input, if it's `-`), one
+        /// path per line. The paths in the file are always taken literally.
+        #[arg(long, value_name = "PATH", requires = "out_dir")]
+        files_from: Option<PathBuf>,
+
+        /// Separate the paths in the `--files-from` list by NUL bytes instead of newlines.
+        #[arg(short = '0', long, requires = "files_from")]
+        null: bool,
+
+        /// Also decode the GVR files in all the subdirectories of the input directories. The
+        /// directory structure is mirrored in `--out-dir`.
+        #[arg(short, long, requires = "out_dir")]
+        recursive: bool,
+
+        /// The number of files to process in parallel when using `--out-dir`. Defaults to the
+        /// number of logical CPUs.
+        #[arg(short, long)]
+        jobs: Option<NonZeroUsize>,
+
+        /// Check all the inputs and print where each output would be saved to, without writing
+        /// anything.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Same as `--dry-run`, but also do all the work except for writing to disk, which shows
+        /// the size of each output file.
+        #[arg(long)]
+        dry_run_full: bool,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+
+        /// Create the parent directories of the output path if they don't exist yet. Batch mode
+        /// always creates the directories inside of the output directory.
+        #[arg(long)]
+        create_dirs: bool,
+
+        /// Skip input files in batch mode whose output file is newer than the input file.
+        #[arg(long, requires = "out_dir")]
+        if_newer: bool,
+
+        /// Decode this mipmap level of the texture instead of the full-size image, which is level
+        /// 0. Each level is half the size of the previous one.
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        mip_level: usize,
+
+        /// Decode every image level of the texture into its own file, named after the output path
+        /// with the level added to it, as in `output_mip0.png`, `output_mip1.png` and so on.
+        #[arg(long, conflicts_with = "mip_level")]
+        all_mips: bool,
+
+        /// Decode every image level of the texture into a single image, with the full-size image
+        /// first and each smaller level next to the previous one. The space around the smaller
+        /// levels is left transparent.
+        #[arg(long, conflicts_with_all = ["mip_level", "all_mips"])]
+        mip_atlas: bool,
+
+        /// Which way the levels of `--mip-atlas` are laid out.
+        #[arg(long, value_enum, value_name = "LAYOUT", default_value_t = AtlasLayout::Horizontal)]
+        mip_atlas_layout: AtlasLayout,
+
+        /// Mirror the decoded image horizontally. With mipmap levels, each level is mirrored on
+        /// its own.
+        #[arg(long)]
+        flip_x: bool,
+
+        /// Mirror the decoded image vertically. With mipmap levels, each level is mirrored on its
+        /// own.
+        #[arg(long)]
+        flip_y: bool,
+
+        /// Save textures of the `index4` and `index8` data formats as indexed-color PNG files,
+        /// with the colors of the palette in the same order and each pixel keeping its index.
+        /// Encoding such an image with `--palette` set to the image itself gives back the same
+        /// texture data. Other textures are decoded as usual.
+        #[arg(long, conflicts_with = "mip_atlas")]
+        keep_indexed: bool,
+
+        /// Also save the palette of an `index4` or `index8` texture to this image file, as a
+        /// swatch with a square for each color, 16 to a row.
+        #[arg(long, value_name = "FILE", conflicts_with = "out_dir")]
+        palette_out: Option<PathBuf>,
+
+        /// The width and height of each square of the `--palette-out` swatch, in pixels.
+        #[arg(
+            long,
+            value_name = "PIXELS",
+            default_value_t = 16,
+            value_parser = clap::value_parser!(u32).range(1..=256),
+            requires = "palette_out"
+        )]
+        palette_cell: u32,
+
+        /// Print the header information of the decoded texture as a single JSON object, together
+        /// with the input and output paths, instead of the success message. This is printed to
+        /// stderr when the image is written to standard output.
+        #[arg(long, conflicts_with = "out_dir")]
+        json: bool,
+    },
+
+    /// Prints the header information of the given GVR texture file, without decoding it.
+    Info {
+        /// Input GVR texture file to inspect.
+        input: PathBuf,
+
+        /// Print the information as a single JSON object instead. Errors are printed to stderr as
+        /// JSON objects as well.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Checks that the given GVR texture files are valid, by fully decoding them without writing
+    /// anything. Exits with the number of invalid files, up to 125.
+    Validate {
+        /// GVR texture files to check. Directories are expanded into all the GVR files inside of
+        /// them.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Treat the paths literally, instead of expanding glob patterns like `*.gvr`.
+        #[arg(long)]
+        no_glob: bool,
+
+        /// Also check the GVR files in all the subdirectories of the given directories.
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// The number of files to check in parallel. Defaults to the number of logical CPUs.
+        #[arg(short, long)]
+        jobs: Option<NonZeroUsize>,
+    },
+
+    /// Reports anything unusual about a GVR texture file, like trailing bytes or dimensions that
+    /// aren't powers of two. Only exits with an error if the texture is broken.
+    Doctor {
+        /// Input GVR texture file to diagnose.
+        input: PathBuf,
+    },
+
+    /// Checks that the mipmap levels of a GVR texture file match its full-size image, by comparing
+    /// each level to the full-size image scaled down to its size. Exits with an error if any level
+    /// differs by more than the tolerance.
+    CheckMips {
+        /// Input GVR texture file to check.
+        input: PathBuf,
+
+        /// The largest average difference per color channel (from 0 to 255) a level can have
+        /// before it's reported as broken. Defaults to 24, or 32 for the `dxt1` levels smaller
+        /// than 16x16, where its compression is more noticeable.
+        #[arg(short, long, value_name = "N")]
+        tolerance: Option<f64>,
+    },
+
+    /// Compares the pixels of two GVR texture files, by decoding both of them. Exits with 0 if
+    /// they're identical, 1 if they differ (including in their dimensions) and 2 on any error.
+    Compare {
+        /// The first GVR texture file.
+        a: PathBuf,
+
+        /// The second GVR texture file.
+        b: PathBuf,
+
+        /// Save an image showing where the textures differ to this file. The differing pixels are
+        /// red, and the identical ones are dimmed.
+        #[arg(long, value_name = "FILE")]
+        diff_out: Option<PathBuf>,
+    },
+
+    /// Checks that a GVR texture file still matches the image it was made from, by decoding it and
+    /// comparing it to the image. Exits with 0 if no channel of any pixel differs by more than the
+    /// tolerance, 1 if one does (or if the dimensions differ) and 2 on any error.
+    ///
+    /// The image is converted to what the texture can hold first: the intensity formats only
+    /// compare the brightness, and the alpha channel is ignored for the formats that don't store
+    /// it. The colors of fully transparent pixels are never compared.
+    CompareImage {
+        /// The source image file.
+        source: PathBuf,
+
+        /// The GVR texture file made from it.
+        texture: PathBuf,
+
+        /// The largest difference (from 0 to 255) any channel of any pixel can have. This should
+        /// leave room for the quantization of the data format, like 8 for `rgb565`.
+        #[arg(short, long, value_name = "N", default_value_t = 0)]
+        tolerance: u8,
+
+        /// Save an image of the differences to this file, with each channel of each pixel being
+        /// its difference made 16 times larger, so that even small errors are visible.
+        #[arg(long, value_name = "FILE")]
+        diff_out: Option<PathBuf>,
+    },
+
+    /// Finds all the GVR textures in the given directories by their contents, no matter what the
+    /// files are named. Prints one line per texture found.
+    Scan {
+        /// Directories to search through, including all of their subdirectories. Files are
+        /// checked directly.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Add this extension (e.g. `gvr`) to the name of every texture found that doesn't have it
+        /// yet. Files are never renamed over existing files.
+        #[arg(long, value_name = "EXT")]
+        rename_ext: Option<String>,
+
+        /// Print each texture found as a single line of JSON instead.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Finds all the GVR textures embedded anywhere inside of the given file, like an archive or an
+    /// executable, and saves each of them as its own GVR file.
+    Extract {
+        /// The file to search for textures.
+        input: PathBuf,
+
+        /// Save the textures into this directory, named after their offset in the input file
+        /// (e.g. `offset_0x00001A20.gvr`).
+        #[arg(short, long, required = true)]
+        out_dir: PathBuf,
+
+        /// Only print the textures that were found, without saving them.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+    },
+
+    /// Writes a GVR texture into a larger file at the given offset, replacing the texture that's
+    /// there. This is the counterpart of `extract`.
+    Inject {
+        /// The file to write the texture into.
+        container: PathBuf,
+
+        /// The GVR texture file to write.
+        input: PathBuf,
+
+        /// Where the texture to replace starts in the container, in decimal or in hexadecimal
+        /// with a `0x` prefix.
+        #[arg(long, value_parser = inject::parse_offset)]
+        offset: u64,
+
+        /// Allow the new texture to be smaller than the one it replaces, padding it with zeros.
+        /// A larger texture never fits.
+        #[arg(long)]
+        allow_smaller: bool,
+    },
+
+    /// Saves each image of a GameCube TPL file as its own GVR texture file, keeping its data
+    /// format, palette and mipmaps.
+    FromTpl {
+        /// The TPL file to convert.
+        input: PathBuf,
+
+        /// Save the textures into this directory, named after the TPL file and the index of each
+        /// image (e.g. `font_000.gvr`). They get a GCIX header with a global index of 0.
+        #[arg(short, long, required = true)]
+        out_dir: PathBuf,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+    },
+
+    /// Combines the given GVR texture files into a single GameCube TPL file, one image each, keeping
+    /// their data format, palette and mipmaps. This is the counterpart of `from-tpl`.
+    ToTpl {
+        /// GVR texture files to combine, in the order of the images of the TPL file.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Where to save the TPL file to.
+        output: PathBuf,
+    },
+
+    /// Works with GVM archives, which hold the GVR textures of most games that use them.
+    Gvm {
+        #[command(subcommand)]
+        command: GvmCommand,
+    },
+
+    /// Works with AFS archives, the file containers many Dreamcast and GameCube games keep their
+    /// textures in.
+    Afs {
+        #[command(subcommand)]
+        command: AfsCommand,
+    },
+
+    /// Works with the `.one` archives of Sonic Heroes, which hold groups of PRS-compressed files.
+    One {
+        #[command(subcommand)]
+        command: OneCommand,
+    },
+
+    /// Works with PRS, the compression many Sega games store their GVR textures and GVM archives
+    /// with. Most other commands decompress their GVR and GVM inputs on their own, except for
+    /// `inject`, `set-header` and `set-index`, which change the files as they are.
+    Prs {
+        #[command(subcommand)]
+        command: PrsCommand,
+    },
+
+    /// Sets the global index in the header of the given GVR texture files, without touching the
+    /// texture data.
+    SetIndex {
+        /// GVR texture files to change, followed by the new global index, in decimal or in
+        /// hexadecimal with a `0x` prefix. With `--from-filename`, all of these are files.
+        #[arg(required = true, value_name = "FILE>... <INDEX")]
+        args: Vec<PathBuf>,
+
+        /// Take the global index of each file from the last number in its file name (e.g. 42 for
+        /// `stage1_tex042.gvr`), instead of giving one index for all the files.
+        #[arg(long)]
+        from_filename: bool,
+    },
+
+    /// Changes the header of the given GVR texture files between GCIX and GBIX, without touching
+    /// the texture data.
+    SetHeader {
+        /// GVR texture files to change.
+        #[arg(required = true, value_name = "FILE")]
+        paths: Vec<PathBuf>,
+
+        /// The new header.
+        #[arg(value_enum, ignore_case = true)]
+        header: HeaderId,
+    },
+
+    /// Saves the color palette of an `index4` or `index8` GVR texture file, e.g. to edit it and
+    /// encode textures with it again using `encode --palette`.
+    ExportPalette {
+        /// Input GVR texture file to take the palette from.
+        input: PathBuf,
+
+        /// Where to save the palette to. Its format is given by the file extension: an image like
+        /// a `.png` file, with 16 colors in each row, a GIMP `.gpl`, an Adobe `.act` or a JASC
+        /// `.pal` file. Only images keep the alpha channel.
+        output: PathBuf,
+    },
+
+    /// Replaces the color palette of an `index4` or `index8` GVR texture file, keeping the palette
+    /// index of each pixel, e.g. to make another color variant of the texture.
+    ReplacePalette {
+        /// Input GVR texture file whose palette to replace.
+        input: PathBuf,
+
+        /// Where to save the GVR texture file with the new palette to.
+        output: PathBuf,
+
+        /// The new palette, in any of the formats `encode --palette` takes. It needs as many
+        /// colors as the palette of the texture has, which is 16 for `index4` and 256 for
+        /// `index8`. The colors are stored in the pixel format of the texture.
+        #[arg(short, long, value_name = "FILE")]
+        palette: PathBuf,
+    },
+
+    /// Adds mipmaps to a GVR texture file or removes them, keeping all of its other settings.
+    #[command(group(ArgGroup::new("mode").required(true).args(["add", "strip"])))]
+    Mips {
         /// Input GVR texture file to operate on.
         input: PathBuf,
 
-        /// Path to where to save the output image file to.
-        /// Make sure to include the file extension for the file, so that it can save the image in
-        /// the given format. Only image formats that support transparency will work.
+        /// Where to save the new GVR texture file to.
         output: PathBuf,
+
+        /// Add mipmaps to the texture. This re-encodes the texture, so it's only supported by the
+        /// data formats that support mipmaps when encoding.
+        #[arg(long)]
+        add: bool,
+
+        /// Remove the mipmaps from the texture, keeping only the full-size level as it is.
+        #[arg(long)]
+        strip: bool,
     },
-}
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-enum HeaderId {
-    /// Sets the GVR texture's header string to "GCIX".
-    Gcix,
-    /// Sets the GVR texture's header string to "GBIX".
-    Gbix,
-}
+    /// Re-encodes the given GVR texture file in another data format. The header magic and the
+    /// global index are carried over from the original texture, unless they're overridden.
+    Convert {
+        /// Input GVR texture file to operate on.
+        input: PathBuf,
 
-impl std::fmt::Display for HeaderId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Gcix => write!(f, "GCIX"),
-            Self::Gbix => write!(f, "GBIX"),
-        }
-    }
-}
+        /// Where to save the converted GVR texture file to.
+        output: PathBuf,
 
-fn main() -> ExitCode {
-    let cli = Cli::parse();
-    match &cli.command {
-        Commands::Encode {
-            input,
-            output,
-            data_format,
-            pixel_format,
-            mipmaps,
-            header,
-            global_index,
-        } => {
-            // mipmap validation
-            if *mipmaps
-                && matches!(
-                    data_format,
-                    DataFormat::Dxt1 | DataFormat::Rgb565 | DataFormat::Rgb5a3
-                )
-                .not()
-            {
-                let possible_value = data_format.to_possible_value().unwrap();
-                let name = possible_value.get_name();
-                let mut cmd = Cli::command();
-                cmd.error(
-                    ErrorKind::ArgumentConflict,
-                    format!("Can't use mipmaps on the `{name}` data format."),
-                )
-                .exit()
-            }
+        /// The format the image data should be re-encoded in.
+        #[arg(short, long, value_enum)]
+        data_format: DataFormat,
 
-            // encode the texture
-            let encoder_result: Result<TextureEncoder, gvrtex::error::TextureEncodeError>;
-            match data_format {
-                DataFormat::Index4 | DataFormat::Index8 => {
-                    if let HeaderId::Gcix = header {
-                        encoder_result = TextureEncoder::new_gcix_palettized(
-                            (*pixel_format).into(),
-                            (*data_format).into(),
-                        );
-                    } else {
-                        encoder_result = TextureEncoder::new_gbix_palettized(
-                            (*pixel_format).into(),
-                            (*data_format).into(),
-                        );
-                    }
-                }
+        /// The format to use for the color data of the color palette, when using either `index4`
+        /// or `index8` data format. Defaults to the pixel format of the original texture, or to
+        /// `rgb5a3` if it has none.
+        #[arg(short, long, value_enum)]
+        pixel_format: Option<PixelFormat>,
 
-                _ => {
-                    if let HeaderId::Gcix = header {
-                        encoder_result = TextureEncoder::new_gcix((*data_format).into());
-                    } else {
-                        encoder_result = TextureEncoder::new_gbix((*data_format).into());
-                    }
-                }
-            }
+        /// Encode the texture with mipmaps. By default, the converted texture has mipmaps if the
+        /// original one has them and the new data format supports them.
+        #[arg(short, long, conflicts_with = "no_mipmaps")]
+        mipmaps: bool,
 
-            if let Err(e) = encoder_result {
-                ceprintln!("<r!>error:</> while initializing:");
-                eprintln!("  {e}");
-                return ExitCode::FAILURE;
-            }
+        /// Encode the texture without mipmaps, even if the original one has them.
+        #[arg(long)]
+        no_mipmaps: bool,
 
-            let mut encoder = encoder_result.unwrap();
-            if *mipmaps {
-                // guaranteed to not fail
-                encoder = encoder.with_mipmaps().unwrap();
-            }
-            if *global_index > 0 {
-                encoder = encoder.with_global_index(*global_index);
-            }
+        /// The magic string to use in the header, instead of the one of the original texture.
+        #[arg(short = 'i', long, value_enum)]
+        header: Option<HeaderId>,
 
-            let encoded = match encoder.encode(input.to_str().expect("Couldn't parse input path."))
-            {
-                Ok(val) => val,
-                Err(e) => {
-                    ceprintln!("<r!>error:</> while encoding texture:");
-                    eprintln!("  {e}");
-                    return ExitCode::FAILURE;
-                }
-            };
+        /// The global index to use in the header, instead of the one of the original texture.
+        #[arg(short, long)]
+        global_index: Option<u32>,
 
-            if let Err(e) = std::fs::write(
-                output.to_str().expect("Couldn't parse output path."),
-                &encoded,
-            ) {
-                ceprintln!("<r!>error:</> while writing output file:");
-                eprintln!("  {e}");
-                return ExitCode::FAILURE;
-            }
+        /// Mirror the image horizontally before re-encoding it.
+        #[arg(long)]
+        flip_x: bool,
 
-            cprintln!("<g!>success:</> saved encoded texture to:");
-            println!("  {}", output.display());
+        /// Mirror the image vertically before re-encoding it.
+        #[arg(long)]
+        flip_y: bool,
+    },
 
-            println!();
+    /// Lists all the data formats textures can be encoded in, along with what each of them
+    /// supports.
+    Formats {
+        /// Print the formats as a JSON array instead.
+        #[arg(long)]
+        json: bool,
+    },
 
-            cprintln!("<c!>info:</>");
-            println!("  Header: {header}");
-            println!("  Data format: {data_format}");
-            if let DataFormat::Index4 | DataFormat::Index8 = data_format {
-                println!("  Pixel format: {pixel_format}");
-            }
-            println!("  Mipmaps: {mipmaps}");
-            println!("  Global index: {global_index}");
-        }
+    /// Analyzes an image and recommends the data formats to encode it in, best first, along with
+    /// the size of the texture each of them would make.
+    Suggest {
+        /// Input image file to analyze.
+        input: PathBuf,
 
-        Commands::Decode { input, output } => {
-            let mut decoder =
-                TextureDecoder::new(input.to_str().expect("Couldn't parse input path."));
-            if let Err(e) = decoder {
-                ceprintln!("<r!>error:</> while opening input file:");
-                eprintln!("  {e}");
-                return ExitCode::FAILURE;
-            }
+        /// Look at every pixel of large images, instead of only a sample of them. This makes the
+        /// color count exact, but takes longer.
+        #[arg(long)]
+        exact: bool,
 
-            if let Err(e) = decoder.as_mut().unwrap().decode() {
-                ceprintln!("<r!>error:</> while decoding input file:");
-                eprintln!("  {e}");
-                return ExitCode::FAILURE;
-            }
+        /// Print the analysis and the recommendations as a single JSON object instead.
+        #[arg(long)]
+        json: bool,
+    },
 
-            if let Err(e) = decoder
-                .unwrap()
-                .save(output.to_str().expect("Couldn't parse the output path."))
-            {
-                ceprintln!("<r!>error:</> while saving output image:");
-                eprintln!("  {e}");
-                return ExitCode::FAILURE;
-            }
+    /// Prints the size of the GVR texture file an image would make in each data format, smallest
+    /// first, without encoding anything.
+    Estimate {
+        /// Input image file, or the dimensions of the image as `WxH`.
+        input: PathBuf,
 
-            cprintln!("<g!>success:</> saved decoded image to:");
-            println!("  {}", output.display());
-        }
-    }
+        /// Include the mipmaps of the formats that support them.
+        #[arg(short, long)]
+        mipmaps: bool,
 
-    ExitCode::SUCCESS
+        /// Print the sizes as a single JSON object instead.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Prints a script that adds tab completion for gvrtex to the given shell.
+    Completions {
+        /// The shell to generate the completion script for.
+        shell: Shell,
+
+        /// Save the script to this file instead of printing it to standard output.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Prints the man page of gvrtex, generated from its command-line options.
+    Man {
+        /// Save the man pages of gvrtex and of each of its commands (`gvrtex.1`,
+        /// `gvrtex-encode.1`, etc) into this directory, instead of printing the main page to
+        /// standard output.
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AfsCommand {
+    /// Lists the entries of an AFS archive, with what each of them holds.
+    List {
+        /// The AFS archive to list.
+        input: PathBuf,
+    },
+
+    /// Saves the entries of an AFS archive as files of their own.
+    Extract {
+        /// The AFS archive to extract.
+        input: PathBuf,
+
+        /// Save the entries into this directory, named after their entry in the archive. Entries
+        /// without a name, or with one that can't be used as a file name, are named after their
+        /// index and what they hold instead (e.g. `00003.gvr`).
+        #[arg(short, long, required = true)]
+        out_dir: PathBuf,
+
+        /// Only save the entries that are GVR textures, decompressing the PRS-compressed ones.
+        /// Each of them is saved with the `.gvr` extension, and textures stored without a GCIX/GBIX
+        /// header get a GCIX header with a global index of 0.
+        #[arg(long)]
+        only_gvr: bool,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+    },
+}
+
+#[derive(Subcommand)]
+enum OneCommand {
+    /// Lists the files of a `.one` archive, with what each of them holds once decompressed.
+    List {
+        /// The `.one` archive to list.
+        input: PathBuf,
+    },
+
+    /// Saves the GVR textures of a `.one` archive as files of their own, decompressed.
+    Extract {
+        /// The `.one` archive to extract.
+        input: PathBuf,
+
+        /// Save the files into this directory, named after their name in the archive, with the
+        /// `.gvr` extension for the GVR textures. Files without a name, or with one that can't be
+        /// used as a file name, are named after their index and what they hold instead (e.g.
+        /// `003.gvr`).
+        #[arg(short, long, required = true)]
+        out_dir: PathBuf,
+
+        /// Save every file of the archive, not only the GVR textures. These keep their name as it
+        /// is.
+        #[arg(long)]
+        all: bool,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+    },
+}
+
+#[derive(Subcommand)]
+enum PrsCommand {
+    /// Compresses a file with PRS.
+    Compress {
+        /// The file to compress.
+        input: PathBuf,
+
+        /// Where to save the compressed data to.
+        output: PathBuf,
+    },
+
+    /// Decompresses a PRS-compressed file.
+    Decompress {
+        /// The PRS-compressed file.
+        input: PathBuf,
+
+        /// Where to save the decompressed data to.
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum GvmCommand {
+    /// Lists the textures of a GVM archive, without decoding or saving any of them.
+    List {
+        /// The GVM archive to list.
+        input: PathBuf,
+
+        /// Only list the entries whose name matches this glob pattern, ignoring case (e.g.
+        /// `tex_*`). Entries are matched by the name `gvm unpack` saves them as.
+        #[arg(long, value_name = "GLOB", value_parser = gvm::parse_filter)]
+        filter: Option<glob::Pattern>,
+
+        /// Print each entry as a single line of JSON instead.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Saves each texture of a GVM archive as its own GVR texture file.
+    Unpack {
+        /// The GVM archive to unpack.
+        input: PathBuf,
+
+        /// Save the textures into this directory, named after their entry in the archive (e.g.
+        /// `tex_grass.gvr`). Entries without a name, or with one that can't be used as a file name,
+        /// are named after their index instead (e.g. `003.gvr`). Textures stored without a
+        /// GCIX/GBIX header get a GCIX header with the global index of their entry.
+        #[arg(short, long, required = true)]
+        out_dir: PathBuf,
+
+        /// Also decode each texture into a PNG image next to it.
+        #[arg(long)]
+        decode: bool,
+
+        /// What to do when an output file already exists.
+        #[arg(long, value_enum, default_value_t = Overwrite::Force)]
+        overwrite: Overwrite,
+    },
+
+    /// Builds a GVM archive from the given GVR texture files, one entry each, named after the
+    /// files. This is the counterpart of `gvm unpack`.
+    Pack {
+        /// Where to save the GVM archive to.
+        output: PathBuf,
+
+        /// GVR texture files to store in the archive, in that order. The names of the entries are
+        /// the file names without their extension, cut off after 28 characters.
+        #[arg(required_unless_present = "from_dir", conflicts_with = "from_dir")]
+        inputs: Vec<PathBuf>,
+
+        /// Store all the `.gvr` files in this directory instead, sorted by their file names.
+        #[arg(long, value_name = "DIR")]
+        from_dir: Option<PathBuf>,
+
+        /// Copy the order of the entries, their names, the fields they have and whether the
+        /// textures keep their GCIX/GBIX header from this GVM archive, so that the new archive can
+        /// replace it. Each of its entries needs a texture file named the way `gvm unpack` saves
+        /// it.
+        #[arg(long = "match", value_name = "ARCHIVE")]
+        match_archive: Option<PathBuf>,
+
+        /// Compress the archive before writing it, for games that load their archives compressed.
+        #[arg(long, value_name = "COMPRESSION")]
+        compress: Option<Compression>,
+    },
+
+    /// Replaces the texture of a single entry of a GVM archive, leaving all the other entries as
+    /// they are.
+    #[command(group(ArgGroup::new("destination").required(true).args(["in_place", "out"])))]
+    Replace {
+        /// The GVM archive with the entry to replace.
+        input: PathBuf,
+
+        /// The entry to replace, named the way `gvm unpack` saves it, ignoring case (e.g.
+        /// `tex_grass` or `003`).
+        #[arg(long)]
+        name: String,
+
+        /// The GVR texture file to put into the entry. It keeps the global index of the entry, and
+        /// the formats and dimensions in the entry table are updated to the ones of the new
+        /// texture.
+        #[arg(long, value_name = "FILE")]
+        with: PathBuf,
+
+        /// Write the new archive over the original one.
+        #[arg(long)]
+        in_place: bool,
+
+        /// Where to save the new archive to.
+        #[arg(short, long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    term::set_color_choice(cli.color);
+    report::set_error_format(cli.error_format);
+    init_logging(cli.verbose, cli.color);
+    match &cli.command {
+        Commands::Encode {
+            paths,
+            out_dir,
+            no_glob,
+            files_from,
+            null,
+            recursive,
+            jobs,
+            dry_run,
+            dry_run_full,
+            overwrite,
+            create_dirs,
+            if_newer,
+            name_template,
+            watch,
+            data_format,
+            prefer_compressed,
+            pixel_format,
+            quantizer,
+            colors,
+            palette,
+            shared_palette,
+            shared_palette_out,
+            quantizer_iterations,
+            transparent_index,
+            transparent_cutoff,
+            stable_palette,
+            mipmaps,
+            mip_images,
+            mip_count,
+            mip_filter,
+            min_mip_size,
+            crop,
+            flip_x,
+            flip_y,
+            resize,
+            scale,
+            max_dimension,
+            resize_filter,
+            pad_pot,
+            pad_color,
+            alpha_threshold,
+            background,
+            alpha_mask,
+            alpha_mask_resize,
+            force_opaque,
+            intensity_channel,
+            luma_weights,
+            dither,
+            dxt_quality,
+            #[cfg(feature = "dxt-alt")]
+            dxt_backend,
+            passthrough,
+            strict,
+            allow_oversize,
+            ignore_exif,
+            header,
+            global_index,
+            metrics,
+            verify,
+            compress,
+            json,
+        } => {
+            let dry_run = DryRun::from_flags(*dry_run, *dry_run_full);
+            let options = ProcessOptions {
+                dry_run,
+                overwrite: *overwrite,
+                if_newer: *if_newer,
+            };
+            let mipmaps = *mipmaps || !mip_images.is_empty();
+            let fixed_format = data_format.fixed();
+            if let Some(data_format) = fixed_format {
+                check_mipmap_support(mipmaps, data_format);
+            }
+            if *prefer_compressed && fixed_format.is_some() {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "`--prefer-compressed` can only be used with `--data-format auto`.",
+                )
+                .exit()
+            }
+            if let Some(data_format) = fixed_format
+                && pixel_format.is_some()
+                && !data_format.is_indexed()
+            {
+                ignored_option(*strict, "--pixel-format", data_format, NO_PALETTE);
+            }
+            if let Some(data_format) = fixed_format
+                && quantizer.is_some()
+                && !data_format.is_indexed()
+            {
+                ignored_option(*strict, "--quantizer", data_format, NO_PALETTE);
+            }
+            if let Some(data_format) = fixed_format
+                && *stable_palette
+                && !data_format.is_indexed()
+            {
+                ignored_option(*strict, "--stable-palette", data_format, NO_PALETTE);
+            }
+            if let Some(data_format) = fixed_format
+                && let Some(colors) = *colors
+            {
+                if !data_format.is_indexed() {
+                    ignored_option(*strict, "--colors", data_format, NO_PALETTE);
+                } else if colors > data_format.palette_colors() {
+                    let possible_value = data_format.to_possible_value().unwrap();
+                    let name = possible_value.get_name();
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ValueValidation,
+                        format!(
+                            "`--colors` can be at most {} with the `{name}` data format, but is {colors}.",
+                            data_format.palette_colors()
+                        ),
+                    )
+                    .exit()
+                }
+            }
+            let fixed_palette = match palette {
+                Some(path) => match palette::load(path) {
+                    Ok(palette) => Some(palette),
+                    Err(e) => {
+                        let e = Error::Palette(e);
+                        print_error(path, &e);
+                        return e.failure().exit_code();
+                    }
+                },
+                None => None,
+            };
+            if let Some(data_format) = fixed_format
+                && let (Some(path), Some(fixed_palette)) = (palette, &fixed_palette)
+            {
+                let colors = fixed_palette.colors().len();
+                if !data_format.is_indexed() {
+                    ignored_option(*strict, "--palette", data_format, NO_PALETTE);
+                } else if colors > data_format.palette_colors() as usize {
+                    let e = Error::PaletteColors {
+                        colors,
+                        data_format,
+                    };
+                    print_error(path, &e);
+                    return e.failure().exit_code();
+                }
+            }
+            if *shared_palette && !fixed_format.is_some_and(|data_format| data_format.is_indexed())
+            {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "`--shared-palette` can only be used with the `index4` and `index8` data formats.",
+                )
+                .exit()
+            }
+            if let Some(index) = *transparent_index {
+                if let Some(data_format) = fixed_format
+                    && !data_format.is_indexed()
+                {
+                    ignored_option(*strict, "--transparent-index", data_format, NO_PALETTE);
+                } else if *pixel_format == Some(PixelFormat::Rgb565) {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        "`--transparent-index` needs a pixel format with an alpha channel, `rgb5a3` or `intensity-a8`.",
+                    )
+                    .exit()
+                }
+                let entries = fixed_format
+                    .filter(|data_format| data_format.is_indexed())
+                    .map_or(palette::MAX_COLORS as u32, |data_format| {
+                        data_format.palette_colors()
+                    });
+                let used = colors.map_or(entries, |colors| colors.min(entries));
+                if u32::from(index) >= used {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ValueValidation,
+                        format!(
+                            "`--transparent-index` has to be below the {used} colors of the palette, but is {index}."
+                        ),
+                    )
+                    .exit()
+                }
+            }
+            if quantizer_iterations.is_some() && *quantizer != Some(PaletteQuantizer::Kmeans) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "`--quantizer-iterations` can only be used with `--quantizer kmeans`.",
+                )
+                .exit()
+            }
+            if let Some(data_format) = fixed_format
+                && *dither != Dither::None
+                && !Dither::supports(data_format)
+            {
+                ignored_option(*strict, "--dither", data_format, "isn't dithered");
+            }
+            let dxt_options = [
+                dxt_quality.is_some().then_some("--dxt-quality"),
+                #[cfg(feature = "dxt-alt")]
+                dxt_backend.is_some().then_some("--dxt-backend"),
+            ];
+            if let Some(data_format) = fixed_format
+                && data_format != DataFormat::Dxt1
+            {
+                for option in dxt_options.into_iter().flatten() {
+                    ignored_option(*strict, option, data_format, "isn't compressed");
+                }
+            }
+            let pixel_format = pixel_format.unwrap_or(PixelFormat::Rgb5a3);
+            let mip_chain = MipChain {
+                count: mip_count.map(usize::from),
+                filter: *mip_filter,
+                min_size: *min_mip_size,
+            };
+            if mip_chain.is_custom() && !mipmaps {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::MissingRequiredArgument,
+                    "`--mip-count`, `--mip-filter` and `--min-mip-size` can only be used together with `--mipmaps`.",
+                )
+                .exit()
+            }
+
+            let intensity_option = match (intensity_channel, luma_weights) {
+                (Some(_), _) => Some("--intensity-channel"),
+                (None, Some(_)) => Some("--luma-weights"),
+                (None, None) => None,
+            };
+            if let Some(option) = intensity_option
+                && let Some(data_format) = fixed_format
+                && !data_format.is_intensity()
+            {
+                let possible_value = data_format.to_possible_value().unwrap();
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    format!(
+                        "`{option}` can only be used with the intensity data formats, not `{}`.",
+                        possible_value.get_name()
+                    ),
+                )
+                .exit()
+            }
+            if luma_weights.is_some()
+                && intensity_channel.is_some_and(|channel| channel != IntensityChannel::Luma)
+            {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "`--luma-weights` can only be used with `--intensity-channel luma`.",
+                )
+                .exit()
+            }
+
+            let background = match (background, fixed_format) {
+                (Some(_), Some(data_format)) if data_format.keeps_partial_alpha(pixel_format) => {
+                    if !cli.quiet {
+                        ceprintln!(
+                            "<c!>notice:</> ignoring `--background`, as the {} data format stores the alpha channel",
+                            data_format.to_possible_value().unwrap().get_name()
+                        );
+                    }
+                    None
+                }
+                (background, _) => {
+                    background.map(|color| Rgba([color[0], color[1], color[2], 0xFF]))
+                }
+            };
+
+            let settings = EncodeSettings {
+                data_format: fixed_format.unwrap_or(DataFormat::Dxt1),
+                auto_format: fixed_format.is_none().then_some(AutoFormat {
+                    prefer_compressed: *prefer_compressed,
+                }),
+                pixel_format,
+                mipmaps,
+                mip_chain,
+                header: *header,
+                global_index: *global_index,
+                transform: Transform {
+                    crop: *crop,
+                    flip: Flip {
+                        x: *flip_x,
+                        y: *flip_y,
+                    },
+                    resize: resize.or(*scale),
+                    max_dimension: *max_dimension,
+                    resize_filter: *resize_filter,
+                    pad_pot: pad_pot.then(|| pad_color.unwrap_or(Rgba([0, 0, 0, 0]))),
+                    alpha_threshold: *alpha_threshold,
+                    background,
+                    resize_alpha_mask: *alpha_mask_resize,
+                    force_opaque: *force_opaque,
+                },
+                allow_oversize: *allow_oversize,
+                ignore_exif: *ignore_exif,
+                intensity_channel: intensity_channel.unwrap_or_default(),
+                luma_weights: luma_weights.unwrap_or_default(),
+                dither: *dither,
+                quantizer: quantizer.unwrap_or_default(),
+                colors: *colors,
+                palette: fixed_palette,
+                transparent_index: transparent_index.map(|index| TransparentIndex {
+                    index,
+                    cutoff: *transparent_cutoff,
+                }),
+                stable_palette: *stable_palette,
+                dxt_quality: dxt_quality.unwrap_or_default(),
+                #[cfg(feature = "dxt-alt")]
+                dxt_backend: dxt_backend.unwrap_or_default(),
+                #[cfg(not(feature = "dxt-alt"))]
+                dxt_backend: DxtBackend::default(),
+                passthrough: *passthrough,
+                quantizer_iterations: quantizer_iterations.unwrap_or(quantize::DEFAULT_ITERATIONS),
+                strict: *strict,
+                metrics: *metrics,
+                verify: *verify,
+                compression: *compress,
+            };
+
+            let Some(out_dir) = out_dir else {
+                let [input, output] = paths.as_slice() else {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::WrongNumberOfValues,
+                        "Expected an input and an output path. Use `--out-dir` to encode multiple files.",
+                    )
+                    .exit()
+                };
+                let input = match expand_single_input(input, *no_glob) {
+                    Ok(val) => val,
+                    Err(code) => return code,
+                };
+
+                if *verify && stdio::is_stdio(output) && !cli.quiet {
+                    ceprintln!(
+                        "<c!>notice:</> the texture is written to standard output, so it can't be verified"
+                    );
+                }
+                let extra = ExtraInputs {
+                    mip_images,
+                    alpha_mask: alpha_mask.as_deref(),
+                };
+                let mut encoded = None;
+                let result = create_dirs_if(*create_dirs, output, dry_run).and_then(|_| {
+                    batch::process_file(&input, output, options, |sink| {
+                        let result = encode::encode_file(&settings, &input, &extra, output, sink)?;
+                        print_auto_format(&input, &result, cli.quiet);
+                        let size = result.size;
+                        encoded = Some(result);
+                        Ok(size)
+                    })
+                });
+                let size = match result {
+                    Ok(Outcome::Processed(size)) => size,
+                    Ok(Outcome::Skipped | Outcome::UpToDate) => {
+                        print_skipped_output(output);
+                        return ExitCode::SUCCESS;
+                    }
+                    Err(e) => {
+                        print_error(&input, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                let to_stderr = stdio::is_stdio(output);
+                if *json {
+                    let data_format = match &encoded {
+                        Some(encoded) => Some(encoded.data_format),
+                        None => fixed_format,
+                    };
+                    let report = EncodeReport {
+                        input: input.display().to_string(),
+                        output: output.display().to_string(),
+                        data_format,
+                        pixel_format: data_format
+                            .filter(|data_format| data_format.is_indexed())
+                            .map(|_| settings.pixel_format),
+                        mipmaps: settings.mipmaps,
+                        width: encoded.as_ref().map(|encoded| encoded.dimensions.0),
+                        height: encoded.as_ref().map(|encoded| encoded.dimensions.1),
+                        levels: encoded.as_ref().map(|encoded| encoded.levels),
+                        size,
+                        palette_colors: encoded.as_ref().and_then(|encoded| encoded.palette_colors),
+                        metrics: encoded
+                            .as_ref()
+                            .and_then(|encoded| encoded.metrics.as_ref())
+                            .map(MetricsReport::new),
+                        uncompressed_size: encoded
+                            .as_ref()
+                            .and_then(|encoded| encoded.uncompressed_size),
+                    };
+                    if to_stderr {
+                        report::eprint_json(&report);
+                    } else {
+                        report::print_json(&report);
+                    }
+                    return ExitCode::SUCCESS;
+                }
+
+                if cli.quiet && dry_run.is_none() {
+                    return ExitCode::SUCCESS;
+                }
+                print_saved("encoded texture", output, dry_run, size);
+                if cli.quiet {
+                    return ExitCode::SUCCESS;
+                }
+
+                if to_stderr {
+                    eprintln!();
+                } else {
+                    println!();
+                }
+                print_encode_info(&settings, encoded.as_ref(), to_stderr);
+                return ExitCode::SUCCESS;
+            };
+
+            // batch mode
+            if paths.len() > 1 && paths.last().is_some_and(|path| has_extension(path, "gvr")) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "Can't use an output path together with `--out-dir`.",
+                )
+                .exit()
+            }
+            if paths.iter().any(|path| stdio::is_stdio(path)) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "Can't read from standard input together with `--out-dir`.",
+                )
+                .exit()
+            }
+            if !out_dir.is_dir() {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::InvalidValue,
+                    format!(
+                        "The output directory `{}` doesn't exist.",
+                        out_dir.display()
+                    ),
+                )
+                .exit()
+            }
+
+            let paths = match gather_inputs(paths, *no_glob, files_from.as_deref(), *null) {
+                Ok(val) => val,
+                Err(code) => return code,
+            };
+            let inputs = match batch::collect_inputs(&paths, batch::is_image_file, *recursive) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_failure(
+                        "while reading input directory",
+                        ErrorReport::new("read", Failure::Input, None, e),
+                    );
+                    return Failure::Input.exit_code();
+                }
+            };
+
+            let format_value = data_format.to_possible_value().unwrap();
+            let output_path = |index, input: &batch::Input| match name_template {
+                Some(template) => {
+                    let values = TemplateValues {
+                        stem: input.stem(),
+                        extension: input.extension(),
+                        format: format_value.get_name(),
+                        global_index: *global_index,
+                        index,
+                    };
+                    batch::named_output_path(input, out_dir, template.render(&values))
+                }
+                None => batch::output_path(input, out_dir, "gvr"),
+            };
+            let outputs: Vec<_> = inputs
+                .iter()
+                .enumerate()
+                .map(|(index, input)| output_path(index, input))
+                .collect();
+            if let Err(code) = check_collisions(&inputs, &outputs) {
+                return code;
+            }
+
+            // every file is encoded with the same palette, which is built from all of them first
+            let mut shared_settings = settings;
+            if *shared_palette {
+                let paths: Vec<_> = inputs.iter().map(|input| input.path.as_path()).collect();
+                match palette::build_shared(&settings, &paths, *jobs) {
+                    Ok(Some((palette, images))) => {
+                        if !cli.quiet {
+                            ceprintln!(
+                                "<c!>notice:</> built a shared {} color palette from {} image(s)",
+                                palette.colors().len(),
+                                images
+                            );
+                        }
+                        if let Some(path) = shared_palette_out {
+                            if dry_run.is_some() {
+                                cprintln!("<c!>dry run:</> would save the shared palette to:");
+                                println!("  {}", path.display());
+                            } else if let Err(e) = palette::save(path, palette.colors()) {
+                                let e = Error::SavePalette(e);
+                                print_error(path, &e);
+                                return e.failure().exit_code();
+                            }
+                        }
+                        shared_settings.palette = Some(palette);
+                    }
+                    // none of the files can be loaded, so they all fail below
+                    Ok(None) => {}
+                    Err(e) => {
+                        print_failure(
+                            "while building the shared palette",
+                            ErrorReport::new(e.stage(), e.failure(), None, &e),
+                        );
+                        return e.failure().exit_code();
+                    }
+                }
+            }
+
+            let work: Vec<_> = inputs.iter().zip(&outputs).collect();
+            let progress = Progress::new(work.len(), cli.quiet || cli.verbose > 0);
+            let results = batch::run(&work, *jobs, |(input, output)| {
+                progress.start(&input.path);
+                let result = prepare_output_dir(output, dry_run).and_then(|_| {
+                    batch::process_file(&input.path, output, options, |sink| {
+                        let encoded = encode::encode_file(
+                            &shared_settings,
+                            &input.path,
+                            &ExtraInputs::default(),
+                            output,
+                            sink,
+                        )?;
+                        print_auto_format(&input.path, &encoded, cli.quiet);
+                        Ok(encoded.size)
+                    })
+                });
+                progress.inc();
+                result
+            });
+            progress.finish();
+
+            let mut report = BatchReport::default();
+            let mut planned = Vec::new();
+            for ((input, output), result) in work.into_iter().zip(results) {
+                match result {
+                    Ok(Outcome::Processed(size)) => {
+                        planned.push((&input.path, describe_planned(output, size), output));
+                        report.record(&input.path, Ok(()));
+                    }
+                    Ok(Outcome::Skipped) => {
+                        print_skipped_output(output);
+                        report.skip(&input.path);
+                    }
+                    Ok(Outcome::UpToDate) => report.skip(&input.path),
+                    Err(e) => report.record(&input.path, Err(e)),
+                }
+            }
+
+            if dry_run.is_some() {
+                cprintln!(
+                    "<c!>dry run:</> would save {} encoded texture(s) to:",
+                    report.succeeded
+                );
+                println!("  {}", out_dir.display());
+                for (input, notes, output) in &planned {
+                    println!("  {} -> {}{notes}", input.display(), output.display());
+                }
+            } else if !cli.quiet {
+                cprintln!(
+                    "<g!>success:</> saved {} encoded texture(s) to:",
+                    report.succeeded
+                );
+                println!("  {}", out_dir.display());
+            }
+
+            if !cli.quiet {
+                println!();
+                print_encode_info(&settings, None, false);
+                println!();
+            }
+            report.print_summary("encode", cli.quiet);
+            if !*watch {
+                return report.exit_code();
+            }
+
+            if !cli.quiet {
+                println!();
+                cprintln!("<c!>watching</> the input files for changes, press Ctrl-C to stop");
+            }
+            // the files are always encoded again, as that's what they were changed for
+            let options = ProcessOptions {
+                overwrite: Overwrite::Force,
+                if_newer: false,
+                ..options
+            };
+            let mut rebuilt = 0;
+            let mut failed = 0;
+            let mut files = HashSet::new();
+            let result = watch::run(&paths, *recursive, out_dir, batch::is_image_file, |path| {
+                // the inputs are collected again, since a new file can change the index of the
+                // files after it in `--name-template`
+                let inputs = match batch::collect_inputs(&paths, batch::is_image_file, *recursive) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_failure(
+                            "while reading input directory",
+                            ErrorReport::new("read", Failure::Input, None, e),
+                        );
+                        return;
+                    }
+                };
+                let Some((index, input)) = inputs
+                    .iter()
+                    .enumerate()
+                    .find(|(_, input)| input.path.canonicalize().is_ok_and(|input| input == path))
+                else {
+                    return;
+                };
+                let output = output_path(index, input);
+                let result = prepare_output_dir(&output, None).and_then(|_| {
+                    batch::process_file(&input.path, &output, options, |sink| {
+                        let encoded = encode::encode_file(
+                            &shared_settings,
+                            &input.path,
+                            &ExtraInputs::default(),
+                            &output,
+                            sink,
+                        )?;
+                        print_auto_format(&input.path, &encoded, cli.quiet);
+                        Ok(encoded.size)
+                    })
+                });
+                files.insert(input.path.clone());
+                let time = watch::timestamp();
+                match result {
+                    Ok(_) => {
+                        rebuilt += 1;
+                        if !cli.quiet {
+                            cprintln!(
+                                "<c!>[{}]</> rebuilt {} -> {}",
+                                time,
+                                input.path.display(),
+                                output.display()
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        if !report::json_errors() {
+                            ceprintln!(
+                                "<r!>[{}]</> failed to rebuild {}",
+                                time,
+                                input.path.display()
+                            );
+                        }
+                        print_error(&input.path, &e);
+                    }
+                }
+            });
+            if let Err(e) = result {
+                print_failure(
+                    "while watching the input files",
+                    ErrorReport::new("watch", e.failure(), None, &e),
+                );
+                return e.failure().exit_code();
+            }
+
+            if !cli.quiet {
+                println!();
+                cprintln!(
+                    "<c!>summary:</> rebuilt {} time(s), {} failed, across {} file(s)",
+                    rebuilt,
+                    failed,
+                    files.len()
+                );
+            }
+            return ExitCode::SUCCESS;
+        }
+
+        Commands::Decode {
+            paths,
+            out_dir,
+            image_format,
+            allow_same_dir,
+            no_glob,
+            files_from,
+            null,
+            recursive,
+            jobs,
+            dry_run,
+            dry_run_full,
+            overwrite,
+            create_dirs,
+            if_newer,
+            mip_level,
+            all_mips,
+            mip_atlas,
+            mip_atlas_layout,
+            flip_x,
+            flip_y,
+            keep_indexed,
+            palette_out,
+            palette_cell,
+            json,
+        } => {
+            let flip = Flip {
+                x: *flip_x,
+                y: *flip_y,
+            };
+            // DDS and KTX2 files hold every level of the texture, so none of these apply
+            let all_levels_format = match out_dir {
+                Some(_) => image_format.filter(|format| format.image_format().is_none()),
+                None => paths
+                    .last()
+                    .and_then(|output| decode::all_levels_format(output, *image_format)),
+            };
+            if let Some(format) = all_levels_format {
+                let options = [
+                    (*mip_level != 0, "--mip-level"),
+                    (*all_mips, "--all-mips"),
+                    (*mip_atlas, "--mip-atlas"),
+                    (*flip_x, "--flip-x"),
+                    (*flip_y, "--flip-y"),
+                    (*keep_indexed, "--keep-indexed"),
+                ];
+                if let Some((_, option)) = options.into_iter().find(|(given, _)| *given) {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        format!(
+                            "`{option}` can't be used when saving {} files, which hold every image level of the texture.",
+                            format.extension().to_uppercase()
+                        ),
+                    )
+                    .exit()
+                }
+            }
+            if *keep_indexed && image_format.is_some_and(|format| format != ImageFormat::Png) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "`--keep-indexed` can only be used with the `png` image format.",
+                )
+                .exit()
+            }
+            let dry_run = DryRun::from_flags(*dry_run, *dry_run_full);
+            let options = ProcessOptions {
+                dry_run,
+                overwrite: *overwrite,
+                if_newer: *if_newer,
+            };
+            let levels = if *mip_atlas {
+                Levels::Atlas(*mip_atlas_layout)
+            } else {
+                Levels::Single(*mip_level)
+            };
+            let Some(out_dir) = out_dir else {
+                let [input, output] = paths.as_slice() else {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::WrongNumberOfValues,
+                        "Expected an input and an output path. Use `--out-dir` to decode multiple files.",
+                    )
+                    .exit()
+                };
+                if stdio::is_stdio(output) && image_format.is_none() {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::MissingRequiredArgument,
+                        "Writing to standard output requires choosing a format with `--image-format`.",
+                    )
+                    .exit()
+                }
+                if stdio::is_stdio(output) && *all_mips {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::ArgumentConflict,
+                        "Can't write multiple mipmap levels to standard output.",
+                    )
+                    .exit()
+                }
+                let input = match expand_single_input(input, *no_glob) {
+                    Ok(val) => val,
+                    Err(code) => return code,
+                };
+                // the checks and the info below look at the texture itself, decompressed if needed
+                let texture = prs::read(&input);
+                if *keep_indexed {
+                    if image_format.is_none()
+                        && image::ImageFormat::from_path(output).ok()
+                            != Some(image::ImageFormat::Png)
+                    {
+                        let mut cmd = Cli::command();
+                        cmd.error(
+                            ErrorKind::ArgumentConflict,
+                            "`--keep-indexed` can only save .png files.",
+                        )
+                        .exit()
+                    }
+                    if let Ok(texture) = &texture
+                        && let Ok(header) = GvrHeader::parse(texture)
+                        && !header.data_format.is_indexed()
+                    {
+                        ceprintln!(
+                            "<y!>warning:</> ignoring `--keep-indexed`, as the {} data format has no color palette",
+                            header.data_format
+                        );
+                    }
+                }
+                // the palette is read up front, so that textures without one fail before decoding
+                let palette_colors = match (palette_out, &texture) {
+                    (Some(_), Ok(texture)) => match palette::read_texture(texture) {
+                        Ok((_, colors)) => colors,
+                        Err(e) => {
+                            print_error(&input, &e);
+                            return e.failure().exit_code();
+                        }
+                    },
+                    // a texture that can't be read fails while decoding it below
+                    _ => Vec::new(),
+                };
+
+                // with `--all-mips`, the first level stands in for all the files when checking
+                // whether the output exists
+                let saved_path = if *all_mips {
+                    decode::level_path(output, 0)
+                } else {
+                    output.clone()
+                };
+                let mut saved_levels = Vec::new();
+                let result = create_dirs_if(*create_dirs, output, dry_run).and_then(|_| {
+                    batch::process_file(&input, &saved_path, options, |sink| {
+                        if !*all_mips {
+                            return decode::decode_file(
+                                &input,
+                                output,
+                                *image_format,
+                                levels,
+                                flip,
+                                *keep_indexed,
+                                sink,
+                            );
+                        }
+                        saved_levels = decode::decode_all_levels(
+                            &input,
+                            output,
+                            *image_format,
+                            flip,
+                            *keep_indexed,
+                            sink,
+                        )?;
+                        Ok(saved_levels.iter().map(|level| level.size).sum())
+                    })
+                });
+                let size = match result {
+                    Ok(Outcome::Processed(size)) => size,
+                    Ok(Outcome::Skipped | Outcome::UpToDate) => {
+                        print_skipped_output(&saved_path);
+                        return ExitCode::SUCCESS;
+                    }
+                    Err(e) => {
+                        print_error(&input, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+
+                if let Some(path) = palette_out
+                    && dry_run.is_none()
+                    && let Err(e) = palette::swatch(&palette_colors, *palette_cell).save(path)
+                {
+                    let e = Error::SavePalette(PaletteError::Image(e));
+                    print_error(path, &e);
+                    return e.failure().exit_code();
+                }
+
+                let to_stderr = stdio::is_stdio(output);
+                let header = texture.map_err(HeaderError::Io).and_then(|texture| {
+                    let header = GvrHeader::parse(&texture)?;
+                    let file_size = std::fs::metadata(&input)?.len();
+                    Ok((header, file_size))
+                });
+                if *json {
+                    let (header, file_size) = match header {
+                        Ok(val) => val,
+                        Err(e) => {
+                            let failure = Failure::from(&e);
+                            report::eprint_json(&ErrorReport::new(
+                                "read",
+                                failure,
+                                Some(&input),
+                                e,
+                            ));
+                            return failure.exit_code();
+                        }
+                    };
+                    let report = DecodeReport {
+                        input: input.display().to_string(),
+                        output: output.display().to_string(),
+                        texture: InfoReport::new(&header, file_size),
+                        levels: all_mips
+                            .then(|| saved_levels.iter().map(LevelReport::new).collect()),
+                    };
+                    if to_stderr {
+                        report::eprint_json(&report);
+                    } else {
+                        report::print_json(&report);
+                    }
+                    return ExitCode::SUCCESS;
+                }
+
+                if cli.quiet && dry_run.is_none() {
+                    return ExitCode::SUCCESS;
+                }
+                if saved_levels.is_empty() {
+                    print_saved("decoded image", &saved_path, dry_run, size);
+                } else {
+                    print_saved_levels(&saved_levels, dry_run);
+                }
+                if let Some(path) = palette_out
+                    && !to_stderr
+                {
+                    print_saved("palette swatch", path, dry_run, None);
+                }
+                // the texture was decoded just fine, so this can only fail if the file changed
+                // in the meantime
+                if cli.quiet || header.is_err() {
+                    return ExitCode::SUCCESS;
+                }
+
+                if to_stderr {
+                    eprintln!();
+                } else {
+                    println!();
+                }
+                print_decode_info(&header.unwrap().0, to_stderr);
+                return ExitCode::SUCCESS;
+            };
+
+            // batch mode
+            if paths.len() > 1 && paths.last().is_some_and(|path| batch::is_image_file(path)) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "Can't use an output path together with `--out-dir`.",
+                )
+                .exit()
+            }
+            if !out_dir.is_dir() {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::InvalidValue,
+                    format!(
+                        "The output directory `{}` doesn't exist.",
+                        out_dir.display()
+                    ),
+                )
+                .exit()
+            }
+            let paths = match gather_inputs(paths, *no_glob, files_from.as_deref(), *null) {
+                Ok(val) => val,
+                Err(code) => return code,
+            };
+            if !allow_same_dir && batch::is_same_dir(&paths, out_dir) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ArgumentConflict,
+                    "The output directory is the same as the input directory. Pass `--allow-same-dir` if this is intended.",
+                )
+                .exit()
+            }
+
+            let inputs = match batch::collect_inputs(&paths, batch::is_gvr_file, *recursive) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_failure(
+                        "while reading input directory",
+                        ErrorReport::new("read", Failure::Input, None, e),
+                    );
+                    return Failure::Input.exit_code();
+                }
+            };
+
+            let image_format = image_format.unwrap_or(ImageFormat::Png);
+            let mut report = BatchReport::default();
+            let (inputs, skipped): (Vec<_>, Vec<_>) = inputs
+                .into_iter()
+                .partition(|input| batch::is_gvr_file(&input.path));
+            for input in &skipped {
+                ceprintln!("<y!>warning:</> skipping non-GVR file:");
+                eprintln!("  {}", input.path.display());
+                report.skip(&input.path);
+            }
+
+            let outputs: Vec<_> = inputs
+                .iter()
+                .map(|input| batch::output_path(input, out_dir, image_format.extension()))
+                .collect();
+            if let Err(code) = check_collisions(&inputs, &outputs) {
+                return code;
+            }
+
+            let work: Vec<_> = inputs.iter().zip(&outputs).collect();
+            let progress = Progress::new(work.len(), cli.quiet || cli.verbose > 0);
+            let results = batch::run(&work, *jobs, |(input, output)| {
+                progress.start(&input.path);
+                let result = prepare_output_dir(output, dry_run).and_then(|_| {
+                    if *all_mips {
+                        let saved_path = decode::level_path(output, 0);
+                        return batch::process_file(&input.path, &saved_path, options, |sink| {
+                            let levels = decode::decode_all_levels(
+                                &input.path,
+                                output,
+                                Some(image_format),
+                                flip,
+                                *keep_indexed,
+                                sink,
+                            )?;
+                            Ok(levels.iter().map(|level| level.size).sum())
+                        });
+                    }
+                    batch::process_file(&input.path, output, options, |sink| {
+                        decode::decode_file(
+                            &input.path,
+                            output,
+                            Some(image_format),
+                            levels,
+                            flip,
+                            *keep_indexed,
+                            sink,
+                        )
+                    })
+                });
+                progress.inc();
+                result
+            });
+            progress.finish();
+
+            let mut planned = Vec::new();
+            for ((input, output), result) in work.into_iter().zip(results) {
+                match result {
+                    Ok(Outcome::Processed(size)) => {
+                        planned.push((&input.path, describe_planned(output, size), output));
+                        report.record(&input.path, Ok(()));
+                    }
+                    Ok(Outcome::Skipped) => {
+                        print_skipped_output(output);
+                        report.skip(&input.path);
+                    }
+                    Ok(Outcome::UpToDate) => report.skip(&input.path),
+                    Err(e) => report.record(&input.path, Err(e)),
+                }
+            }
+
+            if dry_run.is_some() {
+                cprintln!(
+                    "<c!>dry run:</> would save {} decoded image(s) to:",
+                    report.succeeded
+                );
+                println!("  {}", out_dir.display());
+                for (input, notes, output) in &planned {
+                    println!("  {} -> {}{notes}", input.display(), output.display());
+                }
+            } else if !cli.quiet {
+                cprintln!(
+                    "<g!>success:</> saved {} decoded image(s) to:",
+                    report.succeeded
+                );
+                println!("  {}", out_dir.display());
+            }
+
+            if !cli.quiet {
+                println!();
+            }
+            report.print_summary("decode", cli.quiet);
+            return report.exit_code();
+        }
+
+        Commands::Info { input, json } => {
+            let result = prs::read(input).map_err(HeaderError::Io).and_then(|buffer| {
+                let header = GvrHeader::parse(&buffer)?;
+                let file_size = std::fs::metadata(input)?.len();
+                Ok((header, file_size))
+            });
+            let (header, file_size) = match result {
+                Ok(val) => val,
+                Err(e) => {
+                    let failure = Failure::from(&e);
+                    let report = ErrorReport::new("read", failure, Some(input), e);
+                    if *json {
+                        report::eprint_json(&report);
+                    } else {
+                        print_failure("while reading header", report);
+                    }
+                    return failure.exit_code();
+                }
+            };
+
+            if *json {
+                report::print_json(&InfoReport::new(&header, file_size));
+                return ExitCode::SUCCESS;
+            }
+
+            cprintln!("<c!>info:</> {}", input.display());
+            println!("  Header: {}", header.header_id);
+            println!("  Global index: {}", header.global_index);
+            println!("  Data format: {}", header.data_format);
+            if let Some(pixel_format) = header.pixel_format {
+                println!("  Pixel format: {pixel_format}");
+            }
+            println!("  Dimensions: {}x{}", header.width, header.height);
+            println!("  Mipmaps: {}", header.mipmaps);
+            println!("  Data size: {} bytes", header.data_size);
+            println!("  File size: {file_size} bytes");
+        }
+
+        Commands::Validate {
+            paths,
+            no_glob,
+            recursive,
+            jobs,
+        } => {
+            let paths = match expand_inputs(paths, *no_glob) {
+                Ok(val) => val,
+                Err(code) => return code,
+            };
+            let inputs = match batch::collect_inputs(&paths, batch::is_gvr_file, *recursive) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_failure(
+                        "while reading input directory",
+                        ErrorReport::new("read", Failure::Input, None, e),
+                    );
+                    return Failure::Input.exit_code();
+                }
+            };
+
+            let results = batch::run(&inputs, *jobs, |input| validate::validate_file(&input.path));
+            let mut failed = 0;
+            for (input, result) in inputs.iter().zip(&results) {
+                match result {
+                    Ok(_) => {
+                        if !cli.quiet {
+                            cprintln!("<g!>OK</>   {}", input.path.display());
+                        }
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        if report::json_errors() {
+                            let report =
+                                ErrorReport::new("validate", e.failure(), Some(&input.path), e);
+                            report::eprint_json(&report);
+                        } else {
+                            cprintln!("<r!>FAIL</> {}: {}", input.path.display(), e);
+                        }
+                    }
+                }
+            }
+
+            if !cli.quiet {
+                println!();
+                cprintln!(
+                    "<c!>summary:</> {} valid, {} invalid",
+                    inputs.len() - failed,
+                    failed
+                );
+            }
+            return ExitCode::from(failed.min(125) as u8);
+        }
+
+        Commands::Doctor { input } => {
+            let findings = match prs::read(input) {
+                Ok(buffer) => doctor::diagnose(&buffer),
+                Err(e) => Err(e.into()),
+            };
+            let findings = match findings {
+                Ok(val) => val,
+                Err(e) => {
+                    let failure = Failure::from(&e);
+                    print_failure(
+                        "while reading header",
+                        ErrorReport::new("read", failure, Some(input), e),
+                    );
+                    return failure.exit_code();
+                }
+            };
+
+            cprintln!("<c!>doctor:</> {}", input.display());
+            let shown: Vec<_> = findings
+                .iter()
+                .filter(|finding| !cli.quiet || finding.severity > Severity::Info)
+                .collect();
+            if shown.is_empty() && !cli.quiet {
+                cprintln!("<g!>success:</> no problems found");
+            }
+            for finding in shown {
+                match finding.severity {
+                    Severity::Error => cprintln!("<r!>error:</> {}", finding.message),
+                    Severity::Warning => cprintln!("<y!>warning:</> {}", finding.message),
+                    Severity::Info => cprintln!("<c!>info:</> {}", finding.message),
+                }
+                println!("  {}", finding.explanation);
+            }
+
+            if doctor::has_errors(&findings) {
+                return Failure::Codec.exit_code();
+            }
+        }
+
+        Commands::CheckMips { input, tolerance } => {
+            let checks = match prs::read(input) {
+                Ok(buffer) => mips::check_levels(buffer),
+                Err(e) => Err(Error::Open(e)),
+            };
+            let checks = match checks {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+
+            cprintln!("<c!>check-mips:</> {}", input.display());
+            if checks.is_empty() {
+                cprintln!("<c!>notice:</> the texture has no mipmaps, so there's nothing to check");
+                return ExitCode::SUCCESS;
+            }
+            println!("  LEVEL  SIZE     MEAN ERROR  MAX ERROR  TOLERANCE");
+            let mut failed = 0;
+            for check in &checks {
+                let level_tolerance = tolerance.unwrap_or(check.tolerance);
+                let status = if check.mean_error > level_tolerance {
+                    failed += 1;
+                    cformat!("<r!>FAIL</>")
+                } else {
+                    cformat!("<g!>OK</>")
+                };
+                anstream::println!(
+                    "  {:<5}  {:<7}  {:>10.2}  {:>9}  {:>9}  {status}",
+                    check.level,
+                    format!("{}x{}", check.width, check.height),
+                    check.mean_error,
+                    check.max_error,
+                    level_tolerance
+                );
+            }
+
+            if !cli.quiet {
+                println!();
+                cprintln!(
+                    "<c!>summary:</> {} level(s) checked, {} over their tolerance",
+                    checks.len(),
+                    failed
+                );
+            }
+            if failed > 0 {
+                return Failure::Codec.exit_code();
+            }
+        }
+
+        Commands::Compare { a, b, diff_out } => {
+            let mut images = Vec::with_capacity(2);
+            for input in [a, b] {
+                match compare::load(input) {
+                    Ok((_, image)) => images.push(image),
+                    Err(e) => {
+                        print_error(input, &e);
+                        return ExitCode::from(2);
+                    }
+                }
+            }
+
+            let comparison = compare::compare(&images[0], &images[1]);
+            if let Some(diff_out) = diff_out
+                && !matches!(comparison, Comparison::Dimensions { .. })
+                && let Err(e) =
+                    compare::save_diff(&compare::diff_image(&images[0], &images[1]), diff_out)
+            {
+                print_error(diff_out, &e);
+                return ExitCode::from(2);
+            }
+
+            match &comparison {
+                Comparison::Identical => {
+                    cprintln!("<g!>identical:</> {} and {}", a.display(), b.display());
+                }
+                Comparison::Different(difference) => {
+                    cprintln!("<y!>different:</> {} and {}", a.display(), b.display());
+                    print_difference(difference);
+                }
+                Comparison::Dimensions {
+                    a: (a_width, a_height),
+                    b: (b_width, b_height),
+                } => {
+                    cprintln!("<y!>different:</> {} and {}", a.display(), b.display());
+                    println!("  Dimensions: {a_width}x{a_height} and {b_width}x{b_height}");
+                    if diff_out.is_some() && !cli.quiet {
+                        ceprintln!(
+                            "<c!>notice:</> no diff image was saved, as the pixels can't be compared"
+                        );
+                    }
+                }
+            }
+            if !matches!(comparison, Comparison::Identical) {
+                return ExitCode::from(1);
+            }
+        }
+
+        Commands::CompareImage {
+            source,
+            texture,
+            tolerance,
+            diff_out,
+        } => {
+            let (header, mut decoded) = match compare::load(texture) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(texture, &e);
+                    return ExitCode::from(2);
+                }
+            };
+            let mut converted = match encode::load_image(source, true) {
+                Ok(image) => compare::convert_source(image, &header),
+                Err(e) => {
+                    print_error(source, &e);
+                    return ExitCode::from(2);
+                }
+            };
+            compare::clear_transparent(&mut converted);
+            compare::clear_transparent(&mut decoded);
+
+            let comparison = compare::compare(&converted, &decoded);
+            if let Some(diff_out) = diff_out
+                && !matches!(comparison, Comparison::Dimensions { .. })
+                && let Err(e) =
+                    compare::save_diff(&compare::amplified_diff(&converted, &decoded), diff_out)
+            {
+                print_error(diff_out, &e);
+                return ExitCode::from(2);
+            }
+
+            let names = (source.display(), texture.display());
+            let matches = match &comparison {
+                Comparison::Identical => {
+                    cprintln!("<g!>match:</> {} and {}", names.0, names.1);
+                    true
+                }
+                Comparison::Different(difference) => {
+                    let matches = difference.max_delta.iter().all(|delta| delta <= tolerance);
+                    if matches {
+                        cprintln!("<g!>match:</> {} and {}", names.0, names.1);
+                    } else {
+                        cprintln!("<r!>mismatch:</> {} and {}", names.0, names.1);
+                    }
+                    print_difference(difference);
+                    println!("  Tolerance: {tolerance}");
+                    matches
+                }
+                Comparison::Dimensions {
+                    a: (a_width, a_height),
+                    b: (b_width, b_height),
+                } => {
+                    cprintln!("<r!>mismatch:</> {} and {}", names.0, names.1);
+                    println!("  Dimensions: {a_width}x{a_height} and {b_width}x{b_height}");
+                    if diff_out.is_some() && !cli.quiet {
+                        ceprintln!(
+                            "<c!>notice:</> no diff image was saved, as the pixels can't be compared"
+                        );
+                    }
+                    false
+                }
+            };
+            if !matches {
+                return ExitCode::from(1);
+            }
+        }
+
+        Commands::Scan {
+            paths,
+            rename_ext,
+            json,
+        } => {
+            let files = match batch::collect_inputs(paths, |_| true, true) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_failure(
+                        "while reading input directory",
+                        ErrorReport::new("read", Failure::Input, None, e),
+                    );
+                    return Failure::Input.exit_code();
+                }
+            };
+
+            let mut failed = false;
+            for file in &files {
+                let path = &file.path;
+                let hit = match scan::sniff(path) {
+                    Ok(Some(hit)) => hit,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        failed = true;
+                        print_error(path, &Error::Open(e));
+                        continue;
+                    }
+                };
+
+                let mut renamed_to = None;
+                if let (Some(ext), Ok(_)) = (rename_ext, &hit.header)
+                    && !has_extension(path, ext)
+                {
+                    let mut target = path.clone().into_os_string();
+                    target.push(".");
+                    target.push(ext);
+                    let target = PathBuf::from(target);
+                    match output::rename_no_clobber(path, &target) {
+                        Ok(()) => renamed_to = Some(target),
+                        Err(Error::OutputExists(target)) => {
+                            ceprintln!("<y!>warning:</> not renaming, the file already exists:");
+                            eprintln!("  {}", target.display());
+                        }
+                        Err(e) => {
+                            failed = true;
+                            print_error(path, &e);
+                        }
+                    }
+                }
+
+                if *json {
+                    report::print_json(&ScanReport {
+                        path: path.display().to_string(),
+                        magic: hit.magic,
+                        texture: hit.header.as_ref().ok().map(TextureReport::new),
+                        error: hit.header.as_ref().err().map(ToString::to_string),
+                        renamed_to: renamed_to.map(|path| path.display().to_string()),
+                    });
+                    continue;
+                }
+
+                let description = match &hit.header {
+                    Ok(header) => format!(
+                        "{}x{} {}{}",
+                        header.width,
+                        header.height,
+                        header.data_format,
+                        if header.mipmaps { " with mipmaps" } else { "" }
+                    ),
+                    Err(e) => format!("invalid header: {e}"),
+                };
+                match renamed_to {
+                    Some(target) => println!(
+                        "{}: {} {description} (renamed to {})",
+                        path.display(),
+                        hit.magic,
+                        target.display()
+                    ),
+                    None => println!("{}: {} {description}", path.display(), hit.magic),
+                }
+            }
+
+            if failed {
+                return Failure::Batch.exit_code();
+            }
+        }
+
+        Commands::Extract {
+            input,
+            out_dir,
+            dry_run,
+            overwrite,
+        } => {
+            let textures = match extract::find_textures(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            if textures.is_empty() {
+                ceprintln!("<y!>warning:</> no GVR textures found in:");
+                eprintln!("  {}", input.display());
+                return ExitCode::SUCCESS;
+            }
+
+            cprintln!(
+                "<s>{:<10}  {:>8}  {:<26}  {}</>",
+                "OFFSET",
+                "SIZE",
+                "FORMAT",
+                "DIMENSIONS"
+            );
+            for texture in &textures {
+                println!(
+                    "0x{:08X}  {:>8}  {:<26}  {}x{}",
+                    texture.offset,
+                    texture.size,
+                    texture.header.data_format.to_string(),
+                    texture.header.width,
+                    texture.header.height
+                );
+            }
+            println!();
+
+            if *dry_run {
+                cprintln!(
+                    "<c!>dry run:</> would save {} texture(s) to:",
+                    textures.len()
+                );
+                println!("  {}", out_dir.display());
+                return ExitCode::SUCCESS;
+            }
+
+            let mut report = BatchReport::default();
+            for texture in &textures {
+                let output = out_dir.join(format!("offset_0x{:08X}.gvr", texture.offset));
+                if *overwrite == Overwrite::Skip && output.exists() {
+                    print_skipped_output(&output);
+                    report.skip(&output);
+                    continue;
+                }
+                let result = output::create_parent_dir(&output)
+                    .and_then(|_| extract::read_texture(input, texture).map_err(Error::Open))
+                    .and_then(|data| output::Sink::Disk(*overwrite).write(&output, &data))
+                    .map(|_| ());
+                report.record(&output, result);
+            }
+
+            if !cli.quiet {
+                cprintln!("<g!>success:</> saved {} texture(s) to:", report.succeeded);
+                println!("  {}", out_dir.display());
+                println!();
+            }
+            report.print_summary("extract", cli.quiet);
+            return report.exit_code();
+        }
+
+        Commands::Inject {
+            container,
+            input,
+            offset,
+            allow_smaller,
+        } => {
+            let texture = match std::fs::read(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            let patch = match inject::prepare(container, *offset, &texture, *allow_smaller) {
+                Ok(val) => val,
+                Err(e) => {
+                    let path = match e {
+                        inject::InjectError::Texture(_) => input,
+                        _ => container,
+                    };
+                    print_failure(
+                        "while checking the texture to inject",
+                        ErrorReport::new("read", e.failure(), Some(path), &e),
+                    );
+                    return e.failure().exit_code();
+                }
+            };
+
+            if let Err(e) = output::patch_file(container, *offset, &patch.data) {
+                print_error(container, &e);
+                return e.failure().exit_code();
+            }
+            if !cli.quiet {
+                cprintln!("<g!>success:</> injected texture into:");
+                println!("  {}", container.display());
+                match patch.padding {
+                    0 => println!("  at offset 0x{offset:X}, {} bytes", patch.data.len()),
+                    padding => println!(
+                        "  at offset 0x{offset:X}, {} bytes (padded with {padding} zeros)",
+                        patch.data.len()
+                    ),
+                }
+            }
+        }
+
+        Commands::FromTpl {
+            input,
+            out_dir,
+            overwrite,
+        } => {
+            let buffer = match std::fs::read(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            let images = match tpl::parse(&buffer) {
+                Ok(val) => val,
+                Err(e) => {
+                    let e = Error::Tpl(e);
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+            if images.is_empty() {
+                ceprintln!("<y!>warning:</> no images found in:");
+                eprintln!("  {}", input.display());
+                return ExitCode::SUCCESS;
+            }
+
+            cprintln!(
+                "<s>{:<5}  {:<26}  {:<10}  {}</>",
+                "INDEX",
+                "FORMAT",
+                "DIMENSIONS",
+                "LEVELS"
+            );
+            for (index, image) in images.iter().enumerate() {
+                println!(
+                    "{:<5}  {:<26}  {:<10}  {}",
+                    index,
+                    image.format.to_string(),
+                    format!("{}x{}", image.width, image.height),
+                    image.levels
+                );
+            }
+            println!();
+
+            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            let mut report = BatchReport::default();
+            for (index, image) in images.iter().enumerate() {
+                let output = out_dir.join(format!("{stem}_{index:03}.gvr"));
+                if *overwrite == Overwrite::Skip && output.exists() {
+                    print_skipped_output(&output);
+                    report.skip(&output);
+                    continue;
+                }
+                if image.format == TplFormat::C14x2 {
+                    ceprintln!(
+                        "<y!>warning:</> image {} is C14X2, which GVR textures can't store, so it's re-encoded as RGB5A3 without its mipmaps:",
+                        index
+                    );
+                    eprintln!("  {}", output.display());
+                }
+                let result = output::create_parent_dir(&output)
+                    .and_then(|_| image.to_gvr())
+                    .and_then(|gvr| output::Sink::Disk(*overwrite).write(&output, &gvr))
+                    .map(|_| ());
+                report.record(&output, result);
+            }
+
+            if !cli.quiet {
+                cprintln!("<g!>success:</> saved {} texture(s) to:", report.succeeded);
+                println!("  {}", out_dir.display());
+                println!();
+            }
+            report.print_summary("convert", cli.quiet);
+            return report.exit_code();
+        }
+
+        Commands::ToTpl { inputs, output } => {
+            let mut buffers = Vec::with_capacity(inputs.len());
+            for input in inputs {
+                match prs::read(input) {
+                    Ok(val) => buffers.push(val),
+                    Err(e) => {
+                        print_error(input, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                }
+            }
+            let mut images = Vec::with_capacity(inputs.len());
+            for (input, buffer) in inputs.iter().zip(&buffers) {
+                match TplImage::from_gvr(buffer) {
+                    Ok(val) => images.push(val),
+                    Err(e) => {
+                        print_error(input, &e);
+                        return e.failure().exit_code();
+                    }
+                }
+            }
+
+            let size =
+                match output::Sink::Disk(Overwrite::Force).write(output, &tpl::write(&images)) {
+                    Ok(size) => size,
+                    Err(e) => {
+                        print_error(output, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+            if !cli.quiet {
+                print_saved("TPL file", output, None, Some(size));
+                println!();
+                cprintln!("<c!>info:</>");
+                println!("  Images: {}", images.len());
+                println!(
+                    "  With mipmaps: {}",
+                    images.iter().filter(|image| image.levels > 1).count()
+                );
+            }
+        }
+
+        Commands::Gvm { command } => match command {
+            GvmCommand::List {
+                input,
+                filter,
+                json,
+            } => {
+                let buffer = match prs::read(input) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(input, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                };
+                let archive = match gvm::parse(&buffer) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        let e = Error::Gvm(e);
+                        print_error(input, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+
+                let stems = gvm::file_stems(&archive.entries);
+                let listed: Vec<_> = archive
+                    .entries
+                    .iter()
+                    .zip(stems)
+                    .enumerate()
+                    .filter(|(_, (_, stem))| {
+                        filter
+                            .as_ref()
+                            .is_none_or(|filter| gvm::matches_filter(filter, stem))
+                    })
+                    .collect();
+                if *json {
+                    for (index, (entry, stem)) in listed {
+                        report::print_json(&GvmEntryReport {
+                            index,
+                            name: entry.name(),
+                            file_stem: stem,
+                            global_index: entry.texture_global_index(),
+                            texture: TextureReport::new(&entry.header),
+                            size: entry.texture.len(),
+                        });
+                    }
+                    return ExitCode::SUCCESS;
+                }
+
+                cprintln!(
+                    "<s>{:<5}  {:<28}  {:>12}  {:<26}  {:<10}  {:>8}</>",
+                    "INDEX",
+                    "NAME",
+                    "GLOBAL INDEX",
+                    "FORMAT",
+                    "DIMENSIONS",
+                    "SIZE"
+                );
+                for (index, (entry, _)) in &listed {
+                    let header = &entry.header;
+                    println!(
+                        "{:<5}  {:<28}  {:>12}  {:<26}  {:<10}  {:>8}",
+                        index,
+                        entry.name().unwrap_or_else(|| "-".to_string()),
+                        entry
+                            .texture_global_index()
+                            .map_or_else(|| "-".to_string(), |index| index.to_string()),
+                        header.data_format.to_string(),
+                        format!("{}x{}", header.width, header.height),
+                        entry.texture.len()
+                    );
+                }
+                if !cli.quiet {
+                    println!();
+                    match filter {
+                        Some(_) => {
+                            println!("{} of {} entries", listed.len(), archive.entries.len())
+                        }
+                        None => println!("{} entries", archive.entries.len()),
+                    }
+                }
+            }
+
+            GvmCommand::Unpack {
+                input,
+                out_dir,
+                decode,
+                overwrite,
+            } => {
+                let buffer = match prs::read(input) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(input, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                };
+                let archive = match gvm::parse(&buffer) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        let e = Error::Gvm(e);
+                        print_error(input, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                if archive.entries.is_empty() {
+                    ceprintln!("<y!>warning:</> no textures found in:");
+                    eprintln!("  {}", input.display());
+                    return ExitCode::SUCCESS;
+                }
+
+                let mut report = BatchReport::default();
+                let stems = gvm::file_stems(&archive.entries);
+                for (entry, stem) in archive.entries.iter().zip(stems) {
+                    let output = out_dir.join(format!("{stem}.gvr"));
+                    if *overwrite == Overwrite::Skip && output.exists() {
+                        print_skipped_output(&output);
+                        report.skip(&output);
+                        continue;
+                    }
+                    let sink = output::Sink::Disk(*overwrite);
+                    let result = output::create_parent_dir(&output)
+                        .and_then(|_| sink.write(&output, &entry.to_gvr()))
+                        .and_then(|_| match decode {
+                            true => decode::decode_file(
+                                &output,
+                                &output.with_extension("png"),
+                                Some(ImageFormat::Png),
+                                Levels::Single(0),
+                                Flip::default(),
+                                false,
+                                sink,
+                            ),
+                            false => Ok(0),
+                        })
+                        .map(|_| ());
+                    report.record(&output, result);
+                }
+
+                if !cli.quiet {
+                    cprintln!("<g!>success:</> saved {} texture(s) to:", report.succeeded);
+                    println!("  {}", out_dir.display());
+                    println!();
+                }
+                report.print_summary("unpack", cli.quiet);
+                return report.exit_code();
+            }
+
+            GvmCommand::Pack {
+                output,
+                inputs,
+                from_dir,
+                match_archive,
+                compress,
+            } => {
+                let inputs = match from_dir {
+                    Some(dir) => match gvr_files_in(dir) {
+                        Ok(val) => val,
+                        Err(e) => {
+                            print_error(dir, &Error::Open(e));
+                            return Failure::Input.exit_code();
+                        }
+                    },
+                    None => inputs.clone(),
+                };
+                if inputs.len() > usize::from(u16::MAX) {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::TooManyValues,
+                        format!(
+                            "Got {} textures, but GVM archives can only hold up to {}.",
+                            inputs.len(),
+                            u16::MAX
+                        ),
+                    )
+                    .exit()
+                }
+                let mut buffers = Vec::with_capacity(inputs.len());
+                for input in &inputs {
+                    match prs::read(input) {
+                        Ok(val) => buffers.push(val),
+                        Err(e) => {
+                            print_error(input, &Error::Open(e));
+                            return Failure::Input.exit_code();
+                        }
+                    }
+                }
+                let reference_buffer = match match_archive {
+                    Some(path) => match prs::read(path) {
+                        Ok(val) => val,
+                        Err(e) => {
+                            print_error(path, &Error::Open(e));
+                            return Failure::Input.exit_code();
+                        }
+                    },
+                    None => Vec::new(),
+                };
+                let reference = match match_archive {
+                    Some(path) => match gvm::parse(&reference_buffer) {
+                        Ok(val) => Some(val),
+                        Err(e) => {
+                            let e = Error::Gvm(e);
+                            print_error(path, &e);
+                            return e.failure().exit_code();
+                        }
+                    },
+                    None => None,
+                };
+
+                let stems: Vec<_> = inputs
+                    .iter()
+                    .map(|input| input.file_stem().unwrap_or_default().to_string_lossy())
+                    .collect();
+                // the index of the input file and the name of each entry, in the order of the
+                // archive
+                let mut order = Vec::with_capacity(inputs.len());
+                match (&reference, match_archive) {
+                    (Some(reference), Some(path)) => {
+                        let mut unused: Vec<_> = (0..inputs.len()).collect();
+                        let entry_stems = gvm::file_stems(&reference.entries);
+                        for (entry, entry_stem) in reference.entries.iter().zip(&entry_stems) {
+                            let Some(position) = unused
+                                .iter()
+                                .position(|&i| stems[i].eq_ignore_ascii_case(entry_stem))
+                            else {
+                                print_failure(
+                                    "while matching the reference archive",
+                                    ErrorReport::new(
+                                        "read",
+                                        Failure::Usage,
+                                        Some(path),
+                                        format!(
+                                            "The archive has an entry saved as `{entry_stem}.gvr`, but none of the texture files is named like it."
+                                        ),
+                                    ),
+                                );
+                                return Failure::Usage.exit_code();
+                            };
+                            order.push((unused.remove(position), entry.name.unwrap_or_default()));
+                        }
+                        if let Some(&extra) = unused.first() {
+                            print_failure(
+                                "while matching the reference archive",
+                                ErrorReport::new(
+                                    "read",
+                                    Failure::Usage,
+                                    Some(&inputs[extra]),
+                                    "The archive has no entry named like this texture file.",
+                                ),
+                            );
+                            return Failure::Usage.exit_code();
+                        }
+                    }
+                    _ => {
+                        for (index, (input, stem)) in inputs.iter().zip(&stems).enumerate() {
+                            let Some(name) = gvm::entry_name(stem) else {
+                                print_failure(
+                                    "while naming the entries",
+                                    ErrorReport::new(
+                                        "read",
+                                        Failure::Usage,
+                                        Some(input),
+                                        "The file name has characters that aren't ASCII, which the names of GVM entries can't have.",
+                                    ),
+                                );
+                                return Failure::Usage.exit_code();
+                            };
+                            if stem.len() > gvm::NAME_SIZE {
+                                ceprintln!(
+                                    "<y!>warning:</> the entry name is cut off after {} characters:",
+                                    gvm::NAME_SIZE
+                                );
+                                eprintln!("  {}", input.display());
+                            }
+                            order.push((index, name));
+                        }
+                    }
+                }
+
+                let mut entries = Vec::with_capacity(order.len());
+                for (index, name) in order {
+                    match gvm::PackEntry::new(name, &buffers[index]) {
+                        Ok(val) => entries.push(val),
+                        Err(e) => {
+                            print_error(&inputs[index], &e);
+                            return e.failure().exit_code();
+                        }
+                    }
+                }
+                let (flags, bare) = match &reference {
+                    Some(reference) => (
+                        reference.flags,
+                        reference.entries.first().is_none_or(|entry| entry.bare),
+                    ),
+                    None => (gvm::FLAGS_ALL, true),
+                };
+
+                let archive = gvm::write(&entries, flags, bare);
+                let compressed = compress.map(|compression| compression.compress(&archive));
+                let file = compressed.as_deref().unwrap_or(&archive);
+                let size = match output::Sink::Disk(Overwrite::Force).write(output, file) {
+                    Ok(size) => size,
+                    Err(e) => {
+                        print_error(output, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                if !cli.quiet {
+                    print_saved("GVM archive", output, None, Some(size));
+                    println!();
+                    cprintln!("<c!>info:</>");
+                    println!("  Entries: {}", entries.len());
+                    if let Some(compression) = compress {
+                        println!(
+                            "  Compression: {compression} ({} -> {size} bytes)",
+                            archive.len()
+                        );
+                    }
+                }
+            }
+
+            GvmCommand::Replace {
+                input,
+                name,
+                with,
+                in_place,
+                out,
+            } => {
+                let (buffer, compression) = match prs::read_compressed(input) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(input, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                };
+                let archive = match gvm::parse(&buffer) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        let e = Error::Gvm(e);
+                        print_error(input, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                let stems = gvm::file_stems(&archive.entries);
+                let Some(index) = stems
+                    .iter()
+                    .position(|stem| stem.eq_ignore_ascii_case(name))
+                else {
+                    print_failure(
+                        "while finding the entry to replace",
+                        ErrorReport::new(
+                            "read",
+                            Failure::Usage,
+                            Some(input),
+                            format!(
+                                "The archive has no entry named `{name}`. Use `gvm list` to see the names of its entries."
+                            ),
+                        ),
+                    );
+                    return Failure::Usage.exit_code();
+                };
+                let texture_buffer = match prs::read(with) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(with, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                };
+                let texture = match gvm::PackEntry::new([0; gvm::NAME_SIZE], &texture_buffer) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(with, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+
+                let old = &archive.entries[index].header;
+                let new = &texture.header;
+                if (old.pixel_format, old.data_format) != (new.pixel_format, new.data_format) {
+                    let describe = |header: &GvrHeader| match header.pixel_format {
+                        Some(pixel_format) => format!("{} ({pixel_format})", header.data_format),
+                        None => header.data_format.to_string(),
+                    };
+                    ceprintln!(
+                        "<y!>warning:</> the entry changes its format from {} to {}.",
+                        describe(old),
+                        describe(new)
+                    );
+                }
+                if (old.width, old.height) != (new.width, new.height) {
+                    ceprintln!(
+                        "<y!>warning:</> the entry changes its dimensions from {}x{} to {}x{}.",
+                        old.width,
+                        old.height,
+                        new.width,
+                        new.height
+                    );
+                }
+
+                let (replaced, texture_size) = gvm::replace(&buffer, &archive, index, &texture);
+                // a compressed archive is saved compressed again, the way the game loads it
+                let compressed = compression.map(|compression| compression.compress(&replaced));
+                let file = compressed.as_deref().unwrap_or(&replaced);
+                // clap makes sure that exactly one of them is given
+                let output = match (in_place, out) {
+                    (true, _) => input,
+                    (false, Some(out)) => out,
+                    (false, None) => unreachable!(),
+                };
+                let size = match output::Sink::Disk(Overwrite::Force).write(output, file) {
+                    Ok(size) => size,
+                    Err(e) => {
+                        print_error(output, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                if !cli.quiet {
+                    print_saved("GVM archive", output, None, Some(size));
+                    println!();
+                    cprintln!("<c!>info:</>");
+                    // numbered like the `INDEX` column of `gvm list`
+                    println!("  Entry: {index} ({})", stems[index]);
+                    println!(
+                        "  Texture: {} -> {texture_size} bytes",
+                        archive.entries[index].texture.len()
+                    );
+                    if let Some(compression) = compression {
+                        println!(
+                            "  Compression: {compression} ({} -> {size} bytes)",
+                            replaced.len()
+                        );
+                    }
+                }
+            }
+        },
+
+        Commands::Afs { command } => {
+            let input = match command {
+                AfsCommand::List { input } | AfsCommand::Extract { input, .. } => input,
+            };
+            let buffer = match std::fs::read(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            let entries = match afs::parse(&buffer) {
+                Ok(val) => val,
+                Err(e) => {
+                    let e = Error::Afs(e);
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+
+            match command {
+                AfsCommand::List { .. } => {
+                    cprintln!(
+                        "<s>{:<5}  {:<32}  {:>10}  {:>10}  {}</>",
+                        "INDEX",
+                        "NAME",
+                        "OFFSET",
+                        "SIZE",
+                        "CONTENTS"
+                    );
+                    for (index, entry) in entries.iter().enumerate() {
+                        let contents = match contents::Contents::of(entry.data) {
+                            (contents, true) => format!("{contents} (PRS)"),
+                            (contents, false) => contents.to_string(),
+                        };
+                        println!(
+                            "{:<5}  {:<32}  {:>10}  {:>10}  {}",
+                            index,
+                            entry.name.as_deref().unwrap_or("-"),
+                            format!("0x{:X}", entry.offset),
+                            entry.data.len(),
+                            contents
+                        );
+                    }
+                    if !cli.quiet {
+                        println!();
+                        println!("{} entries", entries.len());
+                    }
+                }
+
+                AfsCommand::Extract {
+                    input: _,
+                    out_dir,
+                    only_gvr,
+                    overwrite,
+                } => {
+                    let has_gvr = |entry: &afs::AfsEntry| {
+                        contents::Contents::of(entry.data).0 == contents::Contents::Gvr
+                    };
+                    if *only_gvr && !entries.iter().any(has_gvr) {
+                        ceprintln!("<y!>warning:</> no textures found in:");
+                        eprintln!("  {}", input.display());
+                        return ExitCode::SUCCESS;
+                    }
+
+                    let mut report = BatchReport::default();
+                    let names = afs::file_names(&entries, only_gvr.then_some("gvr"));
+                    for (entry, name) in entries.iter().zip(names) {
+                        let output = out_dir.join(name);
+                        let data = match only_gvr {
+                            true => match contents::gvr_file(entry.data) {
+                                Ok(Some(gvr)) => Cow::Owned(gvr),
+                                Ok(None) => continue,
+                                Err(e) => {
+                                    report.record(&output, Err(Error::Prs(e)));
+                                    continue;
+                                }
+                            },
+                            false => Cow::Borrowed(entry.data),
+                        };
+                        if *overwrite == Overwrite::Skip && output.exists() {
+                            print_skipped_output(&output);
+                            report.skip(&output);
+                            continue;
+                        }
+                        let result = output::create_parent_dir(&output)
+                            .and_then(|_| output::Sink::Disk(*overwrite).write(&output, &data))
+                            .map(|_| ());
+                        report.record(&output, result);
+                    }
+                    if !cli.quiet {
+                        cprintln!("<g!>success:</> saved {} file(s) to:", report.succeeded);
+                        println!("  {}", out_dir.display());
+                        println!();
+                    }
+                    report.print_summary("extract", cli.quiet);
+                    return report.exit_code();
+                }
+            }
+        }
+
+        Commands::One { command } => {
+            let input = match command {
+                OneCommand::List { input } | OneCommand::Extract { input, .. } => input,
+            };
+            let buffer = match std::fs::read(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            let entries = match one::parse(&buffer) {
+                Ok(val) => val,
+                Err(e) => {
+                    let e = Error::One(e);
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+
+            match command {
+                OneCommand::List { .. } => {
+                    cprintln!(
+                        "<s>{:<5}  {:<32}  {:>10}  {:>10}  {:>10}  {}</>",
+                        "INDEX",
+                        "NAME",
+                        "OFFSET",
+                        "COMPRESSED",
+                        "SIZE",
+                        "CONTENTS"
+                    );
+                    for (index, entry) in entries.iter().enumerate() {
+                        let (size, contents) = match prs::decompress(entry.data) {
+                            Ok(data) => (
+                                data.len().to_string(),
+                                contents::Contents::of(&data).0.to_string(),
+                            ),
+                            Err(_) => ("-".to_string(), "invalid PRS data".to_string()),
+                        };
+                        println!(
+                            "{:<5}  {:<32}  {:>10}  {:>10}  {:>10}  {}",
+                            index,
+                            entry.name.as_deref().unwrap_or("-"),
+                            format!("0x{:X}", entry.offset),
+                            entry.data.len(),
+                            size,
+                            contents
+                        );
+                    }
+                    if !cli.quiet {
+                        println!();
+                        println!("{} files", entries.len());
+                    }
+                }
+
+                OneCommand::Extract {
+                    input: _,
+                    out_dir,
+                    all,
+                    overwrite,
+                } => {
+                    let has_gvr = |entry: &one::OneEntry| {
+                        contents::Contents::of(entry.data).0 == contents::Contents::Gvr
+                    };
+                    if !*all && !entries.iter().any(has_gvr) {
+                        ceprintln!("<y!>warning:</> no textures found in:");
+                        eprintln!("  {}", input.display());
+                        return ExitCode::SUCCESS;
+                    }
+
+                    let mut report = BatchReport::default();
+                    let names = one::file_names(&entries, (!*all).then_some("gvr"));
+                    for (entry, name) in entries.iter().zip(names) {
+                        let output = out_dir.join(name);
+                        let result = match all {
+                            true => prs::decompress(entry.data).map(Some),
+                            false => contents::gvr_file(entry.data),
+                        };
+                        let data = match result {
+                            Ok(Some(data)) => data,
+                            Ok(None) => continue,
+                            Err(e) => {
+                                report.record(&output, Err(Error::Prs(e)));
+                                continue;
+                            }
+                        };
+                        if *overwrite == Overwrite::Skip && output.exists() {
+                            print_skipped_output(&output);
+                            report.skip(&output);
+                            continue;
+                        }
+                        let result = output::create_parent_dir(&output)
+                            .and_then(|_| output::Sink::Disk(*overwrite).write(&output, &data))
+                            .map(|_| ());
+                        report.record(&output, result);
+                    }
+
+                    if !cli.quiet {
+                        cprintln!("<g!>success:</> saved {} file(s) to:", report.succeeded);
+                        println!("  {}", out_dir.display());
+                        println!();
+                    }
+                    report.print_summary("extract", cli.quiet);
+                    return report.exit_code();
+                }
+            }
+        }
+
+        Commands::Prs { command } => match command {
+            PrsCommand::Compress { input, output } => {
+                let buffer = match std::fs::read(input) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(input, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                };
+                let compressed = prs::compress(&buffer);
+                let size = match output::Sink::Disk(Overwrite::Force).write(output, &compressed) {
+                    Ok(size) => size,
+                    Err(e) => {
+                        print_error(output, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                if !cli.quiet {
+                    print_saved("compressed file", output, None, Some(size));
+                    println!();
+                    cprintln!("<c!>info:</>");
+                    println!("  Uncompressed size: {} bytes", buffer.len());
+                    println!("  Compressed size: {} bytes", compressed.len());
+                }
+            }
+
+            PrsCommand::Decompress { input, output } => {
+                let buffer = match std::fs::read(input) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        print_error(input, &Error::Open(e));
+                        return Failure::Input.exit_code();
+                    }
+                };
+                let decompressed = match prs::decompress(&buffer) {
+                    Ok(val) => val,
+                    Err(e) => {
+                        let e = Error::Prs(e);
+                        print_error(input, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                let size = match output::Sink::Disk(Overwrite::Force).write(output, &decompressed)
+                {
+                    Ok(size) => size,
+                    Err(e) => {
+                        print_error(output, &e);
+                        return e.failure().exit_code();
+                    }
+                };
+                if !cli.quiet {
+                    print_saved("decompressed file", output, None, Some(size));
+                    println!();
+                    cprintln!("<c!>info:</>");
+                    println!("  Compressed size: {} bytes", buffer.len());
+                    println!("  Decompressed size: {} bytes", decompressed.len());
+                }
+            }
+        },
+
+        Commands::SetIndex {
+            args,
+            from_filename,
+        } => {
+            let (paths, index) = match (from_filename, args.split_last()) {
+                (true, _) => (args.as_slice(), None),
+                (false, Some((index, paths))) if !paths.is_empty() => {
+                    match patch::parse_index(&index.to_string_lossy()) {
+                        Ok(index) => (paths, Some(index)),
+                        Err(e) => {
+                            let mut cmd = Cli::command();
+                            cmd.error(ErrorKind::ValueValidation, e).exit()
+                        }
+                    }
+                }
+                (false, _) => {
+                    let mut cmd = Cli::command();
+                    cmd.error(
+                        ErrorKind::WrongNumberOfValues,
+                        "Expected the files to change, followed by the new global index. Use `--from-filename` to take the index from each file name.",
+                    )
+                    .exit()
+                }
+            };
+
+            return patch_in_place(paths, "while setting global index", cli.quiet, |path| {
+                let index = match index {
+                    Some(index) => index,
+                    None => patch::index_from_filename(path).ok_or(PatchError::NoIndexInName)?,
+                };
+                let old = patch::set_global_index(path, index)?;
+                Ok(format!("{}: global index {old} -> {index}", path.display()))
+            });
+        }
+
+        Commands::SetHeader { paths, header } => {
+            let header = *header;
+            return patch_in_place(paths, "while setting header", cli.quiet, |path| {
+                let old = patch::set_header_id(path, header)?;
+                if old == header {
+                    return Ok(cformat!(
+                        "<c!>notice:</> {} already has a {} header",
+                        path.display(),
+                        header
+                    ));
+                }
+                Ok(format!("{}: header {old} -> {header}", path.display()))
+            });
+        }
+
+        Commands::ExportPalette { input, output } => {
+            let result = prs::read(input)
+                .map_err(Error::Open)
+                .and_then(|texture| palette::read_texture(&texture));
+            let (header, colors) = match result {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+            if let Err(e) = palette::save(output, &colors) {
+                let e = Error::SavePalette(e);
+                print_error(output, &e);
+                return e.failure().exit_code();
+            }
+
+            let transparent = colors.iter().filter(|color| color[3] < 0xFF).count();
+            if transparent > 0 && palette::drops_alpha(output) {
+                ceprintln!(
+                    "<y!>warning:</> {} colors of the palette are transparent, but only images keep the alpha channel",
+                    transparent
+                );
+            }
+            if cli.quiet {
+                return ExitCode::SUCCESS;
+            }
+            print_saved("palette", output, None, None);
+            println!();
+            cprintln!("<c!>info:</>");
+            println!("  Data format: {}", header.data_format);
+            if let Some(pixel_format) = header.pixel_format {
+                println!("  Pixel format: {pixel_format}");
+            }
+            println!("  Colors: {}", colors.len());
+        }
+
+        Commands::ReplacePalette {
+            input,
+            output,
+            palette: palette_path,
+        } => {
+            let palette = match palette::load(palette_path) {
+                Ok(val) => val,
+                Err(e) => {
+                    let e = Error::Palette(e);
+                    print_error(palette_path, &e);
+                    return e.failure().exit_code();
+                }
+            };
+            let result = prs::read_compressed(input)
+                .map_err(Error::Open)
+                .and_then(|(mut texture, compression)| {
+                    let header = palette::replace(&mut texture, &palette)?;
+                    // a compressed texture is saved compressed again, the way the game loads it
+                    if let Some(compression) = compression {
+                        texture = compression.compress(&texture);
+                    }
+                    let size = output::Sink::Disk(Overwrite::Force).write(output, &texture)?;
+                    Ok((header, size))
+                });
+            let (header, size) = match result {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+            if cli.quiet {
+                return ExitCode::SUCCESS;
+            }
+            print_saved("texture", output, None, Some(size));
+            println!();
+            cprintln!("<c!>info:</>");
+            println!("  Data format: {}", header.data_format);
+            if let Some(pixel_format) = header.pixel_format {
+                println!("  Pixel format: {pixel_format}");
+            }
+            println!("  Colors: {}", palette.colors().len());
+        }
+
+        Commands::Mips {
+            input,
+            output,
+            add,
+            strip: _,
+        } => {
+            let (buffer, compression) = match prs::read_compressed(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            let header = match GvrHeader::parse(&buffer) {
+                Ok(val) => val,
+                Err(e) => {
+                    let failure = Failure::from(&e);
+                    print_failure(
+                        "while reading header",
+                        ErrorReport::new("read", failure, Some(input), e),
+                    );
+                    return failure.exit_code();
+                }
+            };
+            check_mipmap_support(*add, header.data_format);
+
+            let result = match (*add, header.mipmaps) {
+                (true, false) => mips::add_mipmaps(&header, buffer),
+                (false, true) => mips::strip_mipmaps(&header, &buffer),
+                (_, has_mipmaps) => {
+                    ceprintln!(
+                        "<y!>warning:</> the texture {} mipmaps already, saving it unchanged:",
+                        if has_mipmaps { "has" } else { "has no" }
+                    );
+                    eprintln!("  {}", input.display());
+                    Ok(buffer)
+                }
+            };
+            let size = match result
+                .map(|data| match compression {
+                    Some(compression) => compression.compress(&data),
+                    None => data,
+                })
+                .and_then(|data| output::Sink::Disk(Overwrite::Force).write(output, &data))
+            {
+                Ok(size) => size,
+                Err(e) => {
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+            if !cli.quiet {
+                print_saved("texture", output, None, Some(size));
+            }
+        }
+
+        Commands::Convert {
+            input,
+            output,
+            data_format,
+            pixel_format,
+            mipmaps,
+            no_mipmaps,
+            header,
+            global_index,
+            flip_x,
+            flip_y,
+        } => {
+            let (buffer, compression) = match prs::read_compressed(input) {
+                Ok(val) => val,
+                Err(e) => {
+                    print_error(input, &Error::Open(e));
+                    return Failure::Input.exit_code();
+                }
+            };
+            let original = match GvrHeader::parse(&buffer) {
+                Ok(val) => val,
+                Err(e) => {
+                    let failure = Failure::from(&e);
+                    print_failure(
+                        "while reading header",
+                        ErrorReport::new("read", failure, Some(input), e),
+                    );
+                    return failure.exit_code();
+                }
+            };
+            check_mipmap_support(*mipmaps, *data_format);
+
+            // the size of the file itself, which is smaller than the texture if it's compressed
+            let input_size = std::fs::metadata(input).map_or(buffer.len(), |metadata| {
+                metadata.len() as usize
+            });
+            let mipmaps =
+                *mipmaps || (!*no_mipmaps && original.mipmaps && data_format.supports_mipmaps());
+            let settings = EncodeSettings {
+                data_format: *data_format,
+                pixel_format: pixel_format
+                    .or(original.pixel_format)
+                    .unwrap_or(PixelFormat::Rgb5a3),
+                header: header.unwrap_or(original.header_id),
+                global_index: global_index.unwrap_or(original.global_index),
+                compression,
+                ..EncodeSettings::from_header(&original, mipmaps)
+            };
+            let flip = Flip {
+                x: *flip_x,
+                y: *flip_y,
+            };
+            let size = match decode::decode_buffer(buffer)
+                .and_then(|mut image| {
+                    flip.apply(&mut image);
+                    settings.encode_image(DynamicImage::ImageRgba8(image))
+                })
+                // a compressed texture is saved compressed again, the way the game loads it
+                .map(|data| match settings.compression {
+                    Some(compression) => compression.compress(&data),
+                    None => data,
+                })
+                .and_then(|data| output::Sink::Disk(Overwrite::Force).write(output, &data))
+            {
+                Ok(size) => size,
+                Err(e) => {
+                    print_error(input, &e);
+                    return e.failure().exit_code();
+                }
+            };
+            if !cli.quiet {
+                print_saved("converted texture", output, None, Some(size));
+                print_conversion(&original, &settings, input_size, size);
+            }
+        }
+
+        Commands::Formats { json } => {
+            if *json {
+                let formats: Vec<_> = DataFormat::value_variants()
+                    .iter()
+                    .map(|format| FormatReport::new(*format))
+                    .collect();
+                report::print_json(&formats);
+                return ExitCode::SUCCESS;
+            }
+
+            let row = |name: &str, bpp: &str, alpha: &str, mipmaps: &str, palette: &str| {
+                format!("{name:<13} {bpp:>3}  {alpha:<7}  {mipmaps:<7}  {palette:<7}")
+            };
+            cprintln!(
+                "<s>{}  {}</>",
+                row("FORMAT", "BPP", "ALPHA", "MIPMAPS", "PALETTE"),
+                "DESCRIPTION"
+            );
+            for format in DataFormat::value_variants() {
+                let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+                let value = format.to_possible_value().unwrap();
+                println!(
+                    "{}  {}",
+                    row(
+                        value.get_name(),
+                        &format.bits_per_pixel().to_string(),
+                        &format.alpha().to_string(),
+                        yes_no(format.supports_mipmaps()),
+                        yes_no(format.is_indexed()),
+                    ),
+                    format.description()
+                );
+            }
+        }
+
+        Commands::Suggest { input, exact, json } => {
+            let image = match encode::load_image(input, true) {
+                Ok(val) => val,
+                Err(e) => {
+                    if *json {
+                        report::eprint_json(&ErrorReport::from_error(input, &e));
+                    } else {
+                        print_error(input, &e);
+                    }
+                    return e.failure().exit_code();
+                }
+            };
+            let (width, height) = (image.width(), image.height());
+            let step = suggest::sample_step(width, height, *exact);
+            let analysis = auto::analyze_sample(&image, step);
+            let suggestions = suggest::suggest(&analysis, width, height);
+            let power_of_two = width.is_power_of_two() && height.is_power_of_two();
+
+            if *json {
+                report::print_json(&SuggestReport {
+                    path: input.display().to_string(),
+                    width,
+                    height,
+                    power_of_two,
+                    sampled: step > 1,
+                    colors: analysis.colors,
+                    grayscale: analysis.grayscale,
+                    alpha: analysis.transparency,
+                    transparent_pixels: analysis.transparent,
+                    translucent_pixels: analysis.translucent,
+                    suggestions: suggestions.iter().map(SuggestionReport::new).collect(),
+                });
+                return ExitCode::SUCCESS;
+            }
+
+            cprintln!("<c!>info:</> {}", input.display());
+            let power_of_two = if power_of_two {
+                "powers of two"
+            } else {
+                "not powers of two"
+            };
+            println!("  Dimensions: {width}x{height} ({power_of_two})");
+            println!("  Colors: {}", analysis.colors);
+            println!(
+                "  Grayscale: {}",
+                if analysis.grayscale { "yes" } else { "no" }
+            );
+            match analysis.transparency {
+                Transparency::None => println!("  Alpha: none"),
+                transparency => println!(
+                    "  Alpha: {transparency} ({} fully and {} partly transparent pixels)",
+                    analysis.transparent, analysis.translucent
+                ),
+            }
+            if step > 1 {
+                println!(
+                    "  Only one pixel in every {step}x{step} was looked at, so the counts are estimates. Pass `--exact` to look at all of them."
+                );
+            }
+
+            println!();
+            cprintln!("<c!>suggestions:</>");
+            for (rank, suggestion) in suggestions.iter().enumerate() {
+                let mut options = format!(
+                    "-d {}",
+                    suggestion
+                        .data_format
+                        .to_possible_value()
+                        .unwrap()
+                        .get_name()
+                );
+                if let Some(pixel_format) = suggestion.pixel_format {
+                    let value = pixel_format.to_possible_value().unwrap();
+                    options.push_str(&format!(" -p {}", value.get_name()));
+                }
+                let fits = if suggestion.fits {
+                    ""
+                } else {
+                    " (needs resizing or padding)"
+                };
+                println!(
+                    "  {}. {options:<22} {:>8} bytes  {}{fits}",
+                    rank + 1,
+                    suggestion.size,
+                    suggestion.reason
+                );
+            }
+        }
+
+        Commands::Estimate {
+            input,
+            mipmaps,
+            json,
+        } => {
+            let size = input.to_str().and_then(estimate::parse_size);
+            let dimensions = match size {
+                Some(size) if !input.exists() => Ok(size),
+                _ => encode::image_dimensions(input, true),
+            };
+            let (width, height) = match dimensions {
+                Ok(val) => val,
+                Err(e) => {
+                    if *json {
+                        report::eprint_json(&ErrorReport::from_error(input, &e));
+                    } else {
+                        print_error(input, &e);
+                    }
+                    return e.failure().exit_code();
+                }
+            };
+            let (Ok(gvr_width), Ok(gvr_height)) = (u16::try_from(width), u16::try_from(height))
+            else {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "The image is {width}x{height}, but GVR textures can be at most {0}x{0}.",
+                        u16::MAX
+                    ),
+                )
+                .exit()
+            };
+            if *mipmaps && let Err(e) = dimensions::check(DataFormat::Dxt1, true, width, height) {
+                let e = Error::Dimensions(e);
+                if *json {
+                    report::eprint_json(&ErrorReport::from_error(input, &e));
+                } else {
+                    print_error(input, &e);
+                }
+                return e.failure().exit_code();
+            }
+
+            let estimates = estimate::estimate(gvr_width, gvr_height, *mipmaps);
+            if *json {
+                report::print_json(&EstimateReport {
+                    width,
+                    height,
+                    mipmaps: *mipmaps,
+                    formats: estimates.iter().map(EstimateFormatReport::new).collect(),
+                });
+                return ExitCode::SUCCESS;
+            }
+
+            if size.is_some() && !input.exists() {
+                cprintln!("<c!>estimate:</> {}x{}", width, height);
+            } else {
+                cprintln!(
+                    "<c!>estimate:</> {} ({}x{})",
+                    input.display(),
+                    width,
+                    height
+                );
+            }
+            let row =
+                |name: &str, size: &str, levels: &str| format!("{name:<13} {size:>9}  {levels:>6}");
+            cprintln!("<s>{}  {}</>", row("FORMAT", "SIZE", "LEVELS"), "NOTES");
+            for estimate in &estimates {
+                let mut notes = Vec::new();
+                if *mipmaps && !estimate.data_format.supports_mipmaps() {
+                    notes.push("no mipmaps");
+                }
+                if !estimate.fits {
+                    notes.push("needs resizing or padding");
+                }
+                println!(
+                    "{}  {}",
+                    row(
+                        estimate.data_format.to_possible_value().unwrap().get_name(),
+                        &estimate.size.to_string(),
+                        &estimate.levels.to_string(),
+                    ),
+                    notes.join(", ")
+                );
+            }
+        }
+
+        Commands::Completions { shell, output } => {
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut Cli::command(), "gvrtex", &mut script);
+
+            let Some(output) = output else {
+                let mut stdout = std::io::stdout().lock();
+                if let Err(e) = stdout.write_all(&script).and_then(|_| stdout.flush()) {
+                    let report = ErrorReport::new("write", Failure::Output, None, e);
+                    print_failure("while writing to standard output", report);
+                    return Failure::Output.exit_code();
+                }
+                return ExitCode::SUCCESS;
+            };
+
+            if let Err(e) = output::Sink::Disk(Overwrite::Force).write(output, &script) {
+                print_error(output, &e);
+                return e.failure().exit_code();
+            }
+            if !cli.quiet {
+                print_saved("completion script", output, None, None);
+            }
+        }
+
+        Commands::Man { out_dir } => {
+            let Some(out_dir) = out_dir else {
+                let mut stdout = std::io::stdout().lock();
+                let result = stdout
+                    .write_all(&man::render_page(Cli::command()))
+                    .and_then(|_| stdout.flush());
+                if let Err(e) = result {
+                    let report = ErrorReport::new("write", Failure::Output, None, e);
+                    print_failure("while writing to standard output", report);
+                    return Failure::Output.exit_code();
+                }
+                return ExitCode::SUCCESS;
+            };
+
+            for (name, page) in man::render_pages(Cli::command()) {
+                let output = out_dir.join(name);
+                if let Err(e) = output::Sink::Disk(Overwrite::Force).write(&output, &page) {
+                    print_error(&output, &e);
+                    return e.failure().exit_code();
+                }
+            }
+            if !cli.quiet {
+                cprintln!("<g!>success:</> saved man pages to:");
+                println!("  {}", out_dir.display());
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Sets up logging to stderr, based on how many times `-v` was given. Without it, only errors are
+/// logged. The `RUST_LOG` environment variable overrides this.
+fn init_logging(verbose: u8, color: ColorChoice) {
+    let level = match verbose {
+        0 => LevelFilter::Error,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .write_style(match color {
+            ColorChoice::Auto => WriteStyle::Auto,
+            ColorChoice::Always => WriteStyle::Always,
+            ColorChoice::Never => WriteStyle::Never,
+        })
+        .parse_default_env()
+        .init();
+}
+
+/// Exits with a usage error if `mipmaps` are requested for a `data_format` that doesn't support
+/// them.
+fn check_mipmap_support(mipmaps: bool, data_format: DataFormat) {
+    if mipmaps && !data_format.supports_mipmaps() {
+        let possible_value = data_format.to_possible_value().unwrap();
+        let name = possible_value.get_name();
+        let mut cmd = Cli::command();
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            format!("Can't use mipmaps on the `{name}` data format."),
+        )
+        .exit()
+    }
+}
+
+/// Why the options of the color palette don't apply to a data format, for [`ignored_option`].
+const NO_PALETTE: &str = "has no color palette";
+
+/// Handles an `option` that the `data_format` the texture is encoded with has no use for, for the
+/// given `reason` (e.g. "isn't dithered"). With `strict`, this exits with a usage error, and
+/// otherwise the option is ignored with a warning.
+fn ignored_option(strict: bool, option: &str, data_format: DataFormat, reason: &str) {
+    let possible_value = data_format.to_possible_value().unwrap();
+    let name = possible_value.get_name();
+    if strict {
+        let mut cmd = Cli::command();
+        cmd.error(
+            ErrorKind::ArgumentConflict,
+            format!("Can't use `{option}` on the `{name}` data format, which {reason}."),
+        )
+        .exit()
+    }
+    ceprintln!(
+        "<y!>warning:</> ignoring `{}`, as the {} data format {}",
+        option,
+        name,
+        reason
+    );
+}
+
+/// Runs `patch` on each of the files at `paths`, printing the message it returns unless `quiet` is
+/// set. Errors are printed with the given `context`, and the exit code is that of the error when
+/// there's a single file, or a batch failure when there are more.
+fn patch_in_place(
+    paths: &[PathBuf],
+    context: &str,
+    quiet: bool,
+    patch: impl Fn(&Path) -> Result<String, PatchError>,
+) -> ExitCode {
+    let mut failures = Vec::new();
+    for path in paths {
+        match patch(path) {
+            Ok(message) => {
+                if !quiet {
+                    anstream::println!("{message}");
+                }
+            }
+            Err(e) => {
+                if report::json_errors() {
+                    report::eprint_json(&ErrorReport::new("patch", e.failure(), Some(path), &e));
+                } else {
+                    ceprintln!("<r!>error:</> {}:", context);
+                    eprintln!("  {}: {e}", path.display());
+                }
+                failures.push(e.failure());
+            }
+        }
+    }
+
+    match failures.as_slice() {
+        [] => ExitCode::SUCCESS,
+        [failure] if paths.len() == 1 => failure.exit_code(),
+        _ => Failure::Batch.exit_code(),
+    }
+}
+
+/// Prints an error that occurred while processing the file at `path`.
+fn print_error(path: &Path, e: &Error) {
+    if report::json_errors() {
+        report::eprint_json(&ErrorReport::from_error(path, e));
+        return;
+    }
+    ceprintln!("<r!>error:</> {}:", e.context());
+    eprintln!("  {e}");
+}
+
+/// Returns all the `.gvr` files directly inside of `dir`, sorted by their file names.
+fn gvr_files_in(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && batch::is_gvr_file(&path) {
+            files.push(path);
+        }
+    }
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(files)
+}
+
+/// Prints an error that isn't the result of processing a single file. In the human format, this
+/// is the `context` describing what went wrong, followed by the message of the `report`.
+fn print_failure(context: &str, report: ErrorReport) {
+    if report::json_errors() {
+        report::eprint_json(&report);
+        return;
+    }
+    ceprintln!("<r!>error:</> {}:", context);
+    eprintln!("  {}", report.message);
+}
+
+/// Prints the amount of pixels that differ between two images, how much they differ and the PSNR.
+fn print_difference(difference: &compare::Difference) {
+    println!(
+        "  Differing pixels: {} of {} ({:.2}%)",
+        difference.pixels,
+        difference.total,
+        difference.pixels as f64 * 100.0 / difference.total as f64
+    );
+    let [red, green, blue, alpha] = difference.max_delta;
+    println!("  Max difference: red {red}, green {green}, blue {blue}, alpha {alpha}");
+    println!("  PSNR: {:.2} dB", difference.psnr);
+}
+
+/// Prints the settings the textures were encoded with, along with the dimensions and the amount of
+/// image levels of the `encoded` texture if there was a single one. These are printed to stderr if `to_stderr` is set, which is the
+/// case when the texture itself is written to stdout.
+fn print_encode_info(settings: &EncodeSettings, encoded: Option<&Encoded>, to_stderr: bool) {
+    let print = |line: String| {
+        if to_stderr {
+            anstream::eprintln!("{line}");
+        } else {
+            anstream::println!("{line}");
+        }
+    };
+
+    print(cformat!("<c!>info:</>"));
+    print(format!("  Header: {}", settings.header));
+    let data_format = encoded.map_or(settings.data_format, |encoded| encoded.data_format);
+    if settings.auto_format.is_some() && encoded.is_none() {
+        print("  Data format: auto".to_string());
+    } else {
+        print(format!("  Data format: {data_format}"));
+    }
+    if data_format.is_indexed() {
+        print(format!("  Pixel format: {}", settings.pixel_format));
+        match settings.quantizer {
+            _ if settings.palette.is_some() => {}
+            PaletteQuantizer::Kmeans => print(format!(
+                "  Quantizer: kmeans ({} iterations at most)",
+                settings.quantizer_iterations
+            )),
+            quantizer => print(format!("  Quantizer: {quantizer}")),
+        }
+        if let Some(palette) = &settings.palette {
+            print(format!(
+                "  Fixed palette: {} colors",
+                palette.colors().len()
+            ));
+        }
+        if let Some(transparent) = settings.transparent_index {
+            print(format!(
+                "  Transparent index: {} (alpha below {})",
+                transparent.index, transparent.cutoff
+            ));
+        }
+        if let Some(used) = encoded.and_then(|encoded| encoded.palette_colors) {
+            print(format!(
+                "  Palette: {used}/{} colors used",
+                data_format.palette_colors()
+            ));
+        }
+    }
+    if data_format == DataFormat::Dxt1 {
+        print(format!("  DXT quality: {}", settings.dxt_quality));
+        if settings.dxt_backend != DxtBackend::Builtin {
+            print(format!("  DXT backend: {}", settings.dxt_backend));
+        }
+    }
+    if let Some(encoded) = encoded {
+        if encoded.source_dimensions != encoded.dimensions {
+            let (width, height) = encoded.source_dimensions;
+            print(format!("  Source dimensions: {width}x{height}"));
+        }
+        let (width, height) = encoded.dimensions;
+        print(format!("  Dimensions: {width}x{height}"));
+    }
+    if let Some(threshold) = settings.transform.alpha_threshold {
+        print(format!("  Alpha threshold: {threshold}"));
+    }
+    print(format!("  Mipmaps: {}", settings.mipmaps));
+    if let Some(encoded) = encoded.filter(|_| settings.mipmaps) {
+        print(format!("  Levels: {}", encoded.levels));
+    }
+    if let Some(metrics) = encoded.and_then(|encoded| encoded.metrics.as_ref()) {
+        let [red, green, blue] = metrics.channel_psnr;
+        let mut channels = format!("red {red:.2}, green {green:.2}, blue {blue:.2}");
+        if let Some(alpha) = metrics.alpha_psnr {
+            channels.push_str(&format!(", alpha {alpha:.2}"));
+        }
+        print(format!("  PSNR: {:.2} dB ({channels})", metrics.psnr));
+        print(format!("  SSIM: {:.4}", metrics.ssim));
+    }
+    if let Some(compression) = settings.compression {
+        match encoded.and_then(|encoded| Some((encoded.uncompressed_size?, encoded.size))) {
+            Some((uncompressed, compressed)) => print(format!(
+                "  Compression: {compression} ({uncompressed} -> {compressed} bytes)"
+            )),
+            None => print(format!("  Compression: {compression}")),
+        }
+    }
+    print(format!("  Global index: {}", settings.global_index));
+}
+
+/// Prints which data format was picked for the image in `input` and why, if it was picked
+/// automatically, unless `quiet` is set.
+fn print_auto_format(input: &Path, encoded: &Encoded, quiet: bool) {
+    if let Some(reason) = encoded.auto_reason
+        && !quiet
+    {
+        ceprintln!(
+            "<c!>notice:</> encoding as {}, as {}:",
+            encoded.data_format,
+            reason
+        );
+        eprintln!("  {}", input.display());
+    }
+}
+
+/// Prints the header information of a decoded texture. Like with [`print_encode_info`], this is
+/// printed to stderr if `to_stderr` is set.
+fn print_decode_info(header: &GvrHeader, to_stderr: bool) {
+    let print = |line: String| {
+        if to_stderr {
+            anstream::eprintln!("{line}");
+        } else {
+            anstream::println!("{line}");
+        }
+    };
+
+    print(cformat!("<c!>info:</>"));
+    print(format!("  Header: {}", header.header_id));
+    print(format!("  Global index: {}", header.global_index));
+    print(format!("  Data format: {}", header.data_format));
+    if let Some(pixel_format) = header.pixel_format {
+        print(format!("  Pixel format: {pixel_format}"));
+    }
+    print(format!("  Dimensions: {}x{}", header.width, header.height));
+    print(format!("  Mipmaps: {}", header.mipmaps));
+    if header.mipmaps {
+        print(format!("  Levels: {}", header.level_count()));
+    }
+}
+
+/// Prints where the output of single-file mode was saved to, describing the `what` that was
+/// saved. When the output was written to stdout, this is printed to stderr instead, so it doesn't
+/// end up mixed into the output data.
+fn print_saved(what: &str, output: &Path, dry_run: Option<DryRun>, size: Option<usize>) {
+    let notes = describe_planned(output, size);
+    match (dry_run.is_some(), stdio::is_stdio(output)) {
+        (false, false) => {
+            cprintln!("<g!>success:</> saved {} to:", what);
+            println!("  {}", output.display());
+        }
+        (true, false) => {
+            cprintln!("<c!>dry run:</> would save {} to:", what);
+            println!("  {}{notes}", output.display());
+        }
+        (false, true) => ceprintln!("<g!>success:</> wrote {} to standard output", what),
+        (true, true) => {
+            ceprintln!(
+                "<c!>dry run:</> would write {} to standard output{}",
+                what,
+                notes
+            )
+        }
+    }
+}
+
+/// Prints how the format and the size of a texture changed by converting it.
+fn print_conversion(
+    original: &GvrHeader,
+    settings: &EncodeSettings,
+    input_size: usize,
+    output_size: usize,
+) {
+    let palette = |format: Option<PixelFormat>| format.map_or("none".to_owned(), |f| f.to_string());
+
+    println!(
+        "  Data format: {} -> {}",
+        original.data_format, settings.data_format
+    );
+    if original.data_format.is_indexed() || settings.data_format.is_indexed() {
+        let new_pixel_format = settings
+            .data_format
+            .is_indexed()
+            .then_some(settings.pixel_format);
+        println!(
+            "  Pixel format: {} -> {}",
+            palette(original.pixel_format),
+            palette(new_pixel_format)
+        );
+    }
+    println!("  Mipmaps: {} -> {}", original.mipmaps, settings.mipmaps);
+    println!("  File size: {input_size} -> {output_size} bytes");
+}
+
+/// Prints the mipmap levels saved by `decode --all-mips`, along with their dimensions.
+fn print_saved_levels(levels: &[decode::SavedLevel], dry_run: Option<DryRun>) {
+    if dry_run.is_some() {
+        cprintln!(
+            "<c!>dry run:</> would save {} mipmap level(s) to:",
+            levels.len()
+        );
+    } else {
+        cprintln!("<g!>success:</> saved {} mipmap level(s) to:", levels.len());
+    }
+    for level in levels {
+        println!(
+            "  {} ({}x{})",
+            level.path.display(),
+            level.width,
+            level.height
+        );
+    }
+    if levels.len() == 1 {
+        cprintln!(
+            "<c!>notice:</> the texture has no mipmaps, so only the full-size image was saved"
+        );
+    }
+}
+
+/// Checks that no two of the batch `inputs` would be saved to the same one of their `outputs`,
+/// printing an error if they would.
+fn check_collisions(inputs: &[batch::Input], outputs: &[PathBuf]) -> Result<(), ExitCode> {
+    let Some((first, second, output)) = batch::find_collision(inputs, outputs) else {
+        return Ok(());
+    };
+
+    if report::json_errors() {
+        let message = format!(
+            "Multiple input files would be saved to the same output file: {} and {}",
+            first.display(),
+            second.display()
+        );
+        report::eprint_json(&ErrorReport::new(
+            "plan",
+            Failure::Usage,
+            Some(output),
+            message,
+        ));
+    } else {
+        ceprintln!("<r!>error:</> multiple input files would be saved to the same output file:");
+        eprintln!("  {}", output.display());
+        eprintln!();
+        eprintln!("  first input: {}", first.display());
+        eprintln!("  second input: {}", second.display());
+    }
+    Err(Failure::Usage.exit_code())
+}
+
+/// Creates the parent directory of an `output` file, unless this is a dry run.
+fn prepare_output_dir(output: &Path, dry_run: Option<DryRun>) -> Result<(), Error> {
+    if dry_run.is_some() {
+        return Ok(());
+    }
+    output::create_parent_dir(output)
+}
+
+/// Creates the parent directory of a single-file mode `output` file, if `--create-dirs` is given.
+fn create_dirs_if(create_dirs: bool, output: &Path, dry_run: Option<DryRun>) -> Result<(), Error> {
+    if !create_dirs {
+        return Ok(());
+    }
+    prepare_output_dir(output, dry_run)
+}
+
+/// Prints a warning about the given `output` file being skipped because it already exists.
+fn print_skipped_output(output: &Path) {
+    ceprintln!("<y!>warning:</> skipping existing output file:");
+    eprintln!("  {}", output.display());
+}
+
+/// Describes what would happen to `output` in a dry run, given the `size` of the output file if
+/// it's known. Returns an empty string if there's nothing to note.
+fn describe_planned(output: &Path, size: Option<usize>) -> String {
+    let mut notes = Vec::new();
+    if let Some(size) = size {
+        notes.push(format!("{size} bytes"));
+    }
+    if !stdio::is_stdio(output) && output.exists() {
+        notes.push("overwrites existing file".to_string());
+    }
+
+    if notes.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", notes.join(", "))
+    }
+}
+
+/// Expands the glob patterns in the input `paths`, unless `no_glob` is set.
+fn expand_inputs(paths: &[PathBuf], no_glob: bool) -> Result<Vec<PathBuf>, ExitCode> {
+    if no_glob {
+        return Ok(paths.to_vec());
+    }
+
+    batch::expand_globs(paths).map_err(|e| {
+        let failure = Failure::from(&e);
+        print_failure(
+            "while expanding input patterns",
+            ErrorReport::new("expand", failure, None, e),
+        );
+        failure.exit_code()
+    })
+}
+
+/// Same as [`expand_inputs()`], but also adds the paths listed in the `files_from` file, if given.
+/// The paths are separated by NUL bytes if `null` is set, otherwise by newlines.
+fn gather_inputs(
+    paths: &[PathBuf],
+    no_glob: bool,
+    files_from: Option<&Path>,
+    null: bool,
+) -> Result<Vec<PathBuf>, ExitCode> {
+    let mut paths = expand_inputs(paths, no_glob)?;
+    if let Some(list) = files_from {
+        match batch::read_file_list(list, null) {
+            Ok(listed) => paths.extend(listed),
+            Err(e) => {
+                if report::json_errors() {
+                    report::eprint_json(&ErrorReport::new("read", Failure::Input, Some(list), e));
+                } else {
+                    ceprintln!("<r!>error:</> while reading input file list:");
+                    eprintln!("  {}: {e}", list.display());
+                }
+                return Err(Failure::Input.exit_code());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Same as [`expand_inputs()`], but for a single input file. A pattern matching multiple files is a
+/// usage error here.
+fn expand_single_input(input: &Path, no_glob: bool) -> Result<PathBuf, ExitCode> {
+    let inputs = expand_inputs(&[input.to_path_buf()], no_glob)?;
+    let [input] = inputs.as_slice() else {
+        let mut cmd = Cli::command();
+        cmd.error(
+            ErrorKind::WrongNumberOfValues,
+            format!(
+                "The input pattern matches {} files. Use `--out-dir` to process multiple files.",
+                inputs.len()
+            ),
+        )
+        .exit()
+    };
+    Ok(input.clone())
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
 }
@@ -0,0 +1,65 @@
+//! Generation of the man pages from the command-line definitions.
+
+use crate::formats::DataFormat;
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::{Command, ValueEnum};
+use clap_mangen::Man;
+
+/// Renders the man page of the main `cmd` on its own.
+pub fn render_page(cmd: Command) -> Vec<u8> {
+    render(with_details(cmd))
+}
+
+/// Renders the man pages of the main `cmd` and of all of its visible subcommands, returning each
+/// one along with its file name (`gvrtex.1`, `gvrtex-encode.1`, etc).
+pub fn render_pages(cmd: Command) -> Vec<(String, Vec<u8>)> {
+    // Building the command names the subcommands after their parents.
+    let mut cmd = with_details(cmd).disable_help_subcommand(true);
+    cmd.build();
+
+    let mut pages = Vec::new();
+    collect_pages(cmd, &mut pages);
+    pages
+}
+
+fn collect_pages(cmd: Command, pages: &mut Vec<(String, Vec<u8>)>) {
+    for subcommand in cmd.get_subcommands().filter(|cmd| !cmd.is_hide_set()) {
+        collect_pages(subcommand.clone(), pages);
+    }
+
+    let name = Man::new(cmd.clone()).get_filename();
+    pages.push((name, render(cmd)));
+}
+
+fn render(cmd: Command) -> Vec<u8> {
+    let mut page = Vec::new();
+    Man::new(cmd)
+        .render(&mut page)
+        .expect("Writing to a Vec should never fail.");
+    page
+}
+
+/// Adds the [`DataFormat::details()`] of each data format to its description in the `encode`
+/// command. The command is only used for rendering, so replacing the value parser is fine.
+fn with_details(cmd: Command) -> Command {
+    cmd.mut_subcommand("encode", |encode| {
+        encode.mut_arg("data_format", |arg| {
+            arg.value_parser(PossibleValuesParser::new(
+                DataFormat::value_variants().iter().map(describe),
+            ))
+        })
+    })
+}
+
+fn describe(format: &DataFormat) -> PossibleValue {
+    let value = format
+        .to_possible_value()
+        .expect("All the data formats have a name.");
+    match (value.get_help(), format.details()) {
+        (Some(help), Some(details)) => {
+            let help = format!("{help}. {details}");
+            value.help(help)
+        }
+        _ => value,
+    }
+}
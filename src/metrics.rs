@@ -0,0 +1,182 @@
+//! Measuring how much an image changed by encoding it, for `encode --metrics` and `compare`.
+
+use image::RgbaImage;
+
+/// The size of the square windows SSIM is worked out over.
+const SSIM_WINDOW: u32 = 8;
+/// How far apart the SSIM windows start, so that they overlap by half.
+const SSIM_STEP: u32 = 4;
+
+/// How close an encoded image is to the original one.
+pub struct Metrics {
+    /// The peak signal-to-noise ratio over all the compared channels, in dB. This is infinite if
+    /// the images are identical.
+    pub psnr: f64,
+    /// The peak signal-to-noise ratio of the red, green and blue channels on their own.
+    pub channel_psnr: [f64; 3],
+    /// The peak signal-to-noise ratio of the alpha channel, if it was compared.
+    pub alpha_psnr: Option<f64>,
+    /// The structural similarity, averaged over the compared channels. This is 1 if the images
+    /// are identical.
+    pub ssim: f64,
+}
+
+/// Compares the `decoded` image to the `original` one, which must have the same dimensions. The
+/// alpha channel is only compared with `alpha`, for the data formats that store it.
+pub fn compare(original: &RgbaImage, decoded: &RgbaImage, alpha: bool) -> Metrics {
+    let channels = if alpha { 4 } else { 3 };
+    let mut squared_errors = [0u64; 4];
+    for (original, decoded) in original.pixels().zip(decoded.pixels()) {
+        for channel in 0..channels {
+            let error = u64::from(original[channel].abs_diff(decoded[channel]));
+            squared_errors[channel] += error * error;
+        }
+    }
+
+    let pixels = u64::from(original.width()) * u64::from(original.height());
+    let total = squared_errors.iter().sum();
+    let ssim = (0..channels)
+        .map(|channel| ssim(original, decoded, channel))
+        .sum::<f64>()
+        / channels as f64;
+    Metrics {
+        psnr: psnr(total, pixels * channels as u64),
+        channel_psnr: [0, 1, 2].map(|channel| psnr(squared_errors[channel], pixels)),
+        alpha_psnr: alpha.then(|| psnr(squared_errors[3], pixels)),
+        ssim,
+    }
+}
+
+/// Works out the peak signal-to-noise ratio in dB from the sum of the `squared_error` of every
+/// compared sample, out of `samples` samples.
+pub fn psnr(squared_error: u64, samples: u64) -> f64 {
+    let mean = squared_error as f64 / samples as f64;
+    10.0 * (255.0 * 255.0 / mean).log10()
+}
+
+/// Works out the structural similarity of a single `channel` of the two images, as the average
+/// over overlapping square windows. Images smaller than a window are compared as a whole.
+fn ssim(original: &RgbaImage, decoded: &RgbaImage, channel: usize) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = original.dimensions();
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+    let starts = |size: u32, window: u32| (0..=size - window).step_by(SSIM_STEP as usize);
+
+    let (mut total, mut windows) = (0.0, 0);
+    for top in starts(height, window_height) {
+        for left in starts(width, window_width) {
+            let (mut sum_x, mut sum_y, mut sum_xx, mut sum_yy, mut sum_xy) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in top..top + window_height {
+                for x in left..left + window_width {
+                    let a = f64::from(original.get_pixel(x, y)[channel]);
+                    let b = f64::from(decoded.get_pixel(x, y)[channel]);
+                    sum_x += a;
+                    sum_y += b;
+                    sum_xx += a * a;
+                    sum_yy += b * b;
+                    sum_xy += a * b;
+                }
+            }
+
+            let n = f64::from(window_width * window_height);
+            let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+            let variance_x = sum_xx / n - mean_x * mean_x;
+            let variance_y = sum_yy / n - mean_y * mean_y;
+            let covariance = sum_xy / n - mean_x * mean_y;
+            total += ((2.0 * mean_x * mean_y + C1) * (2.0 * covariance + C2))
+                / ((mean_x * mean_x + mean_y * mean_y + C1) * (variance_x + variance_y + C2));
+            windows += 1;
+        }
+    }
+    total / f64::from(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn solid(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(16, 16, Rgba(color))
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn identical_images() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| Rgba([x as u8 * 16, y as u8 * 16, 0, 255]));
+        let metrics = compare(&image, &image, true);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert_eq!(metrics.channel_psnr, [f64::INFINITY; 3]);
+        assert_eq!(metrics.alpha_psnr, Some(f64::INFINITY));
+        assert_close(metrics.ssim, 1.0);
+    }
+
+    #[test]
+    fn psnr_known_answers() {
+        // an error of 1 in every sample, and of 255 in every sample
+        assert_close(psnr(100, 100), 48.1308036086791);
+        assert_close(psnr(255 * 255 * 10, 10), 0.0);
+
+        // an error of 16 in the red channel only
+        let metrics = compare(
+            &solid([100, 100, 100, 255]),
+            &solid([116, 100, 100, 255]),
+            false,
+        );
+        assert_close(metrics.channel_psnr[0], 24.04840395556061);
+        assert_eq!(metrics.channel_psnr[1..], [f64::INFINITY; 2]);
+        assert_close(metrics.psnr, 28.81961650275723);
+        assert!(metrics.alpha_psnr.is_none());
+    }
+
+    #[test]
+    fn alpha_only_with_alpha() {
+        let original = solid([100, 100, 100, 255]);
+        let decoded = solid([100, 100, 100, 239]);
+        let metrics = compare(&original, &decoded, false);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+
+        let metrics = compare(&original, &decoded, true);
+        assert_close(metrics.alpha_psnr.unwrap(), 24.04840395556061);
+        assert_close(metrics.psnr, 30.069003868840234);
+    }
+
+    #[test]
+    fn ssim_known_answers() {
+        // flat windows only compare the means: (2 * 100 * 110 + C1) / (100² + 110² + C1)
+        let metrics = compare(
+            &solid([100, 100, 100, 255]),
+            &solid([110, 110, 110, 255]),
+            false,
+        );
+        assert_close(metrics.ssim, 0.9954764440915066);
+        // the alpha channel counts as a fourth channel that's identical
+        let metrics = compare(
+            &solid([100, 100, 100, 255]),
+            &solid([110, 110, 110, 255]),
+            true,
+        );
+        assert_close(metrics.ssim, 0.9966073330686299);
+
+        // an inverted checkerboard has the same means and variances, but the opposite structure
+        let checkerboard = |inverted: bool| {
+            RgbaImage::from_fn(16, 16, |x, y| {
+                let value = if ((x + y) % 2 == 0) != inverted {
+                    255
+                } else {
+                    0
+                };
+                Rgba([value, value, value, 255])
+            })
+        };
+        let metrics = compare(&checkerboard(false), &checkerboard(true), false);
+        assert_close(metrics.ssim, -0.9964064683569576);
+    }
+}
@@ -0,0 +1,450 @@
+//! Adding and removing the mipmaps of GVR textures, and building them from custom images.
+
+use crate::decode;
+use crate::encode::EncodeSettings;
+use crate::error::Error;
+use crate::formats::DataFormat;
+use crate::header::{
+    CHUNK_LENGTH_OFFSET, DIMENSIONS_OFFSET, FLAG_MIPMAPS, FLAGS_OFFSET, GvrHeader, HEADER_SIZE,
+};
+use clap::ValueEnum;
+use gvrtex::error::TextureDecodeError;
+use gvrtex::error::TextureEncodeError;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+
+/// Re-encodes the GVR texture file in `buffer` with mipmaps, keeping all of its other settings.
+///
+/// The texture is decoded and encoded again, so this is lossy for the data formats that are.
+pub fn add_mipmaps(header: &GvrHeader, buffer: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let image = decode::decode_buffer(buffer)?;
+    EncodeSettings::from_header(header, true).encode_image(DynamicImage::ImageRgba8(image))
+}
+
+/// How the mipmap chain is generated when encoding with mipmaps.
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+pub struct MipChain {
+    /// The most image levels to store, counting the full-size image. Defaults to all of them.
+    pub count: Option<usize>,
+    /// How the mipmap levels are scaled down from the full-size image.
+    pub filter: MipFilter,
+    /// The smallest width or height a mipmap level can have. Defaults to 1, where the chain only
+    /// stops once the levels would get smaller than a block of the data format.
+    pub min_size: Option<u32>,
+}
+
+/// The filters the mipmap levels can be scaled down with, which are also used to resize the
+/// input images.
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum MipFilter {
+    /// Picks a single pixel for every pixel of the level, which keeps hard edges, like those of
+    /// pixel art.
+    Nearest,
+    /// Averages all the pixels each pixel of the level covers.
+    Box,
+    /// Linear filtering. This is what gvrtex uses on its own.
+    #[default]
+    Triangle,
+    /// Lanczos filtering with a window of 3, which is the sharpest of the smooth filters.
+    Lanczos3,
+}
+
+impl MipFilter {
+    /// Scales `image` to the given `width` and `height` with this filter.
+    pub fn resize(self, image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let filter = match self {
+            Self::Box => {
+                return DynamicImage::ImageRgba8(box_downsample(&image.to_rgba8(), width, height));
+            }
+            Self::Nearest => FilterType::Nearest,
+            Self::Triangle => FilterType::Triangle,
+            Self::Lanczos3 => FilterType::Lanczos3,
+        };
+        image.resize_exact(width, height, filter)
+    }
+}
+
+impl MipChain {
+    /// Checks if this chain differs from the one gvrtex generates on its own, in which case the
+    /// levels have to be generated by [`encode_custom_levels`] instead.
+    pub fn is_custom(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Returns the amount of mipmap levels to generate for a texture of the given `width` and
+    /// `height`, not counting the full-size image. The chain stops at whichever of the level count
+    /// and the minimum size is reached first. Fails if more levels were asked for than the texture
+    /// can have.
+    pub fn mipmap_levels(
+        &self,
+        data_format: DataFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<usize, Error> {
+        let mut levels = data_format.mipmap_levels(width);
+        if let Some(count) = self.count {
+            if count > levels + 1 {
+                return Err(Error::MipCount {
+                    count,
+                    max: levels + 1,
+                });
+            }
+            levels = count - 1;
+        }
+        if let Some(min_size) = self.min_size {
+            levels = (1..=levels)
+                .take_while(|level| (width >> level).min(height >> level) >= min_size)
+                .count();
+        }
+        Ok(levels)
+    }
+}
+
+/// Encodes `image` with the given `settings`, using the given `custom` images as the mipmap levels
+/// instead of generating them. The first custom image is level 1, and each one has to be exactly
+/// half the size of the previous level. The levels after the last custom image are generated by
+/// scaling it down, following the mipmap chain of the `settings`.
+pub fn encode_custom_levels(
+    settings: &EncodeSettings,
+    image: DynamicImage,
+    custom: &[DynamicImage],
+) -> Result<Vec<u8>, Error> {
+    let (width, height) = (image.width(), image.height());
+    if width != height {
+        return Err(Error::Encode(TextureEncodeError::InvalidDimensionsMipmap(
+            width, height,
+        )));
+    }
+    let levels = settings
+        .mip_chain
+        .mipmap_levels(settings.data_format, width, height)?;
+    if custom.len() > levels {
+        return Err(Error::TooManyMips {
+            given: custom.len(),
+            levels,
+        });
+    }
+
+    let level_settings = EncodeSettings {
+        mipmaps: false,
+        ..*settings
+    };
+    let mut texture = level_settings.encode_image(image.clone())?;
+    let mut source = image;
+    for level in 1..=levels {
+        let expected = ((width >> level).max(1), (height >> level).max(1));
+        let level_image = match custom.get(level - 1) {
+            Some(custom) if custom.dimensions() != expected => {
+                return Err(Error::MipDimensions {
+                    level,
+                    expected,
+                    actual: custom.dimensions(),
+                });
+            }
+            Some(custom) => {
+                source = custom.clone();
+                source.clone()
+            }
+            None => settings
+                .mip_chain
+                .filter
+                .resize(&source, expected.0, expected.1),
+        };
+        texture.extend(encode_level(&level_settings, level_image)?);
+    }
+
+    let data_size = texture.len() - HEADER_SIZE;
+    set_data_size(&mut texture, data_size);
+    texture[FLAGS_OFFSET] |= FLAG_MIPMAPS;
+    Ok(texture)
+}
+
+/// Encodes a single mipmap level with the given `settings`, returning only its texture data.
+///
+/// Levels smaller than a block are placed in the corner of a transparent block first, as that's
+/// how they're stored, and the encoder refuses images smaller than a block.
+fn encode_level(settings: &EncodeSettings, image: DynamicImage) -> Result<Vec<u8>, Error> {
+    let (block_width, block_height) = settings.data_format.block_size();
+    let (width, height) = image.dimensions();
+    let mut padded = RgbaImage::new(
+        width.next_multiple_of(block_width),
+        height.next_multiple_of(block_height),
+    );
+    imageops::replace(&mut padded, &image.into_rgba8(), 0, 0);
+
+    let level_size = settings.data_format.level_size(width, height) as usize;
+    let encoded = settings.encode_image(DynamicImage::ImageRgba8(padded))?;
+    Ok(encoded[HEADER_SIZE..HEADER_SIZE + level_size].to_vec())
+}
+
+/// Removes the mipmaps from the GVR texture file in `buffer`, which has the given `header`.
+///
+/// This only cuts off the mipmap levels and clears the mipmap flag, so the base level stays
+/// exactly the same. Fails if the file is too short to even hold the base level.
+pub fn strip_mipmaps(header: &GvrHeader, buffer: &[u8]) -> Result<Vec<u8>, Error> {
+    let data_size = header.base_data_size() as usize;
+    let Some(base) = buffer.get(..HEADER_SIZE + data_size) else {
+        return Err(Error::Decode(TextureDecodeError::InvalidFile));
+    };
+    let mut stripped = base.to_vec();
+    stripped[FLAGS_OFFSET] &= !FLAG_MIPMAPS;
+    set_data_size(&mut stripped, data_size);
+    Ok(stripped)
+}
+
+/// Cuts the given mipmap `level` out of the GVR texture file in `buffer`, which has the given
+/// `header`, and returns it as a GVR texture file of its own without mipmaps. Level 0 is the
+/// full-size image.
+///
+/// The dimensions of the new texture are rounded up to whole blocks of the data format, as the
+/// smallest levels can be smaller than a single block. Crop the decoded image to
+/// [`GvrHeader::level_dimensions`] to get rid of this padding.
+pub fn level_texture(header: &GvrHeader, buffer: &[u8], level: usize) -> Result<Vec<u8>, Error> {
+    let levels = header.level_count();
+    if level >= levels {
+        return Err(Error::MipLevel { level, levels });
+    }
+
+    // the color palette comes before the first level, and is needed to decode any of them
+    let palette_size = header.level_offset(0) as usize;
+    let start = HEADER_SIZE + header.level_offset(level) as usize;
+    let level_size = header.level_size(level) as usize;
+    let (Some(palette), Some(data)) = (
+        buffer.get(HEADER_SIZE..HEADER_SIZE + palette_size),
+        buffer.get(start..start + level_size),
+    ) else {
+        return Err(Error::Decode(TextureDecodeError::InvalidFile));
+    };
+
+    let (block_width, block_height) = header.data_format.block_size();
+    let (width, height) = header.level_dimensions(level);
+    let mut texture = buffer[..HEADER_SIZE].to_vec();
+    for (offset, size, block) in [(0, width, block_width), (2, height, block_height)] {
+        let start = DIMENSIONS_OFFSET + offset;
+        let size = (size.next_multiple_of(block) as u16).to_be_bytes();
+        texture[start..start + 2].copy_from_slice(&size);
+    }
+    texture[FLAGS_OFFSET] &= !FLAG_MIPMAPS;
+    set_data_size(&mut texture, palette_size + level_size);
+    texture.extend_from_slice(palette);
+    texture.extend_from_slice(data);
+    Ok(texture)
+}
+
+/// Sets the length of the texture chunk in the header of the GVR texture file in `buffer` to fit
+/// `data_size` bytes of texture data.
+pub fn set_data_size(buffer: &mut [u8], data_size: usize) {
+    // the chunk length also counts the 8 bytes of the chunk header after the length itself
+    let chunk_length = (data_size as u32 + 8).to_le_bytes();
+    buffer[CHUNK_LENGTH_OFFSET..CHUNK_LENGTH_OFFSET + 4].copy_from_slice(&chunk_length);
+}
+
+/// How much a single mipmap level differs from the full-size image scaled down to its size.
+pub struct LevelCheck {
+    pub level: usize,
+    pub width: u32,
+    pub height: u32,
+    /// The average difference of all the color and alpha channels, from 0 to 255.
+    pub mean_error: f64,
+    /// The largest difference of any single color or alpha channel.
+    pub max_error: u8,
+    /// The largest mean error this level can have before it's considered broken, see
+    /// [`default_tolerance`].
+    pub tolerance: f64,
+}
+
+/// Returns how large the mean error of a mipmap level of the given size can be by default before
+/// it's considered broken.
+///
+/// `dxt1` only stores two colors for each block of 4x4 pixels, which is noticeable once a level is
+/// only a few blocks across, so its levels smaller than 16x16 are given more room than the rest.
+pub fn default_tolerance(data_format: DataFormat, width: u32, height: u32) -> f64 {
+    if data_format == DataFormat::Dxt1 && width.min(height) < 16 {
+        32.0
+    } else {
+        24.0
+    }
+}
+
+/// Decodes every mipmap level of the GVR texture file in `buffer` and compares it to the full-size
+/// image, scaled down to the size of the level with a box filter. Returns one check per mipmap
+/// level, which is none at all for textures without mipmaps, with the default tolerance of each.
+pub fn check_levels(buffer: Vec<u8>) -> Result<Vec<LevelCheck>, Error> {
+    let header = GvrHeader::parse(&buffer).map_err(Error::Header)?;
+    let levels = header.level_count();
+    let base = decode::decode_buffer(buffer.clone())?;
+
+    let mut checks = Vec::with_capacity(levels - 1);
+    for level in 1..levels {
+        let image = decode::decode_level(&buffer, level)?;
+        let expected = box_downsample(&base, image.width(), image.height());
+        let (mut total, mut max_error) = (0u64, 0u8);
+        for (actual, expected) in image.as_raw().iter().zip(expected.as_raw()) {
+            let error = actual.abs_diff(*expected);
+            total += u64::from(error);
+            max_error = max_error.max(error);
+        }
+        checks.push(LevelCheck {
+            level,
+            width: image.width(),
+            height: image.height(),
+            mean_error: total as f64 / image.as_raw().len() as f64,
+            max_error,
+            tolerance: default_tolerance(header.data_format, image.width(), image.height()),
+        });
+    }
+    Ok(checks)
+}
+
+/// Scales the given `image` down to `width` x `height`, where every pixel is the average of all
+/// the pixels of `image` it covers.
+fn box_downsample(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let (source_width, source_height) = image.dimensions();
+    let span = |i: u32, size: u32, source_size: u32| {
+        let start = i * source_size / size;
+        let end = ((i + 1) * source_size / size).max(start + 1);
+        start..end
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (mut sum, mut count) = ([0u32; 4], 0);
+        for source_y in span(y, height, source_height) {
+            for source_x in span(x, width, source_width) {
+                let pixel = image.get_pixel(source_x, source_y);
+                for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+                    *sum += u32::from(channel);
+                }
+                count += 1;
+            }
+        }
+        image::Rgba(sum.map(|sum| ((sum + count / 2) / count) as u8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Returns a 32x32 `rgb5a3` texture of [`testing::image`] with all of its mipmaps.
+    fn texture() -> Vec<u8> {
+        let settings = EncodeSettings {
+            mipmaps: true,
+            ..testing::settings(DataFormat::Rgb5a3)
+        };
+        settings
+            .encode_image(DynamicImage::ImageRgba8(testing::image(32, 32)))
+            .unwrap()
+    }
+
+    #[test]
+    fn tolerance_by_format_and_size() {
+        assert_eq!(default_tolerance(DataFormat::Rgb565, 2, 2), 24.0);
+        assert_eq!(default_tolerance(DataFormat::Index8, 64, 64), 24.0);
+        assert_eq!(default_tolerance(DataFormat::Dxt1, 16, 16), 24.0);
+        assert_eq!(default_tolerance(DataFormat::Dxt1, 8, 8), 32.0);
+        assert_eq!(default_tolerance(DataFormat::Dxt1, 1, 1), 32.0);
+    }
+
+    #[test]
+    fn box_downsample_rounds_the_average() {
+        let image = RgbaImage::from_raw(2, 2, [0, 10, 255, 255, 1, 20, 255, 0].repeat(2)).unwrap();
+        let scaled = box_downsample(&image, 1, 1);
+        assert_eq!(scaled.get_pixel(0, 0).0, [1, 15, 255, 128]);
+    }
+
+    #[test]
+    fn matching_levels_pass() {
+        let checks = check_levels(texture()).unwrap();
+        let sizes: Vec<_> = checks.iter().map(|check| check.width).collect();
+        assert_eq!(sizes, [16, 8, 4]);
+        for check in checks {
+            assert!(
+                check.mean_error <= check.tolerance,
+                "level {}: {}",
+                check.level,
+                check.mean_error
+            );
+        }
+    }
+
+    #[test]
+    fn broken_level_fails() {
+        let mut buffer = texture();
+        let header = GvrHeader::parse(&buffer).unwrap();
+        let start = HEADER_SIZE + header.level_offset(2) as usize;
+        let end = start + header.level_size(2) as usize;
+        buffer[start..end].fill(0);
+
+        let checks = check_levels(buffer).unwrap();
+        for check in checks {
+            assert_eq!(
+                check.mean_error > check.tolerance,
+                check.level == 2,
+                "level {}: {}",
+                check.level,
+                check.mean_error
+            );
+        }
+    }
+
+    /// Returns a black and white checkerboard of the given `size`, with squares of 2x2 pixels.
+    fn checkerboard(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let value = if (x / 2 + y / 2) % 2 == 0 { 255 } else { 0 };
+            image::Rgba([value, value, value, 255])
+        })
+    }
+
+    #[test]
+    fn nearest_keeps_hard_edges() {
+        let image = DynamicImage::ImageRgba8(checkerboard(16));
+        // each square of the checkerboard becomes a single pixel
+        let expected = RgbaImage::from_fn(8, 8, |x, y| {
+            let value = if (x + y) % 2 == 0 { 255 } else { 0 };
+            image::Rgba([value, value, value, 255])
+        });
+        assert_eq!(MipFilter::Nearest.resize(&image, 8, 8).to_rgba8(), expected);
+
+        let encode = |filter| {
+            let settings = EncodeSettings {
+                mipmaps: true,
+                mip_chain: MipChain {
+                    filter,
+                    ..MipChain::default()
+                },
+                ..testing::settings(DataFormat::Rgb5a3)
+            };
+            settings.encode_image(image.clone()).unwrap()
+        };
+        let nearest = encode(MipFilter::Nearest);
+        let lanczos3 = encode(MipFilter::Lanczos3);
+        assert!(nearest != lanczos3);
+        // black and white are stored exactly, so the level decodes to the same pixels
+        assert_eq!(decode::decode_level(&nearest, 1).unwrap(), expected);
+    }
+
+    #[test]
+    fn min_size_of_the_short_side() {
+        let levels = |count, min_size| {
+            let chain = MipChain {
+                count,
+                min_size,
+                ..MipChain::default()
+            };
+            chain.mipmap_levels(DataFormat::Dxt1, 64, 16)
+        };
+        assert_eq!(levels(None, None).unwrap(), 6);
+        // the height of 16 reaches the limit long before the width of 64 does
+        assert_eq!(levels(None, Some(4)).unwrap(), 2);
+        assert_eq!(levels(None, Some(8)).unwrap(), 1);
+        assert_eq!(levels(None, Some(32)).unwrap(), 0);
+        // whichever of the count and the minimum size is reached first
+        assert_eq!(levels(Some(2), Some(4)).unwrap(), 1);
+        assert_eq!(levels(Some(7), Some(4)).unwrap(), 2);
+        assert!(matches!(
+            levels(Some(8), Some(4)),
+            Err(Error::MipCount { count: 8, max: 7 })
+        ));
+    }
+}
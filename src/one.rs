@@ -0,0 +1,296 @@
+//! The `.one` archives of Sonic Heroes, which hold groups of PRS-compressed files.
+//!
+//! A `.one` archive starts with a header holding the size of the rest of the archive, followed by
+//! a table of names, 64 bytes each. The files follow one after another, each behind a header with
+//! the index of its name in the table, the size of its compressed data and the RenderWare version
+//! it was made for.
+
+use crate::contents::Contents;
+use crate::error::Failure;
+use crate::output;
+
+/// Size of the header at the start of the archive. The size of the archive it holds counts from
+/// right after it.
+const HEADER_SIZE_ONE: usize = 0x0C;
+/// Size of the header of the name table, which holds the size of the table.
+const NAMES_HEADER_SIZE: usize = 0x0C;
+/// Size of each name of the name table, which is padded with zeros.
+const NAME_SIZE: usize = 0x40;
+/// Size of the header in front of each file.
+const ENTRY_HEADER_SIZE: usize = 0x0C;
+
+/// Contains all the possible errors that can occur while parsing a `.one` archive.
+#[derive(Debug)]
+pub enum OneError {
+    /// The file is shorter than the headers of a `.one` archive.
+    TooShort(usize),
+    /// The header says the archive is `declared` bytes, but the file is only `actual` bytes.
+    Size { declared: usize, actual: usize },
+    /// The header says the archive is the given number of bytes, which is less than its headers.
+    Declared(usize),
+    /// The name table of the given size ends past the end of the archive.
+    NameTable(usize),
+    /// The file with the given index ends past the end of the archive.
+    EntryOutOfBounds(usize),
+}
+
+impl std::error::Error for OneError {}
+
+impl std::fmt::Display for OneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(
+                f,
+                "The file is too short to be a .one archive ({len} bytes, the headers alone need {}).",
+                HEADER_SIZE_ONE + NAMES_HEADER_SIZE
+            ),
+            Self::Size { declared, actual } => write!(
+                f,
+                "The header says the archive is {declared} bytes, but the file is only {actual} bytes."
+            ),
+            Self::Declared(size) => write!(
+                f,
+                "The header says the archive is {size} bytes, but its headers alone need {}.",
+                HEADER_SIZE_ONE + NAMES_HEADER_SIZE
+            ),
+            Self::NameTable(size) => write!(
+                f,
+                "The name table of {size} bytes ends past the end of the archive."
+            ),
+            Self::EntryOutOfBounds(index) => {
+                write!(f, "File {index} ends past the end of the archive.")
+            }
+        }
+    }
+}
+
+impl OneError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        Failure::Codec
+    }
+}
+
+/// A single file of a `.one` archive.
+pub struct OneEntry<'a> {
+    /// The name of the file, if its index points into the name table.
+    pub name: Option<String>,
+    /// Where the compressed data of the file starts in the archive.
+    pub offset: usize,
+    /// The PRS-compressed data of the file.
+    pub data: &'a [u8],
+}
+
+/// Parses the `.one` archive in `buf`, checking that the name table and every file are inside of
+/// it.
+pub fn parse(buf: &[u8]) -> Result<Vec<OneEntry<'_>>, OneError> {
+    if buf.len() < HEADER_SIZE_ONE + NAMES_HEADER_SIZE {
+        return Err(OneError::TooShort(buf.len()));
+    }
+    let declared =
+        read_u32(buf, 0x04).ok_or(OneError::TooShort(buf.len()))? as usize + HEADER_SIZE_ONE;
+    if declared > buf.len() {
+        return Err(OneError::Size {
+            declared,
+            actual: buf.len(),
+        });
+    }
+    if declared < HEADER_SIZE_ONE + NAMES_HEADER_SIZE {
+        return Err(OneError::Declared(declared));
+    }
+    // anything past the size the header declares is padding
+    let buf = &buf[..declared];
+
+    let names_size =
+        read_u32(buf, HEADER_SIZE_ONE + 0x04).ok_or(OneError::Declared(declared))? as usize;
+    let names_start = HEADER_SIZE_ONE + NAMES_HEADER_SIZE;
+    let names = names_start
+        .checked_add(names_size)
+        .and_then(|end| buf.get(names_start..end))
+        .ok_or(OneError::NameTable(names_size))?;
+    let names: Vec<_> = names.chunks_exact(NAME_SIZE).map(name).collect();
+
+    let mut entries = Vec::new();
+    let mut offset = names_start + names_size;
+    // the rest of the archive can be padded with zeros
+    while buf
+        .get(offset..)
+        .is_some_and(|rest| rest.iter().any(|&byte| byte != 0))
+    {
+        let index = entries.len();
+        let (Some(name_index), Some(size)) = (read_u32(buf, offset), read_u32(buf, offset + 0x04))
+        else {
+            return Err(OneError::EntryOutOfBounds(index));
+        };
+        let (name_index, size) = (name_index as usize, size as usize);
+        let start = offset + ENTRY_HEADER_SIZE;
+        let data = start
+            .checked_add(size)
+            .and_then(|end| buf.get(start..end))
+            .ok_or(OneError::EntryOutOfBounds(index))?;
+        entries.push(OneEntry {
+            name: names.get(name_index).cloned().flatten(),
+            offset: start,
+            data,
+        });
+        offset = start + size;
+    }
+    Ok(entries)
+}
+
+/// Returns the name in the given `field` of the name table, without the zeros it's padded with,
+/// if it's not empty.
+fn name(field: &[u8]) -> Option<String> {
+    let len = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    Some(String::from_utf8_lossy(&field[..len]).into_owned()).filter(|name| !name.is_empty())
+}
+
+/// Returns the little-endian `u32` at `offset`, if it's inside of `buf`.
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+impl OneEntry<'_> {
+    /// Returns the name of the file if it's usable as a file name.
+    pub fn file_name(&self) -> Option<&str> {
+        self.name
+            .as_deref()
+            .filter(|name| output::is_file_name(name))
+    }
+}
+
+/// Returns the file name `one extract` saves each of the given `entries` as, with the given
+/// `extension` unless it's given `--all`: the name of the file if it's usable as a file name, or
+/// its index in the archive followed by the extension of what it holds otherwise (e.g.
+/// `003.gvr`). Names are never used twice, see [`output::entry_file_names`].
+pub fn file_names(entries: &[OneEntry], extension: Option<&str>) -> Vec<String> {
+    let names = entries.iter().enumerate().map(|(index, entry)| {
+        let numbered = extension.unwrap_or_else(|| Contents::of(entry.data).0.extension());
+        (entry.file_name(), format!("{index:03}.{numbered}"))
+    });
+    output::entry_file_names(names, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a `.one` archive with the given names, and a file of each `(name index, data)`.
+    fn archive(names: &[&str], files: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut table = Vec::new();
+        for name in names {
+            let mut field = [0; NAME_SIZE];
+            field[..name.len()].copy_from_slice(name.as_bytes());
+            table.extend(field);
+        }
+        let mut body = vec![0; NAMES_HEADER_SIZE];
+        body[0x04..0x08].copy_from_slice(&(table.len() as u32).to_le_bytes());
+        body.extend(table);
+        for (name_index, data) in files {
+            body.extend(name_index.to_le_bytes());
+            body.extend((data.len() as u32).to_le_bytes());
+            body.extend(0x1400_FFFFu32.to_le_bytes());
+            body.extend(*data);
+        }
+        let mut buf = vec![0; HEADER_SIZE_ONE];
+        buf[0x04..0x08].copy_from_slice(&(body.len() as u32).to_le_bytes());
+        buf.extend(body);
+        buf
+    }
+
+    #[test]
+    fn parses_files() {
+        let mut buf = archive(&["a.gvr", "b.dff"], &[(0, b"first"), (1, b"second")]);
+        buf.extend([0; 0x20]);
+        let entries = parse(&buf).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name.as_deref(), Some("a.gvr"));
+        assert_eq!(entries[0].data, b"first");
+        assert_eq!(entries[1].name.as_deref(), Some("b.dff"));
+        assert_eq!(entries[1].data, b"second");
+    }
+
+    #[test]
+    fn declared_size_below_headers_fails() {
+        // the header says the archive ends right after it, so the name table isn't in it
+        let mut buf = vec![0; 0x118];
+        buf[0x10..0x14].copy_from_slice(&0x100u32.to_le_bytes());
+        assert!(matches!(
+            parse(&buf),
+            Err(OneError::Declared(HEADER_SIZE_ONE))
+        ));
+    }
+
+    #[test]
+    fn declared_size_past_end_fails() {
+        let mut buf = archive(&["a.gvr"], &[(0, b"data")]);
+        buf.truncate(buf.len() - 1);
+        assert!(matches!(parse(&buf), Err(OneError::Size { .. })));
+    }
+
+    #[test]
+    fn truncated_archives_dont_panic() {
+        let buf = archive(&["a.gvr", "b.gvr"], &[(0, b"first"), (1, b"second")]);
+        for len in 0..buf.len() {
+            let mut truncated = buf[..len].to_vec();
+            // keep the declared size in line, so that the files themselves are cut off
+            if len >= HEADER_SIZE_ONE {
+                let size = (len - HEADER_SIZE_ONE) as u32;
+                truncated[0x04..0x08].copy_from_slice(&size.to_le_bytes());
+            }
+            let _ = parse(&truncated);
+        }
+    }
+
+    #[test]
+    fn unusable_and_duplicate_names_are_numbered() {
+        let buf = archive(
+            &["tex.gvr", "TEX.GVR", "../up.gvr"],
+            &[(0, b"1"), (1, b"2"), (2, b"3"), (7, b"4")],
+        );
+        let entries = parse(&buf).unwrap();
+        assert_eq!(
+            file_names(&entries, None),
+            ["tex.gvr", "001.bin", "002.bin", "003.bin"]
+        );
+    }
+
+    #[test]
+    fn saved_names_never_collide() {
+        let buf = archive(
+            &["tex.prs", "tex.bin", "004.GVR", "000.gvr"],
+            &[
+                (9, b"1"),
+                (0, b"2"),
+                (1, b"3"),
+                (2, b"4"),
+                (9, b"5"),
+                (3, b"6"),
+            ],
+        );
+        let entries = parse(&buf).unwrap();
+        assert_eq!(
+            file_names(&entries, None),
+            [
+                "000.bin", "tex.prs", "tex.bin", "004.GVR", "004.bin", "000.gvr"
+            ]
+        );
+        // textures are saved with the `gvr` extension, so the names are compared with it, and a
+        // number that's taken gets a counter
+        assert_eq!(
+            file_names(&entries, Some("gvr")),
+            [
+                "000.gvr",
+                "tex.gvr",
+                "002.gvr",
+                "004.gvr",
+                "004_1.gvr",
+                "005.gvr"
+            ]
+        );
+    }
+}
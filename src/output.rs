@@ -0,0 +1,254 @@
+//! Writing of output files.
+
+use crate::error::Error;
+use crate::stdio;
+use crate::term::ceprintln;
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// What to do when an output file already exists.
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Overwrite {
+    /// Fail with an error naming the existing file.
+    Error,
+    /// Leave the existing file alone and skip the input.
+    Skip,
+    /// Replace the existing file.
+    Force,
+}
+
+/// Where the output files get written to.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Sink {
+    /// Writes the output files to disk, following the given overwrite policy.
+    Disk(Overwrite),
+    /// Doesn't write anything, for when only reporting what would be written.
+    DryRun,
+}
+
+impl Sink {
+    /// Writes `data` to the file at `path`, returning the amount of bytes written. If `path` is
+    /// `-`, the data is written to standard output instead.
+    ///
+    /// The data is first written to a temporary file next to `path`, which is then moved into
+    /// place, so an interrupted write never leaves a truncated output file behind. Unless the
+    /// overwrite policy is [`Overwrite::Force`], the move refuses to replace an existing file, so a
+    /// file that appeared since the up-front existence check is never clobbered either.
+    pub fn write(self, path: &Path, data: &[u8]) -> Result<usize, Error> {
+        match self {
+            Self::Disk(_) if stdio::is_stdio(path) => {
+                stdio::write_stdout(data).map_err(Error::Write)?
+            }
+            Self::Disk(overwrite) => write_atomic(path, data, overwrite)?,
+            Self::DryRun => {}
+        }
+        Ok(data.len())
+    }
+}
+
+/// Creates the parent directory of the given `output` path (and all of its missing ancestors), if
+/// it doesn't exist yet.
+pub fn create_parent_dir(output: &Path) -> Result<(), Error> {
+    match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            std::fs::create_dir_all(parent).map_err(|e| Error::CreateDir(parent.to_path_buf(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Counts the temporary files created by this process, to keep their names unique.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes `data` to `path` through a temporary file in the same directory.
+fn write_atomic(path: &Path, data: &[u8], overwrite: Overwrite) -> Result<(), Error> {
+    let temp = temp_path(path);
+    let result = create_new(&temp, data)
+        .map_err(Error::Write)
+        .and_then(|_| persist(&temp, path, overwrite));
+    let _ = std::fs::remove_file(&temp);
+
+    match result {
+        Err(Error::Write(e))
+            if matches!(e.kind(), ErrorKind::CrossesDevices | ErrorKind::Unsupported) =>
+        {
+            ceprintln!("<y!>warning:</> can't move a temporary file into place, writing directly:");
+            eprintln!("  {}", path.display());
+            write_direct(path, data, overwrite)
+        }
+        result => result,
+    }
+}
+
+/// Moves the finished `temp` file to `path`, taking the `overwrite` policy into account.
+///
+/// A rename replaces existing files (also on Windows), so it's only used when that's allowed.
+/// Otherwise, a hard link is created instead, which fails if `path` already exists. The `temp`
+/// file is left for the caller to remove in both cases.
+fn persist(temp: &Path, path: &Path, overwrite: Overwrite) -> Result<(), Error> {
+    let result = match overwrite {
+        Overwrite::Force => std::fs::rename(temp, path),
+        Overwrite::Error | Overwrite::Skip => std::fs::hard_link(temp, path),
+    };
+    result.map_err(|e| exists_error(path, e))
+}
+
+/// Overwrites the file at `path` with `data`, starting at `offset`, leaving the rest of the file
+/// as it is.
+///
+/// The changes are made to a temporary copy of the file, which then replaces the original, so an
+/// interrupted write never leaves a half-changed file behind.
+pub fn patch_file(path: &Path, offset: u64, data: &[u8]) -> Result<(), Error> {
+    let temp = temp_path(path);
+    let result = std::fs::copy(path, &temp)
+        .and_then(|_| {
+            let mut file = std::fs::File::options().write(true).open(&temp)?;
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temp, path))
+        .map_err(Error::Write);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Renames the file at `from` to `to`, failing with [`Error::OutputExists`] instead of replacing
+/// the file at `to` if it already exists.
+///
+/// Like [`persist`], this creates a hard link first and then removes `from`. If hard links aren't
+/// supported, it falls back to a plain rename after checking that `to` doesn't exist.
+pub fn rename_no_clobber(from: &Path, to: &Path) -> Result<(), Error> {
+    match std::fs::hard_link(from, to) {
+        Ok(()) => std::fs::remove_file(from).map_err(Error::Write),
+        Err(e) if e.kind() == ErrorKind::Unsupported => {
+            if to.exists() {
+                return Err(Error::OutputExists(to.to_path_buf()));
+            }
+            std::fs::rename(from, to).map_err(Error::Write)
+        }
+        Err(e) => Err(exists_error(to, e)),
+    }
+}
+
+/// Writes `data` straight to `path`, for when writing through a temporary file isn't possible.
+fn write_direct(path: &Path, data: &[u8], overwrite: Overwrite) -> Result<(), Error> {
+    match overwrite {
+        Overwrite::Force => std::fs::write(path, data).map_err(Error::Write),
+        Overwrite::Error | Overwrite::Skip => {
+            create_new(path, data).map_err(|e| exists_error(path, e))
+        }
+    }
+}
+
+/// Creates a new file at `path` with the given `data`, failing if the file already exists.
+fn create_new(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Turns an IO error from creating the file at `path` into an [`Error`], reporting an existing
+/// file with its path.
+fn exists_error(path: &Path, err: std::io::Error) -> Error {
+    match err.kind() {
+        ErrorKind::AlreadyExists => Error::OutputExists(path.to_path_buf()),
+        _ => Error::Write(err),
+    }
+}
+
+/// Returns the path of a new temporary file in the same directory as `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+/// How much of the work is still done in a dry run.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum DryRun {
+    /// Only validate the inputs and compute the output paths.
+    Paths,
+    /// Also do all the encoding and decoding, to know the size of each output file.
+    Full,
+}
+
+impl DryRun {
+    /// Returns the dry run mode selected by the `--dry-run` and `--dry-run-full` flags, if any.
+    pub fn from_flags(dry_run: bool, dry_run_full: bool) -> Option<Self> {
+        match (dry_run, dry_run_full) {
+            (_, true) => Some(Self::Full),
+            (true, false) => Some(Self::Paths),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Checks if the `name` of an entry of an archive is usable as a file name, which it isn't if it
+/// contains anything but printable ASCII characters, or any path separators.
+pub fn is_file_name(name: &str) -> bool {
+    name.bytes()
+        .all(|byte| byte.is_ascii_graphic() && !b"/\\:*?\"<>|".contains(&byte))
+        && name != "."
+        && name != ".."
+}
+
+/// Returns the file name each entry of an archive is extracted as, for `afs extract` and
+/// `one extract`. Each entry is given as its name if it's usable as a file name, and the name to
+/// number it with otherwise (e.g. `00003.gvr`). Names get their extension replaced by `extension`
+/// if it's given. An entry whose name an earlier entry already got, ignoring case, is numbered
+/// instead, and a number that's taken as well is followed by a counter (e.g. `00003_1.gvr`), so
+/// that no two entries are saved to the same file.
+pub fn entry_file_names<'a>(
+    entries: impl Iterator<Item = (Option<&'a str>, String)>,
+    extension: Option<&str>,
+) -> Vec<String> {
+    let mut taken = HashSet::new();
+    entries
+        .map(|(name, numbered)| {
+            let name = name.map(|name| match extension {
+                Some(extension) => Path::new(name)
+                    .with_extension(extension)
+                    .to_string_lossy()
+                    .into_owned(),
+                None => name.to_string(),
+            });
+            if let Some(name) = name
+                && taken.insert(name.to_ascii_lowercase())
+            {
+                return name;
+            }
+            let (stem, extension) = numbered.split_once('.').unwrap_or((&numbered, ""));
+            let mut name = numbered.clone();
+            let mut counter = 1;
+            while !taken.insert(name.to_ascii_lowercase()) {
+                name = format!("{stem}_{counter}.{extension}");
+                counter += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// Checks that the file at `path` can be opened for reading. Standard input (`-`) always counts
+/// as readable.
+pub fn check_readable(path: &Path) -> std::io::Result<()> {
+    if stdio::is_stdio(path) {
+        return Ok(());
+    }
+    std::fs::File::open(path).map(|_| ())
+}
@@ -0,0 +1,764 @@
+//! The color palettes of `index4` and `index8` textures: loading the fixed palettes of
+//! `--palette`, building the one of `--shared-palette`, and writing the palettes built by
+//! `--dither` and `--quantizer` into textures.
+//!
+//! The encoder always builds the palette itself, with imagequant, which doesn't keep the colors of
+//! an image exactly even when it has few enough of them, least of all the partly transparent ones.
+//! The texture is still encoded by the encoder for its header, but its data is then replaced with
+//! the palette and indices built here.
+
+use crate::batch;
+use crate::dither;
+use crate::encode::{self, EncodeSettings};
+use crate::error::Error;
+use crate::formats::{DataFormat, PixelFormat};
+use crate::header::{GvrHeader, HEADER_SIZE};
+use crate::indexed;
+use crate::quantize::ColorHistogram;
+use gvrtex::error::{TextureDecodeError, TextureEncodeError};
+use image::{GrayImage, ImageError, Luma, Rgba, RgbaImage};
+use log::{debug, info};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The most colors a palette can have, which is the size of the palette of `index8`.
+pub const MAX_COLORS: usize = 256;
+
+/// A palette given up front, which the pixels are matched to instead of building a palette for
+/// each image.
+#[derive(Copy, Clone)]
+pub struct FixedPalette {
+    colors: [Rgba<u8>; MAX_COLORS],
+    len: usize,
+}
+
+impl FixedPalette {
+    /// Creates a palette of the given `colors`, in the same order.
+    pub fn new(colors: &[Rgba<u8>]) -> Result<Self, PaletteError> {
+        if colors.is_empty() {
+            return Err(PaletteError::Empty);
+        }
+        if colors.len() > MAX_COLORS {
+            return Err(PaletteError::TooManyColors(colors.len()));
+        }
+        let mut palette = Self {
+            colors: [Rgba([0; 4]); MAX_COLORS],
+            len: colors.len(),
+        };
+        palette.colors[..colors.len()].copy_from_slice(colors);
+        Ok(palette)
+    }
+
+    /// Returns the colors of the palette.
+    pub fn colors(&self) -> &[Rgba<u8>] {
+        &self.colors[..self.len]
+    }
+}
+
+/// An entry of the palette that's kept fully transparent, for `--transparent-index`.
+#[derive(Copy, Clone)]
+pub struct TransparentIndex {
+    /// Where the transparent color is in the palette.
+    pub index: u8,
+    /// The pixels whose alpha is below this get the transparent color, and only those.
+    pub cutoff: u8,
+}
+
+impl TransparentIndex {
+    /// Checks if a pixel with the given `alpha`, which goes from 0 to 255, gets the transparent
+    /// color.
+    pub fn covers(self, alpha: f32) -> bool {
+        alpha.round() < f32::from(self.cutoff)
+    }
+}
+
+/// Why a palette file couldn't be loaded.
+#[derive(Debug)]
+pub enum PaletteError {
+    /// The file couldn't be read.
+    Io(std::io::Error),
+    /// The image of the palette couldn't be decoded.
+    Image(ImageError),
+    /// The file extension isn't one of a supported palette file.
+    Extension,
+    /// The file isn't a valid palette file of the format of its extension.
+    Invalid(&'static str),
+    /// The palette has no colors.
+    Empty,
+    /// The palette has more colors than any texture can store.
+    TooManyColors(usize),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Image(err) => write!(f, "{err}"),
+            Self::Extension => write!(
+                f,
+                "Unknown palette file extension. Use an image like a .png file, or an .act, .pal or .gpl file."
+            ),
+            Self::Invalid(reason) => write!(f, "Invalid palette file: {reason}."),
+            Self::Empty => write!(f, "The palette has no colors."),
+            Self::TooManyColors(colors) => write!(
+                f,
+                "The palette has {colors} colors, but palettes can have at most {MAX_COLORS}."
+            ),
+        }
+    }
+}
+
+/// Loads the palette in `path`, the format of which is given by its file extension:
+///
+/// - `.act`: an Adobe color table, with 256 colors of 3 bytes each, optionally followed by the
+///   amount of colors that are used and the index of a transparent color.
+/// - `.pal`: a JASC palette, as a text file.
+/// - `.gpl`: a GIMP palette, as a text file.
+/// - Any image, like a `.png` strip: every pixel is a color, from left to right and top to bottom.
+///   Indexed-color PNG files, like the ones of `decode --keep-indexed`, give their palette instead.
+///
+/// Only images have partly transparent colors. The transparent color of an `.act` file is fully
+/// transparent, and all the other colors of the other formats are opaque. In an `rgb5a3` palette,
+/// opaque colors are stored with 5 bits for each channel, and the others with 3 bits of alpha and
+/// 4 bits for each channel.
+pub fn load(path: &Path) -> Result<FixedPalette, PaletteError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let colors = match extension.as_deref() {
+        Some("act") => parse_act(&std::fs::read(path).map_err(PaletteError::Io)?)?,
+        Some("pal") => parse_jasc(&std::fs::read_to_string(path).map_err(PaletteError::Io)?)?,
+        Some("gpl") => parse_gimp(&std::fs::read_to_string(path).map_err(PaletteError::Io)?)?,
+        Some("png") => {
+            let bytes = std::fs::read(path).map_err(PaletteError::Io)?;
+            match indexed::read_png(&bytes) {
+                Some(indexed) => indexed.colors,
+                None => image::load_from_memory_with_format(&bytes, image::ImageFormat::Png)
+                    .map_err(PaletteError::Image)?
+                    .into_rgba8()
+                    .pixels()
+                    .copied()
+                    .collect(),
+            }
+        }
+        Some(_) if image::ImageFormat::from_path(path).is_ok() => image::open(path)
+            .map_err(PaletteError::Image)?
+            .into_rgba8()
+            .pixels()
+            .copied()
+            .collect(),
+        _ => return Err(PaletteError::Extension),
+    };
+    let palette = FixedPalette::new(&colors)?;
+    info!("{}: loaded {} palette colors", path.display(), colors.len());
+    Ok(palette)
+}
+
+/// Parses an Adobe color table, which is 768 bytes of colors, optionally followed by a big endian
+/// 16-bit amount of colors used and a 16-bit index of the transparent color, which is `0xFFFF` if
+/// there is none.
+fn parse_act(bytes: &[u8]) -> Result<Vec<Rgba<u8>>, PaletteError> {
+    let (colors, transparent) = match bytes.len() {
+        768 => (MAX_COLORS, None),
+        772 => (
+            usize::from(u16::from_be_bytes([bytes[768], bytes[769]])).min(MAX_COLORS),
+            Some(usize::from(u16::from_be_bytes([bytes[770], bytes[771]]))),
+        ),
+        _ => {
+            return Err(PaletteError::Invalid(
+                "an .act file has to be 768 or 772 bytes",
+            ));
+        }
+    };
+    Ok(bytes[..colors * 3]
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, color)| {
+            let alpha = if Some(index) == transparent { 0 } else { 0xFF };
+            Rgba([color[0], color[1], color[2], alpha])
+        })
+        .collect())
+}
+
+/// Parses a JASC palette, which starts with the lines `JASC-PAL`, `0100` and the amount of colors,
+/// followed by a line of red, green and blue values for each color.
+fn parse_jasc(text: &str) -> Result<Vec<Rgba<u8>>, PaletteError> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("JASC-PAL") || lines.next() != Some("0100") {
+        return Err(PaletteError::Invalid(
+            "a .pal file has to start with `JASC-PAL` and `0100`",
+        ));
+    }
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.parse().ok())
+        .ok_or(PaletteError::Invalid("the amount of colors is missing"))?;
+    let colors = lines
+        .filter(|line| !line.is_empty())
+        .take(count)
+        .map(|line| {
+            let channels: Vec<u8> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| PaletteError::Invalid("a color isn't three numbers up to 255"))?;
+            match channels[..] {
+                [r, g, b] => Ok(Rgba([r, g, b, 0xFF])),
+                _ => Err(PaletteError::Invalid(
+                    "a color isn't three numbers up to 255",
+                )),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if colors.len() < count {
+        return Err(PaletteError::Invalid(
+            "there are fewer colors than the file says",
+        ));
+    }
+    Ok(colors)
+}
+
+/// Saves the `colors` of a palette to `path`, in the format given by its file extension, which is
+/// one of those [`load`] reads, or `.gpl` for a GIMP palette.
+///
+/// Images are 16 pixels wide if the amount of colors is a multiple of 16, like the full palettes
+/// of `index4` and `index8`, and a single row of pixels otherwise. The other formats drop the
+/// alpha channel, except that `.act` files keep the first fully transparent color as their
+/// transparent one.
+pub fn save(path: &Path, colors: &[Rgba<u8>]) -> Result<(), PaletteError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("act") => {
+            let mut bytes = vec![0; MAX_COLORS * 3];
+            for (entry, color) in bytes.chunks_exact_mut(3).zip(colors) {
+                entry.copy_from_slice(&color.0[..3]);
+            }
+            // the first fully transparent color is the transparent one
+            let transparent = colors.iter().position(|color| color[3] == 0);
+            bytes.extend((colors.len() as u16).to_be_bytes());
+            bytes.extend(
+                transparent
+                    .map_or(0xFFFF, |index| index as u16)
+                    .to_be_bytes(),
+            );
+            std::fs::write(path, bytes).map_err(PaletteError::Io)
+        }
+        Some("gpl") => {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let mut text = format!("GIMP Palette\nName: {name}\nColumns: 16\n#\n");
+            for (index, Rgba([r, g, b, _])) in colors.iter().enumerate() {
+                text.push_str(&format!("{r:3} {g:3} {b:3}\tIndex {index}\n"));
+            }
+            std::fs::write(path, text).map_err(PaletteError::Io)
+        }
+        Some("pal") => {
+            let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", colors.len());
+            for Rgba([r, g, b, _]) in colors {
+                text.push_str(&format!("{r} {g} {b}\r\n"));
+            }
+            std::fs::write(path, text).map_err(PaletteError::Io)
+        }
+        Some(_) if image::ImageFormat::from_path(path).is_ok() => {
+            let width = if colors.len().is_multiple_of(16) {
+                16
+            } else {
+                colors.len()
+            };
+            let height = colors.len().div_ceil(width.max(1));
+            let image = RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+                colors[y as usize * width + x as usize]
+            });
+            image.save(path).map_err(PaletteError::Image)
+        }
+        _ => Err(PaletteError::Extension),
+    }
+}
+
+/// Draws the `colors` of a palette as a swatch image, with a square of `cell` pixels for each of
+/// them, 16 to a row. The squares after the last color are left transparent.
+pub fn swatch(colors: &[Rgba<u8>], cell: u32) -> RgbaImage {
+    let rows = colors.len().div_ceil(16) as u32;
+    RgbaImage::from_fn(16 * cell, rows * cell, |x, y| {
+        let index = (y / cell * 16 + x / cell) as usize;
+        colors.get(index).copied().unwrap_or(Rgba([0; 4]))
+    })
+}
+
+/// Reads the color palette stored in the GVR texture file `texture`, with the colors the ones it
+/// decodes to. Returns the header of the texture along with it.
+pub fn read_texture(texture: &[u8]) -> Result<(GvrHeader, Vec<Rgba<u8>>), Error> {
+    let header = GvrHeader::parse(texture).map_err(Error::Header)?;
+    let data_format = header.data_format;
+    let Some(pixel_format) = header.pixel_format.filter(|_| data_format.is_indexed()) else {
+        return Err(Error::NoPalette(data_format));
+    };
+    if header.external_palette {
+        return Err(Error::ExternalPalette);
+    }
+    let size = data_format.palette_size() as usize;
+    let palette = texture
+        .get(HEADER_SIZE..HEADER_SIZE + size)
+        .ok_or(Error::TruncatedPalette {
+            size: texture.len(),
+            palette: size,
+        })?;
+    let colors = palette
+        .chunks_exact(2)
+        .map(|color| decode_color([color[0], color[1]], pixel_format))
+        .collect();
+    Ok((header, colors))
+}
+
+/// Replaces the color palette stored in the GVR texture file `texture` with `palette`, storing its
+/// colors in the pixel format of the texture and leaving the rest of the file untouched. The new
+/// palette needs as many colors as the old one has entries.
+pub fn replace(texture: &mut [u8], palette: &FixedPalette) -> Result<GvrHeader, Error> {
+    let (header, old) = read_texture(texture)?;
+    let colors = palette.colors();
+    if colors.len() != old.len() {
+        return Err(Error::PaletteEntries {
+            colors: colors.len(),
+            entries: old.len(),
+        });
+    }
+    let pixel_format = header
+        .pixel_format
+        .expect("Textures with a palette have a pixel format.");
+    for (entry, &color) in texture[HEADER_SIZE..].chunks_exact_mut(2).zip(colors) {
+        entry.copy_from_slice(&encode_color(color, pixel_format));
+    }
+    Ok(header)
+}
+
+/// Reads the palette index of every pixel of the given image `level` of the GVR texture file
+/// `texture` with the given `header`, which has one of the indexed data formats.
+pub fn read_indices(texture: &[u8], header: &GvrHeader, level: usize) -> Result<GrayImage, Error> {
+    let data_format = header.data_format;
+    let (block_width, block_height) = data_format.block_size();
+    let (width, height) = header.level_dimensions(level);
+    let data = usize::try_from(header.level_offset(level))
+        .ok()
+        .and_then(|offset| texture.get(HEADER_SIZE + offset..))
+        .filter(|data| data.len() as u64 >= header.level_size(level))
+        .ok_or(Error::Decode(TextureDecodeError::InvalidFile))?;
+
+    // the blocks go over the edges of textures whose size isn't a multiple of theirs
+    let mut indices = GrayImage::new(width, height);
+    let mut position = 0;
+    for block_y in (0..height).step_by(block_height as usize) {
+        for block_x in (0..width).step_by(block_width as usize) {
+            for y in block_y..block_y + block_height {
+                for x in block_x..block_x + block_width {
+                    let index = if data_format == DataFormat::Index8 {
+                        data[position]
+                    } else {
+                        // the first pixel of each byte is in its high nibble
+                        data[position / 2] >> (4 * (1 - position % 2)) & 0xF
+                    };
+                    position += 1;
+                    if x < width && y < height {
+                        indices.put_pixel(x, y, Luma([index]));
+                    }
+                }
+            }
+        }
+    }
+    Ok(indices)
+}
+
+/// Checks if saving a palette to `path` drops the alpha channel of its colors, which only images
+/// keep.
+pub fn drops_alpha(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            ["act", "pal", "gpl"]
+                .iter()
+                .any(|format| extension.eq_ignore_ascii_case(format))
+        })
+}
+
+/// Builds one palette for all the images in `inputs`, for `--shared-palette`, with the palette
+/// settings of `settings`. The images are loaded and changed like they are for encoding, using
+/// `jobs` worker threads, and only their colors are kept, so that large batches fit in memory.
+///
+/// Images that can't be loaded are left out, and fail when they're encoded instead. Returns the
+/// palette, with the colors the pixel format stores, and how many images it was built from, or
+/// `None` if none of them could be loaded.
+pub fn build_shared(
+    settings: &EncodeSettings,
+    inputs: &[&Path],
+    jobs: Option<NonZeroUsize>,
+) -> Result<Option<(FixedPalette, usize)>, Error> {
+    let histogram = Mutex::new(ColorHistogram::default());
+    let counted = batch::run(inputs, jobs, |input| match settings.palette_pixels(input) {
+        Ok(pixels) => {
+            histogram.lock().unwrap().add(pixels);
+            true
+        }
+        Err(e) => {
+            debug!("{}: left out of the shared palette: {e}", input.display());
+            false
+        }
+    });
+    let images = counted.into_iter().filter(|&counted| counted).count();
+    let histogram = histogram.into_inner().unwrap();
+    info!(
+        "building a shared palette from the {} colors of {images} images",
+        histogram.len()
+    );
+    if histogram.is_empty() {
+        return Ok(None);
+    }
+
+    let colors = settings
+        .quantizer
+        .shared_palette(
+            &histogram,
+            settings.palette_colors(),
+            settings.quantizer_iterations,
+        )
+        .map_err(|e| Error::Encode(TextureEncodeError::Palette(e)))?;
+    let (mut pixels, decoded) = dither::stored_palette(colors, settings.pixel_format);
+    if settings.stable_palette {
+        (pixels, _) = dither::sort_palette(pixels, decoded);
+    }
+    Ok(Some((FixedPalette::new(&pixels).unwrap(), images)))
+}
+
+/// Parses a GIMP palette, which starts with the line `GIMP Palette`, optionally followed by the
+/// `Name` and `Columns` of the palette, and then has a line of red, green and blue values for each
+/// color, which can be followed by the name of the color. Lines starting with `#` are comments.
+fn parse_gimp(text: &str) -> Result<Vec<Rgba<u8>>, PaletteError> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("GIMP Palette") {
+        return Err(PaletteError::Invalid(
+            "a .gpl file has to start with `GIMP Palette`",
+        ));
+    }
+    lines
+        .filter(|line| {
+            !line.is_empty()
+                && !line.starts_with('#')
+                && !line.starts_with("Name:")
+                && !line.starts_with("Columns:")
+        })
+        .map(|line| {
+            let channels: Vec<u8> = line
+                .split_whitespace()
+                .take(3)
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| {
+                    PaletteError::Invalid("a color doesn't start with three numbers up to 255")
+                })?;
+            match channels[..] {
+                [r, g, b] => Ok(Rgba([r, g, b, 0xFF])),
+                _ => Err(PaletteError::Invalid(
+                    "a color doesn't start with three numbers up to 255",
+                )),
+            }
+        })
+        .collect()
+}
+
+/// An image made of indices into a palette.
+pub struct Indexed {
+    /// The colors of the palette, as the pixels the encoder stores them for.
+    pub colors: Vec<Rgba<u8>>,
+    /// The index of the color of each pixel.
+    pub indices: GrayImage,
+}
+
+/// Replaces the data of an encoded `texture` of the same size as `indexed` with its palette and
+/// indices, stored the way the encoder of the given formats stores them.
+pub fn write(
+    texture: &mut Vec<u8>,
+    indexed: &Indexed,
+    data_format: DataFormat,
+    pixel_format: PixelFormat,
+) {
+    let (block_width, block_height) = data_format.block_size();
+    texture.truncate(HEADER_SIZE);
+
+    // the palette is padded with transparent black, like the encoder does
+    let padding = (data_format.palette_colors() as usize).saturating_sub(indexed.colors.len());
+    for &color in indexed
+        .colors
+        .iter()
+        .chain(std::iter::repeat_n(&Rgba([0; 4]), padding))
+    {
+        texture.extend(encode_color(color, pixel_format));
+    }
+
+    let indices = &indexed.indices;
+    let mut nibble = None;
+    for block_y in (0..indices.height()).step_by(block_height as usize) {
+        for block_x in (0..indices.width()).step_by(block_width as usize) {
+            for y in block_y..block_y + block_height {
+                for x in block_x..block_x + block_width {
+                    let index = indices.get_pixel(x, y)[0];
+                    if data_format == DataFormat::Index8 {
+                        texture.push(index);
+                    } else if let Some(high) = nibble.take() {
+                        texture.push(high << 4 | index & 0xF);
+                    } else {
+                        nibble = Some(index);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Counts how many colors of the palette the pixels of an encoded `texture` of one of the indexed
+/// formats use.
+pub fn colors_used(texture: &[u8], data_format: DataFormat) -> usize {
+    let data = texture
+        .get(HEADER_SIZE + data_format.palette_size() as usize..)
+        .unwrap_or_default();
+    let mut used = [false; 256];
+    for &byte in data {
+        if data_format == DataFormat::Index8 {
+            used[usize::from(byte)] = true;
+        } else {
+            used[usize::from(byte >> 4)] = true;
+            used[usize::from(byte & 0xF)] = true;
+        }
+    }
+    used.iter().filter(|&&used| used).count()
+}
+
+/// Returns the color a texture decodes the given palette color to, once it's stored in the given
+/// pixel format.
+pub fn stored_color(color: Rgba<u8>, pixel_format: PixelFormat) -> Rgba<u8> {
+    decode_color(encode_color(color, pixel_format), pixel_format)
+}
+
+/// Decodes a color of the palette stored in the given pixel format, like the decoder does.
+pub fn decode_color(stored: [u8; 2], pixel_format: PixelFormat) -> Rgba<u8> {
+    let stored = u16::from_be_bytes(stored);
+    let expand = |shift: u16, bits: u16| {
+        let max = (1 << bits) - 1;
+        ((stored >> shift & max) as f32 * 255.0 / max as f32) as u8
+    };
+    match pixel_format {
+        PixelFormat::IntensityA8 => {
+            let [alpha, intensity] = stored.to_be_bytes();
+            Rgba([intensity, intensity, intensity, alpha])
+        }
+        PixelFormat::Rgb565 => Rgba([expand(11, 5), expand(5, 6), expand(0, 5), 0xFF]),
+        PixelFormat::Rgb5a3 if stored & 0x8000 == 0 => {
+            Rgba([expand(8, 4), expand(4, 4), expand(0, 4), expand(12, 3)])
+        }
+        PixelFormat::Rgb5a3 => Rgba([expand(10, 5), expand(5, 5), expand(0, 5), 0xFF]),
+    }
+}
+
+/// Stores a color of the palette in the given pixel format, truncating each channel like the
+/// encoder does.
+fn encode_color(Rgba([r, g, b, a]): Rgba<u8>, pixel_format: PixelFormat) -> [u8; 2] {
+    let bits = |value: u8, bits: u32| u16::from(value >> (8 - bits));
+    let color = match pixel_format {
+        // grays keep their intensity, which the encoder comes out one too low for in some cases,
+        // so that exported palettes can be used as they are
+        PixelFormat::IntensityA8 if r == g && g == b => return [a, r],
+        PixelFormat::IntensityA8 => return [a, encode::encoded_intensity([r, g, b])],
+        PixelFormat::Rgb565 => bits(r, 5) << 11 | bits(g, 6) << 5 | bits(b, 5),
+        // colors that aren't close to opaque keep 3 bits of alpha and 4 of each color
+        PixelFormat::Rgb5a3 if a <= 0xDA => {
+            bits(a, 3) << 12 | bits(r, 4) << 8 | bits(g, 4) << 4 | bits(b, 4)
+        }
+        PixelFormat::Rgb5a3 => 0x8000 | bits(r, 5) << 10 | bits(g, 5) << 5 | bits(b, 5),
+    };
+    color.to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes `contents` to a fixture file called `name` in a directory of its own, and returns its
+    /// path.
+    fn fixture(name: &str, contents: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gvrtex-palette-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Returns the bytes of an `.act` file with the given `colors`, followed by the amount of
+    /// colors used and the transparent index if `trailer` is given.
+    fn act(colors: &[[u8; 3]], trailer: Option<(u16, u16)>) -> Vec<u8> {
+        let mut bytes = vec![0; MAX_COLORS * 3];
+        for (entry, color) in bytes.chunks_exact_mut(3).zip(colors) {
+            entry.copy_from_slice(color);
+        }
+        if let Some((count, transparent)) = trailer {
+            bytes.extend(count.to_be_bytes());
+            bytes.extend(transparent.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn act_without_trailer() {
+        let path = fixture("plain.act", &act(&[[255, 0, 0], [0, 255, 0]], None));
+        let palette = load(&path).unwrap();
+        // without the trailer all 256 colors are used, and all of them are opaque
+        assert_eq!(palette.colors().len(), MAX_COLORS);
+        assert_eq!(palette.colors()[0], Rgba([255, 0, 0, 255]));
+        assert_eq!(palette.colors()[1], Rgba([0, 255, 0, 255]));
+        assert!(palette.colors().iter().all(|color| color[3] == 255));
+    }
+
+    #[test]
+    fn act_with_transparent_index() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let path = fixture("transparent.act", &act(&colors, Some((3, 1))));
+        let palette = load(&path).unwrap();
+        assert_eq!(
+            palette.colors(),
+            [
+                Rgba([255, 0, 0, 255]),
+                Rgba([0, 255, 0, 0]),
+                Rgba([0, 0, 255, 255]),
+            ]
+        );
+
+        // 0xFFFF is no transparent color at all
+        let colors = parse_act(&act(&colors, Some((3, 0xFFFF)))).unwrap();
+        assert!(colors.iter().all(|color| color[3] == 255));
+    }
+
+    #[test]
+    fn act_of_the_wrong_size() {
+        let mut bytes = act(&[], None);
+        bytes.push(0);
+        assert!(matches!(parse_act(&bytes), Err(PaletteError::Invalid(_))));
+    }
+
+    #[test]
+    fn jasc_palette() {
+        let text = "JASC-PAL\r\n0100\r\n3\r\n255 0 0\r\n0 128 0\r\n\r\n0 0 64\r\n";
+        let palette = load(&fixture("colors.pal", text.as_bytes())).unwrap();
+        assert_eq!(
+            palette.colors(),
+            [
+                Rgba([255, 0, 0, 255]),
+                Rgba([0, 128, 0, 255]),
+                Rgba([0, 0, 64, 255]),
+            ]
+        );
+
+        let invalid = [
+            "JASC-PAL\n0100\n",
+            "JASC-PAL\n0100\n3\n255 0 0\n",
+            "JASC-PAL\n0100\n1\n256 0 0\n",
+            "JASC-PAL\n0100\n1\n255 0\n",
+            "GIMP Palette\n",
+        ];
+        for text in invalid {
+            assert!(
+                matches!(parse_jasc(text), Err(PaletteError::Invalid(_))),
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn gimp_palette() {
+        let text = "GIMP Palette\nName: Test\nColumns: 16\n#\n255   0   0\tRed\n  0 128   0\n# comment\n0 0 64 Dark blue\n";
+        let palette = load(&fixture("colors.gpl", text.as_bytes())).unwrap();
+        assert_eq!(
+            palette.colors(),
+            [
+                Rgba([255, 0, 0, 255]),
+                Rgba([0, 128, 0, 255]),
+                Rgba([0, 0, 64, 255]),
+            ]
+        );
+
+        for text in ["JASC-PAL\n", "GIMP Palette\n255 0\n", "GIMP Palette\nred\n"] {
+            assert!(
+                matches!(parse_gimp(text), Err(PaletteError::Invalid(_))),
+                "{text:?}"
+            );
+        }
+        assert!(matches!(
+            load(&fixture("empty.gpl", b"GIMP Palette\n")),
+            Err(PaletteError::Empty)
+        ));
+    }
+
+    #[test]
+    fn png_strip() {
+        let colors = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 255, 0, 128]),
+            Rgba([0, 0, 255, 0]),
+        ];
+        let path = fixture("strip.png", &[]);
+        RgbaImage::from_fn(3, 1, |x, _| colors[x as usize])
+            .save(&path)
+            .unwrap();
+        // images are the only palette files with partly transparent colors
+        assert_eq!(load(&path).unwrap().colors(), colors);
+
+        assert!(matches!(
+            load(&fixture("colors.txt", b"")),
+            Err(PaletteError::Extension)
+        ));
+    }
+
+    #[test]
+    fn swatch_layout() {
+        let colors: Vec<_> = (0..20).map(|i| Rgba([i * 10, 0, 255 - i, 255])).collect();
+        let image = swatch(&colors, 3);
+        // 16 squares to a row, with the second row only partly filled
+        assert_eq!(image.dimensions(), (48, 6));
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let index = (y / 3 * 16 + x / 3) as usize;
+            let expected = colors.get(index).copied().unwrap_or(Rgba([0; 4]));
+            assert_eq!(*pixel, expected, "{x}, {y}");
+        }
+
+        assert_eq!(swatch(&colors[..16], 1).dimensions(), (16, 1));
+    }
+
+    #[test]
+    fn alpha_in_rgb5a3_palettes() {
+        let colors = parse_act(&act(&[[255, 0, 0], [0, 255, 0]], Some((2, 1)))).unwrap();
+        // opaque colors keep 5 bits for each channel
+        assert_eq!(encode_color(colors[0], PixelFormat::Rgb5a3), [0xFC, 0x00]);
+        assert_eq!(
+            stored_color(colors[0], PixelFormat::Rgb5a3),
+            Rgba([255, 0, 0, 255])
+        );
+        // the transparent color of an .act file has no alpha bits set
+        assert_eq!(encode_color(colors[1], PixelFormat::Rgb5a3), [0x00, 0xF0]);
+        assert_eq!(stored_color(colors[1], PixelFormat::Rgb5a3)[3], 0);
+
+        // the colors of .pal and .gpl files are all opaque
+        let colors = parse_gimp("GIMP Palette\n8 16 24\n").unwrap();
+        assert_eq!(
+            stored_color(colors[0], PixelFormat::Rgb5a3),
+            Rgba([8, 16, 24, 255])
+        );
+
+        // partly transparent colors of images keep 3 bits of alpha and 4 of each channel
+        let color = Rgba([0xFF, 0x80, 0x00, 0x80]);
+        assert_eq!(encode_color(color, PixelFormat::Rgb5a3), [0x4F, 0x80]);
+        assert_eq!(
+            stored_color(color, PixelFormat::Rgb5a3),
+            Rgba([255, 136, 0, 145])
+        );
+    }
+}
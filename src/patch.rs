@@ -0,0 +1,100 @@
+//! Editing the header of GVR texture files in place, without touching the texture data.
+
+use crate::error::{Error, Failure};
+use crate::formats::HeaderId;
+use crate::header::{GvrHeader, HeaderError};
+use crate::output;
+use std::num::ParseIntError;
+use std::path::Path;
+
+/// Where the magic string is stored in the GCIX/GBIX header.
+const MAGIC_OFFSET: u64 = 0x00;
+/// Where the global index is stored in the GCIX/GBIX header, as a big-endian u32.
+const GLOBAL_INDEX_OFFSET: u64 = 0x08;
+
+/// Contains all the possible errors that can occur while editing a header.
+#[derive(Debug)]
+pub enum PatchError {
+    /// The header couldn't be read or parsed.
+    Header(HeaderError),
+    /// The file name doesn't contain a number to use as the global index.
+    NoIndexInName,
+    /// The edited header couldn't be written.
+    Write(Error),
+}
+
+impl std::error::Error for PatchError {}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Header(err) => write!(f, "{err}"),
+            Self::NoIndexInName => write!(f, "The file name doesn't contain a number."),
+            Self::Write(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<HeaderError> for PatchError {
+    fn from(value: HeaderError) -> Self {
+        Self::Header(value)
+    }
+}
+
+impl PatchError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        match self {
+            Self::Header(err) => err.into(),
+            Self::NoIndexInName => Failure::Usage,
+            Self::Write(err) => err.failure(),
+        }
+    }
+}
+
+/// Parses a number given on the command line, either in decimal or in hexadecimal with a `0x`
+/// prefix.
+pub fn parse_number(text: &str) -> Result<u64, ParseIntError> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+}
+
+/// Parses a global index given on the command line, like [`parse_number`].
+pub fn parse_index(text: &str) -> Result<u32, String> {
+    let index = parse_number(text).map_err(|e| format!("invalid index `{text}`: {e}"))?;
+    u32::try_from(index).map_err(|_| format!("the index `{text}` doesn't fit in 32 bits"))
+}
+
+/// Returns the last number in the file stem of `path` (e.g. 42 for `stage1_tex042.gvr`), to use
+/// as its global index.
+pub fn index_from_filename(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    stem[start..end].parse().ok()
+}
+
+/// Sets the global index in the header of the GVR file at `path` to `index`, returning the
+/// previous index. The file needs a GCIX/GBIX header, as that's where the index is stored.
+pub fn set_global_index(path: &Path, index: u32) -> Result<u32, PatchError> {
+    let header = GvrHeader::read(path)?;
+    if header.global_index != index {
+        output::patch_file(path, GLOBAL_INDEX_OFFSET, &index.to_be_bytes())
+            .map_err(PatchError::Write)?;
+    }
+    Ok(header.global_index)
+}
+
+/// Sets the magic string of the GVR file at `path` to the one of `header_id`, returning the
+/// previous header id. Both headers are laid out the same, so only the magic string changes.
+pub fn set_header_id(path: &Path, header_id: HeaderId) -> Result<HeaderId, PatchError> {
+    let header = GvrHeader::read(path)?;
+    if header.header_id != header_id {
+        output::patch_file(path, MAGIC_OFFSET, header_id.magic()).map_err(PatchError::Write)?;
+    }
+    Ok(header.header_id)
+}
@@ -0,0 +1,50 @@
+//! Progress reporting for batch operations.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::path::Path;
+
+/// A progress bar shown on stderr while processing a batch of files.
+///
+/// If the bar is disabled, all the methods do nothing, so callers don't need to check for it.
+pub struct Progress(Option<ProgressBar>);
+
+impl Progress {
+    /// Creates a new progress bar for processing `len` files. The bar is only shown if stderr is
+    /// a terminal and `quiet` isn't set.
+    pub fn new(len: usize, quiet: bool) -> Self {
+        if quiet || !std::io::stderr().is_terminal() {
+            return Self(None);
+        }
+
+        let bar = ProgressBar::new(len as u64).with_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] [{bar:30}] {pos}/{len} (ETA {eta}) {wide_msg}",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        );
+        Self(Some(bar))
+    }
+
+    /// Marks the given file as the one currently being processed.
+    pub fn start(&self, path: &Path) {
+        if let Some(bar) = &self.0 {
+            bar.set_message(path.display().to_string());
+        }
+    }
+
+    /// Marks a file as finished processing.
+    pub fn inc(&self) {
+        if let Some(bar) = &self.0 {
+            bar.inc(1);
+        }
+    }
+
+    /// Removes the progress bar from the terminal.
+    pub fn finish(&self) {
+        if let Some(bar) = &self.0 {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,521 @@
+//! PRS, the LZ77 compression many Sega games store their GVR textures and GVM archives with.
+//!
+//! The compressed data is a stream of commands, each picked by one or two control bits: a literal
+//! byte, a short copy of 2 to 5 bytes from up to 256 bytes back, or a long copy of up to 256 bytes
+//! from up to 8191 bytes back. The control bits are packed into bytes of their own, read from the
+//! lowest bit up, which sit in the stream right where the first of their bits is needed.
+
+use crate::error::Failure;
+use clap::ValueEnum;
+use log::info;
+use std::path::Path;
+
+/// The most bytes any PRS data is decompressed into, far more than any texture or archive of the
+/// games holds. Data that would decompress into more is treated as invalid, instead of filling up
+/// the memory.
+const MAX_SIZE: usize = 256 << 20;
+/// The magic strings of the files that are decompressed transparently: GVR textures, with or
+/// without their GCIX/GBIX header, and GVM archives.
+const KNOWN_MAGICS: [&[u8; 4]; 4] = [b"GCIX", b"GBIX", b"GVRT", b"GVMH"];
+/// The farthest back a short copy can reach.
+const SHORT_DISTANCE: usize = 0x100;
+/// The most bytes a short copy can copy.
+const SHORT_SIZE: usize = 5;
+/// The farthest back a long copy can reach. One more would be stored as 0, which marks the end of
+/// the data when the size is stored in its own byte.
+const LONG_DISTANCE: usize = 0x1FFF;
+/// The most bytes a long copy can copy.
+const LONG_SIZE: usize = 0x100;
+/// The most bytes a long copy can copy without storing the size in its own byte.
+const LONG_SIZE_INLINE: usize = 9;
+/// Number of bits of the hashes the earlier positions are looked up by.
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are tried at most to find the longest copy.
+const MAX_CANDIDATES: usize = 256;
+
+/// The compressions the output files can be compressed with.
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// PRS, which many Sega games load their GVR textures and GVM archives with.
+    Prs,
+}
+
+impl Compression {
+    /// Compresses `data` with this compression.
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Prs => compress(data),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prs => write!(f, "PRS"),
+        }
+    }
+}
+
+/// Contains all the possible errors that can occur while decompressing PRS data.
+#[derive(Debug)]
+pub enum PrsError {
+    /// The data ends in the middle of a command, or without the command that marks its end.
+    Truncated,
+    /// The copy at the given `position` of the compressed data reaches `distance` bytes back, past
+    /// the start of the decompressed data.
+    Distance { position: usize, distance: usize },
+    /// The data decompresses into more than the given number of bytes.
+    TooLarge(usize),
+}
+
+impl std::error::Error for PrsError {}
+
+impl std::fmt::Display for PrsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated => write!(
+                f,
+                "The PRS data ends in the middle of a command, before the end of the compressed data."
+            ),
+            Self::Distance { position, distance } => write!(
+                f,
+                "The copy at offset 0x{position:X} of the PRS data reaches {distance} bytes back, past the start of the decompressed data."
+            ),
+            Self::TooLarge(limit) => {
+                write!(f, "The PRS data decompresses into more than {limit} bytes.")
+            }
+        }
+    }
+}
+
+impl PrsError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        Failure::Codec
+    }
+}
+
+/// Reads the commands and the control bits of the PRS data in `src`.
+struct Reader<'a> {
+    src: &'a [u8],
+    position: usize,
+    /// The control bits that haven't been used yet, from the lowest bit up.
+    bits: u8,
+    bits_left: u8,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, PrsError> {
+        let byte = *self.src.get(self.position).ok_or(PrsError::Truncated)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn bit(&mut self) -> Result<bool, PrsError> {
+        if self.bits_left == 0 {
+            self.bits = self.byte()?;
+            self.bits_left = 8;
+        }
+        let bit = self.bits & 1 != 0;
+        self.bits >>= 1;
+        self.bits_left -= 1;
+        Ok(bit)
+    }
+}
+
+/// Writes the commands and the control bits of PRS data.
+struct Writer {
+    out: Vec<u8>,
+    /// Where the byte holding the current control bits is.
+    control: usize,
+    bits_used: u8,
+}
+
+impl Writer {
+    fn bit(&mut self, bit: bool) {
+        // the next byte of control bits goes right where its first bit is needed, which is where
+        // the decompressor reads it
+        if self.bits_used == 8 {
+            self.control = self.out.len();
+            self.out.push(0);
+            self.bits_used = 0;
+        }
+        if bit {
+            self.out[self.control] |= 1 << self.bits_used;
+        }
+        self.bits_used += 1;
+    }
+
+    fn literal(&mut self, byte: u8) {
+        self.bit(true);
+        self.out.push(byte);
+    }
+
+    fn copy(&mut self, distance: usize, size: usize) {
+        if distance <= SHORT_DISTANCE && size <= SHORT_SIZE {
+            let size = size - 2;
+            self.bit(false);
+            self.bit(false);
+            self.bit(size & 0b10 != 0);
+            self.bit(size & 0b01 != 0);
+            self.out.push((SHORT_DISTANCE - distance) as u8);
+        } else {
+            self.bit(false);
+            self.bit(true);
+            let value = ((0x2000 - distance) as u16) << 3;
+            if size <= LONG_SIZE_INLINE {
+                self.out.extend((value | (size - 2) as u16).to_le_bytes());
+            } else {
+                self.out.extend(value.to_le_bytes());
+                self.out.push((size - 1) as u8);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.bit(false);
+        self.bit(true);
+        self.out.extend([0, 0]);
+        self.out
+    }
+}
+
+/// Finds the earlier positions of the data being compressed that a copy can be made from.
+struct Matcher<'a> {
+    data: &'a [u8],
+    /// The last position each hash was seen at.
+    head: Vec<usize>,
+    /// The position before each position whose first three bytes have the same hash.
+    previous: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            previous: vec![usize::MAX; data.len()],
+        }
+    }
+
+    /// Returns the hash of the three bytes at `position`.
+    fn hash(&self, position: usize) -> usize {
+        let bytes = &self.data[position..position + 3];
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+        (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Makes `position` available to the copies of the positions after it.
+    fn insert(&mut self, position: usize) {
+        if position + 3 <= self.data.len() {
+            let hash = self.hash(position);
+            self.previous[position] = self.head[hash];
+            self.head[hash] = position;
+        }
+    }
+
+    /// Returns the distance and the size of the longest copy that can be made at `position`. The
+    /// size is 0 if there's none.
+    fn longest_copy(&self, position: usize) -> (usize, usize) {
+        let data = self.data;
+        let max_size = LONG_SIZE.min(data.len() - position);
+        let matching = |start: usize| {
+            data[start..]
+                .iter()
+                .zip(&data[position..position + max_size])
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+
+        let mut best = (0, 0);
+        if max_size >= 3 {
+            let mut candidate = self.head[self.hash(position)];
+            for _ in 0..MAX_CANDIDATES {
+                if candidate == usize::MAX || position - candidate > LONG_DISTANCE {
+                    break;
+                }
+                let size = matching(candidate);
+                if size > best.1 {
+                    best = (position - candidate, size);
+                    if size == max_size {
+                        break;
+                    }
+                }
+                candidate = self.previous[candidate];
+            }
+        }
+        // copies of only two bytes aren't hashed, but are still worth it as short copies
+        if best.1 < 2 && max_size >= 2 {
+            let nearest = (1..=SHORT_DISTANCE.min(position))
+                .find(|&distance| matching(position - distance) >= 2);
+            if let Some(distance) = nearest {
+                best = (distance, 2);
+            }
+        }
+        best
+    }
+}
+
+/// Compresses `data` with PRS.
+///
+/// Each copy is the longest one found among the last positions that start with the same three
+/// bytes, which doesn't give the smallest output possible, but is quick and comes close to it.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = Writer {
+        out: Vec::with_capacity(data.len() / 2),
+        control: 0,
+        bits_used: 8,
+    };
+    let mut matcher = Matcher::new(data);
+    let mut position = 0;
+    while position < data.len() {
+        let (distance, size) = matcher.longest_copy(position);
+        if size < 2 || (size == 2 && distance > SHORT_DISTANCE) {
+            writer.literal(data[position]);
+            matcher.insert(position);
+            position += 1;
+            continue;
+        }
+        writer.copy(distance, size);
+        for copied in position..position + size {
+            matcher.insert(copied);
+        }
+        position += size;
+    }
+    writer.finish()
+}
+
+/// Decompresses the PRS data in `buf`.
+pub fn decompress(buf: &[u8]) -> Result<Vec<u8>, PrsError> {
+    let mut out = Vec::new();
+    inflate(buf, &mut out, MAX_SIZE)?;
+    Ok(out)
+}
+
+/// Decompresses the PRS data in `buf` into `out`, failing with [`PrsError::TooLarge`] once it
+/// would hold more than `limit` bytes. The bytes up to the limit are still in `out` then.
+fn inflate(buf: &[u8], out: &mut Vec<u8>, limit: usize) -> Result<(), PrsError> {
+    let mut reader = Reader {
+        src: buf,
+        position: 0,
+        bits: 0,
+        bits_left: 0,
+    };
+    loop {
+        if reader.bit()? {
+            if out.len() == limit {
+                return Err(PrsError::TooLarge(limit));
+            }
+            out.push(reader.byte()?);
+            continue;
+        }
+
+        let position = reader.position;
+        let (distance, size) = if reader.bit()? {
+            let value = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+            if value == 0 {
+                return Ok(());
+            }
+            let distance = 0x2000 - usize::from(value >> 3);
+            let size = match value & 0x07 {
+                0 => usize::from(reader.byte()?) + 1,
+                size => usize::from(size) + 2,
+            };
+            (distance, size)
+        } else {
+            let size = ((usize::from(reader.bit()?) << 1) | usize::from(reader.bit()?)) + 2;
+            let distance = 0x100 - usize::from(reader.byte()?);
+            (distance, size)
+        };
+
+        if distance > out.len() {
+            return Err(PrsError::Distance { position, distance });
+        }
+        // the copied bytes can overlap the ones being written, repeating them
+        for _ in 0..size {
+            if out.len() == limit {
+                return Err(PrsError::TooLarge(limit));
+            }
+            out.push(out[out.len() - distance]);
+        }
+    }
+}
+
+/// Reads the file at `path`. If it doesn't start with the magic string of a GVR texture or a GVM
+/// archive, but holds PRS data that decompresses into one, the decompressed data is returned
+/// instead.
+pub fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    read_compressed(path).map(|(buffer, _)| buffer)
+}
+
+/// Same as [`read`], but also returns the compression the file was decompressed from, if it was,
+/// so that the commands which save the texture or archive again can compress it the same way.
+pub fn read_compressed(path: &Path) -> std::io::Result<(Vec<u8>, Option<Compression>)> {
+    let buffer = std::fs::read(path)?;
+    if has_known_magic(&buffer) {
+        return Ok((buffer, None));
+    }
+
+    if magic(&buffer).is_some_and(|magic| has_known_magic(&magic))
+        && let Ok(decompressed) = decompress(&buffer)
+    {
+        info!(
+            "{}: decompressed {} bytes of PRS data into {} bytes",
+            path.display(),
+            buffer.len(),
+            decompressed.len()
+        );
+        return Ok((decompressed, Some(Compression::Prs)));
+    }
+    Ok((buffer, None))
+}
+
+/// Returns the magic string the PRS data in `buf` decompresses into, if it's PRS data at all. Only
+/// the start is decompressed, to find out whether the rest is worth it.
+pub fn magic(buf: &[u8]) -> Option<[u8; 4]> {
+    let mut start = Vec::with_capacity(4);
+    match inflate(buf, &mut start, 4) {
+        Err(PrsError::TooLarge(_)) => start.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Returns whether `buf` starts with one of the magic strings of [`KNOWN_MAGICS`].
+fn has_known_magic(buf: &[u8]) -> bool {
+    buf.get(..4)
+        .is_some_and(|magic| KNOWN_MAGICS.iter().any(|known| magic == *known))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::DataFormat;
+    use crate::testing;
+
+    /// Returns `len` bytes of a xorshift sequence, so that the tests always see the same data.
+    fn noise(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Data with both short and long copies in it, and some literals in between.
+    fn sample() -> Vec<u8> {
+        let mut data = b"GCIX\x08\x00\x00\x00".to_vec();
+        data.extend(noise(1, 64));
+        data.extend(data.clone());
+        data.extend([0xAA; 300]);
+        data.extend(noise(2, 32));
+        data.extend_from_within(8..200);
+        data
+    }
+
+    #[test]
+    fn roundtrip() {
+        // repeats from right at the farthest distances, one longer than the longest copy
+        let mut far = noise(3, LONG_DISTANCE + 600);
+        let start = far.len() - LONG_DISTANCE;
+        far.extend_from_within(start..start + LONG_SIZE + 10);
+        let start = far.len() - SHORT_DISTANCE;
+        far.extend_from_within(start..start + SHORT_SIZE);
+        let inputs = [
+            Vec::new(),
+            vec![0x42],
+            sample(),
+            noise(4, 5000),
+            vec![0; 3 * LONG_SIZE + 1],
+            far,
+            testing::texture(DataFormat::Rgb5a3, 64, 64),
+        ];
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(
+                decompress(&compressed).unwrap(),
+                input,
+                "{} bytes",
+                input.len()
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_streams_fail() {
+        let compressed = compress(&sample());
+        assert!(decompress(&compressed).is_ok());
+        for len in 0..compressed.len() {
+            assert!(
+                decompress(&compressed[..len]).is_err(),
+                "the first {len} bytes decompressed"
+            );
+        }
+    }
+
+    #[test]
+    fn random_streams_dont_panic() {
+        for seed in 1..2000 {
+            let len = (seed as usize * 7) % 300;
+            if let Ok(out) = decompress(&noise(seed, len)) {
+                assert!(out.len() <= MAX_SIZE);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_streams_dont_panic() {
+        let compressed = compress(&sample());
+        for position in 0..compressed.len() {
+            for flip in [0x01, 0x10, 0x80, 0xFF] {
+                let mut corrupted = compressed.clone();
+                corrupted[position] ^= flip;
+                let _ = decompress(&corrupted);
+            }
+        }
+    }
+
+    #[test]
+    fn copy_before_start_fails() {
+        // a short copy from 1 byte back, before any byte was written
+        let result = decompress(&[0b0000_0000, 0xFF]);
+        assert!(matches!(
+            result,
+            Err(PrsError::Distance { distance: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn output_is_limited() {
+        // a single byte repeated by copies of 256 bytes from 1 byte back, over and over
+        let mut writer = Writer {
+            out: Vec::new(),
+            control: 0,
+            bits_used: 8,
+        };
+        writer.literal(0xAA);
+        for _ in 0..1000 {
+            writer.copy(1, LONG_SIZE);
+        }
+        let stream = writer.finish();
+        assert_eq!(decompress(&stream).unwrap().len(), 1 + 1000 * LONG_SIZE);
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            inflate(&stream, &mut out, 4096),
+            Err(PrsError::TooLarge(4096))
+        ));
+        assert_eq!(out.len(), 4096);
+    }
+
+    #[test]
+    fn magic_of_compressed_texture() {
+        let compressed = compress(&sample());
+        assert_eq!(magic(&compressed), Some(*b"GCIX"));
+        assert_eq!(magic(&[]), None);
+    }
+}
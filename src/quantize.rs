@@ -0,0 +1,398 @@
+//! Building the color palettes of the indexed formats, for `--quantizer`.
+//!
+//! The encoder builds its palettes with imagequant. The other quantizers build the palette here
+//! instead, and it's written to the texture in place of the encoder's one.
+//!
+//! For `--shared-palette`, the colors of all the images of a batch are counted in one
+//! [`ColorHistogram`] first, one image at a time, and a single palette is built from it.
+
+use clap::ValueEnum;
+use image::Rgba;
+use std::collections::HashMap;
+
+/// How many times `kmeans` refines its palette by default.
+pub const DEFAULT_ITERATIONS: u32 = 10;
+
+/// How NeuQuant samples the image: it learns from every 10th pixel, which is its usual compromise
+/// between speed and quality.
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// How many colors a [`ColorHistogram`] holds at most before it merges similar ones, which keeps
+/// the memory it takes bounded on large batches.
+const MAX_HISTOGRAM_COLORS: usize = 1 << 20;
+
+/// How many pixels NeuQuant learns from at most when building a palette from a histogram.
+const NEUQUANT_MAX_PIXELS: u64 = 1 << 20;
+
+/// The algorithms the color palettes can be built with.
+#[derive(Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
+pub enum PaletteQuantizer {
+    /// The one the encoder uses, imagequant.
+    #[default]
+    Imagequant,
+    /// Splits the colors of the image in two along their widest channel until there are enough
+    /// groups, and takes the average color of each group.
+    MedianCut,
+    /// Starts from the `median-cut` palette and refines it, moving each color to the average of
+    /// the pixels closest to it. This gives a lower error, but is slower.
+    Kmeans,
+    /// Trains a neural network on a sample of the pixels, which keeps colors that only cover a
+    /// small part of the image.
+    Neuquant,
+}
+
+impl std::fmt::Display for PaletteQuantizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_possible_value().unwrap().get_name())
+    }
+}
+
+impl PaletteQuantizer {
+    /// Builds a palette of at most `colors` colors for the `pixels` of an image of the given
+    /// dimensions. `kmeans` refines its palette up to `iterations` times.
+    pub fn palette(
+        self,
+        pixels: &[Rgba<u8>],
+        (width, height): (usize, usize),
+        colors: u32,
+        iterations: u32,
+    ) -> Result<Vec<Rgba<u8>>, imagequant::Error> {
+        let palette = match self {
+            Self::Imagequant => {
+                let pixels: Vec<_> = pixels
+                    .iter()
+                    .map(|&Rgba([r, g, b, a])| imagequant::RGBA::new(r, g, b, a))
+                    .collect();
+                let mut attributes = imagequant::new();
+                attributes.set_max_colors(colors)?;
+                let mut image = attributes.new_image(pixels, width, height, 0.0)?;
+                let mut quantized = attributes.quantize(&mut image)?;
+                quantized
+                    .palette_vec()
+                    .into_iter()
+                    .map(|color| Rgba([color.r, color.g, color.b, color.a]))
+                    .collect()
+            }
+            Self::MedianCut | Self::Kmeans => {
+                // a single image is small enough to count all of its colors exactly
+                let mut histogram = ColorHistogram {
+                    max_colors: usize::MAX,
+                    ..ColorHistogram::default()
+                };
+                histogram.add(pixels.iter().copied());
+                return self.shared_palette(&histogram, colors, iterations);
+            }
+            Self::Neuquant => {
+                let bytes: Vec<u8> = pixels.iter().flat_map(|pixel| pixel.0).collect();
+                neuquant(&bytes, colors)
+            }
+        };
+        Ok(palette)
+    }
+
+    /// Builds a palette of at most `colors` colors for all the pixels counted in the `histogram`,
+    /// like [`Self::palette`] does for a single image.
+    pub fn shared_palette(
+        self,
+        histogram: &ColorHistogram,
+        colors: u32,
+        iterations: u32,
+    ) -> Result<Vec<Rgba<u8>>, imagequant::Error> {
+        let entries = &histogram.entries;
+        let palette = match self {
+            Self::Imagequant => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|entry| {
+                        let [r, g, b, a] = entry.color;
+                        imagequant::HistogramEntry {
+                            color: imagequant::RGBA::new(r, g, b, a),
+                            count: entry.count.min(u64::from(u32::MAX)) as u32,
+                        }
+                    })
+                    .collect();
+                let mut attributes = imagequant::new();
+                attributes.set_max_colors(colors)?;
+                let mut shared = imagequant::Histogram::new(&attributes);
+                shared.add_colors(&entries, 0.0)?;
+                let mut quantized = shared.quantize(&attributes)?;
+                quantized
+                    .palette_vec()
+                    .into_iter()
+                    .map(|color| Rgba([color.r, color.g, color.b, color.a]))
+                    .collect()
+            }
+            Self::MedianCut => median_cut(entries, colors as usize),
+            Self::Kmeans => {
+                let palette = median_cut(entries, colors as usize);
+                kmeans(entries, palette, iterations)
+            }
+            Self::Neuquant => {
+                // every color is repeated about as often as it's in the images, scaled down to
+                // at most as many pixels as NeuQuant needs to learn from
+                let total: u64 = entries.iter().map(|entry| entry.count).sum();
+                let scale = NEUQUANT_MAX_PIXELS as f64 / total.max(NEUQUANT_MAX_PIXELS) as f64;
+                let bytes: Vec<u8> = entries
+                    .iter()
+                    .flat_map(|entry| {
+                        let repeats = ((entry.count as f64 * scale).round() as usize).max(1);
+                        std::iter::repeat_n(entry.color, repeats).flatten()
+                    })
+                    .collect();
+                neuquant(&bytes, colors)
+            }
+        };
+        Ok(palette)
+    }
+}
+
+/// Builds a palette of at most `colors` colors with NeuQuant, from the RGBA `bytes` of pixels.
+fn neuquant(bytes: &[u8], colors: u32) -> Vec<Rgba<u8>> {
+    color_quant::NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, colors as usize, bytes)
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|color| Rgba([color[0], color[1], color[2], color[3]]))
+        .collect()
+}
+
+/// Counts how many pixels have each color, over any amount of images, in the order the colors
+/// first appear.
+///
+/// Once there are more than [`MAX_HISTOGRAM_COLORS`] colors, the lowest bit of every channel is
+/// dropped, merging the colors that only differ in it, as many times as it takes.
+pub struct ColorHistogram {
+    indices: HashMap<[u8; 4], usize>,
+    entries: Vec<Entry>,
+    /// How many colors there can be before bits are dropped.
+    max_colors: usize,
+    /// How many of the lowest bits of each channel are dropped.
+    dropped_bits: u32,
+}
+
+impl Default for ColorHistogram {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::new(),
+            entries: Vec::new(),
+            max_colors: MAX_HISTOGRAM_COLORS,
+            dropped_bits: 0,
+        }
+    }
+}
+
+impl ColorHistogram {
+    /// Counts the colors of the given `pixels`.
+    pub fn add(&mut self, pixels: impl IntoIterator<Item = Rgba<u8>>) {
+        for pixel in pixels {
+            self.count(pixel.0, 1);
+        }
+    }
+
+    /// Returns how many distinct colors were counted.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks if no colors were counted yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Counts `count` more pixels of the given `color`.
+    fn count(&mut self, color: [u8; 4], count: u64) {
+        // the dropped bits are set to the middle of the colors that are merged
+        let color = match self.dropped_bits {
+            0 => color,
+            bits => color.map(|value| value & u8::MAX << bits | 1 << (bits - 1)),
+        };
+        let index = *self.indices.entry(color).or_insert_with(|| {
+            self.entries.push(Entry { color, count: 0 });
+            self.entries.len() - 1
+        });
+        self.entries[index].count += count;
+
+        if self.entries.len() > self.max_colors && self.dropped_bits < 7 {
+            self.dropped_bits += 1;
+            self.indices.clear();
+            for entry in std::mem::take(&mut self.entries) {
+                self.count(entry.color, entry.count);
+            }
+        }
+    }
+}
+
+/// A color of an image, together with how many pixels have it.
+#[derive(Copy, Clone)]
+struct Entry {
+    color: [u8; 4],
+    count: u64,
+}
+
+/// Returns the average color of the `entries`, weighted by how many pixels have each color.
+fn average(entries: &[Entry]) -> Rgba<u8> {
+    let total: u64 = entries.iter().map(|entry| entry.count).sum();
+    Rgba(std::array::from_fn(|channel| {
+        let sum: u64 = entries
+            .iter()
+            .map(|entry| u64::from(entry.color[channel]) * entry.count)
+            .sum();
+        ((sum + total / 2) / total.max(1)) as u8
+    }))
+}
+
+/// Returns the channel the colors of `entries` are spread the widest over, and how wide.
+fn widest_channel(entries: &[Entry]) -> (usize, u8) {
+    (0..4)
+        .map(|channel| {
+            let values = entries.iter().map(|entry| entry.color[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+/// Builds a palette of at most `colors` colors with the median cut algorithm. The group of colors
+/// with the widest channel, weighted by how many pixels it covers, is split in two along that
+/// channel, so that both halves cover about as many pixels, until there are `colors` groups.
+fn median_cut(histogram: &[Entry], colors: usize) -> Vec<Rgba<u8>> {
+    let mut entries = histogram.to_vec();
+    // each group is a range of `entries`, which are sorted along the channel it's split on
+    let mut groups = Vec::with_capacity(colors);
+    groups.push(0..entries.len());
+    while groups.len() < colors {
+        let Some((index, channel)) = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(index, group)| {
+                let (channel, range) = widest_channel(&entries[group.clone()]);
+                let pixels: u64 = entries[group.clone()].iter().map(|entry| entry.count).sum();
+                (index, channel, u64::from(range) * pixels)
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+            .map(|(index, channel, _)| (index, channel))
+        else {
+            break;
+        };
+
+        let group = groups[index].clone();
+        let slice = &mut entries[group.clone()];
+        slice.sort_by_key(|entry| entry.color[channel]);
+        let half = slice.iter().map(|entry| entry.count).sum::<u64>() / 2;
+        let mut pixels = 0;
+        let mut split = 1;
+        for (offset, entry) in slice.iter().enumerate() {
+            pixels += entry.count;
+            if pixels > half {
+                split = offset.clamp(1, slice.len() - 1);
+                break;
+            }
+        }
+        groups[index] = group.start..group.start + split;
+        groups.push(group.start + split..group.end);
+    }
+
+    groups
+        .into_iter()
+        .map(|group| average(&entries[group]))
+        .collect()
+}
+
+/// Refines a `palette` with Lloyd's k-means algorithm, up to `iterations` times or until it stops
+/// changing. Every color of the `histogram` is given to the closest color of the palette, which is
+/// then moved to the average of the colors it was given.
+fn kmeans(histogram: &[Entry], mut palette: Vec<Rgba<u8>>, iterations: u32) -> Vec<Rgba<u8>> {
+    for _ in 0..iterations {
+        let mut sums = vec![[0u64; 4]; palette.len()];
+        let mut counts = vec![0u64; palette.len()];
+        for entry in histogram {
+            let nearest = (0..palette.len())
+                .min_by_key(|&index| distance(entry.color, palette[index].0))
+                .unwrap_or(0);
+            for (sum, value) in sums[nearest].iter_mut().zip(entry.color) {
+                *sum += u64::from(value) * entry.count;
+            }
+            counts[nearest] += entry.count;
+        }
+
+        let mut changed = false;
+        for (color, (sum, count)) in palette.iter_mut().zip(sums.iter().zip(counts)) {
+            // colors that no pixel is closest to are left where they are
+            if count == 0 {
+                continue;
+            }
+            let average = Rgba(sum.map(|sum| ((sum + count / 2) / count) as u8));
+            changed |= *color != average;
+            *color = average;
+        }
+        if !changed {
+            break;
+        }
+    }
+    palette
+}
+
+/// Returns the squared distance between two colors.
+fn distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    (0..4)
+        .map(|channel| u32::from(a[channel].abs_diff(b[channel])).pow(2))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Returns the mean squared error of the `pixels` when each takes the nearest color of the
+    /// `palette`.
+    fn mse(pixels: &[Rgba<u8>], palette: &[Rgba<u8>]) -> f64 {
+        let total: u64 = pixels
+            .iter()
+            .map(|pixel| {
+                let nearest = palette.iter().map(|color| distance(pixel.0, color.0));
+                u64::from(nearest.min().unwrap())
+            })
+            .sum();
+        total as f64 / pixels.len() as f64
+    }
+
+    #[test]
+    fn kmeans_beats_median_cut() {
+        let pixels: Vec<_> = testing::photo().pixels().copied().collect();
+        for colors in [16, 256] {
+            let palette = |quantizer: PaletteQuantizer| {
+                let palette = quantizer
+                    .palette(&pixels, (64, 64), colors, DEFAULT_ITERATIONS)
+                    .unwrap();
+                assert!(palette.len() <= colors as usize, "{quantizer}");
+                palette
+            };
+            let median_cut = mse(&pixels, &palette(PaletteQuantizer::MedianCut));
+            let kmeans = mse(&pixels, &palette(PaletteQuantizer::Kmeans));
+            assert!(
+                kmeans < median_cut,
+                "{colors} colors: {kmeans} kmeans, {median_cut} median-cut"
+            );
+        }
+    }
+
+    #[test]
+    fn few_colors_are_kept() {
+        // an image with fewer colors than the palette keeps all of them exactly
+        let colors = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 0, 255, 128]),
+            Rgba([0, 0, 0, 0]),
+        ];
+        let pixels: Vec<_> = (0..64).map(|i| colors[i % 3]).collect();
+        for quantizer in [PaletteQuantizer::MedianCut, PaletteQuantizer::Kmeans] {
+            let palette = quantizer
+                .palette(&pixels, (8, 8), 16, DEFAULT_ITERATIONS)
+                .unwrap();
+            assert_eq!(mse(&pixels, &palette), 0.0, "{quantizer}");
+        }
+    }
+}
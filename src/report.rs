@@ -0,0 +1,351 @@
+//! Machine-readable reports, printed as JSON when requested.
+
+use crate::auto::Transparency;
+use crate::decode::SavedLevel;
+use crate::error::{Error, Failure};
+use crate::estimate::Estimate;
+use crate::formats::{Alpha, DataFormat, HeaderId, PixelFormat};
+use crate::header::GvrHeader;
+use crate::metrics::Metrics;
+use crate::suggest::Suggestion;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How errors are printed to stderr.
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// A colored heading describing what went wrong, followed by the error message.
+    Human,
+    /// A single line of JSON per error.
+    Json,
+}
+
+/// Whether errors are printed as JSON, set once from `--error-format`.
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Applies the given `--error-format` to all the errors printed from now on.
+pub fn set_error_format(format: ErrorFormat) {
+    JSON_ERRORS.store(format == ErrorFormat::Json, Ordering::Relaxed);
+}
+
+/// Checks if errors should be printed as JSON.
+pub fn json_errors() -> bool {
+    JSON_ERRORS.load(Ordering::Relaxed)
+}
+
+/// The header information of a GVR texture file, as printed by `info --json`.
+#[derive(Serialize)]
+pub struct InfoReport {
+    pub magic: HeaderId,
+    pub global_index: u32,
+    pub data_format: DataFormat,
+    /// Always `null` for data formats that aren't indexed.
+    pub pixel_format: Option<PixelFormat>,
+    pub width: u16,
+    pub height: u16,
+    pub mipmaps: bool,
+    pub data_size: u32,
+    pub file_size: u64,
+}
+
+impl InfoReport {
+    pub fn new(header: &GvrHeader, file_size: u64) -> Self {
+        Self {
+            magic: header.header_id,
+            global_index: header.global_index,
+            data_format: header.data_format,
+            pixel_format: header.pixel_format,
+            width: header.width,
+            height: header.height,
+            mipmaps: header.mipmaps,
+            data_size: header.data_size,
+            file_size,
+        }
+    }
+}
+
+/// A decoded GVR texture file, as printed by `decode --json`.
+#[derive(Serialize)]
+pub struct DecodeReport {
+    pub input: String,
+    pub output: String,
+    /// The header information of the input file, the same as `info --json` prints.
+    pub texture: InfoReport,
+    /// The files every mipmap level was saved to. Always `null` without `--all-mips`.
+    pub levels: Option<Vec<LevelReport>>,
+}
+
+/// A single mipmap level saved by `decode --all-mips`.
+#[derive(Serialize)]
+pub struct LevelReport {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl LevelReport {
+    pub fn new(level: &SavedLevel) -> Self {
+        Self {
+            path: level.path.display().to_string(),
+            width: level.width,
+            height: level.height,
+        }
+    }
+}
+
+/// The capabilities of a single data format, as printed by `formats --json`.
+#[derive(Serialize)]
+pub struct FormatReport {
+    pub name: DataFormat,
+    pub bits_per_pixel: u32,
+    pub alpha: Alpha,
+    pub mipmaps: bool,
+    /// Whether the format uses a color palette, whose format is set with `--pixel-format`.
+    pub palette: bool,
+    pub description: String,
+}
+
+impl FormatReport {
+    pub fn new(format: DataFormat) -> Self {
+        Self {
+            name: format,
+            bits_per_pixel: format.bits_per_pixel(),
+            alpha: format.alpha(),
+            mipmaps: format.supports_mipmaps(),
+            palette: format.is_indexed(),
+            description: format.description(),
+        }
+    }
+}
+
+/// A GVR texture found by `scan --json`.
+#[derive(Serialize)]
+pub struct ScanReport {
+    pub path: String,
+    pub magic: &'static str,
+    /// `null` if the header couldn't be parsed, in which case `error` says why.
+    pub texture: Option<TextureReport>,
+    pub error: Option<String>,
+    /// The new path of the file, if it was renamed by `--rename-ext`.
+    pub renamed_to: Option<String>,
+}
+
+/// An entry of a GVM archive, as printed by `gvm list --json`.
+#[derive(Serialize)]
+pub struct GvmEntryReport {
+    pub index: usize,
+    /// `null` if the entries of the archive have no names.
+    pub name: Option<String>,
+    /// What `gvm unpack` names the texture file, without the extension.
+    pub file_stem: String,
+    /// `null` if neither the entry nor the texture has a global index.
+    pub global_index: Option<u32>,
+    pub texture: TextureReport,
+    /// The size of the texture in the archive, in bytes.
+    pub size: usize,
+}
+
+/// The parts of a GVR header that describe the texture itself.
+#[derive(Serialize)]
+pub struct TextureReport {
+    pub data_format: DataFormat,
+    /// Always `null` for data formats that aren't indexed.
+    pub pixel_format: Option<PixelFormat>,
+    pub width: u16,
+    pub height: u16,
+    pub mipmaps: bool,
+}
+
+impl TextureReport {
+    pub fn new(header: &GvrHeader) -> Self {
+        Self {
+            data_format: header.data_format,
+            pixel_format: header.pixel_format,
+            width: header.width,
+            height: header.height,
+            mipmaps: header.mipmaps,
+        }
+    }
+}
+
+/// An error that occurred while processing a file, printed to stderr in JSON mode.
+#[derive(Serialize)]
+pub struct ErrorReport {
+    /// The step the error occurred in, e.g. `encode` or `write`.
+    pub stage: &'static str,
+    /// The category of the error, which decides the exit code.
+    pub kind: &'static str,
+    /// The file the error is about. This is `null` for errors that aren't about a single file.
+    pub path: Option<String>,
+    pub message: String,
+}
+
+impl ErrorReport {
+    pub fn new(
+        stage: &'static str,
+        failure: Failure,
+        path: Option<&Path>,
+        error: impl std::fmt::Display,
+    ) -> Self {
+        Self {
+            stage,
+            kind: failure.name(),
+            path: path.map(|path| path.display().to_string()),
+            message: error.to_string(),
+        }
+    }
+
+    /// Creates a report of an `error` that occurred while processing the file at `path`.
+    pub fn from_error(path: &Path, error: &Error) -> Self {
+        Self::new(error.stage(), error.failure(), Some(path), error)
+    }
+}
+
+/// The counts of a finished batch, printed to stderr as the last report in JSON mode.
+#[derive(Serialize)]
+pub struct SummaryReport {
+    pub summary: BatchCounts,
+}
+
+#[derive(Serialize)]
+pub struct BatchCounts {
+    /// What was done to the files, e.g. `encode`.
+    pub action: String,
+    pub succeeded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Prints the given `report` as a single line of JSON to stdout.
+pub fn print_json(report: &impl Serialize) {
+    println!("{}", serde_json::to_string(report).unwrap());
+}
+
+/// Prints the given `report` as a single line of JSON to stderr.
+pub fn eprint_json(report: &impl Serialize) {
+    eprintln!("{}", serde_json::to_string(report).unwrap());
+}
+
+/// The analysis of an image and the data formats recommended for it, as printed by
+/// `suggest --json`.
+#[derive(Serialize)]
+pub struct SuggestReport {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub power_of_two: bool,
+    /// Whether only some of the pixels were looked at, which makes the counts estimates.
+    pub sampled: bool,
+    pub colors: usize,
+    pub grayscale: bool,
+    pub alpha: Transparency,
+    pub transparent_pixels: u64,
+    pub translucent_pixels: u64,
+    pub suggestions: Vec<SuggestionReport>,
+}
+
+/// A single recommended data format in a [`SuggestReport`].
+#[derive(Serialize)]
+pub struct SuggestionReport {
+    pub data_format: DataFormat,
+    /// Always `null` for data formats that aren't indexed.
+    pub pixel_format: Option<PixelFormat>,
+    pub size: u64,
+    pub fits: bool,
+    pub reason: &'static str,
+}
+
+impl SuggestionReport {
+    pub fn new(suggestion: &Suggestion) -> Self {
+        Self {
+            data_format: suggestion.data_format,
+            pixel_format: suggestion.pixel_format,
+            size: suggestion.size,
+            fits: suggestion.fits,
+            reason: suggestion.reason,
+        }
+    }
+}
+
+/// The size of the texture files an image would make, as printed by `estimate --json`.
+#[derive(Serialize)]
+pub struct EstimateReport {
+    pub width: u32,
+    pub height: u32,
+    pub mipmaps: bool,
+    /// Sorted by size, smallest first.
+    pub formats: Vec<EstimateFormatReport>,
+}
+
+/// The size of the texture file a single data format would make, in an [`EstimateReport`].
+#[derive(Serialize)]
+pub struct EstimateFormatReport {
+    pub data_format: DataFormat,
+    pub size: u64,
+    pub levels: usize,
+    pub fits: bool,
+}
+
+impl EstimateFormatReport {
+    pub fn new(estimate: &Estimate) -> Self {
+        Self {
+            data_format: estimate.data_format,
+            size: estimate.size,
+            levels: estimate.levels,
+            fits: estimate.fits,
+        }
+    }
+}
+
+/// An encoded GVR texture file, as printed by `encode --json`.
+#[derive(Serialize)]
+pub struct EncodeReport {
+    pub input: String,
+    pub output: String,
+    /// `null` in a dry run with `--data-format auto` that doesn't encode.
+    pub data_format: Option<DataFormat>,
+    /// Always `null` for data formats that aren't indexed.
+    pub pixel_format: Option<PixelFormat>,
+    pub mipmaps: bool,
+    /// The dimensions, levels and size of the texture are `null` in a dry run that doesn't encode.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub levels: Option<usize>,
+    pub size: Option<usize>,
+    /// How many colors of the palette are used. Always `null` for data formats that aren't
+    /// indexed.
+    pub palette_colors: Option<usize>,
+    /// Only set with `--metrics`.
+    pub metrics: Option<MetricsReport>,
+    /// The size of the texture before it was compressed, only set with `--compress`.
+    pub uncompressed_size: Option<usize>,
+}
+
+/// How close an encoded texture is to its input image, as measured by `encode --metrics`. The
+/// PSNR values are in dB, and `null` for channels that are identical.
+#[derive(Serialize)]
+pub struct MetricsReport {
+    pub psnr: f64,
+    pub psnr_red: f64,
+    pub psnr_green: f64,
+    pub psnr_blue: f64,
+    /// Always `null` for data formats that don't store the alpha channel.
+    pub psnr_alpha: Option<f64>,
+    pub ssim: f64,
+}
+
+impl MetricsReport {
+    pub fn new(metrics: &Metrics) -> Self {
+        let [red, green, blue] = metrics.channel_psnr;
+        Self {
+            psnr: metrics.psnr,
+            psnr_red: red,
+            psnr_green: green,
+            psnr_blue: blue,
+            psnr_alpha: metrics.alpha_psnr,
+            ssim: metrics.ssim,
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Finding GVR textures by their contents, no matter what the files are named.
+
+use crate::header::{GvrHeader, HEADER_SIZE, HeaderError};
+use std::io::Read;
+use std::path::Path;
+
+/// A file that starts with the magic string of a GVR texture.
+pub struct Hit {
+    /// The magic string the file starts with, which is either "GCIX", "GBIX" or "GVRT".
+    pub magic: &'static str,
+    /// The header of the texture, unless it doesn't parse.
+    pub header: Result<GvrHeader, HeaderError>,
+}
+
+/// Checks if the file at `path` is a GVR texture, by only reading the start of the file.
+///
+/// Besides the usual GCIX/GBIX header, this also detects bare "GVRT" texture chunks, which some
+/// games store without a header in front of them.
+pub fn sniff(path: &Path) -> std::io::Result<Option<Hit>> {
+    let mut buf = Vec::with_capacity(HEADER_SIZE);
+    std::fs::File::open(path)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut buf)?;
+
+    let hit = match buf.get(..4) {
+        Some(b"GCIX") => Hit {
+            magic: "GCIX",
+            header: GvrHeader::parse(&buf),
+        },
+        Some(b"GBIX") => Hit {
+            magic: "GBIX",
+            header: GvrHeader::parse(&buf),
+        },
+        Some(b"GVRT") => Hit {
+            magic: "GVRT",
+            header: GvrHeader::parse_chunk(&buf),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(hit))
+}
@@ -0,0 +1,180 @@
+//! The end point fitting of libsquish, for `--dxt-backend squish` with the `dxt-alt` feature.
+//!
+//! This is a port of its three fits rather than a binding, so the blocks are still laid out by
+//! [`crate::dxt`] and the feature needs no other crates. The range fit takes the colors at both
+//! ends of the direction the block varies in the most. The cluster fit sorts the colors along
+//! that direction and tries every way of splitting them into runs that share an index, solving
+//! the end points of each split by least squares. The iterative cluster fit repeats that along
+//! the direction between the end points it found, until the order of the colors stops changing.
+
+use crate::dxt::{self, DxtQuality};
+
+/// How many times the iterative cluster fit sorts the colors again at most, like libsquish.
+const ITERATIONS: usize = 8;
+
+/// Fits the two end points of a block to its opaque `colors`, for the mode with 3 colors if
+/// `three` is set. `fast` uses the range fit, `normal` the cluster fit and `high` the iterative
+/// cluster fit.
+pub fn fit(colors: &[[i32; 3]], three: bool, quality: DxtQuality) -> [u16; 2] {
+    if colors.is_empty() {
+        return [0, 0];
+    }
+    let (mean, axis) = dxt::principal_axis(colors);
+    let Some(mut axis) = axis else {
+        let endpoint = dxt::to_rgb565(mean);
+        return [endpoint, endpoint];
+    };
+
+    if quality == DxtQuality::Fast {
+        let project = |color: &&[i32; 3]| dot(color.map(|value| value as f32), axis);
+        let start = colors
+            .iter()
+            .min_by(|a, b| project(a).total_cmp(&project(b)));
+        let end = colors
+            .iter()
+            .max_by(|a, b| project(a).total_cmp(&project(b)));
+        return [start, end].map(|color| dxt::to_rgb565(color.unwrap().map(|value| value as f32)));
+    }
+
+    let iterations = if quality == DxtQuality::High {
+        ITERATIONS
+    } else {
+        1
+    };
+    let mut best: Option<Cluster> = None;
+    let mut order = Vec::new();
+    for _ in 0..iterations {
+        let cluster = Cluster::fit(colors, axis, three);
+        if cluster.order == order {
+            break;
+        }
+        let [start, end] = cluster.endpoints.map(dxt::decode_rgb565);
+        let direction: [f32; 3] =
+            std::array::from_fn(|channel| (end[channel] - start[channel]) as f32);
+        order.clone_from(&cluster.order);
+        if best.as_ref().is_none_or(|best| cluster.error < best.error) {
+            best = Some(cluster);
+        }
+        let length = dot(direction, direction).sqrt();
+        if length < f32::EPSILON {
+            break;
+        }
+        axis = direction.map(|value| value / length);
+    }
+    best.map_or([0, 0], |best| best.endpoints)
+}
+
+/// The best end points the cluster fit found for one order of the colors.
+struct Cluster {
+    endpoints: [u16; 2],
+    /// Its error, leaving out the sum of the squared colors, which is the same for every split.
+    error: f32,
+    /// The index of each color, sorted along the axis.
+    order: Vec<usize>,
+}
+
+impl Cluster {
+    /// Sorts `colors` along `axis`, then tries every split of them into runs with the same index,
+    /// 4 of them or 3 if `three` is set, and keeps the one whose end points have the lowest error.
+    fn fit(colors: &[[i32; 3]], axis: [f32; 3], three: bool) -> Self {
+        let points: Vec<[f32; 3]> = colors
+            .iter()
+            .map(|color| color.map(|value| value as f32))
+            .collect();
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        order.sort_by(|&a, &b| dot(points[a], axis).total_cmp(&dot(points[b], axis)));
+
+        // the sum of the colors before each position, so that each run is summed at once
+        let mut sums = vec![[0.0f32; 3]; points.len() + 1];
+        for (position, &index) in order.iter().enumerate() {
+            sums[position + 1] =
+                std::array::from_fn(|channel| sums[position][channel] + points[index][channel]);
+        }
+        let run = |start: usize, end: usize| -> [f32; 3] {
+            std::array::from_fn(|channel| sums[end][channel] - sums[start][channel])
+        };
+
+        let count = points.len();
+        let mut best = Self {
+            endpoints: [0, 0],
+            error: f32::INFINITY,
+            order: Vec::new(),
+        };
+        let mut consider = |runs: &[(f32, usize, [f32; 3])]| {
+            if let Some((endpoints, error)) = solve(runs)
+                && error < best.error
+            {
+                best.endpoints = endpoints;
+                best.error = error;
+            }
+        };
+        // the runs take the indices from the first end point to the second
+        for first in 0..=count {
+            for second in first..=count {
+                if three {
+                    consider(&[
+                        (1.0, first, run(0, first)),
+                        (0.5, second - first, run(first, second)),
+                        (0.0, count - second, run(second, count)),
+                    ]);
+                    continue;
+                }
+                for third in second..=count {
+                    consider(&[
+                        (1.0, first, run(0, first)),
+                        (2.0 / 3.0, second - first, run(first, second)),
+                        (1.0 / 3.0, third - second, run(second, third)),
+                        (0.0, count - third, run(third, count)),
+                    ]);
+                }
+            }
+        }
+        best.order = order;
+        best
+    }
+}
+
+/// Solves the end points for runs of colors, given as how much of the first end point each run
+/// is made of, how many colors it has and their sum. Returns them stored as RGB565, with the
+/// error of the colors the decoder makes of them, or `None` if the runs don't fix them.
+fn solve(runs: &[(f32, usize, [f32; 3])]) -> Option<([u16; 2], f32)> {
+    let (mut aa, mut ab, mut bb) = (0.0, 0.0, 0.0);
+    let (mut ax, mut bx) = ([0.0f32; 3], [0.0f32; 3]);
+    for &(alpha, count, sum) in runs {
+        let beta = 1.0 - alpha;
+        let count = count as f32;
+        aa += alpha * alpha * count;
+        ab += alpha * beta * count;
+        bb += beta * beta * count;
+        for channel in 0..3 {
+            ax[channel] += alpha * sum[channel];
+            bx[channel] += beta * sum[channel];
+        }
+    }
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let first = std::array::from_fn(|channel| (ax[channel] * bb - bx[channel] * ab) / determinant);
+    let second = std::array::from_fn(|channel| (bx[channel] * aa - ax[channel] * ab) / determinant);
+    let endpoints = [dxt::to_rgb565(first), dxt::to_rgb565(second)];
+
+    // like libsquish, measure the error with the end points snapped to what can be stored
+    let [a, b] = endpoints.map(|endpoint| dxt::decode_rgb565(endpoint).map(|value| value as f32));
+    let error = (0..3)
+        .map(|channel| {
+            a[channel] * a[channel] * aa
+                + b[channel] * b[channel] * bb
+                + 2.0
+                    * (a[channel] * b[channel] * ab
+                        - a[channel] * ax[channel]
+                        - b[channel] * bx[channel])
+        })
+        .sum();
+    Some((endpoints, error))
+}
+
+/// Returns the dot product of two vectors.
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|channel| a[channel] * b[channel]).sum()
+}
@@ -0,0 +1,42 @@
+//! Support for `-` as a path, which stands for the standard input or output.
+
+use crate::error::Error;
+use std::io::{IsTerminal, Read, Write};
+use std::path::Path;
+
+/// Checks if the given `path` is `-`, meaning standard input or output should be used instead.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Reads all of standard input into a buffer. Fails if standard input is a terminal, since image
+/// data is never typed in by hand.
+pub fn read_stdin() -> Result<Vec<u8>, Error> {
+    let mut stdin = std::io::stdin().lock();
+    if stdin.is_terminal() {
+        return Err(Error::Stdin(std::io::Error::other(
+            "Standard input is a terminal. Pipe the image data into gvrtex instead, e.g. `convert input.png png:- | gvrtex encode - output.gvr`.",
+        )));
+    }
+
+    let mut buf = Vec::new();
+    stdin.read_to_end(&mut buf).map_err(Error::Stdin)?;
+    Ok(buf)
+}
+
+/// Writes `data` to standard output. Fails if standard output is a terminal, since the data is
+/// binary.
+///
+/// Rust never translates line endings on standard output, so this is safe for binary data on
+/// Windows as well.
+pub fn write_stdout(data: &[u8]) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if stdout.is_terminal() {
+        return Err(std::io::Error::other(
+            "Standard output is a terminal. Redirect it into a file or pipe it into another program instead.",
+        ));
+    }
+
+    stdout.write_all(data)?;
+    stdout.flush()
+}
@@ -0,0 +1,107 @@
+//! Recommending the data formats to encode an image in, for the `suggest` command.
+
+use crate::auto::{self, Analysis, AutoFormat, Transparency};
+use crate::dimensions;
+use crate::formats::{DataFormat, PixelFormat};
+use crate::header::HEADER_SIZE;
+
+/// The amount of pixels above which images are only sampled, unless an exact analysis is asked
+/// for. This keeps 4K images down to a fraction of a second.
+const SAMPLE_PIXELS: u64 = 1 << 20;
+
+/// A data format recommended for an image.
+pub struct Suggestion {
+    pub data_format: DataFormat,
+    /// The format of the color palette, for the indexed formats.
+    pub pixel_format: Option<PixelFormat>,
+    /// The size of the GVR texture file, without mipmaps.
+    pub size: u64,
+    /// Whether the image can be encoded at its size, without resizing or padding it. Images larger
+    /// than the GameCube supports never fit.
+    pub fits: bool,
+    pub reason: &'static str,
+}
+
+/// Returns which pixels to look at in an image of the given size, as the step between them in
+/// both directions. With `exact`, or if the image is small enough, this is 1.
+pub fn sample_step(width: u32, height: u32, exact: bool) -> u32 {
+    let pixels = u64::from(width) * u64::from(height);
+    if exact || pixels <= SAMPLE_PIXELS {
+        return 1;
+    }
+    (pixels as f64 / SAMPLE_PIXELS as f64).sqrt().ceil() as u32
+}
+
+/// Returns the data formats worth encoding an image with the given `analysis` and size in, the
+/// best one first. The first one is what `--data-format auto` would pick, without mipmaps.
+pub fn suggest(analysis: &Analysis, width: u32, height: u32) -> Vec<Suggestion> {
+    let transparency = analysis.transparency;
+    let palette = match transparency {
+        Transparency::None => PixelFormat::Rgb565,
+        _ => PixelFormat::Rgb5a3,
+    };
+    let auto = AutoFormat {
+        prefer_compressed: false,
+    };
+    let choice = auto::choose(analysis, auto, false, palette);
+
+    let mut candidates = vec![(choice.data_format, choice.reason)];
+    if analysis.grayscale {
+        if transparency == Transparency::None {
+            candidates.push((
+                DataFormat::Intensity4,
+                "half the size, with 16 shades of gray",
+            ));
+        } else {
+            candidates.push((DataFormat::IntensityA8, "the image is grayscale"));
+            candidates.push((
+                DataFormat::IntensityA4,
+                "half the size, with 16 shades of gray",
+            ));
+        }
+    }
+    if analysis.colors <= 16 {
+        candidates.push((DataFormat::Index4, "the image has at most 16 colors"));
+    }
+    if analysis.colors <= 256 {
+        candidates.push((DataFormat::Index8, "the image has at most 256 colors"));
+    }
+    match transparency {
+        Transparency::None => {
+            candidates.push((DataFormat::Rgb565, "keeps the colors at 16 bits"));
+            candidates.push((DataFormat::Dxt1, "the smallest, but with lossy compression"));
+        }
+        Transparency::Binary => {
+            candidates.push((DataFormat::Rgb5a3, "keeps the transparent pixels"));
+            candidates.push((
+                DataFormat::Dxt1,
+                "the smallest, and its 1-bit alpha fits the image",
+            ));
+        }
+        Transparency::Smooth => {
+            candidates.push((DataFormat::Rgb5a3, "keeps the alpha channel at 3 bits"));
+        }
+    }
+    candidates.push((DataFormat::Argb8888, "lossless, but the largest"));
+
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    for (data_format, reason) in candidates {
+        if suggestions
+            .iter()
+            .any(|suggestion| suggestion.data_format == data_format)
+        {
+            continue;
+        }
+        suggestions.push(Suggestion {
+            data_format,
+            pixel_format: data_format.is_indexed().then_some(palette),
+            size: HEADER_SIZE as u64
+                + u64::from(data_format.palette_size())
+                + data_format.level_size(width, height),
+            fits: dimensions::check(data_format, false, width, height).is_ok()
+                && dimensions::check_limits(width, height).is_ok(),
+            reason,
+        });
+    }
+    suggestions
+}
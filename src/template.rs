@@ -0,0 +1,244 @@
+//! Templates for naming the output files of a batch.
+//!
+//! A template is a file name containing placeholders in braces, like `{stem}_{format}.gvr`. Literal
+//! braces are written by doubling them (`{{` and `}}`). The numeric placeholders take an optional
+//! width, to which they get padded with zeros (e.g. `{index:04}`).
+
+use std::ffi::{OsStr, OsString};
+
+/// A parsed output file name template.
+#[derive(Clone, Debug)]
+pub struct NameTemplate {
+    parts: Vec<Part>,
+}
+
+/// A single piece of a template.
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    /// The file stem of the input file.
+    Stem,
+    /// The file extension of the input file, without the dot.
+    Extension,
+    /// The command-line name of the data format the texture is encoded with.
+    Format,
+    /// The global index stored in the texture, padded to the given width.
+    GlobalIndex(usize),
+    /// The position of the input file in the batch, starting at 0 and padded to the given width.
+    Index(usize),
+}
+
+/// The values the placeholders of a template get replaced with, for a single input file.
+pub struct TemplateValues<'a> {
+    pub stem: &'a OsStr,
+    pub extension: &'a OsStr,
+    pub format: &'a str,
+    pub global_index: u32,
+    pub index: usize,
+}
+
+/// Contains all the possible errors that can occur while parsing a template.
+#[derive(Debug)]
+pub enum TemplateError {
+    Empty,
+    /// A placeholder isn't one of the known ones.
+    UnknownPlaceholder(String),
+    /// A `{` isn't closed by a matching `}`.
+    UnclosedBrace,
+    /// A `}` doesn't close any placeholder, and isn't doubled either.
+    UnmatchedBrace,
+    /// The width given to a placeholder isn't a number.
+    InvalidWidth(String),
+    /// A width was given to a placeholder that isn't numeric.
+    UnexpectedWidth(String),
+    /// The template contains a path separator, which would save the file outside of its directory.
+    PathSeparator,
+}
+
+impl std::error::Error for TemplateError {}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "The template is empty."),
+            Self::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder `{{{name}}}`, expected one of `{{stem}}`, `{{ext}}`, `{{format}}`, `{{global_index}}` or `{{index}}`."
+            ),
+            Self::UnclosedBrace => write!(
+                f,
+                "Unclosed `{{` in the template. Use `{{{{` for a literal brace."
+            ),
+            Self::UnmatchedBrace => write!(
+                f,
+                "Unmatched `}}` in the template. Use `}}}}` for a literal brace."
+            ),
+            Self::InvalidWidth(width) => write!(f, "Invalid placeholder width `{width}`."),
+            Self::UnexpectedWidth(name) => {
+                write!(f, "The `{{{name}}}` placeholder doesn't take a width.")
+            }
+            Self::PathSeparator => write!(f, "The template can't contain path separators."),
+        }
+    }
+}
+
+impl NameTemplate {
+    /// Parses the given `template` string, checking that all of its placeholders are known.
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        if template.is_empty() {
+            return Err(TemplateError::Empty);
+        }
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => literal.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => literal.push('}'),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(TemplateError::UnclosedBrace),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::parse_placeholder(&placeholder)?);
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace),
+                c if std::path::is_separator(c) => return Err(TemplateError::PathSeparator),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Builds the file name for an input file, replacing the placeholders with the given `values`.
+    pub fn render(&self, values: &TemplateValues) -> OsString {
+        let mut name = OsString::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => name.push(text),
+                Part::Stem => name.push(values.stem),
+                Part::Extension => name.push(values.extension),
+                Part::Format => name.push(values.format),
+                Part::GlobalIndex(width) => name.push(format!("{:0width$}", values.global_index)),
+                Part::Index(width) => name.push(format!("{:0width$}", values.index)),
+            }
+        }
+        name
+    }
+}
+
+impl Part {
+    /// Parses the contents of a placeholder, without the braces.
+    fn parse_placeholder(placeholder: &str) -> Result<Self, TemplateError> {
+        let (name, width) = match placeholder.split_once(':') {
+            Some((name, width)) => (name, Some(width)),
+            None => (placeholder, None),
+        };
+        let width = match width {
+            Some(width) if !width.is_empty() && width.bytes().all(|b| b.is_ascii_digit()) => Some(
+                width
+                    .parse()
+                    .map_err(|_| TemplateError::InvalidWidth(width.to_string()))?,
+            ),
+            Some(width) => return Err(TemplateError::InvalidWidth(width.to_string())),
+            None => None,
+        };
+
+        let part = match name {
+            "global_index" => return Ok(Self::GlobalIndex(width.unwrap_or(0))),
+            "index" => return Ok(Self::Index(width.unwrap_or(0))),
+            "stem" => Self::Stem,
+            "ext" => Self::Extension,
+            "format" => Self::Format,
+            _ => return Err(TemplateError::UnknownPlaceholder(name.to_string())),
+        };
+        match width {
+            Some(_) => Err(TemplateError::UnexpectedWidth(name.to_string())),
+            None => Ok(part),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> OsString {
+        let values = TemplateValues {
+            stem: OsStr::new("grass"),
+            extension: OsStr::new("png"),
+            format: "dxt1",
+            global_index: 42,
+            index: 7,
+        };
+        NameTemplate::parse(template).unwrap().render(&values)
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(render("{stem}.gvr"), "grass.gvr");
+        assert_eq!(render("{stem}_{ext}.gvr"), "grass_png.gvr");
+        assert_eq!(render("{stem}_{format}.gvr"), "grass_dxt1.gvr");
+        assert_eq!(render("tex{global_index}.gvr"), "tex42.gvr");
+        assert_eq!(render("tex{index}.gvr"), "tex7.gvr");
+        assert_eq!(render("plain.gvr"), "plain.gvr");
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(render("{global_index:05}.gvr"), "00042.gvr");
+        assert_eq!(render("{index:04}.gvr"), "0007.gvr");
+        // a width that's too small for the number doesn't cut it off
+        assert_eq!(render("{global_index:1}.gvr"), "42.gvr");
+        assert_eq!(render("{index:0}.gvr"), "7.gvr");
+    }
+
+    #[test]
+    fn literal_braces() {
+        assert_eq!(render("{{stem}}.gvr"), "{stem}.gvr");
+        assert_eq!(render("{{{stem}}}.gvr"), "{grass}.gvr");
+        assert_eq!(render("a}}b{{c.gvr"), "a}b{c.gvr");
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |template| NameTemplate::parse(template).unwrap_err();
+        assert!(matches!(parse(""), TemplateError::Empty));
+        assert!(matches!(
+            parse("{name}.gvr"),
+            TemplateError::UnknownPlaceholder(name) if name == "name"
+        ));
+        assert!(matches!(parse("{stem.gvr"), TemplateError::UnclosedBrace));
+        assert!(matches!(parse("stem}.gvr"), TemplateError::UnmatchedBrace));
+        assert!(matches!(
+            parse("{index:x}.gvr"),
+            TemplateError::InvalidWidth(width) if width == "x"
+        ));
+        assert!(matches!(
+            parse("{index:}.gvr"),
+            TemplateError::InvalidWidth(width) if width.is_empty()
+        ));
+        assert!(matches!(
+            parse("{index:99999999999999999999999}.gvr"),
+            TemplateError::InvalidWidth(_)
+        ));
+        assert!(matches!(
+            parse("{stem:3}.gvr"),
+            TemplateError::UnexpectedWidth(name) if name == "stem"
+        ));
+        assert!(matches!(parse("out/{stem}.gvr"), TemplateError::PathSeparator));
+    }
+}
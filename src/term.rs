@@ -0,0 +1,33 @@
+//! Colored terminal output.
+//!
+//! The color codes of `color_print` are inserted at compile time, so the macros here print through
+//! `anstream` instead, which strips the codes again when the stream they're printed to shouldn't
+//! get any colors. Stdout and stderr are checked independently.
+
+/// Same as [`color_print::cprintln!`], but only prints colors when stdout supports them.
+macro_rules! cprintln {
+    ($($arg:tt)*) => {
+        anstream::println!("{}", color_print::cformat!($($arg)*))
+    };
+}
+
+/// Same as [`color_print::ceprintln!`], but only prints colors when stderr supports them.
+macro_rules! ceprintln {
+    ($($arg:tt)*) => {
+        anstream::eprintln!("{}", color_print::cformat!($($arg)*))
+    };
+}
+
+pub(crate) use {ceprintln, cprintln};
+
+/// Applies the given `--color` choice to all the output. With `auto`, the `NO_COLOR` and
+/// `CLICOLOR_FORCE` environment variables are respected, and otherwise colors are only used on
+/// terminals.
+pub fn set_color_choice(choice: clap::ColorChoice) {
+    let choice = match choice {
+        clap::ColorChoice::Auto => anstream::ColorChoice::Auto,
+        clap::ColorChoice::Always => anstream::ColorChoice::Always,
+        clap::ColorChoice::Never => anstream::ColorChoice::Never,
+    };
+    choice.write_global();
+}
@@ -0,0 +1,71 @@
+//! Helpers shared by the tests of the other modules, for building images and textures to test
+//! with.
+
+use crate::encode::EncodeSettings;
+use crate::formats::{DataFormat, HeaderId, PixelFormat};
+use crate::header::GvrHeader;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Returns the header of a texture in the given `data_format`, with the `rgb5a3` pixel format for
+/// the indexed formats.
+pub fn header(data_format: DataFormat) -> GvrHeader {
+    GvrHeader {
+        header_id: HeaderId::Gcix,
+        global_index: 0,
+        data_format,
+        pixel_format: data_format.is_indexed().then_some(PixelFormat::Rgb5a3),
+        width: 0,
+        height: 0,
+        mipmaps: false,
+        internal_palette: data_format.is_indexed(),
+        external_palette: false,
+        data_size: 0,
+    }
+}
+
+/// Returns the settings `encode` uses for the given `data_format` when no other options are given.
+pub fn settings(data_format: DataFormat) -> EncodeSettings {
+    EncodeSettings::from_header(&header(data_format), false)
+}
+
+/// Returns an image of the given size with a different color for every pixel: red goes up from
+/// left to right, green from top to bottom, and blue and alpha change with both.
+pub fn image(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let red = x * 255 / width.saturating_sub(1).max(1);
+        let green = y * 255 / height.saturating_sub(1).max(1);
+        Rgba([
+            red as u8,
+            green as u8,
+            ((x + y) * 16 % 256) as u8,
+            (255 - (x * y) % 128) as u8,
+        ])
+    })
+}
+
+/// Returns a GVR texture file of [`image`] with the given size, encoded in `data_format`.
+pub fn texture(data_format: DataFormat, width: u32, height: u32) -> Vec<u8> {
+    settings(data_format)
+        .encode_image(DynamicImage::ImageRgba8(image(width, height)))
+        .unwrap()
+}
+
+/// Returns a 64x64 opaque image that looks like a photo: smooth shading in all three channels,
+/// with a little noise on top of it.
+pub fn photo() -> RgbaImage {
+    let mut state = 0x2545_f491_u32;
+    let mut noise = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state % 9) as f32 - 4.0
+    };
+    RgbaImage::from_fn(64, 64, |x, y| {
+        let (x, y) = (x as f32 / 64.0, y as f32 / 64.0);
+        let r = 150.0 + 90.0 * (x * 5.0 + y * 2.0).sin();
+        let g = 110.0 + 70.0 * (y * 4.0).cos() * x;
+        let b = 60.0 + 50.0 * ((x - 0.5) * (y - 0.3) * 20.0).sin();
+        let [r, g, b] = [r, g, b].map(|value| (value + noise()).clamp(0.0, 255.0) as u8);
+        Rgba([r, g, b, 255])
+    })
+}
@@ -0,0 +1,612 @@
+//! TPL files, the texture files of the GameCube SDK, which hold any number of textures in the same
+//! data formats as GVR textures.
+//!
+//! The image data and the palettes of both are stored the same way, so converting between them
+//! only rebuilds the headers. The one TPL format GVR textures don't have, C14X2, is decoded and
+//! encoded again as `rgb5a3` instead.
+
+use crate::encode::EncodeSettings;
+use crate::error::{Error, Failure};
+use crate::formats::{DataFormat, HeaderId, PixelFormat};
+use crate::header::{GvrHeader, HEADER_SIZE};
+use crate::palette;
+use gvrtex::error::TextureDecodeError;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// The magic number every TPL file starts with.
+const MAGIC: [u8; 4] = [0x00, 0x20, 0xAF, 0x30];
+/// Size of the header at the start of the file, which is followed by the image table.
+const FILE_HEADER_SIZE: usize = 0x0C;
+/// Size of each entry of the image table, which holds the offsets of the image and palette
+/// headers of an image.
+const TABLE_ENTRY_SIZE: usize = 0x08;
+/// Size of the header of each image.
+const IMAGE_HEADER_SIZE: usize = 0x24;
+/// Size of the header of each palette.
+const PALETTE_HEADER_SIZE: usize = 0x0C;
+/// The image data and the palettes written are aligned to 32 bytes, like the GameCube needs.
+const DATA_ALIGNMENT: usize = 32;
+
+/// The code of the C14X2 format, 14-bit indices into a color palette.
+const FORMAT_C14X2: u32 = 0x0A;
+/// The min filter of images without mipmaps, which filters linearly.
+const FILTER_LINEAR: u32 = 1;
+/// The min filter of images with mipmaps, which filters linearly within and between levels.
+const FILTER_LINEAR_MIP_LINEAR: u32 = 5;
+
+/// Contains all the possible errors that can occur while parsing a TPL file.
+#[derive(Debug)]
+pub enum TplError {
+    /// The file is shorter than the header of a TPL file.
+    TooShort(usize),
+    /// The file doesn't start with the magic number of TPL files.
+    UnknownMagic([u8; 4]),
+    /// The table of `count` images at `offset` ends past the end of the file.
+    ImageTable { count: usize, offset: usize },
+    /// The given `part` of the image with the given index ends past the end of the file.
+    OutOfBounds { image: usize, part: &'static str },
+    /// The image with the given index has a format that isn't known.
+    UnknownFormat { image: usize, code: u32 },
+    /// The palette of the image with the given index has a pixel format that isn't known.
+    UnknownPaletteFormat { image: usize, code: u32 },
+    /// The image with the given index has one of the indexed formats, but no palette.
+    MissingPalette(usize),
+}
+
+impl std::error::Error for TplError {}
+
+impl std::fmt::Display for TplError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(
+                f,
+                "The file is too short to be a TPL file ({len} bytes, the header alone needs {FILE_HEADER_SIZE})."
+            ),
+            Self::UnknownMagic(magic) => write!(
+                f,
+                "Unknown magic number {}, expected the 00 20 AF 30 of TPL files.",
+                magic.map(|byte| format!("{byte:02X}")).join(" ")
+            ),
+            Self::ImageTable { count, offset } => write!(
+                f,
+                "The table of {count} images at offset 0x{offset:X} ends past the end of the file."
+            ),
+            Self::OutOfBounds { image, part } => {
+                write!(
+                    f,
+                    "The {part} of image {image} ends past the end of the file."
+                )
+            }
+            Self::UnknownFormat { image, code } => {
+                write!(f, "Image {image} has an unknown format 0x{code:02X}.")
+            }
+            Self::UnknownPaletteFormat { image, code } => write!(
+                f,
+                "The palette of image {image} has an unknown pixel format 0x{code:X}."
+            ),
+            Self::MissingPalette(image) => write!(
+                f,
+                "Image {image} has an indexed format, but no color palette."
+            ),
+        }
+    }
+}
+
+impl TplError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        Failure::Codec
+    }
+}
+
+/// The format of an image in a TPL file.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TplFormat {
+    /// One of the data formats of GVR textures, whose data is stored the same way in both.
+    Shared(DataFormat),
+    /// 14-bit indices into a color palette, which GVR textures don't have.
+    C14x2,
+}
+
+impl std::fmt::Display for TplFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Shared(data_format) => write!(f, "{data_format}"),
+            Self::C14x2 => write!(f, "14-bit Indexed (C14X2)"),
+        }
+    }
+}
+
+/// The color palette of an image in a TPL file.
+pub struct TplPalette<'a> {
+    pub pixel_format: PixelFormat,
+    /// The colors, each of which takes up 2 bytes.
+    data: &'a [u8],
+}
+
+/// A single image of a TPL file, borrowing its data from the file or the GVR texture it comes
+/// from.
+pub struct TplImage<'a> {
+    pub width: u16,
+    pub height: u16,
+    pub format: TplFormat,
+    /// The amount of image levels stored, counting the full-size image.
+    pub levels: usize,
+    /// The data of all the image levels, without the palette.
+    data: &'a [u8],
+    pub palette: Option<TplPalette<'a>>,
+}
+
+/// Parses all the images of the TPL file in `buf`, checking that all of their headers and data
+/// are inside of the file.
+pub fn parse(buf: &[u8]) -> Result<Vec<TplImage<'_>>, TplError> {
+    if buf.len() < FILE_HEADER_SIZE {
+        return Err(TplError::TooShort(buf.len()));
+    }
+    let magic: [u8; 4] = buf[0x00..0x04].try_into().unwrap();
+    if magic != MAGIC {
+        return Err(TplError::UnknownMagic(magic));
+    }
+
+    let count = read_u32(buf, 0x04) as usize;
+    let offset = read_u32(buf, 0x08) as usize;
+    let table = count
+        .checked_mul(TABLE_ENTRY_SIZE)
+        .and_then(|size| section(buf, offset, size))
+        .ok_or(TplError::ImageTable { count, offset })?;
+    table
+        .chunks_exact(TABLE_ENTRY_SIZE)
+        .enumerate()
+        .map(|(index, entry)| {
+            let header_offset = read_u32(entry, 0x00) as usize;
+            let palette_offset = read_u32(entry, 0x04) as usize;
+            parse_image(buf, index, header_offset, palette_offset)
+        })
+        .collect()
+}
+
+/// Parses the image with the given `index`, whose image header is at `header_offset` in `buf`,
+/// and whose palette header is at `palette_offset` unless that's 0.
+fn parse_image(
+    buf: &[u8],
+    index: usize,
+    header_offset: usize,
+    palette_offset: usize,
+) -> Result<TplImage<'_>, TplError> {
+    let out_of_bounds = |part| TplError::OutOfBounds { image: index, part };
+    let header =
+        section(buf, header_offset, IMAGE_HEADER_SIZE).ok_or(out_of_bounds("image header"))?;
+    let height = u16::from_be_bytes(header[0x00..0x02].try_into().unwrap());
+    let width = u16::from_be_bytes(header[0x02..0x04].try_into().unwrap());
+    let code = read_u32(header, 0x04);
+    let format = match code {
+        FORMAT_C14X2 => TplFormat::C14x2,
+        _ => u8::try_from(code)
+            .ok()
+            .and_then(DataFormat::from_code)
+            .map(TplFormat::Shared)
+            .ok_or(TplError::UnknownFormat { image: index, code })?,
+    };
+    // the largest level of detail is the index of the smallest level, which can't get smaller
+    // than 1x1
+    let max_lod = usize::from(header[0x22]);
+    let max_levels = 1 + width.max(height).checked_ilog2().unwrap_or(0) as usize;
+
+    let mut image = TplImage {
+        width,
+        height,
+        format,
+        levels: (max_lod + 1).min(max_levels),
+        data: &[],
+        palette: None,
+    };
+    let data_offset = read_u32(header, 0x08) as usize;
+    image.data = section(buf, data_offset, image.data_size()).ok_or(out_of_bounds("image data"))?;
+
+    if palette_offset != 0 {
+        let header = section(buf, palette_offset, PALETTE_HEADER_SIZE)
+            .ok_or(out_of_bounds("palette header"))?;
+        let colors = usize::from(u16::from_be_bytes(header[0x00..0x02].try_into().unwrap()));
+        let code = read_u32(header, 0x04);
+        let pixel_format = u8::try_from(code)
+            .ok()
+            .and_then(PixelFormat::from_code)
+            .ok_or(TplError::UnknownPaletteFormat { image: index, code })?;
+        let data = section(buf, read_u32(header, 0x08) as usize, colors * 2)
+            .ok_or(out_of_bounds("palette data"))?;
+        image.palette = Some(TplPalette { pixel_format, data });
+    }
+    if image.is_indexed() && image.palette.is_none() {
+        return Err(TplError::MissingPalette(index));
+    }
+    Ok(image)
+}
+
+impl<'a> TplImage<'a> {
+    /// Takes the image of the GVR texture file in `buffer`, with all of its image levels and its
+    /// palette.
+    pub fn from_gvr(buffer: &'a [u8]) -> Result<Self, Error> {
+        let header = GvrHeader::parse(buffer).map_err(Error::Header)?;
+        let data_format = header.data_format;
+        if header.external_palette || (data_format.is_indexed() && !header.internal_palette) {
+            return Err(Error::ExternalPalette);
+        }
+
+        let levels = header.level_count();
+        let palette_end = HEADER_SIZE + header.level_offset(0) as usize;
+        let data = buffer
+            .get(palette_end..HEADER_SIZE + header.level_offset(levels) as usize)
+            .ok_or(Error::Decode(TextureDecodeError::InvalidFile))?;
+        let palette = header
+            .pixel_format
+            .filter(|_| header.internal_palette)
+            .map(|pixel_format| TplPalette {
+                pixel_format,
+                data: &buffer[HEADER_SIZE..palette_end],
+            });
+        Ok(Self {
+            width: header.width,
+            height: header.height,
+            format: TplFormat::Shared(data_format),
+            levels,
+            data,
+            palette,
+        })
+    }
+
+    /// Converts this image into a GVR texture file, with a GCIX header and a global index of 0.
+    ///
+    /// Textures can't store more levels than a full mipmap chain of GVR textures has, so any
+    /// smaller levels are left out. C14X2 images are encoded again as `rgb5a3`, and only keep
+    /// their full-size level.
+    pub fn to_gvr(&self) -> Result<Vec<u8>, Error> {
+        let data_format = match self.format {
+            TplFormat::Shared(data_format) => data_format,
+            TplFormat::C14x2 => return self.c14x2_to_gvr(),
+        };
+        let palette = self.palette.as_ref().filter(|_| data_format.is_indexed());
+        let mut header = GvrHeader {
+            header_id: HeaderId::Gcix,
+            global_index: 0,
+            data_format,
+            pixel_format: palette.map(|palette| palette.pixel_format),
+            width: self.width,
+            height: self.height,
+            mipmaps: self.levels > 1,
+            internal_palette: palette.is_some(),
+            external_palette: false,
+            data_size: 0,
+        };
+        let levels = self.levels.min(header.full_level_count());
+        header.mipmaps = levels > 1;
+        header.data_size = header.level_offset(levels) as u32;
+
+        let mut gvr = header.to_bytes().to_vec();
+        if let Some(palette) = palette {
+            // a palette with fewer colors than the data format has is filled up with black
+            let size = data_format.palette_size() as usize;
+            gvr.extend(palette.data.iter().take(size));
+            gvr.resize(HEADER_SIZE + size, 0);
+        }
+        let palette_size = header.level_offset(0);
+        gvr.extend(&self.data[..(u64::from(header.data_size) - palette_size) as usize]);
+        Ok(gvr)
+    }
+
+    /// Decodes the full-size level of this C14X2 image, and encodes it as an `rgb5a3` GVR texture.
+    fn c14x2_to_gvr(&self) -> Result<Vec<u8>, Error> {
+        let palette = self
+            .palette
+            .as_ref()
+            .expect("Indexed images have a palette.");
+        let colors: Vec<_> = palette
+            .data
+            .chunks_exact(2)
+            .map(|color| palette::decode_color([color[0], color[1]], palette.pixel_format))
+            .collect();
+        // the indices are stored in blocks of 4x4 pixels, in the lowest 14 bits of each 2 bytes
+        let blocks_x = u32::from(self.width).div_ceil(4);
+        let image = RgbaImage::from_fn(self.width.into(), self.height.into(), |x, y| {
+            let block = (y / 4 * blocks_x + x / 4) as usize;
+            let offset = (block * 16 + (y % 4 * 4 + x % 4) as usize) * 2;
+            let index = u16::from_be_bytes([self.data[offset], self.data[offset + 1]]) & 0x3FFF;
+            colors
+                .get(usize::from(index))
+                .copied()
+                .unwrap_or(Rgba([0; 4]))
+        });
+
+        let header = GvrHeader {
+            header_id: HeaderId::Gcix,
+            global_index: 0,
+            data_format: DataFormat::Rgb5a3,
+            pixel_format: None,
+            width: self.width,
+            height: self.height,
+            mipmaps: false,
+            internal_palette: false,
+            external_palette: false,
+            data_size: 0,
+        };
+        EncodeSettings::from_header(&header, false).encode_image(DynamicImage::ImageRgba8(image))
+    }
+
+    /// Returns the data format the image data is laid out in. C14X2 uses the same blocks of 4x4
+    /// pixels with 2 bytes each as `rgb5a3`.
+    fn storage_format(&self) -> DataFormat {
+        match self.format {
+            TplFormat::Shared(data_format) => data_format,
+            TplFormat::C14x2 => DataFormat::Rgb5a3,
+        }
+    }
+
+    /// Checks if this image stores indices into a color palette.
+    fn is_indexed(&self) -> bool {
+        match self.format {
+            TplFormat::Shared(data_format) => data_format.is_indexed(),
+            TplFormat::C14x2 => true,
+        }
+    }
+
+    /// Returns the size in bytes of all the image levels, each of which is half the size of the
+    /// previous one, but never smaller than 1x1.
+    fn data_size(&self) -> usize {
+        (0..self.levels)
+            .map(|level| {
+                let shrink = |size: u16| (u32::from(size) >> level).max(1);
+                self.storage_format()
+                    .level_size(shrink(self.width), shrink(self.height)) as usize
+            })
+            .sum()
+    }
+}
+
+/// Builds a TPL file holding the given `images`, in that order.
+///
+/// The headers of all the images come first, followed by the palettes and the image data, each of
+/// which starts on a multiple of 32 bytes.
+pub fn write(images: &[TplImage]) -> Vec<u8> {
+    let headers_size: usize = images
+        .iter()
+        .map(|image| match image.palette {
+            Some(_) => IMAGE_HEADER_SIZE + PALETTE_HEADER_SIZE,
+            None => IMAGE_HEADER_SIZE,
+        })
+        .sum();
+    let table_offset = FILE_HEADER_SIZE;
+    let mut header_offset = table_offset + images.len() * TABLE_ENTRY_SIZE;
+
+    let mut tpl = vec![0; (header_offset + headers_size).next_multiple_of(DATA_ALIGNMENT)];
+    tpl[0x00..0x04].copy_from_slice(&MAGIC);
+    write_u32(&mut tpl, 0x04, images.len() as u32);
+    write_u32(&mut tpl, 0x08, table_offset as u32);
+
+    for (index, image) in images.iter().enumerate() {
+        let entry = table_offset + index * TABLE_ENTRY_SIZE;
+        let image_header = header_offset;
+        write_u32(&mut tpl, entry, image_header as u32);
+        header_offset += IMAGE_HEADER_SIZE;
+
+        if let Some(palette) = &image.palette {
+            let palette_header = header_offset;
+            header_offset += PALETTE_HEADER_SIZE;
+            write_u32(&mut tpl, entry + 0x04, palette_header as u32);
+            let colors = (palette.data.len() / 2) as u16;
+            tpl[palette_header..palette_header + 2].copy_from_slice(&colors.to_be_bytes());
+            write_u32(
+                &mut tpl,
+                palette_header + 0x04,
+                palette.pixel_format.code().into(),
+            );
+            let data_offset = tpl.len() as u32;
+            write_u32(&mut tpl, palette_header + 0x08, data_offset);
+            tpl.extend(palette.data);
+            tpl.resize(tpl.len().next_multiple_of(DATA_ALIGNMENT), 0);
+        }
+
+        let format = match image.format {
+            TplFormat::Shared(data_format) => data_format.code().into(),
+            TplFormat::C14x2 => FORMAT_C14X2,
+        };
+        let min_filter = match image.levels {
+            1 => FILTER_LINEAR,
+            _ => FILTER_LINEAR_MIP_LINEAR,
+        };
+        tpl[image_header..image_header + 2].copy_from_slice(&image.height.to_be_bytes());
+        tpl[image_header + 0x02..image_header + 0x04].copy_from_slice(&image.width.to_be_bytes());
+        write_u32(&mut tpl, image_header + 0x04, format);
+        let data_offset = tpl.len() as u32;
+        write_u32(&mut tpl, image_header + 0x08, data_offset);
+        // both wrap modes stay 0, which clamps, and the LOD bias stays 0.0
+        write_u32(&mut tpl, image_header + 0x14, min_filter);
+        write_u32(&mut tpl, image_header + 0x18, FILTER_LINEAR);
+        tpl[image_header + 0x22] = (image.levels - 1) as u8;
+        tpl.extend(image.data);
+        tpl.resize(tpl.len().next_multiple_of(DATA_ALIGNMENT), 0);
+    }
+    tpl
+}
+
+/// Returns the `size` bytes at `offset` in `buf`, or `None` if they aren't all inside of it.
+fn section(buf: &[u8], offset: usize, size: usize) -> Option<&[u8]> {
+    buf.get(offset..offset.checked_add(size)?)
+}
+
+/// Reads the big-endian u32 at `offset` in `buf`, which must be long enough to hold it.
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Writes `value` as a big-endian u32 at `offset` in `buf`, which must be long enough to hold it.
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Where [`tpl`] puts the image table entry, the image header and the palette header of its
+    /// single image.
+    const ENTRY: usize = FILE_HEADER_SIZE;
+    const IMAGE_HEADER: usize = ENTRY + TABLE_ENTRY_SIZE;
+    const PALETTE_HEADER: usize = IMAGE_HEADER + IMAGE_HEADER_SIZE;
+
+    /// Returns a TPL file of the single image of `texture`.
+    fn tpl(texture: &[u8]) -> Vec<u8> {
+        write(&[TplImage::from_gvr(texture).unwrap()])
+    }
+
+    #[test]
+    fn indexed_roundtrip() {
+        for data_format in [DataFormat::Index4, DataFormat::Index8] {
+            let texture = testing::texture(data_format, 16, 8);
+            let buf = tpl(&texture);
+            let images = parse(&buf).unwrap();
+            assert_eq!(images.len(), 1);
+            let image = &images[0];
+            assert!(
+                image.format == TplFormat::Shared(data_format),
+                "{data_format}"
+            );
+            assert_eq!((image.width, image.height, image.levels), (16, 8, 1));
+
+            let palette = image.palette.as_ref().unwrap();
+            let palette_size = data_format.palette_size() as usize;
+            assert!(palette.pixel_format == PixelFormat::Rgb5a3, "{data_format}");
+            assert!(
+                palette.data == &texture[HEADER_SIZE..HEADER_SIZE + palette_size],
+                "{data_format}"
+            );
+            assert!(
+                image.data == &texture[HEADER_SIZE + palette_size..],
+                "{data_format}"
+            );
+            // the palette and the image data are aligned
+            assert_eq!(
+                read_u32(&buf, PALETTE_HEADER + 0x08) as usize % DATA_ALIGNMENT,
+                0
+            );
+            assert_eq!(
+                read_u32(&buf, IMAGE_HEADER + 0x08) as usize % DATA_ALIGNMENT,
+                0
+            );
+
+            assert!(image.to_gvr().unwrap() == texture, "{data_format}");
+        }
+    }
+
+    #[test]
+    fn invalid_headers_fail() {
+        assert!(matches!(parse(&MAGIC), Err(TplError::TooShort(4))));
+        let mut buf = tpl(&testing::texture(DataFormat::Rgb565, 8, 8));
+        buf[0x00] = 0xFF;
+        assert!(matches!(parse(&buf), Err(TplError::UnknownMagic(_))));
+    }
+
+    #[test]
+    fn truncated_image_table_fails() {
+        let mut buf = tpl(&testing::texture(DataFormat::Rgb565, 8, 8));
+        write_u32(&mut buf, 0x04, 100);
+        assert!(matches!(
+            parse(&buf),
+            Err(TplError::ImageTable {
+                count: 100,
+                offset: FILE_HEADER_SIZE
+            })
+        ));
+        // a table whose size overflows
+        write_u32(&mut buf, 0x04, u32::MAX);
+        write_u32(&mut buf, 0x08, u32::MAX);
+        assert!(matches!(parse(&buf), Err(TplError::ImageTable { .. })));
+    }
+
+    #[test]
+    fn headers_past_end_fail() {
+        let mut buf = tpl(&testing::texture(DataFormat::Index4, 8, 8));
+        let len = buf.len() as u32;
+        write_u32(&mut buf, ENTRY, len - 4);
+        assert!(matches!(
+            parse(&buf),
+            Err(TplError::OutOfBounds {
+                image: 0,
+                part: "image header"
+            })
+        ));
+
+        let mut buf = tpl(&testing::texture(DataFormat::Index4, 8, 8));
+        write_u32(&mut buf, ENTRY + 0x04, len - 4);
+        assert!(matches!(
+            parse(&buf),
+            Err(TplError::OutOfBounds {
+                image: 0,
+                part: "palette header"
+            })
+        ));
+    }
+
+    #[test]
+    fn data_past_end_fails() {
+        let buf = tpl(&testing::texture(DataFormat::Index4, 8, 8));
+        let image_data = read_u32(&buf, IMAGE_HEADER + 0x08) as usize;
+        let image_end = image_data + DataFormat::Index4.level_size(8, 8) as usize;
+        assert!(matches!(
+            parse(&buf[..image_end - 1]),
+            Err(TplError::OutOfBounds {
+                image: 0,
+                part: "image data"
+            })
+        ));
+
+        // the 16 colors of the palette take up 32 bytes
+        let mut buf = buf;
+        let len = buf.len() as u32;
+        write_u32(&mut buf, PALETTE_HEADER + 0x08, len - 16);
+        assert!(matches!(
+            parse(&buf),
+            Err(TplError::OutOfBounds {
+                image: 0,
+                part: "palette data"
+            })
+        ));
+    }
+
+    #[test]
+    fn unknown_formats_fail() {
+        let mut buf = tpl(&testing::texture(DataFormat::Index8, 8, 8));
+        write_u32(&mut buf, IMAGE_HEADER + 0x04, 0x07);
+        assert!(matches!(
+            parse(&buf),
+            Err(TplError::UnknownFormat {
+                image: 0,
+                code: 0x07
+            })
+        ));
+
+        let mut buf = tpl(&testing::texture(DataFormat::Index8, 8, 8));
+        write_u32(&mut buf, PALETTE_HEADER + 0x04, 0x03);
+        assert!(matches!(
+            parse(&buf),
+            Err(TplError::UnknownPaletteFormat {
+                image: 0,
+                code: 0x03
+            })
+        ));
+    }
+
+    #[test]
+    fn indexed_image_without_palette_fails() {
+        let mut buf = tpl(&testing::texture(DataFormat::Index8, 8, 8));
+        write_u32(&mut buf, ENTRY + 0x04, 0);
+        assert!(matches!(parse(&buf), Err(TplError::MissingPalette(0))));
+    }
+
+    #[test]
+    fn truncated_files_dont_panic() {
+        let buf = tpl(&testing::texture(DataFormat::Index4, 8, 8));
+        for len in 0..buf.len() {
+            let _ = parse(&buf[..len]);
+        }
+    }
+}
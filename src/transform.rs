@@ -0,0 +1,605 @@
+//! Changes made to the input images before they're encoded.
+
+use crate::dimensions::{self, DimensionError};
+use crate::encode;
+use crate::formats::DataFormat;
+use crate::mips::MipFilter;
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba, RgbaImage, imageops};
+use log::info;
+use std::path::Path;
+
+/// All the changes to make to each input image, in the order they're applied.
+#[derive(Copy, Clone, Default)]
+pub struct Transform {
+    /// Crop the image to this rectangle.
+    pub crop: Option<Crop>,
+    /// Mirror the image.
+    pub flip: Flip,
+    /// Resize the image to these dimensions.
+    pub resize: Option<Resize>,
+    /// Scale the image down if its width or height is larger than this.
+    pub max_dimension: Option<u32>,
+    /// The filter to resize the image with.
+    pub resize_filter: MipFilter,
+    /// Pad the image up to power-of-two dimensions, filling the new pixels with this color.
+    pub pad_pot: Option<Rgba<u8>>,
+    /// Make the pixels whose alpha is below this fully transparent, and all the others fully
+    /// opaque.
+    pub alpha_threshold: Option<u8>,
+    /// Blend the image over this color, for data formats that don't store the alpha channel.
+    pub background: Option<Rgba<u8>>,
+    /// Resize alpha masks to the size of the image they're for, instead of refusing the ones that
+    /// don't match.
+    pub resize_alpha_mask: bool,
+    /// Make every pixel fully opaque, keeping its color. This comes after the `background`, so it
+    /// only changes anything if there's no background to blend over.
+    pub force_opaque: bool,
+}
+
+/// A rectangle to crop an image to.
+#[derive(Debug, Copy, Clone)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// Parses a rectangle given as `x,y,width,height`, where the width and height can't be 0.
+    pub fn parse(crop: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid rectangle `{crop}`, expected `x,y,width,height`");
+        let values = crop
+            .split(',')
+            .map(|value| value.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        let &[x, y, width, height] = values.as_slice() else {
+            return Err(invalid());
+        };
+        if width == 0 || height == 0 {
+            return Err(format!(
+                "invalid rectangle `{crop}`, the width and height can't be 0"
+            ));
+        }
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Checks if this rectangle lies fully inside an image of the given `dimensions`.
+    fn fits(&self, dimensions: (u32, u32)) -> bool {
+        let (width, height) = dimensions;
+        self.x
+            .checked_add(self.width)
+            .is_some_and(|right| right <= width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|bottom| bottom <= height)
+    }
+}
+
+impl std::fmt::Display for Crop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Which ways to mirror an image. Flipping both ways is the same as rotating it by 180 degrees.
+#[derive(Copy, Clone, Default)]
+pub struct Flip {
+    /// Mirror the image horizontally, swapping its left and right sides.
+    pub x: bool,
+    /// Mirror the image vertically, swapping its top and bottom.
+    pub y: bool,
+}
+
+impl Flip {
+    /// Mirrors the given `image` in place.
+    pub fn apply<I: GenericImage>(self, image: &mut I) {
+        if self.x {
+            imageops::flip_horizontal_in_place(image);
+        }
+        if self.y {
+            imageops::flip_vertical_in_place(image);
+        }
+    }
+}
+
+/// How to resize an image.
+#[derive(Copy, Clone)]
+pub enum Resize {
+    /// Resize the image to these dimensions. When either side is left out, it's derived from the
+    /// other one, keeping the aspect ratio of the image.
+    Size {
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+    /// Multiply both dimensions of the image by this factor.
+    Scale(f32),
+}
+
+impl Resize {
+    /// Parses dimensions given as `WxH`, `Wx` or `xH`.
+    pub fn parse(size: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid size `{size}`, expected `WxH`, `Wx` or `xH`");
+        let (width, height) = size.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let side = |side: &str| match side {
+            "" => Ok(None),
+            _ => match side.parse::<u32>() {
+                Ok(0) | Err(_) => Err(invalid()),
+                Ok(side) => Ok(Some(side)),
+            },
+        };
+
+        match (side(width)?, side(height)?) {
+            (None, None) => Err(invalid()),
+            (width, height) => Ok(Self::Size { width, height }),
+        }
+    }
+
+    /// Parses a scale factor, which has to be larger than 0.
+    pub fn parse_scale(factor: &str) -> Result<Self, String> {
+        match factor.parse::<f32>() {
+            Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(Self::Scale(factor)),
+            _ => Err(format!(
+                "invalid scale factor `{factor}`, expected a number larger than 0"
+            )),
+        }
+    }
+
+    /// Returns the dimensions an image of the given `dimensions` is resized to.
+    pub fn size(self, dimensions: (u32, u32)) -> (u32, u32) {
+        let (width, height) = dimensions;
+        let scale = |side: u32, new_side: u32, other_side: u32| {
+            let scaled = (u64::from(other_side) * u64::from(new_side) + u64::from(side) / 2)
+                / u64::from(side);
+            u32::try_from(scaled).unwrap_or(u32::MAX).max(1)
+        };
+        match self {
+            Self::Size {
+                width: new_width,
+                height: new_height,
+            } => match (new_width, new_height) {
+                (Some(new_width), Some(new_height)) => (new_width, new_height),
+                (Some(new_width), None) => (new_width, scale(width, new_width, height)),
+                (None, Some(new_height)) => (scale(height, new_height, width), new_height),
+                (None, None) => dimensions,
+            },
+            Self::Scale(factor) => {
+                // the float to int casts saturate, so huge factors end up as u32::MAX
+                let scale = |side: u32| ((side as f64 * f64::from(factor)).round() as u32).max(1);
+                (scale(width), scale(height))
+            }
+        }
+    }
+}
+
+impl Transform {
+    /// Checks if none of the changes that touch the pixels are set, so that every image is
+    /// encoded as it's stored.
+    pub fn is_identity(&self) -> bool {
+        self.crop.is_none()
+            && !self.flip.x
+            && !self.flip.y
+            && self.resize.is_none()
+            && self.max_dimension.is_none()
+            && self.pad_pot.is_none()
+            && self.alpha_threshold.is_none()
+            && self.background.is_none()
+            && !self.force_opaque
+    }
+
+    /// Applies these changes to the given `image` loaded from `input`, which is going to be
+    /// encoded in `data_format`, with or without `mipmaps`. Fails if the image is too small for
+    /// the crop rectangle.
+    pub fn apply(
+        &self,
+        input: &Path,
+        mut image: DynamicImage,
+        data_format: DataFormat,
+        mipmaps: bool,
+    ) -> Result<DynamicImage, DimensionError> {
+        let input = input.display();
+        if let Some(crop) = self.crop {
+            let (width, height) = image.dimensions();
+            if !crop.fits((width, height)) {
+                return Err(DimensionError::Crop {
+                    crop,
+                    width,
+                    height,
+                });
+            }
+            info!("{input}: cropping image to {crop}");
+            image = image.crop_imm(crop.x, crop.y, crop.width, crop.height);
+        }
+        self.flip.apply(&mut image);
+        if let Some(resize) = self.resize {
+            let (width, height) = resize.size(image.dimensions());
+            if image.dimensions() != (width, height) {
+                info!("{input}: resizing image to {width}x{height}");
+                image = self.resize_filter.resize(&image, width, height);
+            }
+        }
+        if let Some(max) = self.max_dimension {
+            let (width, height) = clamped_size(image.dimensions(), max);
+            if image.dimensions() != (width, height) {
+                info!(
+                    "{input}: clamping {}x{} image to {width}x{height}",
+                    image.width(),
+                    image.height()
+                );
+                image = self.resize_filter.resize(&image, width, height);
+            }
+        }
+        if let Some(color) = self.pad_pot {
+            let (width, height) = image.dimensions();
+            let (padded_width, padded_height) = padded_size(data_format, mipmaps, width, height);
+            if (width, height) != (padded_width, padded_height) {
+                info!("{input}: padding image to {padded_width}x{padded_height}");
+                image = pad(image, padded_width, padded_height, color);
+            }
+        }
+        if let Some(threshold) = self.alpha_threshold {
+            let changed = binarize_alpha(&mut image, |alpha| alpha >= threshold);
+            info!("{input}: cut the alpha of {changed} pixels at {threshold}");
+        }
+        if let Some(color) = self.background {
+            composite(&mut image, color, data_format);
+        }
+        if self.force_opaque {
+            let changed = binarize_alpha(&mut image, |_| true);
+            if changed > 0 {
+                info!("{input}: made {changed} transparent or translucent pixels opaque");
+            }
+        }
+        Ok(image)
+    }
+
+    /// Replaces the alpha channel of `image` with the intensity of the grayscale `mask`, which has
+    /// to be the same size unless [`Self::resize_alpha_mask`] is set. This comes before all the
+    /// other changes.
+    pub fn apply_alpha_mask(
+        &self,
+        image: &mut DynamicImage,
+        mut mask: DynamicImage,
+    ) -> Result<(), DimensionError> {
+        if mask.dimensions() != image.dimensions() {
+            if !self.resize_alpha_mask {
+                return Err(DimensionError::AlphaMask {
+                    mask: mask.dimensions(),
+                    image: image.dimensions(),
+                });
+            }
+            mask = self
+                .resize_filter
+                .resize(&mask, image.width(), image.height());
+        }
+
+        if encode::bits_per_channel(image) > 8 || encode::bits_per_channel(&mask) > 8 {
+            let mask = mask.into_luma16();
+            let mut pixels = std::mem::take(image).into_rgba16();
+            for (pixel, alpha) in pixels.pixels_mut().zip(mask.pixels()) {
+                pixel[3] = alpha[0];
+            }
+            *image = DynamicImage::ImageRgba16(pixels);
+        } else {
+            let mask = mask.into_luma8();
+            let mut pixels = std::mem::take(image).into_rgba8();
+            for (pixel, alpha) in pixels.pixels_mut().zip(mask.pixels()) {
+                pixel[3] = alpha[0];
+            }
+            *image = DynamicImage::ImageRgba8(pixels);
+        }
+        Ok(())
+    }
+
+    /// Applies these changes to a custom mipmap `level`, for a full-size image of the given
+    /// `source_dimensions`. Custom levels aren't cropped, so they have to be made for the cropped
+    /// full-size image. Other than that, the level is flipped and resized along with the full-size
+    /// image, and padded up to the size of that level in the padded full-size image. Images that
+    /// are too large to be padded are returned as is, so that the mismatch is reported when
+    /// encoding them.
+    pub fn apply_level(
+        &self,
+        mut image: DynamicImage,
+        data_format: DataFormat,
+        source_dimensions: (u32, u32),
+        level: usize,
+    ) -> DynamicImage {
+        self.flip.apply(&mut image);
+        let shrink =
+            |(width, height): (u32, u32)| ((width >> level).max(1), (height >> level).max(1));
+        let mut dimensions = match self.crop {
+            Some(crop) => (crop.width, crop.height),
+            None => source_dimensions,
+        };
+        if let Some(resize) = self.resize {
+            dimensions = resize.size(dimensions);
+            let (width, height) = shrink(dimensions);
+            if image.dimensions() != (width, height) {
+                image = self.resize_filter.resize(&image, width, height);
+            }
+        }
+        if let Some(color) = self.pad_pot {
+            // custom levels always come with mipmaps
+            let (width, height) =
+                shrink(padded_size(data_format, true, dimensions.0, dimensions.1));
+            if image.width() <= width && image.height() <= height {
+                image = pad(image, width, height, color);
+            }
+        }
+        if let Some(threshold) = self.alpha_threshold {
+            binarize_alpha(&mut image, |alpha| alpha >= threshold);
+        }
+        if let Some(color) = self.background {
+            composite(&mut image, color, data_format);
+        }
+        if self.force_opaque {
+            binarize_alpha(&mut image, |_| true);
+        }
+        image
+    }
+}
+
+/// Returns the dimensions an image of the given `dimensions` is scaled down to, so that neither
+/// side is larger than `max`. Images that already fit are left alone.
+///
+/// If both dimensions are powers of two, they're halved until they fit, so that they stay powers
+/// of two. Otherwise, the larger side is scaled down to exactly `max`.
+pub fn clamped_size(dimensions: (u32, u32), max: u32) -> (u32, u32) {
+    let (width, height) = dimensions;
+    let larger = width.max(height);
+    if larger <= max {
+        return dimensions;
+    }
+
+    if width.is_power_of_two() && height.is_power_of_two() {
+        let shift = larger.ilog2() - max.ilog2();
+        return ((width >> shift).max(1), (height >> shift).max(1));
+    }
+    let scale = |side: u32| {
+        let scaled = (u64::from(side) * u64::from(max) + u64::from(larger) / 2) / u64::from(larger);
+        (scaled as u32).max(1)
+    };
+    (scale(width), scale(height))
+}
+
+/// Returns the power-of-two dimensions an image of the given `width` and `height` is padded up
+/// to for `data_format`. With `mipmaps`, both sides are padded up to the larger one, since mipmaps
+/// need square textures.
+pub fn padded_size(data_format: DataFormat, mipmaps: bool, width: u32, height: u32) -> (u32, u32) {
+    let (mut width, mut height) = (width.next_power_of_two(), height.next_power_of_two());
+    if mipmaps {
+        width = width.max(height);
+        height = width;
+    }
+    // the block sizes are powers of two as well, so this keeps the dimensions powers of two
+    if let Some(multiple) = dimensions::block_multiple(data_format) {
+        width = width.max(multiple);
+        height = height.max(multiple);
+    }
+    (width, height)
+}
+
+/// Places `image` at the top left of a new image of the given `width` and `height`, filled with
+/// `color`. Images with more than 8 bits per channel keep 16 bits in the padded image.
+fn pad(image: DynamicImage, width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
+    if encode::bits_per_channel(&image) > 8 {
+        let color = Rgba(color.0.map(|channel| u16::from(channel) * 257));
+        let mut padded = ImageBuffer::from_pixel(width, height, color);
+        imageops::replace(&mut padded, &image.into_rgba16(), 0, 0);
+        return DynamicImage::ImageRgba16(padded);
+    }
+    let mut padded = RgbaImage::from_pixel(width, height, color);
+    imageops::replace(&mut padded, &image.into_rgba8(), 0, 0);
+    DynamicImage::ImageRgba8(padded)
+}
+
+/// Blends `image` over the opaque `color`, making every pixel opaque. For `dxt1`, the pixels that
+/// the encoder turns fully transparent are left alone, so that they stay transparent.
+fn composite(image: &mut DynamicImage, color: Rgba<u8>, data_format: DataFormat) {
+    // below this alpha, the dxt1 encoder makes pixels transparent
+    let keep_below = if data_format == DataFormat::Dxt1 {
+        16
+    } else {
+        0
+    };
+
+    if encode::bits_per_channel(image) > 8 {
+        let mut pixels = std::mem::take(image).into_rgba16();
+        let color = color.0.map(|channel| u32::from(channel) * 257);
+        for pixel in pixels.pixels_mut() {
+            if pixel[3] >= u16::from(keep_below) * 257 {
+                *pixel = Rgba(blend(pixel.0, color, u16::MAX.into()).map(|value| value as u16));
+            }
+        }
+        *image = DynamicImage::ImageRgba16(pixels);
+    } else {
+        let mut pixels = std::mem::take(image).into_rgba8();
+        let color = color.0.map(u32::from);
+        for pixel in pixels.pixels_mut() {
+            if pixel[3] >= keep_below {
+                *pixel = Rgba(blend(pixel.0, color, u8::MAX.into()).map(|value| value as u8));
+            }
+        }
+        *image = DynamicImage::ImageRgba8(pixels);
+    }
+}
+
+/// Makes every pixel of `image` either fully opaque or fully transparent, depending on whether
+/// `opaque` returns true for its alpha, scaled to 8 bits. Returns how many pixels changed.
+fn binarize_alpha(image: &mut DynamicImage, opaque: impl Fn(u8) -> bool) -> usize {
+    let mut changed = 0;
+    if encode::bits_per_channel(image) > 8 {
+        let mut pixels = std::mem::take(image).into_rgba16();
+        for pixel in pixels.pixels_mut() {
+            let alpha = ((u32::from(pixel[3]) * 255 + 32767) / 65535) as u8;
+            let new_alpha = if opaque(alpha) { u16::MAX } else { 0 };
+            changed += usize::from(pixel[3] != new_alpha);
+            pixel[3] = new_alpha;
+        }
+        *image = DynamicImage::ImageRgba16(pixels);
+    } else {
+        let mut pixels = std::mem::take(image).into_rgba8();
+        for pixel in pixels.pixels_mut() {
+            let new_alpha = if opaque(pixel[3]) { u8::MAX } else { 0 };
+            changed += usize::from(pixel[3] != new_alpha);
+            pixel[3] = new_alpha;
+        }
+        *image = DynamicImage::ImageRgba8(pixels);
+    }
+    changed
+}
+
+/// Blends the `pixel` over the `background` color, where `max` is the largest value a channel can
+/// have. Returns the opaque result, rounded to the nearest value.
+fn blend<T: Into<u32>>(pixel: [T; 4], background: [u32; 4], max: u32) -> [u32; 4] {
+    let [r, g, b, a] = pixel.map(Into::into);
+    let mix =
+        |channel: u32, background: u32| (channel * a + background * (max - a) + max / 2) / max;
+    [
+        mix(r, background[0]),
+        mix(g, background[1]),
+        mix(b, background[2]),
+        max,
+    ]
+}
+
+/// The colors that can be given by name instead of in hexadecimal.
+const NAMED_COLORS: [(&str, [u8; 4]); 9] = [
+    ("black", [0x00, 0x00, 0x00, 0xFF]),
+    ("white", [0xFF, 0xFF, 0xFF, 0xFF]),
+    ("gray", [0x80, 0x80, 0x80, 0xFF]),
+    ("grey", [0x80, 0x80, 0x80, 0xFF]),
+    ("red", [0xFF, 0x00, 0x00, 0xFF]),
+    ("green", [0x00, 0x80, 0x00, 0xFF]),
+    ("blue", [0x00, 0x00, 0xFF, 0xFF]),
+    ("magenta", [0xFF, 0x00, 0xFF, 0xFF]),
+    ("transparent", [0x00, 0x00, 0x00, 0x00]),
+];
+
+/// Parses a color given as `RRGGBB` or `RRGGBBAA` in hexadecimal, optionally starting with `#`,
+/// or as one of the [`NAMED_COLORS`].
+pub fn parse_color(color: &str) -> Result<Rgba<u8>, String> {
+    if let Some((_, channels)) = NAMED_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(color))
+    {
+        return Ok(Rgba(*channels));
+    }
+
+    let digits = color.strip_prefix('#').unwrap_or(color);
+    let invalid =
+        || format!("invalid color `{color}`, expected `RRGGBB`, `RRGGBBAA` or a name like `white`");
+    if !matches!(digits.len(), 6 | 8) || !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap();
+    let alpha = if digits.len() == 8 { channel(3) } else { 0xFF };
+    Ok(Rgba([channel(0), channel(1), channel(2), alpha]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::testing;
+
+    #[test]
+    fn blend_rounds_to_nearest() {
+        let pixel = [200u8, 100, 0, 128];
+        assert_eq!(blend(pixel, [255; 4], 255), [227, 177, 127, 255]);
+        assert_eq!(blend(pixel, [0; 4], 255), [100, 50, 0, 255]);
+        assert_eq!(
+            blend([200u8, 100, 0, 64], [128; 4], 255),
+            [146, 121, 96, 255]
+        );
+        // fully opaque and fully transparent pixels keep their color and take the background's
+        assert_eq!(blend([1u8, 2, 3, 255], [128; 4], 255), [1, 2, 3, 255]);
+        assert_eq!(blend([1u8, 2, 3, 0], [128; 4], 255), [128, 128, 128, 255]);
+        // 16 bits per channel give the same result, scaled up
+        let pixel = [200u16 * 257, 100 * 257, 0, 128 * 257];
+        assert_eq!(
+            blend(pixel, [65535; 4], 65535),
+            [58440, 45539, 32639, 65535]
+        );
+    }
+
+    #[test]
+    fn composite_over_background() {
+        let pixels = [[200, 100, 0, 128], [10, 20, 30, 255], [10, 20, 30, 8]];
+        let image = RgbaImage::from_fn(3, 1, |x, _| Rgba(pixels[x as usize]));
+        let white = Rgba([255, 255, 255, 255]);
+
+        let mut composited = DynamicImage::ImageRgba8(image.clone());
+        composite(&mut composited, white, DataFormat::Rgb565);
+        let composited = composited.into_rgba8();
+        assert_eq!(composited.get_pixel(0, 0).0, [227, 177, 127, 255]);
+        assert_eq!(composited.get_pixel(1, 0).0, [10, 20, 30, 255]);
+        assert_eq!(composited.get_pixel(2, 0).0, [247, 248, 248, 255]);
+
+        // dxt1 keeps the pixels it would make transparent anyway
+        let mut composited = DynamicImage::ImageRgba8(image.clone());
+        composite(&mut composited, white, DataFormat::Dxt1);
+        let composited = composited.into_rgba8();
+        assert_eq!(composited.get_pixel(0, 0).0, [227, 177, 127, 255]);
+        assert_eq!(composited.get_pixel(2, 0).0, [10, 20, 30, 8]);
+
+        // images with 16 bits per channel stay that way
+        let mut composited =
+            DynamicImage::ImageRgba16(DynamicImage::ImageRgba8(image).into_rgba16());
+        composite(&mut composited, white, DataFormat::Rgb565);
+        let DynamicImage::ImageRgba16(composited) = composited else {
+            panic!("expected 16 bits per channel");
+        };
+        assert_eq!(composited.get_pixel(0, 0).0, [58440, 45539, 32639, 65535]);
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("#FF8000").unwrap().0, [0xFF, 0x80, 0x00, 0xFF]);
+        assert_eq!(parse_color("ff8000").unwrap().0, [0xFF, 0x80, 0x00, 0xFF]);
+        assert_eq!(
+            parse_color("#ff800040").unwrap().0,
+            [0xFF, 0x80, 0x00, 0x40]
+        );
+        assert_eq!(parse_color("White").unwrap().0, [0xFF; 4]);
+        assert_eq!(parse_color("grey").unwrap(), parse_color("gray").unwrap());
+        assert_eq!(parse_color("transparent").unwrap().0, [0; 4]);
+        for invalid in ["", "#", "#FFF", "#FF80001", "#GG8000", "purple-ish"] {
+            assert!(parse_color(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn alpha_threshold_cut_line() {
+        // the alpha of each pixel is its x coordinate
+        let gradient = RgbaImage::from_fn(256, 8, |x, _| Rgba([255, 128, 0, x as u8]));
+        for threshold in [1, 128, 255] {
+            let transform = Transform {
+                alpha_threshold: Some(threshold),
+                ..Transform::default()
+            };
+            let image = DynamicImage::ImageRgba8(gradient.clone());
+            let image = transform
+                .apply(Path::new("gradient.png"), image, DataFormat::Dxt1, false)
+                .unwrap();
+            let texture = testing::settings(DataFormat::Dxt1)
+                .encode_image(image.clone())
+                .unwrap();
+            let decoded = decode::decode_buffer(texture).unwrap();
+
+            for x in 0..256 {
+                let alpha = if x < u32::from(threshold) { 0 } else { 255 };
+                assert_eq!(image.get_pixel(x, 0)[3], alpha, "{threshold}: {x}");
+                assert_eq!(decoded.get_pixel(x, 7)[3], alpha, "{threshold}: {x}");
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Checking of GVR texture files for errors, without writing anything.
+
+use crate::error::Failure;
+use crate::header::{GvrHeader, HEADER_SIZE, HeaderError};
+use crate::prs;
+use gvrtex::TextureDecoder;
+use gvrtex::error::TextureDecodeError;
+use std::path::Path;
+
+/// Contains all the possible problems a GVR texture file can have.
+#[derive(Debug)]
+pub enum ValidateError {
+    /// The header couldn't be read or parsed.
+    Header(HeaderError),
+    /// The file ends before the end of the texture data the header describes.
+    Truncated { expected: u32, actual: usize },
+    /// There's more data after the end of the texture data, which the decoder refuses.
+    TrailingData(usize),
+    /// The texture data is smaller than what the dimensions and the data format need.
+    DataSizeMismatch { expected: u64, actual: u32 },
+    /// The color palette is stored in a separate file, which isn't supported.
+    ExternalPalette,
+    /// The texture data couldn't be decoded.
+    Decode(TextureDecodeError),
+}
+
+impl std::error::Error for ValidateError {}
+
+impl std::fmt::Display for ValidateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Header(err) => write!(f, "{err}"),
+            Self::Truncated { expected, actual } => write!(
+                f,
+                "The file is truncated, the header describes {expected} bytes of texture data but only {actual} bytes follow it."
+            ),
+            Self::TrailingData(len) => {
+                write!(f, "There are {len} trailing bytes after the texture data.")
+            }
+            Self::DataSizeMismatch { expected, actual } => write!(
+                f,
+                "The texture data is {actual} bytes long, but the dimensions and data format in the header need {expected} bytes."
+            ),
+            Self::ExternalPalette => write!(
+                f,
+                "The texture uses an external palette, which can't be decoded yet."
+            ),
+            Self::Decode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl ValidateError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        match self {
+            Self::Header(err) => err.into(),
+            _ => Failure::Codec,
+        }
+    }
+}
+
+/// Checks the GVR texture file at `path`, by parsing its header, checking that the texture data
+/// is as large as the header says, and fully decoding it. Returns the header if the file is
+/// valid.
+pub fn validate_file(path: &Path) -> Result<GvrHeader, ValidateError> {
+    let buffer = prs::read(path).map_err(|e| ValidateError::Header(e.into()))?;
+    let header = GvrHeader::parse(&buffer).map_err(ValidateError::Header)?;
+
+    let actual = buffer.len() - HEADER_SIZE;
+    let data_size = header.data_size as usize;
+    if actual < data_size {
+        return Err(ValidateError::Truncated {
+            expected: header.data_size,
+            actual,
+        });
+    }
+    if actual > data_size {
+        return Err(ValidateError::TrailingData(actual - data_size));
+    }
+    let expected = header.expected_data_size();
+    // mipmap chains that stop early are fine, as long as they don't stop in the middle of a level
+    if u64::from(header.data_size) < expected && !header.ends_on_level() {
+        return Err(ValidateError::DataSizeMismatch {
+            expected,
+            actual: header.data_size,
+        });
+    }
+
+    if header.external_palette {
+        return Err(ValidateError::ExternalPalette);
+    }
+    TextureDecoder::new_from_buffer(buffer)
+        .decode()
+        .map_err(ValidateError::Decode)?;
+    Ok(header)
+}
@@ -0,0 +1,115 @@
+//! Checking the GVR texture files written by `encode --verify`, by reading them back from disk.
+
+use crate::decode;
+use crate::error::Error;
+use crate::header::{GvrHeader, HeaderError};
+use crate::prs::{self, PrsError};
+use std::path::Path;
+
+/// Why a written texture file failed verification.
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The file couldn't be read back.
+    Read(std::io::Error),
+    /// The file on disk isn't what was written to it.
+    Contents { written: usize, read: usize },
+    /// The compressed file couldn't be decompressed.
+    Decompress(PrsError),
+    /// The header of the file couldn't be read.
+    Header(HeaderError),
+    /// The texture is a different size than the image that was encoded.
+    Dimensions {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The texture has a different amount of image levels than were encoded.
+    Levels { expected: usize, actual: usize },
+    /// One of the image levels of the texture couldn't be decoded.
+    Decode { level: usize, error: Box<Error> },
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(err) => write!(f, "Couldn't read the written file back: {err}"),
+            Self::Contents { written, read } if written == read => write!(
+                f,
+                "The written file doesn't contain the encoded texture, it was corrupted while writing it."
+            ),
+            Self::Contents { written, read } => write!(
+                f,
+                "The written file is {read} bytes, but {written} bytes were written to it."
+            ),
+            Self::Decompress(err) => write!(f, "The written file can't be decompressed: {err}"),
+            Self::Header(err) => write!(f, "The written file has an invalid header: {err}"),
+            Self::Dimensions {
+                expected: (expected_width, expected_height),
+                actual: (width, height),
+            } => write!(
+                f,
+                "The written texture is {width}x{height}, but the encoded image is {expected_width}x{expected_height}."
+            ),
+            Self::Levels { expected, actual } => write!(
+                f,
+                "The written texture has {actual} image levels, but {expected} were encoded."
+            ),
+            Self::Decode { level, error } => {
+                write!(
+                    f,
+                    "Image level {level} of the written texture can't be decoded: {error}"
+                )
+            }
+        }
+    }
+}
+
+/// Reads the texture file at `path` back from disk and checks that it's the `encoded` file, that
+/// it has the given `dimensions` and amount of image `levels`, and that every level of it can be
+/// decoded. A `compressed` file is decompressed for the checks after the first.
+pub fn verify_output(
+    path: &Path,
+    encoded: &[u8],
+    compressed: bool,
+    dimensions: (u32, u32),
+    levels: usize,
+) -> Result<(), VerifyError> {
+    let written = std::fs::read(path).map_err(VerifyError::Read)?;
+    if written != encoded {
+        return Err(VerifyError::Contents {
+            written: encoded.len(),
+            read: written.len(),
+        });
+    }
+    let written = match compressed {
+        true => prs::decompress(&written).map_err(VerifyError::Decompress)?,
+        false => written,
+    };
+
+    let header = GvrHeader::parse(&written).map_err(VerifyError::Header)?;
+    let actual = (header.width.into(), header.height.into());
+    if actual != dimensions {
+        return Err(VerifyError::Dimensions {
+            expected: dimensions,
+            actual,
+        });
+    }
+    if header.level_count() != levels {
+        return Err(VerifyError::Levels {
+            expected: levels,
+            actual: header.level_count(),
+        });
+    }
+
+    for level in 0..levels {
+        let decoded = if level == 0 {
+            decode::decode_buffer(written.clone())
+        } else {
+            decode::decode_level(&written, level)
+        };
+        decoded.map_err(|error| VerifyError::Decode {
+            level,
+            error: Box::new(error),
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! Watching the input files of `encode --watch`, to encode them again whenever they change.
+
+use crate::error::Failure;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// How long to wait after a change before rebuilding, so that the files saved several times in a
+/// row (as many editors do) are only rebuilt once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Contains all the possible errors that can occur while setting up the watching.
+#[derive(Debug)]
+pub enum WatchError {
+    /// The watcher itself can't be started.
+    Watcher(notify::Error),
+    /// The input file or directory at the given path can't be watched.
+    Watch(PathBuf, notify::Error),
+    /// The Ctrl-C handler that stops the watching can't be set up.
+    Interrupt(ctrlc::Error),
+}
+
+impl std::error::Error for WatchError {}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Watcher(e) => write!(f, "Can't start watching for changes: {e}"),
+            Self::Watch(path, e) => write!(f, "Can't watch `{}` for changes: {e}", path.display()),
+            Self::Interrupt(e) => write!(f, "Can't set up the Ctrl-C handler: {e}"),
+        }
+    }
+}
+
+impl WatchError {
+    /// Returns the category of this error.
+    pub fn failure(&self) -> Failure {
+        Failure::Input
+    }
+}
+
+/// What the watcher and the Ctrl-C handler send to the watching loop.
+enum Message {
+    /// The files at the given paths were created or changed.
+    Changed(Vec<PathBuf>),
+    /// Ctrl-C was pressed.
+    Stop,
+}
+
+/// Watches the input `paths` for changes until Ctrl-C is pressed, calling `rebuild` with each file
+/// that `filter` accepts once it was created or changed.
+///
+/// Directories are watched for the files directly inside of them, or the files in all of their
+/// subdirectories too with `recursive`, while files are watched on their own. Anything inside of
+/// `ignore` is left out, so that the output files don't trigger another rebuild. The paths given
+/// to `rebuild` are canonical.
+pub fn run(
+    paths: &[PathBuf],
+    recursive: bool,
+    ignore: &Path,
+    filter: fn(&Path) -> bool,
+    mut rebuild: impl FnMut(&Path),
+) -> Result<(), WatchError> {
+    let (sender, receiver) = mpsc::channel();
+    let stop = sender.clone();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(Message::Stop);
+    })
+    .map_err(WatchError::Interrupt)?;
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        // changes to the permissions or the times alone don't change the image
+        if matches!(
+            event.kind,
+            EventKind::Create(_)
+                | EventKind::Modify(ModifyKind::Any | ModifyKind::Data(_) | ModifyKind::Name(_))
+        ) {
+            let _ = sender.send(Message::Changed(event.paths));
+        }
+    })
+    .map_err(WatchError::Watcher)?;
+
+    let mode = if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    let mut dirs = Vec::new();
+    let mut files = HashSet::new();
+    for path in paths {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| WatchError::Watch(path.clone(), notify::Error::io(e)))?;
+        // editors often save a file by replacing it, which a watch on the file itself misses, so
+        // the directory it's in is watched instead
+        let result = if canonical.is_dir() {
+            let result = watcher.watch(&canonical, mode);
+            dirs.push(canonical);
+            result
+        } else {
+            let parent = canonical.parent().unwrap_or(Path::new("/")).to_path_buf();
+            files.insert(canonical);
+            watcher.watch(&parent, RecursiveMode::NonRecursive)
+        };
+        result.map_err(|e| WatchError::Watch(path.clone(), e))?;
+    }
+
+    let ignore = ignore
+        .canonicalize()
+        .unwrap_or_else(|_| ignore.to_path_buf());
+    let is_input = |path: &Path| {
+        if path.starts_with(&ignore) || !filter(path) {
+            return false;
+        }
+        files.contains(path)
+            || dirs.iter().any(|dir| {
+                path.parent() == Some(dir.as_path()) || (recursive && path.starts_with(dir))
+            })
+    };
+
+    let mut pending = BTreeSet::new();
+    loop {
+        match receiver.recv_timeout(DEBOUNCE) {
+            Ok(Message::Changed(paths)) => {
+                pending.extend(paths.into_iter().filter(|path| is_input(path)));
+            }
+            Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            // nothing changed for a while, so the files are done being written
+            Err(RecvTimeoutError::Timeout) => {
+                for path in std::mem::take(&mut pending) {
+                    // files that were only there for a moment, like the temporary files of editors
+                    if path.is_file() {
+                        rebuild(&path);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the current local time as `HH:MM:SS`, for the line printed for each rebuild.
+pub fn timestamp() -> String {
+    jiff::Zoned::now().strftime("%H:%M:%S").to_string()
+}
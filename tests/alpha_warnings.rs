@@ -0,0 +1,89 @@
+//! The warning about the transparent pixels of images encoded in data formats that don't store the
+//! alpha channel, which `--strict` turns into an error.
+
+mod common;
+
+use common::{TestDir, gvrtex, stderr};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// The data formats that don't store the alpha channel, along with their display names.
+const NO_ALPHA: [(&str, &str); 3] = [
+    ("intensity4", "Intensity 4-bit"),
+    ("intensity8", "Intensity 8-bit"),
+    ("rgb565", "RGB565"),
+];
+
+/// Runs `encode` on `input` in `dir` for every data format that doesn't store the alpha channel,
+/// with `--strict` if given, and returns the exit code and the standard error of each run.
+fn encode(dir: &TestDir, input: &str, strict: bool) -> Vec<(Option<i32>, String)> {
+    NO_ALPHA
+        .iter()
+        .map(|(data_format, _)| {
+            let mut cmd = gvrtex(dir.path());
+            cmd.args(["encode", input, "out.gvr", "-d", data_format]);
+            if strict {
+                cmd.arg("--strict");
+            }
+            let output = cmd.output().unwrap();
+            (output.status.code(), stderr(&output))
+        })
+        .collect()
+}
+
+#[test]
+fn opaque_rgba_input() {
+    let dir = TestDir::new("opaque_rgba_input");
+    // an alpha channel that's fully opaque everywhere doesn't lose anything
+    RgbaImage::from_pixel(16, 16, Rgba([10, 20, 30, 255]))
+        .save(dir.join("in.png"))
+        .unwrap();
+    for strict in [false, true] {
+        for (code, stderr) in encode(&dir, "in.png", strict) {
+            assert_eq!(code, Some(0), "{stderr}");
+            assert!(!stderr.contains("transparent"), "{stderr}");
+        }
+    }
+}
+
+#[test]
+fn transparent_input() {
+    let dir = TestDir::new("transparent_input");
+    // a quarter of the pixels of the image are translucent
+    common::image(16, 16).save(dir.join("in.png")).unwrap();
+
+    for ((code, stderr), (_, name)) in encode(&dir, "in.png", false).into_iter().zip(NO_ALPHA) {
+        assert_eq!(code, Some(0), "{stderr}");
+        let warning = format!(
+            "warning: 64 transparent pixels will be opaque, as {name} textures don't store the alpha channel"
+        );
+        assert!(stderr.contains(&warning), "{stderr}");
+    }
+    for ((code, stderr), (_, name)) in encode(&dir, "in.png", true).into_iter().zip(NO_ALPHA) {
+        assert_eq!(code, Some(2), "{stderr}");
+        let error = format!(
+            "64 pixels of the image are transparent, but {name} textures don't store the alpha channel"
+        );
+        assert!(stderr.contains(&error), "{stderr}");
+    }
+
+    // the formats that store the alpha channel keep it
+    let output = gvrtex(dir.path())
+        .args(["encode", "in.png", "out.gvr", "-d", "rgb5a3", "--strict"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!stderr(&output).contains("transparent"));
+}
+
+#[test]
+fn no_alpha_input() {
+    let dir = TestDir::new("no_alpha_input");
+    let image = DynamicImage::ImageRgba8(common::image(16, 16)).into_rgb8();
+    image.save(dir.join("in.png")).unwrap();
+    for strict in [false, true] {
+        for (code, stderr) in encode(&dir, "in.png", strict) {
+            assert_eq!(code, Some(0), "{stderr}");
+            assert!(!stderr.contains("transparent"), "{stderr}");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Batch mode with `--out-dir`, and how the output paths are made from the input paths.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image, stderr};
+
+/// The files of the nested tree, with the size of the image saved to each. All of them have the
+/// same stem, so only their directories keep their outputs apart.
+const TREE: [(&str, u32); 3] = [("x.png", 8), ("sub/x.png", 16), ("sub/deeper/x.png", 32)];
+
+/// Creates the nested tree of [`TREE`] inside the `tree` directory of `dir`.
+fn create_tree(dir: &TestDir) {
+    for (path, size) in TREE {
+        let path = dir.join("tree").join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        save_image(&path, size, size);
+    }
+}
+
+#[test]
+fn recursive_mirrors_the_tree() {
+    let dir = TestDir::new("recursive_mirrors_the_tree");
+    create_tree(&dir);
+
+    // the trailing slash and the `..` don't end up in the output paths
+    for (input, out_dir) in [("tree/", "encoded"), ("tree/sub/../", "encoded_dots")] {
+        std::fs::create_dir(dir.join(out_dir)).unwrap();
+        gvrtex(dir.path())
+            .args(["encode", input, "--recursive", "--out-dir", out_dir])
+            .assert()
+            .success();
+    }
+    std::fs::create_dir(dir.join("decoded")).unwrap();
+    gvrtex(dir.path())
+        .args(["decode", "encoded", "--recursive", "--out-dir", "decoded"])
+        .assert()
+        .success();
+
+    for (path, size) in TREE {
+        let texture = std::fs::read(dir.join("encoded").join(path).with_extension("gvr")).unwrap();
+        let dots = std::fs::read(dir.join("encoded_dots").join(path).with_extension("gvr"));
+        assert!(dots.unwrap() == texture, "{path}");
+
+        let dimensions = image::image_dimensions(dir.join("decoded").join(path)).unwrap();
+        assert_eq!(dimensions, (size, size), "{path}");
+    }
+}
+
+#[test]
+fn same_stems_collide_without_recursive() {
+    let dir = TestDir::new("same_stems_collide_without_recursive");
+    create_tree(&dir);
+    std::fs::create_dir(dir.join("out")).unwrap();
+
+    let output = gvrtex(dir.path())
+        .args(["encode", "tree/x.png", "tree/sub/x.png", "--out-dir", "out"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("would be saved to the same output file"));
+    assert!(std::fs::read_dir(dir.join("out")).unwrap().next().is_none());
+}
@@ -0,0 +1,85 @@
+//! Helpers shared by the integration tests, which run the `gvrtex` binary on files in a directory
+//! of their own.
+
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+/// The command-line names of all the data formats.
+pub const DATA_FORMATS: [&str; 10] = [
+    "intensity4",
+    "intensity8",
+    "intensity-a4",
+    "intensity-a8",
+    "rgb565",
+    "rgb5a3",
+    "argb8888",
+    "index4",
+    "index8",
+    "dxt1",
+];
+
+/// Returns a command running the `gvrtex` binary in `dir`, without colors in its output.
+pub fn gvrtex(dir: &Path) -> Command {
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("gvrtex");
+    cmd.current_dir(dir).env("NO_COLOR", "1");
+    cmd
+}
+
+/// An empty directory for the files of a single test, which is deleted again when it's dropped.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Creates the directory for the test with the given `name`, removing what an earlier run of
+    /// the test left in it.
+    pub fn new(name: &str) -> Self {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Returns an image of the given size with a different color for every pixel, and partly
+/// transparent pixels in the bottom right.
+pub fn image(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let alpha = if x >= width / 2 && y >= height / 2 {
+            128
+        } else {
+            255
+        };
+        Rgba([
+            (x * 255 / width.max(2).saturating_sub(1)) as u8,
+            (y * 255 / height.max(2).saturating_sub(1)) as u8,
+            ((x + y) * 16 % 256) as u8,
+            alpha,
+        ])
+    })
+}
+
+/// Saves [`image`] with the given size to `path`, in the format of its extension.
+pub fn save_image(path: &Path, width: u32, height: u32) {
+    image(width, height).save(path).unwrap();
+}
+
+/// Returns the standard error output of a finished command as a string.
+pub fn stderr(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
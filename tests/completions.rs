@@ -0,0 +1,43 @@
+//! The shell completion scripts of the `completions` command.
+
+mod common;
+
+use common::{DATA_FORMATS, TestDir, gvrtex};
+
+#[test]
+fn bash_completes_commands_and_values() {
+    let dir = TestDir::new("bash_completes_commands_and_values");
+    let output = gvrtex(dir.path())
+        .args(["completions", "bash"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+
+    for command in ["encode", "decode"] {
+        assert!(script.contains(command), "{command}");
+    }
+    for value in DATA_FORMATS
+        .iter()
+        .chain(&["rgb565", "rgb5a3", "gcix", "gbix"])
+    {
+        assert!(script.contains(value), "{value}");
+    }
+}
+
+#[test]
+fn every_shell_to_a_file() {
+    let dir = TestDir::new("every_shell_to_a_file");
+    for shell in ["bash", "elvish", "fish", "powershell", "zsh"] {
+        let output = gvrtex(dir.path())
+            .args(["completions", shell, "-o", shell])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{shell}");
+        // only the success message is printed, not the script
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!stdout.contains("decode"), "{shell}");
+        let script = std::fs::read_to_string(dir.join(shell)).unwrap();
+        assert!(script.contains("decode"), "{shell}");
+    }
+}
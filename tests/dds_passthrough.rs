@@ -0,0 +1,78 @@
+//! Encoding the BC1 blocks of DDS files into `dxt1` textures as they are, with `--passthrough`.
+
+mod common;
+
+use common::{TestDir, gvrtex, stderr};
+use image::Rgba;
+
+/// Saves a `dxt1` texture of an image of the given size with some transparent pixels to `src.gvr`
+/// in `dir`, with mipmaps if `mipmaps` is set, and the DDS file of its BC1 blocks to `src.dds`.
+fn create_dds(dir: &TestDir, width: u32, height: u32, mipmaps: bool) {
+    let mut image = common::image(width, height);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if (x + 3 * y) % 7 == 0 {
+            *pixel = Rgba([0; 4]);
+        }
+    }
+    image.save(dir.join("src.png")).unwrap();
+    let mut cmd = gvrtex(dir.path());
+    cmd.args(["encode", "src.png", "src.gvr", "-d", "dxt1"]);
+    if mipmaps {
+        cmd.arg("--mipmaps");
+    }
+    cmd.assert().success();
+    gvrtex(dir.path())
+        .args(["decode", "src.gvr", "src.dds"])
+        .assert()
+        .success();
+}
+
+/// Encodes `src.dds` in `dir` to `out.gvr` with `--passthrough` and the given other `args`, and
+/// returns the standard error after checking that it succeeded.
+fn passthrough(dir: &TestDir, args: &[&str]) -> String {
+    let output = gvrtex(dir.path())
+        .args(["encode", "src.dds", "out.gvr", "--passthrough"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    stderr(&output)
+}
+
+#[test]
+fn passthrough_roundtrip() {
+    let dir = TestDir::new("passthrough_roundtrip");
+    // a size that leaves partly used tiles on the right and the bottom
+    create_dds(&dir, 24, 20, false);
+    let stderr = passthrough(&dir, &["-d", "dxt1"]);
+    assert!(!stderr.contains("warning"), "{stderr}");
+    // the blocks of the DDS file are the ones the texture was saved from, so nothing changes
+    let texture = std::fs::read(dir.join("out.gvr")).unwrap();
+    assert!(texture == std::fs::read(dir.join("src.gvr")).unwrap());
+}
+
+#[test]
+fn mipmap_levels_are_passed_through() {
+    let dir = TestDir::new("mipmap_levels_are_passed_through");
+    create_dds(&dir, 32, 32, true);
+    let stderr = passthrough(&dir, &["-d", "dxt1", "--mipmaps"]);
+    assert!(!stderr.contains("warning"), "{stderr}");
+    let texture = std::fs::read(dir.join("out.gvr")).unwrap();
+    assert!(texture == std::fs::read(dir.join("src.gvr")).unwrap());
+}
+
+#[test]
+fn fallback_with_warning() {
+    let dir = TestDir::new("passthrough_fallback_with_warning");
+    create_dds(&dir, 32, 32, false);
+    let stderr = passthrough(&dir, &["-d", "rgb5a3"]);
+    assert!(stderr.contains(
+        "warning: src.dds: can't pass the blocks of the DDS file through, as the data format isn't `dxt1`, so it's compressed again"
+    ), "{stderr}");
+
+    let stderr = passthrough(&dir, &["-d", "dxt1", "--mipmaps"]);
+    assert!(
+        stderr.contains("as it has 1 image levels, but the texture needs 6"),
+        "{stderr}"
+    );
+}
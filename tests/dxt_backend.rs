@@ -0,0 +1,31 @@
+//! Compressing `dxt1` textures with `--dxt-backend squish`, which needs the `dxt-alt` feature.
+
+#![cfg(feature = "dxt-alt")]
+
+mod common;
+
+use common::{TestDir, gvrtex};
+
+#[test]
+fn squish_roundtrip() {
+    let dir = TestDir::new("squish_roundtrip");
+    // a size that isn't a multiple of the 8x8 tiles, with transparent pixels
+    let mut image = common::image(20, 12);
+    image.put_pixel(0, 0, image::Rgba([0; 4]));
+    image.save(dir.join("in.png")).unwrap();
+    for quality in ["fast", "normal", "high"] {
+        gvrtex(dir.path())
+            .args(["encode", "in.png", "out.gvr", "-d", "dxt1"])
+            .args(["--dxt-backend", "squish", "--dxt-quality", quality])
+            .assert()
+            .success();
+        gvrtex(dir.path())
+            .args(["decode", "out.gvr", "out.png"])
+            .assert()
+            .success();
+        let decoded = image::open(dir.join("out.png")).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (20, 12), "{quality}");
+        assert_eq!(decoded.get_pixel(0, 0)[3], 0, "{quality}");
+        assert_eq!(decoded.get_pixel(1, 0)[3], 255, "{quality}");
+    }
+}
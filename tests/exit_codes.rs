@@ -0,0 +1,89 @@
+//! The exit code of each category of failure, for the scripts that tell them apart.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image};
+
+/// Creates a valid texture at `valid.gvr` and a file of random-looking bytes at `broken.gvr` in
+/// `dir`.
+fn create_inputs(dir: &TestDir) {
+    save_image(&dir.join("in.png"), 16, 16);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "valid.gvr", "-d", "rgb5a3"])
+        .assert()
+        .success();
+    let broken: Vec<u8> = (0..100u32).map(|i| (i * 37 + 11) as u8).collect();
+    std::fs::write(dir.join("broken.gvr"), broken).unwrap();
+}
+
+#[test]
+fn success() {
+    let dir = TestDir::new("exit_success");
+    create_inputs(&dir);
+    gvrtex(dir.path())
+        .args(["decode", "valid.gvr", "out.png"])
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn usage() {
+    let dir = TestDir::new("exit_usage");
+    create_inputs(&dir);
+    gvrtex(dir.path())
+        .args(["decode", "valid.gvr", "out.png", "--no-such-option"])
+        .assert()
+        .code(2);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "out.gvr", "-d", "no-such-format"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn input() {
+    let dir = TestDir::new("exit_input");
+    gvrtex(dir.path())
+        .args(["decode", "missing.gvr", "out.png"])
+        .assert()
+        .code(3);
+    gvrtex(dir.path())
+        .args(["encode", "missing.png", "out.gvr"])
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn codec() {
+    let dir = TestDir::new("exit_codec");
+    create_inputs(&dir);
+    gvrtex(dir.path())
+        .args(["decode", "broken.gvr", "out.png"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn output() {
+    let dir = TestDir::new("exit_output");
+    create_inputs(&dir);
+    // a directory is in the way of the output file
+    std::fs::create_dir(dir.join("out.png")).unwrap();
+    gvrtex(dir.path())
+        .args(["decode", "valid.gvr", "out.png"])
+        .assert()
+        .code(5);
+}
+
+#[test]
+fn batch() {
+    let dir = TestDir::new("exit_batch");
+    create_inputs(&dir);
+    std::fs::create_dir(dir.join("out")).unwrap();
+    gvrtex(dir.path())
+        .args(["decode", "valid.gvr", "broken.gvr", "--out-dir", "out"])
+        .assert()
+        .code(6);
+    // the file that could be decoded is still saved
+    assert!(dir.join("out/valid.png").is_file());
+}
@@ -0,0 +1,162 @@
+//! Building GVM archives with `gvm pack`, taking them apart again with `gvm unpack` and replacing
+//! their entries with `gvm replace`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image};
+use std::path::Path;
+
+/// The textures packed by the tests, by file stem and data format. The last name is longer than
+/// the 28 characters of an entry name.
+const TEXTURES: [(&str, &str); 3] = [
+    ("zeta", "rgb565"),
+    ("alpha", "dxt1"),
+    ("a_texture_with_a_very_long_name", "index4"),
+];
+
+/// Encodes [`TEXTURES`] in `dir`, each with a global index of its own.
+fn create_textures(dir: &TestDir) {
+    save_image(&dir.join("in.png"), 16, 16);
+    for (index, (stem, data_format)) in TEXTURES.iter().enumerate() {
+        gvrtex(dir.path())
+            .args([
+                "encode",
+                "in.png",
+                &format!("{stem}.gvr"),
+                "-d",
+                data_format,
+            ])
+            .args(["--global-index", &(index + 10).to_string()])
+            .assert()
+            .success();
+    }
+}
+
+/// Unpacks the archive `archive` in `dir` into the directory `out_dir`.
+fn unpack(dir: &TestDir, archive: &str, out_dir: &str) {
+    gvrtex(dir.path())
+        .args(["gvm", "unpack", archive, "-o", out_dir])
+        .assert()
+        .success();
+}
+
+/// Returns the file names and contents of the files in `dir`, sorted by their names.
+fn files(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, std::fs::read(path).unwrap())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Returns the names of the entries of the archive `archive` in `dir`, in their order.
+fn entry_names(dir: &TestDir, archive: &str) -> Vec<String> {
+    let output = gvrtex(dir.path())
+        .args(["gvm", "list", archive, "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            entry["name"].as_str().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[test]
+fn unpack_pack_unpack_roundtrip() {
+    let dir = TestDir::new("unpack_pack_unpack_roundtrip");
+    create_textures(&dir);
+    let inputs = TEXTURES.map(|(stem, _)| format!("{stem}.gvr"));
+    gvrtex(dir.path())
+        .args(["gvm", "pack", "first.gvm"])
+        .args(&inputs)
+        .assert()
+        .success();
+    assert_eq!(
+        entry_names(&dir, "first.gvm"),
+        ["zeta", "alpha", "a_texture_with_a_very_long_n"]
+    );
+    unpack(&dir, "first.gvm", "first");
+
+    // the unpacked textures are the ones that were packed, with their names cut off
+    let unpacked = files(&dir.join("first"));
+    assert_eq!(unpacked.len(), 3);
+    for (stem, _) in TEXTURES {
+        let texture = std::fs::read(dir.join(format!("{stem}.gvr"))).unwrap();
+        let name = format!("{}.gvr", &stem[..stem.len().min(28)]);
+        assert!(unpacked.contains(&(name, texture)), "{stem}");
+    }
+
+    gvrtex(dir.path())
+        .args(["gvm", "pack", "second.gvm", "--from-dir", "first"])
+        .args(["--match", "first.gvm"])
+        .assert()
+        .success();
+    unpack(&dir, "second.gvm", "second");
+    assert!(files(&dir.join("second")) == unpacked);
+    // matching the archive also keeps the order of its entries and their fields
+    assert!(
+        std::fs::read(dir.join("second.gvm")).unwrap()
+            == std::fs::read(dir.join("first.gvm")).unwrap()
+    );
+}
+
+#[test]
+fn from_dir_is_sorted() {
+    let dir = TestDir::new("from_dir_is_sorted");
+    create_textures(&dir);
+    std::fs::create_dir(dir.join("textures")).unwrap();
+    for (stem, _) in TEXTURES {
+        let name = format!("{stem}.gvr");
+        std::fs::rename(dir.join(&name), dir.join("textures").join(&name)).unwrap();
+    }
+    gvrtex(dir.path())
+        .args(["gvm", "pack", "out.gvm", "--from-dir", "textures"])
+        .assert()
+        .success();
+    assert_eq!(
+        entry_names(&dir, "out.gvm"),
+        ["a_texture_with_a_very_long_n", "alpha", "zeta"]
+    );
+}
+
+#[test]
+fn replace_needs_a_destination() {
+    let dir = TestDir::new("replace_needs_a_destination");
+    create_textures(&dir);
+    gvrtex(dir.path())
+        .args(["gvm", "pack", "in.gvm", "zeta.gvr", "alpha.gvr"])
+        .assert()
+        .success();
+    let original = std::fs::read(dir.join("in.gvm")).unwrap();
+    let replace = |args: &[&str]| {
+        gvrtex(dir.path())
+            .args(["gvm", "replace", "in.gvm", "--name", "zeta", "--with"])
+            .arg("a_texture_with_a_very_long_name.gvr")
+            .args(args)
+            .assert()
+    };
+
+    // neither of them, or both of them, is a usage error that leaves the archive alone
+    replace(&[]).code(2);
+    replace(&["--in-place", "-o", "out.gvm"]).code(2);
+    assert!(std::fs::read(dir.join("in.gvm")).unwrap() == original);
+    assert!(!dir.join("out.gvm").exists());
+
+    replace(&["-o", "out.gvm"]).success();
+    assert!(std::fs::read(dir.join("in.gvm")).unwrap() == original);
+    replace(&["--in-place"]).success();
+    assert!(
+        std::fs::read(dir.join("in.gvm")).unwrap() == std::fs::read(dir.join("out.gvm")).unwrap()
+    );
+    assert!(std::fs::read(dir.join("in.gvm")).unwrap() != original);
+}
@@ -0,0 +1,157 @@
+//! The options that only apply to some data formats, which are ignored with a warning on the
+//! others, or refused with `--strict`.
+
+mod common;
+
+use common::{DATA_FORMATS, TestDir, gvrtex, save_image, stderr};
+
+/// The options of the color palette, along with a value for the ones that take one.
+const PALETTE_OPTIONS: [&[&str]; 4] = [
+    &["--pixel-format", "rgb565"],
+    &["--quantizer", "median-cut"],
+    &["--stable-palette"],
+    &["--colors", "8"],
+];
+
+fn is_indexed(data_format: &str) -> bool {
+    data_format.starts_with("index")
+}
+
+#[test]
+fn palette_options_by_data_format() {
+    let dir = TestDir::new("palette_options_by_data_format");
+    save_image(&dir.join("in.png"), 16, 16);
+
+    for data_format in DATA_FORMATS {
+        // without any of the options, nothing is ignored
+        let output = gvrtex(dir.path())
+            .args(["encode", "in.png", "out.gvr", "-d", data_format, "--strict"])
+            .args(["--force-opaque"])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{data_format}: {}",
+            stderr(&output)
+        );
+        assert!(!stderr(&output).contains("ignoring"), "{data_format}");
+
+        for option in PALETTE_OPTIONS {
+            let flag = option[0];
+            let output = gvrtex(dir.path())
+                .args(["encode", "in.png", "out.gvr", "-d", data_format])
+                .args(option)
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{data_format} {flag}: {}",
+                stderr(&output)
+            );
+            let warning = format!("warning: ignoring `{flag}`, as the {data_format} data format");
+            assert_eq!(
+                stderr(&output).contains(&warning),
+                !is_indexed(data_format),
+                "{data_format} {flag}: {}",
+                stderr(&output)
+            );
+
+            let output = gvrtex(dir.path())
+                .args(["encode", "in.png", "out.gvr", "-d", data_format, "--strict"])
+                .args(["--force-opaque"])
+                .args(option)
+                .output()
+                .unwrap();
+            if is_indexed(data_format) {
+                assert!(
+                    output.status.success(),
+                    "{data_format} {flag}: {}",
+                    stderr(&output)
+                );
+            } else {
+                assert_eq!(output.status.code(), Some(2), "{data_format} {flag}");
+                assert!(
+                    stderr(&output).contains(&format!(
+                        "Can't use `{flag}` on the `{data_format}` data format, which has no color palette."
+                    )),
+                    "{data_format} {flag}: {}",
+                    stderr(&output)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn dither_by_data_format() {
+    let dir = TestDir::new("dither_by_data_format");
+    save_image(&dir.join("in.png"), 16, 16);
+
+    for data_format in DATA_FORMATS {
+        let dithered = !matches!(data_format, "argb8888" | "dxt1");
+        let output = gvrtex(dir.path())
+            .args(["encode", "in.png", "out.gvr", "-d", data_format])
+            .args(["--dither", "ordered4"])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{data_format}: {}",
+            stderr(&output)
+        );
+        assert_eq!(
+            stderr(&output).contains("ignoring `--dither`"),
+            !dithered,
+            "{data_format}"
+        );
+
+        let output = gvrtex(dir.path())
+            .args(["encode", "in.png", "out.gvr", "-d", data_format, "--strict"])
+            .args(["--force-opaque", "--dither", "ordered4"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(if dithered { 0 } else { 2 }),
+            "{data_format}: {}",
+            stderr(&output)
+        );
+    }
+}
+
+#[test]
+fn dxt_quality_by_data_format() {
+    let dir = TestDir::new("dxt_quality_by_data_format");
+    save_image(&dir.join("in.png"), 16, 16);
+
+    for data_format in DATA_FORMATS {
+        let compressed = data_format == "dxt1";
+        let output = gvrtex(dir.path())
+            .args(["encode", "in.png", "out.gvr", "-d", data_format])
+            .args(["--dxt-quality", "high"])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{data_format}: {}",
+            stderr(&output)
+        );
+        assert_eq!(
+            stderr(&output).contains("ignoring `--dxt-quality`"),
+            !compressed,
+            "{data_format}"
+        );
+
+        let output = gvrtex(dir.path())
+            .args(["encode", "in.png", "out.gvr", "-d", data_format, "--strict"])
+            .args(["--force-opaque", "--dxt-quality", "high"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(if compressed { 0 } else { 2 }),
+            "{data_format}: {}",
+            stderr(&output)
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! The mipmap chains that `encode` generates, and how they're decoded again.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image, stderr};
+use std::path::Path;
+
+/// Returns the number of levels that `decode --all-mips` saves from the texture at `path` in `dir`.
+fn decoded_levels(dir: &Path, path: &str) -> usize {
+    gvrtex(dir)
+        .args(["decode", path, "level.png", "--all-mips"])
+        .assert()
+        .success();
+    let count = std::fs::read_dir(dir)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with("level_mip")
+        })
+        .count();
+    for level in 0..count {
+        std::fs::remove_file(dir.join(format!("level_mip{level}.png"))).unwrap();
+    }
+    count
+}
+
+#[test]
+fn logged_levels_match_the_chain() {
+    let dir = TestDir::new("logged_levels_match_the_chain");
+    save_image(&dir.join("in.png"), 32, 32);
+
+    let cases: [(&[&str], usize); 3] = [
+        (&[], 5),
+        (&["--mip-count", "4"], 3),
+        (&["--min-mip-size", "8"], 2),
+    ];
+    for (options, levels) in cases {
+        let output = gvrtex(dir.path())
+            .args([
+                "-v",
+                "encode",
+                "in.png",
+                "out.gvr",
+                "-d",
+                "dxt1",
+                "--mipmaps",
+            ])
+            .args(options)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{options:?}: {}", stderr(&output));
+        let logged = format!("in.png: generating {levels} mipmap levels");
+        assert!(
+            stderr(&output).contains(&logged),
+            "{options:?}: {}",
+            stderr(&output)
+        );
+        // the levels exclude the base image
+        assert_eq!(
+            decoded_levels(dir.path(), "out.gvr"),
+            levels + 1,
+            "{options:?}"
+        );
+    }
+}
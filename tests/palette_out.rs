@@ -0,0 +1,51 @@
+//! Saving the palette of indexed textures as a swatch with `decode --palette-out`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image, stderr};
+
+#[test]
+fn swatch_of_index4_texture() {
+    let dir = TestDir::new("swatch_of_index4_texture");
+    save_image(&dir.join("in.png"), 16, 16);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "in.gvr", "-d", "index4"])
+        .assert()
+        .success();
+    gvrtex(dir.path())
+        .args(["decode", "in.gvr", "out.png", "--palette-out", "swatch.png"])
+        .arg("--palette-cell")
+        .arg("4")
+        .assert()
+        .success();
+
+    // the 16 colors of the palette make a single row of 4x4 squares
+    let swatch = image::open(dir.join("swatch.png")).unwrap().to_rgba8();
+    assert_eq!(swatch.dimensions(), (64, 4));
+    for (x, y, pixel) in swatch.enumerate_pixels() {
+        assert_eq!(pixel, swatch.get_pixel(x / 4 * 4, 0), "{x}, {y}");
+    }
+    // every color of the decoded image is in the palette
+    let decoded = image::open(dir.join("out.png")).unwrap().to_rgba8();
+    for pixel in decoded.pixels() {
+        assert!(swatch.pixels().any(|color| color == pixel), "{pixel:?}");
+    }
+}
+
+#[test]
+fn non_indexed_texture() {
+    let dir = TestDir::new("palette_out_non_indexed_texture");
+    save_image(&dir.join("in.png"), 16, 16);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "in.gvr", "-d", "rgb5a3"])
+        .assert()
+        .success();
+    let output = gvrtex(dir.path())
+        .args(["decode", "in.gvr", "out.png", "--palette-out", "swatch.png"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("RGB5A3 textures have no color palette"));
+    assert!(!dir.join("swatch.png").exists());
+    assert!(!dir.join("out.png").exists());
+}
@@ -0,0 +1,69 @@
+//! PRS compression of files with `prs`, and of the output of `encode` and `gvm pack` with
+//! `--compress prs`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image};
+
+#[test]
+fn prs_compress_roundtrip() {
+    let dir = TestDir::new("prs_compress_roundtrip");
+    save_image(&dir.join("in.png"), 32, 32);
+    for (input, compressed, decompressed) in [
+        ("in.png", "png.prs", "png.out"),
+        ("empty", "empty.prs", "empty.out"),
+    ] {
+        if input == "empty" {
+            std::fs::write(dir.join(input), []).unwrap();
+        }
+        gvrtex(dir.path())
+            .args(["prs", "compress", input, compressed])
+            .assert()
+            .success();
+        gvrtex(dir.path())
+            .args(["prs", "decompress", compressed, decompressed])
+            .assert()
+            .success();
+        let original = std::fs::read(dir.join(input)).unwrap();
+        assert!(
+            std::fs::read(dir.join(decompressed)).unwrap() == original,
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn compressed_outputs() {
+    let dir = TestDir::new("compressed_outputs");
+    save_image(&dir.join("in.png"), 32, 32);
+    for (path, compress) in [("plain.gvr", false), ("compressed.gvr", true)] {
+        let mut cmd = gvrtex(dir.path());
+        cmd.args(["encode", "in.png", path, "-d", "rgb5a3"]);
+        if compress {
+            cmd.args(["--compress", "prs"]);
+        }
+        cmd.assert().success();
+    }
+    let plain = std::fs::read(dir.join("plain.gvr")).unwrap();
+    assert!(std::fs::read(dir.join("compressed.gvr")).unwrap() != plain);
+    gvrtex(dir.path())
+        .args(["prs", "decompress", "compressed.gvr", "decompressed.gvr"])
+        .assert()
+        .success();
+    assert!(std::fs::read(dir.join("decompressed.gvr")).unwrap() == plain);
+
+    for (path, compress) in [("plain.gvm", false), ("compressed.gvm", true)] {
+        let mut cmd = gvrtex(dir.path());
+        cmd.args(["gvm", "pack", path, "plain.gvr"]);
+        if compress {
+            cmd.args(["--compress", "prs"]);
+        }
+        cmd.assert().success();
+    }
+    gvrtex(dir.path())
+        .args(["prs", "decompress", "compressed.gvm", "decompressed.gvm"])
+        .assert()
+        .success();
+    let archive = std::fs::read(dir.join("plain.gvm")).unwrap();
+    assert!(std::fs::read(dir.join("decompressed.gvm")).unwrap() == archive);
+}
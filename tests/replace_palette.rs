@@ -0,0 +1,73 @@
+//! Swapping the palette of indexed textures with `replace-palette`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image, stderr};
+
+/// The size of the header of a GVR texture file, after which the palette is stored.
+const HEADER_SIZE: usize = 0x20;
+
+/// Saves a GIMP palette of `count` colors to `path` in `dir`, with a different gray for each.
+fn save_palette(dir: &TestDir, path: &str, count: usize) {
+    let mut text = String::from("GIMP Palette\n");
+    for index in 0..count {
+        let value = index * 255 / (count - 1);
+        text.push_str(&format!("{value} {value} {value}\n"));
+    }
+    std::fs::write(dir.join(path), text).unwrap();
+}
+
+#[test]
+fn only_the_palette_changes() {
+    let dir = TestDir::new("only_the_palette_changes");
+    save_image(&dir.join("in.png"), 16, 16);
+    for (data_format, colors) in [("index4", 16), ("index8", 256)] {
+        gvrtex(dir.path())
+            .args(["encode", "in.png", "in.gvr", "-d", data_format])
+            .args(["--pixel-format", "rgb565"])
+            .assert()
+            .success();
+        save_palette(&dir, "grays.gpl", colors);
+        gvrtex(dir.path())
+            .args(["replace-palette", "in.gvr", "out.gvr", "-p", "grays.gpl"])
+            .assert()
+            .success();
+
+        let input = std::fs::read(dir.join("in.gvr")).unwrap();
+        let output = std::fs::read(dir.join("out.gvr")).unwrap();
+        let palette_end = HEADER_SIZE + colors * 2;
+        assert_eq!(input.len(), output.len(), "{data_format}");
+        assert!(
+            input[..HEADER_SIZE] == output[..HEADER_SIZE],
+            "{data_format}"
+        );
+        assert!(
+            input[palette_end..] == output[palette_end..],
+            "{data_format}"
+        );
+        // the grays are stored in rgb565, from black to white
+        let palette = &output[HEADER_SIZE..palette_end];
+        assert_eq!(palette[..2], [0x00, 0x00], "{data_format}");
+        assert_eq!(palette[palette.len() - 2..], [0xFF, 0xFF], "{data_format}");
+    }
+}
+
+#[test]
+fn entry_count_mismatch() {
+    let dir = TestDir::new("entry_count_mismatch");
+    save_image(&dir.join("in.png"), 16, 16);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "in.gvr", "-d", "index8"])
+        .assert()
+        .success();
+    save_palette(&dir, "grays.gpl", 16);
+    let output = gvrtex(dir.path())
+        .args(["replace-palette", "in.gvr", "out.gvr", "-p", "grays.gpl"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains(
+        "The palette has 16 colors, but the palette of the texture has 256. Both need the same amount."
+    ));
+    assert!(!dir.join("out.gvr").exists());
+}
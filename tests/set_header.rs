@@ -0,0 +1,56 @@
+//! Switching the header of GVR textures between GCIX and GBIX with `set-header`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image};
+
+/// Decodes the texture at `path` in `dir` and returns the pixels of the decoded image.
+fn decode(dir: &TestDir, path: &str) -> Vec<u8> {
+    gvrtex(dir.path())
+        .args(["decode", path, "decoded.png"])
+        .assert()
+        .success();
+    image::open(dir.join("decoded.png"))
+        .unwrap()
+        .to_rgba8()
+        .into_raw()
+}
+
+#[test]
+fn set_header_roundtrip() {
+    let dir = TestDir::new("set_header_roundtrip");
+    save_image(&dir.join("in.png"), 16, 16);
+    for path in ["a.gvr", "b.gvr"] {
+        gvrtex(dir.path())
+            .args(["encode", "in.png", path, "-d", "dxt1", "--header", "gcix"])
+            .assert()
+            .success();
+    }
+    let original = std::fs::read(dir.join("a.gvr")).unwrap();
+    let pixels = decode(&dir, "a.gvr");
+
+    gvrtex(dir.path())
+        .args(["set-header", "a.gvr", "b.gvr", "gbix"])
+        .assert()
+        .success();
+    for path in ["a.gvr", "b.gvr"] {
+        let changed = std::fs::read(dir.join(path)).unwrap();
+        assert_eq!(&changed[..4], b"GBIX", "{path}");
+        assert!(changed[4..] == original[4..], "{path}");
+        assert!(decode(&dir, path) == pixels, "{path}");
+    }
+
+    // a file that already has the header is left alone
+    let output = gvrtex(dir.path())
+        .args(["set-header", "a.gvr", "GBIX"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("already has a GBIX header"));
+
+    gvrtex(dir.path())
+        .args(["set-header", "a.gvr", "gcix"])
+        .assert()
+        .success();
+    assert!(std::fs::read(dir.join("a.gvr")).unwrap() == original);
+}
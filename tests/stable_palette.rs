@@ -0,0 +1,49 @@
+//! Encoding the same image into the same bytes every time with `--stable-palette`.
+
+mod common;
+
+use common::{TestDir, gvrtex};
+use image::{Rgba, RgbaImage};
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Returns an image with smooth shading in all channels and a little noise on top of it, so that
+/// it has more colors than any palette.
+fn photo() -> RgbaImage {
+    let mut state = 0x2545_f491_u32;
+    RgbaImage::from_fn(32, 32, |x, y| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let noise = state % 16;
+        Rgba([
+            (x * 7 + noise) as u8,
+            (y * 7 + noise) as u8,
+            ((x + y) * 3 + noise) as u8,
+            if (x + y) % 5 == 0 { 160 } else { 255 },
+        ])
+    })
+}
+
+#[test]
+fn fifty_encodes_are_identical() {
+    let dir = TestDir::new("fifty_encodes_are_identical");
+    photo().save(dir.join("in.png")).unwrap();
+    for data_format in ["index4", "index8"] {
+        let hashes: HashSet<u64> = (0..50)
+            .map(|_| {
+                gvrtex(dir.path())
+                    .args(["encode", "in.png", "out.gvr", "-d", data_format])
+                    .arg("--stable-palette")
+                    .assert()
+                    .success();
+                let mut hasher = DefaultHasher::new();
+                std::fs::read(dir.join("out.gvr"))
+                    .unwrap()
+                    .hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect();
+        assert_eq!(hashes.len(), 1, "{data_format}");
+    }
+}
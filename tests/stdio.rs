@@ -0,0 +1,34 @@
+//! Reading the input from standard input and writing the output to standard output with `-`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image};
+
+#[test]
+fn decode_to_stdout_roundtrip() {
+    let dir = TestDir::new("decode_to_stdout_roundtrip");
+    save_image(&dir.join("in.png"), 16, 16);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "in.gvr", "-d", "argb8888"])
+        .assert()
+        .success();
+
+    let output = gvrtex(dir.path())
+        .args(["decode", "in.gvr", "-", "--image-format", "png"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // the messages go to standard error, so that only the image is on standard output
+    let decoded = image::load_from_memory(&output.stdout).unwrap().to_rgba8();
+    assert_eq!(decoded, image::open(dir.join("in.png")).unwrap().to_rgba8());
+
+    // `argb8888` is lossless, so encoding the piped image again gives the same texture
+    gvrtex(dir.path())
+        .args(["encode", "-", "out.gvr", "-d", "argb8888"])
+        .write_stdin(output.stdout)
+        .assert()
+        .success();
+    let input = std::fs::read(dir.join("in.gvr")).unwrap();
+    let output = std::fs::read(dir.join("out.gvr")).unwrap();
+    assert!(input == output);
+}
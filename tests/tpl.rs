@@ -0,0 +1,43 @@
+//! Combining GVR textures into TPL files with `to-tpl`, and splitting them again with `from-tpl`.
+
+mod common;
+
+use common::{TestDir, gvrtex, save_image};
+
+#[test]
+fn tpl_roundtrip() {
+    let dir = TestDir::new("tpl_roundtrip");
+    save_image(&dir.join("in.png"), 32, 32);
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "a.gvr", "-d", "index8"])
+        .assert()
+        .success();
+    gvrtex(dir.path())
+        .args(["encode", "in.png", "b.gvr", "-d", "rgb565", "--mipmaps"])
+        .assert()
+        .success();
+
+    gvrtex(dir.path())
+        .args(["to-tpl", "a.gvr", "b.gvr", "out.tpl"])
+        .assert()
+        .success();
+    gvrtex(dir.path())
+        .args(["from-tpl", "out.tpl", "--out-dir", "split"])
+        .assert()
+        .success();
+    for (input, output) in [("a.gvr", "out_000.gvr"), ("b.gvr", "out_001.gvr")] {
+        let input = std::fs::read(dir.join(input)).unwrap();
+        let output = std::fs::read(dir.join("split").join(output)).unwrap();
+        assert!(input == output, "{output:?}");
+    }
+}
+
+#[test]
+fn tpl_needs_an_input_and_an_output() {
+    let dir = TestDir::new("tpl_needs_an_input_and_an_output");
+    gvrtex(dir.path())
+        .args(["to-tpl", "a.gvr"])
+        .assert()
+        .code(2);
+    assert!(!dir.join("a.gvr").exists());
+}